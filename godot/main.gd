@@ -22,6 +22,7 @@ func _ready():
 	GlobalAudioState.bpm_changed.connect(_on_bpm_changed)
 	GlobalAudioState.seed_changed.connect(_on_seed_changed)
 	GlobalAudioState.graph_debug_str_changed.connect(_on_graph_debug_str_changed)
+	GlobalAudioState.generation_timings_str_changed.connect(_on_graph_debug_str_changed)
 
 	# update UI
 	update_slider()
@@ -127,7 +128,7 @@ func _on_randomize_button_pressed():
 #########
 
 func update_slider():
-	bpm_hslider.value = GlobalAudioState.bpm
+	bpm_hslider.set_value_no_signal(GlobalAudioState.bpm) # So a ramp doesn't get cancelled by the slider
 	# Don't call this every frame or you can't slide it manually anymore
 
 func update_bpm_label():
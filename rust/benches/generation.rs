@@ -0,0 +1,33 @@
+//! Benchmarks of the constellation generation. Run with `cargo bench`.
+//! Uses the points of a fixed seed, so only clustering and connecting (plus the cheap phases after it) get measured, not the Poisson sampling.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use musical_constellations_rust::gd::graph::{
+    graph_builder::ConstellationBuilder, graph_generate::ConstellationGraph,
+};
+use rand::SeedableRng as _;
+use rand_xoshiro::Xoshiro256Plus;
+
+fn cluster_and_connect(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cluster_and_connect");
+    group.sample_size(20);
+
+    for n in [2000, 10000] {
+        let mut rng = Xoshiro256Plus::seed_from_u64(821);
+        let points = ConstellationGraph::generate_points_uniform(n, 5.0, &mut rng);
+
+        group.bench_with_input(BenchmarkId::from_parameter(n), &points, |b, points| {
+            b.iter(|| {
+                let mut rng = Xoshiro256Plus::seed_from_u64(821);
+                ConstellationBuilder::new()
+                    .build_from_points(points.clone(), &mut rng)
+                    .unwrap()
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, cluster_and_connect);
+criterion_main!(benches);
@@ -2,7 +2,9 @@ use godot::prelude::{GodotConvert, Var};
 use serde::{Deserialize, Serialize};
 use strum::EnumIter;
 
-#[derive(Debug, Default, Clone, Copy, EnumIter, GodotConvert, Var, Serialize, Deserialize)]
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, EnumIter, GodotConvert, Var, Serialize, Deserialize,
+)]
 #[godot(via = i64)]
 pub enum Chord {
     #[default]
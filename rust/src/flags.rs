@@ -10,6 +10,19 @@ pub struct Flag {
 /// If true, play a metronome sound at every tick.
 pub static USE_METRONOME: LazyLock<Flag> = LazyLock::new(|| Flag::new(false));
 
+/// If true, walks ignore incoming ticks, so they freeze until unpaused. Notes that are already playing finish normally.
+/// Toggle it with `AudioState::toggle_walks_paused`, so the UI hears about it.
+pub static WALKS_PAUSED: LazyLock<Flag> = LazyLock::new(|| Flag::new(false));
+
+/// If true, everything is silenced (but not stopped), see `AudioState::set_paused`.
+pub static PAUSED: LazyLock<Flag> = LazyLock::new(|| Flag::new(false));
+
+/// True while the tick thread is ramping the BPM, see `AudioState::ramp_bpm`. The tick thread clears it when the ramp ends.
+pub static BPM_RAMPING: LazyLock<Flag> = LazyLock::new(|| Flag::new(false));
+
+/// True once the tempo follows incoming MIDI clock, see `--midi-in`.
+pub static EXTERNAL_CLOCK: LazyLock<Flag> = LazyLock::new(|| Flag::new(false));
+
 impl Flag {
     pub const fn new(initial: bool) -> Self {
         Self {
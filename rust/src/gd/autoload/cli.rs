@@ -10,7 +10,7 @@ use godot::{
 
 use crate::gd::autoload::state_main::parse_hexseed;
 
-pub static GAME_ARGS: LazyLock<InnerArgs> = LazyLock::new(|| parse_cli_godot_args());
+pub static GAME_ARGS: LazyLock<InnerArgs> = LazyLock::new(parse_cli_godot_args);
 
 // We have a "data bundle" here, see https://godot-rust.github.io/book/register/constructors.html#objects-without-a-base-field
 // That means we don't need a `base` field, and we can skip `base = ...` since the default is RefCounted.
@@ -30,6 +30,10 @@ pub struct InnerArgs {
     #[arg(long, value_parser = parse_hexseed)]
     pub seed: Option<u64>,
 
+    /// BPM to start at, instead of the saved one
+    #[arg(long)]
+    pub bpm: Option<f64>,
+
     /// Skip intro animation
     #[arg(long)]
     #[var]
@@ -40,6 +44,55 @@ pub struct InnerArgs {
     #[var]
     pub windowed: bool,
 
+    /// How many points the constellation has, instead of the default of the scene. Gets clamped to 10..=20000
+    #[arg(long)]
+    pub num_points: Option<i64>,
+
+    /// Build the constellation from the points in this file instead of generating them.
+    /// Either a .json file (e.g. [[0, 1, 0], ...]) or a CSV file with x,y,z per line. Supports res:// and user:// paths.
+    #[arg(long)]
+    pub points_file: Option<String>,
+
+    /// Make walks follow the seed, so the same clicks branch the same way every session
+    #[arg(long)]
+    pub deterministic_walks: bool,
+
+    /// Send MIDI clock to the first MIDI output port with this in its name (case-insensitive), so external gear can follow the tempo.
+    /// Logs the available ports if none match. Only works if the game was built with the `midi` feature.
+    #[arg(long)]
+    pub midi_out: Option<String>,
+
+    /// Follow the MIDI clock and Start/Stop/Continue messages coming in on the first MIDI input port with this in its name (case-insensitive).
+    /// Logs the available ports if none match. Only works if the game was built with the `midi` feature.
+    #[arg(long)]
+    pub midi_in: Option<String>,
+
+    /// Play the nodes closest in pitch to the notes coming in on the first MIDI input port with this in its name (case-insensitive), e.g. from a keyboard.
+    /// Logs the available ports if none match. Only works if the game was built with the `midi` feature.
+    #[arg(long)]
+    pub midi_notes: Option<String>,
+
+    /// Only listen to this MIDI channel (1-16) with `--midi-notes`, instead of all of them
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=16))]
+    pub midi_channel: Option<u8>,
+
+    /// With `--midi-notes`, notes at least this loud (1-127) start a walk, softer ones only play the node
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(1..=127))]
+    pub midi_walk_velocity: u8,
+
+    /// Run the stress test with this many notes once the intro is done, then print the results and quit. For benchmarking scripts.
+    #[arg(long)]
+    pub stress: Option<u32>,
+
+    /// Join the Ableton Link session on the local network, sharing the tempo and beat grid with other apps.
+    /// Only works if the game was built with the `link` feature.
+    #[arg(long)]
+    pub link: bool,
+
+    /// Moves every cluster of points closer to or further from the center by up to this fraction of the radius, for some visual depth (e.g. 0.1)
+    #[arg(long, default_value_t = 0.0)]
+    pub radial_jitter: f32,
+
     /// If true, tracing::info and related macros will log using godot_print. If false, they use stdout.
     /// Must be true to use Godot's log-to-disk functionality.
     #[arg(
@@ -62,8 +115,20 @@ impl Default for InnerArgs {
     fn default() -> Self {
         Self {
             seed: None,
+            bpm: None,
             skip_intro: false,
             windowed: false,
+            num_points: None,
+            points_file: None,
+            radial_jitter: 0.0,
+            deterministic_walks: false,
+            midi_out: None,
+            midi_in: None,
+            midi_notes: None,
+            midi_channel: None,
+            midi_walk_velocity: 100,
+            stress: None,
+            link: false,
             log_to_godot: true,
         }
     }
@@ -98,12 +163,12 @@ pub fn parse_cli_godot_args() -> InnerArgs {
             use clap::error::ErrorKind;
 
             // if the errortype is DisplayHelp, DisplayHelpOnMissingArgumentOrSubcommand, or DisplayVersion, do not print an error, instead just print the help
-            let is_fake_error = match err.kind() {
-                ErrorKind::DisplayHelp => true,
-                ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => true,
-                ErrorKind::DisplayVersion => true,
-                _ => false,
-            };
+            let is_fake_error = matches!(
+                err.kind(),
+                ErrorKind::DisplayHelp
+                    | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+                    | ErrorKind::DisplayVersion
+            );
 
             if is_fake_error {
                 godot_print!(">>> {err}");
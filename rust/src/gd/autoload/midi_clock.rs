@@ -0,0 +1,178 @@
+//! MIDI clock output, so external gear (like hardware synths) can follow our tempo. Enable it with `--midi-out`.
+//! Actually sending anything needs the `midi` feature, the timing math doesn't.
+
+use std::time::Duration;
+
+use crate::gd::autoload::state_tick::{Tick, Transport};
+
+/// Fixed by the MIDI spec (24 PPQN), a beat is a quarter note.
+pub const MIDI_CLOCKS_PER_BEAT: usize = 24;
+
+pub const MIDI_CLOCK: u8 = 0xF8;
+pub const MIDI_START: u8 = 0xFA;
+pub const MIDI_CONTINUE: u8 = 0xFB;
+pub const MIDI_STOP: u8 = 0xFC;
+
+/// When to send the MIDI clocks of `tick`, as fractions of the tick (0..1).
+/// Clock k of a beat lands at k/24 of the beat, so every beat gets exactly `MIDI_CLOCKS_PER_BEAT` clocks,
+/// even when they don't divide evenly over the ticks (e.g. 5 ticks per beat).
+pub fn midi_clock_offsets(tick: &Tick) -> impl Iterator<Item = f64> + use<> {
+    let (index, ticks_per_beat) = (tick.tick, tick.ticks_per_beat);
+    let first = (MIDI_CLOCKS_PER_BEAT * index).div_ceil(ticks_per_beat);
+    let end = (MIDI_CLOCKS_PER_BEAT * (index + 1)).div_ceil(ticks_per_beat);
+    (first..end).map(move |clock| {
+        (clock * ticks_per_beat - MIDI_CLOCKS_PER_BEAT * index) as f64 / MIDI_CLOCKS_PER_BEAT as f64
+    })
+}
+
+/// Sends the MIDI clocks of `tick` with `send`, sleeping in between. `interval` is how long the tick lasts.
+/// Clocks are timed from `Tick::emitted_at`, so handling the tick late doesn't shift them.
+pub fn send_midi_clocks(tick: &Tick, interval: Duration, mut send: impl FnMut(&[u8])) {
+    for offset in midi_clock_offsets(tick) {
+        spin_sleep::sleep_until(tick.emitted_at + interval.mul_f64(offset));
+        send(&[MIDI_CLOCK]);
+    }
+}
+
+/// Keeps track of what to tell external gear when our transport changes, see `Transport`.
+#[derive(Debug)]
+pub struct MidiTransport {
+    playing: bool,
+    /// Reset while stopped, so the next play starts from the top.
+    from_top: bool,
+}
+
+impl Default for MidiTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiTransport {
+    /// The tick thread plays right away, so we start out playing too.
+    pub const fn new() -> Self {
+        Self {
+            playing: true,
+            from_top: false,
+        }
+    }
+
+    /// The message to send for `transport`, if any.
+    pub fn message(&mut self, transport: Transport) -> Option<u8> {
+        match transport {
+            Transport::Play if !self.playing => {
+                self.playing = true;
+                let from_top = std::mem::take(&mut self.from_top);
+                Some(if from_top { MIDI_START } else { MIDI_CONTINUE })
+            }
+            Transport::Stop if self.playing => {
+                self.playing = false;
+                Some(MIDI_STOP)
+            }
+            Transport::Reset if self.playing => Some(MIDI_START),
+            Transport::Reset => {
+                self.from_top = true;
+                None
+            }
+            Transport::Play | Transport::Stop => None,
+        }
+    }
+}
+
+#[cfg(feature = "midi")]
+mod output {
+    use std::{
+        sync::{Mutex, atomic::Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use midir::{MidiOutput, MidiOutputConnection};
+
+    use super::{MIDI_START, MidiTransport, send_midi_clocks};
+    use crate::gd::autoload::state_tick::{CURRENT_BPM, Transport, subscribe_to_ticks};
+
+    static MIDI_OUT: Mutex<Option<MidiOutputConnection>> = Mutex::new(None);
+    static MIDI_TRANSPORT: Mutex<MidiTransport> = Mutex::new(MidiTransport::new());
+
+    /// Connects to the first MIDI output port with `port_filter` in its name (case-insensitive).
+    /// Logs the available ports if none match.
+    pub fn connect_midi_out(port_filter: &str) -> Option<MidiOutputConnection> {
+        let output = MidiOutput::new("Musical Constellations")
+            .inspect_err(|err| tracing::error!("failed to open MIDI output: {err}"))
+            .ok()?;
+
+        let ports = output
+            .ports()
+            .into_iter()
+            .filter_map(|port| Some((output.port_name(&port).ok()?, port)))
+            .collect::<Vec<_>>();
+        let filter = port_filter.to_lowercase();
+        let Some((name, port)) = ports
+            .iter()
+            .find(|(name, _)| name.to_lowercase().contains(&filter))
+        else {
+            let names = ports.iter().map(|(name, _)| name).collect::<Vec<_>>();
+            tracing::error!(
+                "no MIDI output port matches {port_filter:?}, available ports: {names:?}"
+            );
+            return None;
+        };
+
+        tracing::info!("sending MIDI clock to {name:?}");
+        output
+            .connect(port, "clock")
+            .inspect_err(|err| tracing::error!("failed to connect to MIDI port {name:?}: {err}"))
+            .ok()
+    }
+
+    /// Connects to the port matching `port_filter` (see `connect_midi_out`), and sends MIDI clock from its own thread from then on.
+    /// Failing to connect is logged, but not fatal.
+    pub fn start_midi_clock(port_filter: &str) {
+        let Some(connection) = connect_midi_out(port_filter) else {
+            return;
+        };
+        *MIDI_OUT.lock().unwrap() = Some(connection);
+        send_midi(&[MIDI_START]); // The tick thread is already playing
+
+        let mut ticks = subscribe_to_ticks();
+        thread::spawn(move || {
+            while let Some(tick) = ticks.blocking_wait() {
+                // The tick thread already moved on to the next tick, so this is the BPM until then
+                let bpm = CURRENT_BPM.load(Ordering::Relaxed) as f64;
+                let interval = Duration::from_secs_f64(60.0 / bpm / tick.ticks_per_beat as f64);
+                send_midi_clocks(&tick, interval, send_midi);
+            }
+        });
+    }
+
+    /// Tells external gear our transport changed, see `AudioState::transport_play` and friends.
+    pub fn send_midi_transport(transport: Transport) {
+        let message = MIDI_TRANSPORT.lock().unwrap().message(transport);
+        if let Some(message) = message {
+            send_midi(&[message]);
+        }
+    }
+
+    /// Gives up on the port if sending fails (e.g. it got unplugged), instead of logging every clock.
+    fn send_midi(message: &[u8]) {
+        let mut out = MIDI_OUT.lock().unwrap();
+        if let Some(connection) = out.as_mut()
+            && let Err(err) = connection.send(message)
+        {
+            tracing::error!("failed to send MIDI message {message:02X?}, disconnecting: {err}");
+            *out = None;
+        }
+    }
+}
+
+#[cfg(feature = "midi")]
+pub use output::{connect_midi_out, send_midi_transport, start_midi_clock};
+
+#[cfg(not(feature = "midi"))]
+pub fn start_midi_clock(_port_filter: &str) {
+    tracing::error!("--midi-out needs the `midi` feature, ignoring it");
+}
+
+#[cfg(not(feature = "midi"))]
+pub fn send_midi_transport(_transport: Transport) {}
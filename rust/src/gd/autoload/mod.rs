@@ -1,3 +1,4 @@
 pub mod cli;
+pub mod midi_clock;
 pub mod state_main;
 pub mod state_tick;
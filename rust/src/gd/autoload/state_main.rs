@@ -1,18 +1,91 @@
-use std::{cell::OnceCell, sync::atomic::Ordering};
+use std::{
+    cell::{Cell, OnceCell},
+    collections::{BTreeMap, VecDeque},
+    path::PathBuf,
+    rc::Rc,
+    sync::atomic::Ordering,
+    time::Instant,
+};
 
-use godot::{classes::Engine, prelude::*};
-use tracing::instrument;
+use async_executor::LocalExecutor;
+use godot::{
+    classes::{
+        AudioServer, Engine, Performance, ProjectSettings, notify::NodeNotification,
+        performance::Monitor,
+    },
+    global::{Error, linear_to_db},
+    meta::AsArg,
+    prelude::*,
+};
+use tracing::{info_span, instrument};
 
 use crate::{
+    async_node::AsyncNode,
     built_info,
+    flags::{BPM_RAMPING, EXTERNAL_CLOCK, PAUSED, USE_METRONOME, WALKS_PAUSED},
     gd::{
-        autoload::{cli::GAME_ARGS, state_tick::set_bpm_internal},
+        autoload::{
+            cli::GAME_ARGS,
+            midi_clock::{send_midi_transport, start_midi_clock},
+            state_tick::{
+                BpmMessage, CURRENT_BPM, TICK_LAG, TICK_LATENCY, TickForwarding, TimeSignature,
+                Transport, forward_ticks, set_bpm_internal, set_time_signature_internal,
+                set_transport_internal, subscribe_to_ticks,
+            },
+        },
+        graph::{
+            graph_generate::GenerationTimings,
+            graph_main::{AudioGraph, ConstellationStats},
+            graph_walk::ACTIVE_WALKERS,
+        },
         node_stream::ACTIVE_STREAMS,
     },
+    link_sync::{incoming_link_tempos, link_peer_count, set_link_enabled},
+    midi_sync::{incoming_midi_transports, start_midi_notes, start_midi_sync},
+    palette::{ColorMode, NodeColors},
+    presets::{PRESETS_DIR, Preset, PresetError, PresetStore},
+    session::{BuildInfo, SESSION_SETTINGS, SessionSnapshot, load_session_settings, save_session},
+    settings::{SaveDebounce, Settings, load_settings, save_settings},
+    util::{FrameTimes, MainThreadGuard},
 };
 
+/// Beyond this, swung sixteenths start sounding like they're landing on the next tick.
+pub const MAX_SWING: f64 = 0.75;
+
+/// Beyond this, humanized notes start sounding sloppy instead of human.
+pub const MAX_HUMANIZE_MS: f64 = 30.0;
+
+/// Longest bar you can set with `set_time_signature`, e.g. 16/4.
+pub const MAX_BEATS_PER_BAR: i64 = 16;
+
+/// Finest subdivision you can set with `set_time_signature`, 12 is what MIDI uses.
+pub const MAX_TICKS_PER_BEAT: i64 = 12;
+
+/// Fewer points than this barely make a constellation.
+pub const MIN_NUM_POINTS: i64 = 10;
+
+/// Beyond this, generating takes too long and the frame rate drops.
+pub const MAX_NUM_POINTS: i64 = 20000;
+
+/// The Master bus, see `master_gain`.
+const MASTER_BUS: i32 = 0;
+
+/// How long `set_paused` takes to fade the sound out or back in, so it doesn't click.
+const PAUSE_FADE_SECS: f64 = 0.25;
+
+/// How many frames the frame time percentiles in `get_perf_str` cover, 4 seconds at 60 FPS.
+const FRAME_TIMES_WINDOW: usize = 240;
+
+/// How many generations to show in the timings table.
+const GENERATION_TIMINGS_HISTORY: usize = 3;
+
+/// Set in `AudioState::ready`, see `AudioState::try_autoload`.
+pub static MAIN_THREAD: MainThreadGuard = MainThreadGuard::new();
+
 thread_local! {
     pub static AUDIOSTATE_AUTOLOAD_NODEPATH: OnceCell<NodePath> = const { OnceCell::new() };
+    /// The autoload found last, so hot paths (like reading the BPM for every note) don't look it up in the scene tree every time.
+    static AUDIOSTATE_INSTANCE: Cell<Option<InstanceId>> = const { Cell::new(None) };
     //Note: we can't store the Node itself in a global, since it's immutable.
     //Note2: you can just do get_node("/root/Frac3dAutoload") instead of storing the NodePath.
 }
@@ -24,33 +97,209 @@ pub struct AudioState {
     base: Base<Node>,
 
     #[init(val = 115.0)] // Note: if this value is smaller than the hslider min, it will emit twice.
-    #[var(get, set = set_bpm)]
+    /// During a ramp, this is the target, and `get_bpm` returns the BPM the tick thread is at right now (also when following MIDI clock).
+    #[var(get = get_bpm, set = set_bpm)]
     bpm: f64,
+    /// Whether we still owe a `bpm_changed` signal for the end of a ramp, see `ramp_bpm`.
+    bpm_ramping: bool,
+
+    /// Changed with `set_time_signature`, the tick thread applies it on the next bar.
+    #[init(val = 4)]
+    #[var(get)]
+    beats_per_bar: i64,
+    #[init(val = 4)]
+    #[var(get)]
+    ticks_per_beat: i64,
+
+    /// False after `transport_stop`, until `transport_play`. No ticks arrive while stopped.
+    #[init(val = true)]
+    #[var(get)]
+    transport_playing: bool,
+
+    /// Whether we're in the Ableton Link session, see `link_sync`. Always false without the `link` feature.
+    #[var(get, set = set_link_enabled)]
+    link_enabled: bool,
+    /// How many other apps are in the Link session.
+    #[var(get)]
+    link_peer_count: i64,
+
+    /// See `enable_tick_signal`.
+    tick_forwarding: TickForwarding,
+    executor: Option<Rc<LocalExecutor<'static>>>,
 
     #[init]
     #[var(get, set = set_seed)]
     seed: i64, // u64 not supported :(
 
+    /// How many points the constellation has (`MIN_NUM_POINTS..=MAX_NUM_POINTS`), changing it regenerates the constellation.
+    /// 0 until `--num-points` or the AudioGraph sets it, the latter uses its `num_points` export as the default.
+    #[var(get, set = set_num_points)]
+    num_points: i64,
+
+    /// Multiplies the volume of the Master bus, so 1 keeps the volume from the bus layout.
+    #[init(val = 1.0)]
+    #[var(get, set = set_master_gain)]
+    master_gain: f64,
+    /// The Master bus volume from the bus layout, before `master_gain`.
+    master_bus_volume_db: f32,
+
+    /// Silences everything and freezes the walks, without stopping them like the panic button does.
+    #[var(get, set = set_paused)]
+    paused: bool,
+    pause_state: PauseState,
+    /// If true, pause while the game window isn't focused.
+    #[var]
+    pause_on_focus_loss: bool,
+    /// Multiplies `master_gain`, fades to 0 while paused, see `PAUSE_FADE_SECS`.
+    #[init(val = 1.0)]
+    pause_gain: f64,
+
+    /// What gets saved, see `update_settings`. Doesn't include CLI args, unless the user changed the setting since.
+    settings: Settings,
+    settings_save: SaveDebounce,
+
+    /// How far off-beat sixteenths get delayed, as a fraction of a tick (0..=`MAX_SWING`). See `swing_delay_ticks`.
+    #[var(get, set = set_swing)]
+    swing: f64,
+
+    /// Every note of a walk gets delayed by up to this many milliseconds (0..=`MAX_HUMANIZE_MS`), see `humanize_delay`.
+    #[var(get, set = set_humanize_ms)]
+    humanize_ms: f64,
+
+    /// If true, notes that start late because we handled their tick late (e.g. at a low FPS) get their swing and humanize delays shortened,
+    /// so they stay closer to the grid. See `Tick::lag`.
+    #[init(val = true)]
+    #[var]
+    compensate_tick_lag: bool,
+
+    /// What the colors of the nodes tell you. Changing it recolors the nodes, their edges and the stats.
+    #[var(get, set = set_color_mode)]
+    color_mode: ColorMode,
+
+    /// If true, walks derive their rng from the seed, see `deterministic_walk_rng`. Defaults to `--deterministic-walks`.
+    #[var]
+    deterministic_walks: bool,
+
     #[var(get, set=set_graph_debug_str)]
     graph_debug_str: GString,
+    /// What `graph_debug_str` shows, see `get_stats_dict`. None until the first constellation is generated.
+    constellation_stats: Option<ConstellationStats>,
+    /// The stats of every AudioGraph in the scene, by instance id. The ones of the focused graph are in the two fields above.
+    graph_stats: BTreeMap<i64, (GString, ConstellationStats)>,
+
+    #[init(val = FrameTimes::new(FRAME_TIMES_WINDOW))]
+    frame_times: FrameTimes,
+
+    /// Timings of the last `GENERATION_TIMINGS_HISTORY` generations, newest first.
+    generation_timings: VecDeque<GenerationTimings>,
+    #[var(get)]
+    generation_timings_str: GString,
+
+    /// Progress of the current constellation generation phase (0..1), use this for a progress bar.
+    #[var(get)]
+    generation_progress: f64,
+    #[var(get)]
+    generation_phase: GString,
 }
 
 #[godot_api]
 impl INode for AudioState {
     fn ready(&mut self) {
-        self.set_bpm(self.bpm); // This triggers signal + atomic, which starts the ticker
+        MAIN_THREAD.set_main_thread();
+        let saved_settings = load_settings();
+        let settings = saved_settings.with_cli_args(&GAME_ARGS);
+        self.master_bus_volume_db = AudioServer::singleton().get_bus_volume_db(MASTER_BUS);
 
-        if let Some(cli_seed) = GAME_ARGS.seed {
-            self.set_seed(cli_seed as i64); // Bitwise conversion
-        } else {
-            self.set_seed(0xDEADBEEF); //3735928559
+        self.set_bpm(settings.bpm); // This triggers signal + atomic, which starts the ticker
+        if let Some(port_filter) = &GAME_ARGS.midi_out {
+            start_midi_clock(port_filter);
         }
+        if let Some(port_filter) = &GAME_ARGS.midi_in {
+            start_midi_sync(port_filter);
+        }
+        if let Some(port_filter) = &GAME_ARGS.midi_notes {
+            start_midi_notes(port_filter, GAME_ARGS.midi_channel);
+        }
+        if GAME_ARGS.link {
+            self.set_link_enabled(true);
+        }
+
+        self.set_seed(settings.seed as i64); // Bitwise conversion
+        self.set_master_gain(settings.master_gain);
+        if let Some(num_points) = GAME_ARGS.num_points {
+            self.update_num_points(num_points); // No constellation yet, so nothing to regenerate
+        }
+        USE_METRONOME.set(settings.use_metronome);
+        self.deterministic_walks = GAME_ARGS.deterministic_walks;
+
+        // Applying the settings above isn't a change, and the CLI args shouldn't get saved
+        self.settings = saved_settings;
+        self.settings_save.clear();
+
         //Store the nodepath of this node
         AUDIOSTATE_AUTOLOAD_NODEPATH.with(|cell| {
             cell.set(self.base().get_path())
                 .expect("AUDIOSTATE_AUTOLOAD_NODEPATH initialized twice")
         });
     }
+
+    fn process(&mut self, delta: f64) {
+        self.frame_times.push(delta);
+        self.tick_deferred();
+
+        for transport in incoming_midi_transports() {
+            self.set_transport(transport);
+        }
+        if let Some(bpm) = incoming_link_tempos().last() {
+            self.set_bpm(bpm);
+        }
+        let peer_count = link_peer_count() as i64;
+        if peer_count != self.link_peer_count {
+            self.link_peer_count = peer_count;
+            self.signals().link_peer_count_changed().emit(peer_count);
+        }
+
+        let pause_gain_target = if self.paused { 0.0 } else { 1.0 };
+        if self.pause_gain != pause_gain_target {
+            self.pause_gain =
+                fade_toward(self.pause_gain, pause_gain_target, delta, PAUSE_FADE_SECS);
+            self.apply_master_bus_volume();
+        }
+
+        if self.bpm_ramping && !BPM_RAMPING.get() {
+            self.bpm_ramping = false;
+
+            let bpm = self.bpm;
+            self.signals().bpm_changed().emit(bpm);
+        }
+
+        // The metronome gets toggled straight on the flag, so check it here
+        let use_metronome = USE_METRONOME.get();
+        if use_metronome != self.settings.use_metronome {
+            self.update_settings(|settings| settings.use_metronome = use_metronome);
+        }
+        if self.settings_save.take_due(Instant::now()) {
+            save_settings(&self.settings);
+        }
+    }
+
+    fn on_notification(&mut self, what: NodeNotification) {
+        match what {
+            NodeNotification::APPLICATION_FOCUS_OUT if self.pause_on_focus_loss => {
+                self.pause(true);
+            }
+            NodeNotification::APPLICATION_FOCUS_IN => self.resume(true),
+            _ => {}
+        }
+    }
+
+    fn exit_tree(&mut self) {
+        // Don't lose changes from the last `SETTINGS_SAVE_DEBOUNCE` when quitting
+        if self.settings_save.is_pending() {
+            self.settings_save.clear();
+            save_settings(&self.settings);
+        }
+    }
 }
 
 #[godot_api]
@@ -58,34 +307,265 @@ impl AudioState {
     #[signal]
     fn bpm_changed(bpm: f64);
     #[signal]
+    fn time_signature_changed(beats_per_bar: i64, ticks_per_beat: i64);
+    #[signal]
+    fn transport_state_changed(playing: bool);
+    #[signal]
+    fn link_enabled_changed(link_enabled: bool);
+    #[signal]
+    fn link_peer_count_changed(link_peer_count: i64);
+    /// Only emitted after `enable_tick_signal(true)`.
+    #[signal]
+    fn tick(tick: i64, beat: i64, bar: i64, total_ticks: i64);
+    #[signal]
     fn seed_changed(seed: i64);
     #[signal]
+    fn num_points_changed(num_points: i64);
+    #[signal]
+    fn color_mode_changed(color_mode: i64);
+    #[signal]
+    fn master_gain_changed(master_gain: f64);
+    #[signal]
+    fn swing_changed(swing: f64);
+    #[signal]
+    fn humanize_ms_changed(humanize_ms: f64);
+    #[signal]
+    fn walks_paused_changed(walks_paused: bool);
+    #[signal]
+    fn paused_changed(paused: bool);
+    #[signal]
     fn graph_debug_str_changed(graph_debug_str: GString);
+    #[signal]
+    fn generation_progress_changed(phase: GString, progress: f64);
+    #[signal]
+    fn generation_timings_str_changed(generation_timings_str: GString);
 
-    /// Gets the autoload instance of this node.
+    /// Gets the autoload instance of this node. Panics if that fails, see `try_autoload`.
     pub fn autoload() -> Gd<Self> {
-        AUDIOSTATE_AUTOLOAD_NODEPATH.with(|nodepath| {
-            let nodepath = nodepath.get().expect(
-                "AUDIOSTATE_AUTOLOAD_NODEPATH missing, ensure you're calling from the main thread",
-            );
-            Engine::singleton()
-                .get_main_loop()
-                .unwrap()
-                .cast::<SceneTree>()
-                .get_root()
-                .unwrap()
-                .get_node_as::<Self>(nodepath)
-        })
+        Self::try_autoload().expect(
+            "AudioState autoload missing, ensure you're calling from the main thread after it's ready",
+        )
+    }
+
+    /// Gets the autoload instance of this node, or None (with a warning) if called from another thread or before it's ready.
+    /// Use this where it's fine to skip something, like updating the statistics.
+    pub fn try_autoload() -> Option<Gd<Self>> {
+        if let Err(err) = MAIN_THREAD.check() {
+            tracing::warn!("can't get the AudioState autoload: {err}");
+            return None;
+        }
+        if let Some(instance_id) = AUDIOSTATE_INSTANCE.get()
+            && let Ok(state) = Gd::try_from_instance_id(instance_id)
+        {
+            return Some(state);
+        }
+
+        let Some(nodepath) = AUDIOSTATE_AUTOLOAD_NODEPATH.with(|nodepath| nodepath.get().cloned())
+        else {
+            tracing::warn!("can't get the AudioState autoload, it isn't ready yet");
+            return None;
+        };
+        let state = Engine::singleton()
+            .get_main_loop()
+            .and_then(|main_loop| main_loop.try_cast::<SceneTree>().ok())
+            .and_then(|tree| tree.get_root())
+            .and_then(|root| root.try_get_node_as::<Self>(&nodepath));
+        match state {
+            Some(state) => {
+                AUDIOSTATE_INSTANCE.set(Some(state.instance_id()));
+                Some(state)
+            }
+            None => {
+                tracing::warn!(
+                    "can't get the AudioState autoload, there is no {nodepath} in the scene tree"
+                );
+                None
+            }
+        }
     }
 
+    #[func]
+    pub fn get_bpm(&self) -> f64 {
+        if self.bpm_ramping || EXTERNAL_CLOCK.get() {
+            CURRENT_BPM.load(Ordering::Relaxed) as f64
+        } else {
+            self.bpm
+        }
+    }
+
+    /// Stops any ongoing ramp.
     #[func]
     pub fn set_bpm(&mut self, bpm: f64) {
-        set_bpm_internal(bpm);
+        set_bpm_internal(BpmMessage::Set(bpm));
         self.bpm = bpm;
+        self.bpm_ramping = false;
+        BPM_RAMPING.set(false);
+        self.update_settings(|settings| settings.bpm = bpm);
 
         self.signals().bpm_changed().emit(bpm);
     }
 
+    /// Linearly changes the BPM to `target` over `bars` bars, instead of jumping there.
+    /// Emits `bpm_changed` when the ramp starts and when it ends, `get_bpm` follows the ramp in between.
+    #[func]
+    pub fn ramp_bpm(&mut self, target: f64, bars: i64) {
+        if bars <= 0 {
+            self.set_bpm(target);
+            return;
+        }
+
+        let bpm = self.get_bpm();
+        BPM_RAMPING.set(true); // Before sending, so the tick thread can't finish the ramp first
+        set_bpm_internal(BpmMessage::Ramp {
+            target,
+            bars: bars as usize,
+        });
+        self.bpm = target;
+        self.bpm_ramping = true;
+        self.update_settings(|settings| settings.bpm = target);
+
+        self.signals().bpm_changed().emit(bpm);
+    }
+
+    /// E.g. (3, 4) for 3/4, or (6, 2) for 6/8 with an eighth note per beat.
+    /// Takes effect at the start of the next bar, so bars stay aligned.
+    #[func]
+    pub fn set_time_signature(&mut self, beats_per_bar: i64, ticks_per_beat: i64) {
+        let beats_per_bar = beats_per_bar.clamp(1, MAX_BEATS_PER_BAR);
+        let ticks_per_beat = ticks_per_beat.clamp(1, MAX_TICKS_PER_BEAT);
+        if (beats_per_bar, ticks_per_beat) != (self.beats_per_bar, self.ticks_per_beat) {
+            set_time_signature_internal(TimeSignature {
+                beats_per_bar: beats_per_bar as usize,
+                ticks_per_beat: ticks_per_beat as usize,
+            });
+            self.beats_per_bar = beats_per_bar;
+            self.ticks_per_beat = ticks_per_beat;
+
+            self.signals()
+                .time_signature_changed()
+                .emit(beats_per_bar, ticks_per_beat);
+        }
+    }
+
+    /// Starts the ticks again, after `transport_stop`.
+    #[func]
+    pub fn transport_play(&mut self) {
+        self.set_transport(Transport::Play);
+    }
+
+    /// Stops the ticks, walks freeze until `transport_play`.
+    #[func]
+    pub fn transport_stop(&mut self) {
+        self.set_transport(Transport::Stop);
+    }
+
+    /// Goes back to bar 0, e.g. for a clean take. Doesn't stop or start the ticks.
+    /// Running walks carry on from the new tick, so their swing and collisions stay on the grid.
+    #[func]
+    pub fn transport_reset(&mut self) {
+        self.set_transport(Transport::Reset);
+    }
+
+    /// Emits `tick` for every tick from now on, or stops doing so. Off by default, since a signal per tick isn't free.
+    /// Ticks are forwarded from `process`, so they arrive up to a frame after the tick thread sent them (more at a low FPS).
+    /// Don't use this for anything that needs to be sample-accurate, like playing notes.
+    #[func]
+    pub fn enable_tick_signal(&mut self, enabled: bool) {
+        if !enabled {
+            self.tick_forwarding.disable();
+            return;
+        }
+        let Some(cancel) = self.tick_forwarding.enable() else {
+            return; // Already enabled
+        };
+
+        let ticks = subscribe_to_ticks();
+        self.spawn_local_task(false, info_span!("tick_signal"), async move |this| {
+            forward_ticks(ticks, cancel, |tick| {
+                this.signals().tick().emit(
+                    tick.tick as i64,
+                    tick.beat as i64,
+                    tick.bar as i64,
+                    tick.total_ticks as i64,
+                );
+            })
+            .await;
+        });
+    }
+
+    fn set_transport(&mut self, transport: Transport) {
+        set_transport_internal(transport);
+        send_midi_transport(transport);
+        match transport {
+            Transport::Play => self.transport_playing = true,
+            Transport::Stop => self.transport_playing = false,
+            Transport::Reset => {}
+        }
+
+        let playing = self.transport_playing;
+        self.signals().transport_state_changed().emit(playing);
+    }
+
+    #[func]
+    pub fn set_swing(&mut self, swing: f64) {
+        let swing = swing.clamp(0.0, MAX_SWING);
+        if swing != self.swing {
+            self.swing = swing;
+
+            self.signals().swing_changed().emit(swing);
+        }
+    }
+
+    #[func]
+    pub fn set_color_mode(&mut self, color_mode: ColorMode) {
+        if color_mode == self.color_mode {
+            return;
+        }
+        tracing::info!(?color_mode, "color_mode changed");
+        self.color_mode = color_mode;
+        self.signals()
+            .color_mode_changed()
+            .emit(color_mode.to_godot());
+        if let Some(mut graph) = AudioGraph::current() {
+            // Deferred, since recoloring needs this node, which is still bound
+            graph.call_deferred("recolor_nodes", &[]);
+        }
+    }
+
+    #[func]
+    pub fn get_walks_paused(&self) -> bool {
+        WALKS_PAUSED.get()
+    }
+
+    /// Joins or leaves the Ableton Link session. Stays false if the game was built without the `link` feature.
+    #[func]
+    pub fn set_link_enabled(&mut self, link_enabled: bool) {
+        let link_enabled = set_link_enabled(link_enabled, self.get_bpm());
+        if link_enabled != self.link_enabled {
+            self.link_enabled = link_enabled;
+            self.signals().link_enabled_changed().emit(link_enabled);
+        }
+    }
+
+    /// Pauses or resumes all walks, see `WALKS_PAUSED`.
+    #[func]
+    pub fn toggle_walks_paused(&mut self) {
+        let walks_paused = WALKS_PAUSED.toggle();
+        tracing::info!("walks paused: {walks_paused}");
+
+        self.signals().walks_paused_changed().emit(walks_paused);
+    }
+
+    #[func]
+    pub fn set_humanize_ms(&mut self, humanize_ms: f64) {
+        let humanize_ms = humanize_ms.clamp(0.0, MAX_HUMANIZE_MS);
+        if humanize_ms != self.humanize_ms {
+            self.humanize_ms = humanize_ms;
+
+            self.signals().humanize_ms_changed().emit(humanize_ms);
+        }
+    }
+
     #[func]
     /// Sets the seed from a string. Returns false if parsing the string failed.
     #[cfg_attr(feature = "enable-tracing", instrument(skip(self)))]
@@ -110,11 +590,55 @@ impl AudioState {
     pub fn set_seed(&mut self, seed: i64) {
         if seed != self.seed {
             self.seed = seed;
+            self.update_settings(|settings| settings.seed = seed as u64);
 
             self.signals().seed_changed().emit(seed);
         }
     }
 
+    /// Clamps `num_points` to `MIN_NUM_POINTS..=MAX_NUM_POINTS`, and regenerates the constellation if it changed.
+    #[func]
+    pub fn set_num_points(&mut self, num_points: i64) {
+        if self.update_num_points(num_points)
+            && let Some(mut graph) = AudioGraph::current()
+        {
+            // Deferred, since regenerating needs this node, which is still bound
+            graph.call_deferred("regenerate", &[]);
+        }
+    }
+
+    /// Sets `num_points` if it wasn't set yet (by `--num-points`), without regenerating. Returns the `num_points` to use.
+    pub fn init_num_points(&mut self, default: i64) -> i64 {
+        if self.num_points == 0 {
+            self.update_num_points(default);
+        }
+        self.num_points
+    }
+
+    /// Like `set_num_points`, but doesn't regenerate. Returns true if it changed.
+    fn update_num_points(&mut self, num_points: i64) -> bool {
+        let num_points = clamp_num_points(num_points);
+        if num_points == self.num_points {
+            return false;
+        }
+        tracing::info!(num_points, "num_points changed");
+        self.num_points = num_points;
+        self.signals().num_points_changed().emit(num_points);
+        true
+    }
+
+    #[func]
+    pub fn set_master_gain(&mut self, master_gain: f64) {
+        let master_gain = master_gain.max(0.0);
+        if master_gain != self.master_gain {
+            self.master_gain = master_gain;
+            self.apply_master_bus_volume();
+            self.update_settings(|settings| settings.master_gain = master_gain);
+
+            self.signals().master_gain_changed().emit(master_gain);
+        }
+    }
+
     #[func]
     pub fn get_seed_str(&self) -> String {
         format!("{:016X}", self.seed) //Format as 16 chars with 0 padding
@@ -123,17 +647,278 @@ impl AudioState {
     #[func]
     pub fn randomize_seed(&mut self) {
         self.seed = rand::random();
+        let seed = self.seed as u64;
+        self.update_settings(|settings| settings.seed = seed);
         //Note - this does not trigger the signal
     }
 
+    /// Saves the seed, BPM, generation parameters and walk settings under `name`, see `presets`.
+    /// Fails with `ERR_ALREADY_EXISTS` if there already is a preset with this name, unless `overwrite` is set.
+    #[func]
+    pub fn save_preset(&self, name: GString, overwrite: bool) -> Error {
+        let Some(graph) = AudioGraph::current() else {
+            tracing::warn!("can't save a preset while the constellation is reloading");
+            return Error::ERR_UNCONFIGURED;
+        };
+        let graph = graph.bind();
+        let preset = Preset {
+            seed: self.seed as u64, // Bitwise conversion
+            bpm: self.bpm,
+            generation: graph.generation_params(self.num_points),
+            walk_strategy: graph.walk_strategy(),
+            palette: self.settings.palette.clone(),
+        };
+
+        let name = name.to_string();
+        match preset_store().save(&name, &preset, overwrite) {
+            Ok(()) => {
+                tracing::info!(?preset, "saved preset {name:?}");
+                Error::OK
+            }
+            Err(err) => preset_error(err),
+        }
+    }
+
+    /// Loads a preset saved with `save_preset`, and regenerates the constellation with it.
+    #[func]
+    pub fn load_preset(&mut self, name: GString) -> Error {
+        let name = name.to_string();
+        let preset = match preset_store().load(&name) {
+            Ok(preset) => preset,
+            Err(err) => return preset_error(err),
+        };
+        tracing::info!(?preset, "loading preset {name:?}");
+
+        self.set_bpm(preset.bpm);
+        self.set_seed(preset.seed as i64); // Bitwise conversion
+        self.update_num_points(preset.generation.num_points); // Regenerates below anyway
+        self.update_settings(|settings| {
+            settings.walk_strategy = preset.walk_strategy;
+            settings.palette = preset.palette.clone();
+        });
+
+        if let Some(mut graph) = AudioGraph::current() {
+            graph
+                .bind_mut()
+                .apply_preset(Some(&preset.generation), Some(preset.walk_strategy));
+            // Deferred, since regenerating needs this node, which is still bound
+            graph.call_deferred("regenerate", &[]);
+        }
+        Error::OK
+    }
+
+    /// The names of all saved presets, sorted.
+    #[func]
+    pub fn list_presets(&self) -> PackedStringArray {
+        match preset_store().list() {
+            Ok(names) => names.iter().map(GString::from).collect(),
+            Err(err) => {
+                tracing::error!("{err}");
+                PackedStringArray::new()
+            }
+        }
+    }
+
+    /// Bundles the seed, BPM, generation parameters, stats, build info and the latest recording into one JSON file, to share the constellation.
+    /// Supports res:// and user:// paths. See `import_session`.
+    #[func]
+    pub fn export_session(&self, path: GString) -> bool {
+        let Some(graph) = AudioGraph::current() else {
+            tracing::warn!("can't export a session while the constellation is reloading");
+            return false;
+        };
+        let graph = graph.bind();
+        let snapshot = SessionSnapshot {
+            build: BuildInfo::current(),
+            seed: self.seed as u64, // Bitwise conversion
+            bpm: self.bpm,
+            generation: graph.generation_params(self.num_points),
+            walk_strategy: graph.walk_strategy(),
+            stats: self.constellation_stats.clone(),
+            recording: graph.last_recording().cloned(),
+        };
+
+        let path = globalize_path(&path);
+        match save_session(&path, &snapshot) {
+            Ok(()) => {
+                tracing::info!("exported session to {path:?}");
+                true
+            }
+            Err(err) => {
+                tracing::error!("{err}");
+                false
+            }
+        }
+    }
+
+    /// Applies the settings of a file from `export_session` (see `SESSION_SETTINGS`), and regenerates the constellation with them.
+    /// Returns the settings it couldn't apply, because they're missing or invalid (or all of them, if the file can't be read).
+    #[func]
+    pub fn import_session(&mut self, path: GString) -> PackedStringArray {
+        let path = globalize_path(&path);
+        let (settings, mut skipped) = match load_session_settings(&path) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                tracing::error!("{err}");
+                return SESSION_SETTINGS
+                    .iter()
+                    .map(|&key| GString::from(key))
+                    .collect();
+            }
+        };
+        tracing::info!(?settings, ?skipped, "importing session from {path:?}");
+
+        if let Some(seed) = settings.seed {
+            self.set_seed(seed as i64); // Bitwise conversion
+        }
+        if let Some(bpm) = settings.bpm {
+            self.set_bpm(bpm);
+        }
+        if let Some(walk_strategy) = settings.walk_strategy {
+            self.update_settings(|settings| settings.walk_strategy = walk_strategy);
+        }
+
+        match AudioGraph::current() {
+            Some(mut graph) => {
+                if let Some(generation) = &settings.generation {
+                    self.update_num_points(generation.num_points); // Regenerates below anyway
+                }
+                graph
+                    .bind_mut()
+                    .apply_preset(settings.generation.as_ref(), settings.walk_strategy);
+                // Deferred, since regenerating needs this node, which is still bound
+                graph.call_deferred("regenerate", &[]);
+            }
+            None if settings.generation.is_some() => {
+                tracing::warn!(
+                    "can't apply the generation parameters while the constellation is reloading"
+                );
+                skipped.push("generation".to_string());
+            }
+            None => {}
+        }
+        skipped.iter().map(GString::from).collect()
+    }
+
+    #[func]
+    pub fn delete_preset(&self, name: GString) -> Error {
+        let name = name.to_string();
+        match preset_store().delete(&name) {
+            Ok(()) => {
+                tracing::info!("deleted preset {name:?}");
+                Error::OK
+            }
+            Err(err) => preset_error(err),
+        }
+    }
+
+    /// Sets the Master bus volume, from `master_gain` and `pause_gain`.
+    fn apply_master_bus_volume(&self) {
+        let gain = self.master_gain * self.pause_gain;
+        AudioServer::singleton().set_bus_volume_db(
+            MASTER_BUS,
+            self.master_bus_volume_db + linear_to_db(gain) as f32,
+        );
+    }
+
+    /// Fades out all sound, stops the metronome and freezes the walks (see `WALKS_PAUSED`). Unpausing brings all of it back.
+    #[func]
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.pause(false);
+        } else {
+            self.resume(false);
+        }
+    }
+
+    fn pause(&mut self, by_focus_loss: bool) {
+        if !self.pause_state.pause(WALKS_PAUSED.get(), by_focus_loss) {
+            return;
+        }
+        tracing::info!(by_focus_loss, "paused");
+        self.paused = true;
+        PAUSED.set(true);
+        self.set_walks_paused(true);
+        self.signals().paused_changed().emit(true);
+    }
+
+    fn resume(&mut self, by_focus_loss: bool) {
+        let Some(walks_paused) = self.pause_state.resume(by_focus_loss) else {
+            return;
+        };
+        tracing::info!(by_focus_loss, "resumed");
+        self.paused = false;
+        PAUSED.set(false);
+        self.set_walks_paused(walks_paused);
+        self.signals().paused_changed().emit(false);
+    }
+
+    fn set_walks_paused(&mut self, walks_paused: bool) {
+        if WALKS_PAUSED.get() != walks_paused {
+            self.toggle_walks_paused();
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// The colors for `color_mode`, with the palette from the settings.
+    pub fn node_colors(&self) -> NodeColors {
+        NodeColors::new(self.color_mode, &self.settings.palette)
+    }
+
+    /// Changes the settings, and saves them soon if that actually changed something (see `SETTINGS_SAVE_DEBOUNCE`).
+    pub fn update_settings(&mut self, update: impl FnOnce(&mut Settings)) {
+        let old_settings = self.settings.clone();
+        update(&mut self.settings);
+        if self.settings != old_settings {
+            self.settings_save.changed(Instant::now());
+        }
+    }
+
     /// Get the performance string, shown on the bottom-left.
     #[func]
     pub fn get_perf_str(&self) -> String {
+        let tick_latency = match TICK_LATENCY.lock().unwrap().stats(Instant::now()) {
+            Some((avg, max)) => format!(
+                "{:>3.0} ms avg tick latency\n{:>3.0} ms max tick latency",
+                avg.as_secs_f64() * 1000.0,
+                max.as_secs_f64() * 1000.0
+            ),
+            None => "  - ms avg tick latency\n  - ms max tick latency".to_owned(),
+        };
+        let frame_times = match self.frame_times.percentiles([50.0, 95.0, 99.0]) {
+            Some([p50, p95, p99]) => format!(
+                "{:>5.1} ms p50 frame time\n{:>5.1} ms p95 frame time\n{:>5.1} ms p99 frame time",
+                p50 * 1000.0,
+                p95 * 1000.0,
+                p99 * 1000.0
+            ),
+            None => "    - ms p50 frame time\n    - ms p95 frame time\n    - ms p99 frame time"
+                .to_owned(),
+        };
+        let (missed_ticks, max_missed_ticks) = TICK_LAG.stats(Instant::now());
+        // Time spent in all `process` callbacks (e.g. of the AudioNodes), excluding rendering
+        let process_time = Performance::singleton().get_monitor(Monitor::TIME_PROCESS) * 1000.0;
+        // The node spheres are a single MultiMesh with one shared material, so these shouldn't grow with the node count
+        let objects = Performance::singleton().get_monitor(Monitor::RENDER_TOTAL_OBJECTS_IN_FRAME);
+        let draw_calls =
+            Performance::singleton().get_monitor(Monitor::RENDER_TOTAL_DRAW_CALLS_IN_FRAME);
+        let resources = Performance::singleton().get_monitor(Monitor::OBJECT_RESOURCE_COUNT);
+        let cancelling =
+            AudioGraph::current().map_or(0, |graph| graph.bind().get_cancelling_count());
+        let edge_calls = AudioGraph::current().map_or(0, |graph| graph.bind().edge_flush_calls());
         format!(
-            "{:>3} FPS\n{:>3} playing streams\n{:>3} active tweens",
+            "{:>3} FPS\n{frame_times}\n{process_time:>5.1} ms process time\n{objects:>3} rendered objects\n{draw_calls:>3} draw calls\n{resources:>3} resources\n{:>3} playing streams\n{:>3} active tweens\n{:>3} active walkers\n{cancelling:>3} cancelling nodes\n{edge_calls:>3} edge MultiMesh calls\n{tick_latency}\n{missed_ticks:>3} missed ticks (max {max_missed_ticks:>3})",
             Engine::singleton().get_frames_per_second(),
             ACTIVE_STREAMS.load(Ordering::Relaxed),
             self.base().get_tree().unwrap().get_processed_tweens().len(),
+            ACTIVE_WALKERS.load(Ordering::Relaxed),
         )
     }
 
@@ -141,11 +926,81 @@ impl AudioState {
     #[func]
     pub fn get_debug_str(&self) -> String {
         format!(
-            "Statistics\n----------------------\n{}",
-            (self.get_graph_debug_str())
+            "Statistics\n----------------------\n{}\n{}",
+            self.get_graph_debug_str(),
+            self.get_generation_timings_str()
         )
     }
 
+    /// Remembers the timings of a finished generation, so regeneration performance can be compared in-game.
+    pub fn push_generation_timings(&mut self, timings: GenerationTimings) {
+        self.generation_timings.push_front(timings);
+        self.generation_timings.truncate(GENERATION_TIMINGS_HISTORY);
+
+        let history = self.generation_timings.iter().cloned().collect::<Vec<_>>();
+        let generation_timings_str = GString::from(&format!(
+            "Generation timings (ms, newest first):\n{}",
+            GenerationTimings::format_table(&history)
+        ));
+        self.generation_timings_str = GString::clone(&generation_timings_str); // Cheap clone (refcounted)
+        self.signals()
+            .generation_timings_str_changed()
+            .emit(&generation_timings_str);
+    }
+
+    /// The statistics of the constellation (see `ConstellationStats::to_dictionary`), for UIs that want more than `graph_debug_str`.
+    /// Empty until the first constellation is generated.
+    #[func]
+    pub fn get_stats_dict(&self) -> Dictionary {
+        self.constellation_stats
+            .as_ref()
+            .map_or_else(Dictionary::new, ConstellationStats::to_dictionary)
+    }
+
+    /// The stats of every AudioGraph in the scene by instance id, each a dictionary with `debug_str` and `stats`
+    /// (see `get_stats_dict`). `graph_debug_str` only shows the focused graph, see `AudioGraph::input_focus`.
+    #[func]
+    pub fn get_graph_stats(&self) -> Dictionary {
+        self.graph_stats
+            .iter()
+            .map(|(&id, (debug_str, stats))| {
+                let entry = dict! {
+                    "debug_str": debug_str.clone(),
+                    "stats": stats.to_dictionary(),
+                };
+                (id, entry)
+            })
+            .collect()
+    }
+
+    /// Remembers the stats of `graph`, and shows them if it's the focused graph.
+    pub fn set_graph_stats(
+        &mut self,
+        graph: InstanceId,
+        debug_str: GString,
+        constellation_stats: ConstellationStats,
+    ) {
+        // Graphs that were freed (e.g. by reloading the audio world) don't need theirs anymore
+        self.graph_stats.retain(|&id, _| {
+            Gd::<AudioGraph>::try_from_instance_id(InstanceId::from_i64(id)).is_ok()
+        });
+        self.graph_stats
+            .insert(graph.to_i64(), (debug_str, constellation_stats));
+        if AudioGraph::current().is_none_or(|current| current.instance_id() == graph) {
+            self.show_graph_stats(graph);
+        }
+    }
+
+    /// Shows the stats of `graph` in `graph_debug_str` and `get_stats_dict`, e.g. when it gets the focus.
+    pub fn show_graph_stats(&mut self, graph: InstanceId) {
+        if let Some((debug_str, constellation_stats)) =
+            self.graph_stats.get(&graph.to_i64()).cloned()
+        {
+            self.constellation_stats = Some(constellation_stats);
+            self.set_graph_debug_str(debug_str);
+        }
+    }
+
     #[func]
     pub fn set_graph_debug_str(&mut self, graph_debug_str: GString) {
         self.graph_debug_str = GString::clone(&graph_debug_str); // Cheap clone (refcounted)
@@ -154,6 +1009,15 @@ impl AudioState {
             .emit(&graph_debug_str);
     }
 
+    #[func]
+    pub fn set_generation_progress(&mut self, phase: GString, progress: f64) {
+        self.generation_phase = GString::clone(&phase); // Cheap clone (refcounted)
+        self.generation_progress = progress;
+        self.signals()
+            .generation_progress_changed()
+            .emit(&phase, progress);
+    }
+
     #[func]
     pub fn get_version_str(&self) -> String {
         format!(
@@ -183,6 +1047,94 @@ CI: {}
     }
 }
 
+/// Keeps track of why we're paused, see `AudioState::set_paused`.
+#[derive(Debug, Default, Clone)]
+pub struct PauseState {
+    paused: bool,
+    /// Only resume on focus when it was the focus loss that paused
+    by_focus_loss: bool,
+    /// Whether the walks were paused by hand before pausing, so they stay paused after resuming
+    walks_paused: bool,
+}
+
+impl PauseState {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns false if we're paused already. `walks_paused` is whether `WALKS_PAUSED` is set now.
+    pub fn pause(&mut self, walks_paused: bool, by_focus_loss: bool) -> bool {
+        if self.paused {
+            return false;
+        }
+        *self = Self {
+            paused: true,
+            by_focus_loss,
+            walks_paused,
+        };
+        true
+    }
+
+    /// Returns whether the walks should stay paused, or None if there's nothing to resume.
+    /// Regaining focus doesn't resume if the pause was by hand.
+    pub fn resume(&mut self, by_focus_loss: bool) -> Option<bool> {
+        if !self.paused || (by_focus_loss && !self.by_focus_loss) {
+            return None;
+        }
+        self.paused = false;
+        Some(self.walks_paused)
+    }
+}
+
+/// Moves `current` toward `target` so it goes from 0 to 1 in `fade_secs`.
+pub fn fade_toward(current: f64, target: f64, delta: f64, fade_secs: f64) -> f64 {
+    let step = delta / fade_secs;
+    if current < target {
+        (current + step).min(target)
+    } else {
+        (current - step).max(target)
+    }
+}
+
+pub fn clamp_num_points(num_points: i64) -> i64 {
+    num_points.clamp(MIN_NUM_POINTS, MAX_NUM_POINTS)
+}
+
+fn preset_store() -> PresetStore {
+    PresetStore::new(globalize_path(PRESETS_DIR))
+}
+
+/// Resolves res:// and user:// paths, so they can be used with `std::fs`.
+fn globalize_path(path: impl AsArg<GString>) -> PathBuf {
+    PathBuf::from(
+        ProjectSettings::singleton()
+            .globalize_path(path)
+            .to_string(),
+    )
+}
+
+/// Logs the error, and turns it into the closest Godot error.
+fn preset_error(err: PresetError) -> Error {
+    tracing::error!("{err}");
+    match err {
+        PresetError::InvalidName(_) => Error::ERR_INVALID_PARAMETER,
+        PresetError::AlreadyExists(_) => Error::ERR_ALREADY_EXISTS,
+        PresetError::NotFound(_) => Error::ERR_FILE_NOT_FOUND,
+        PresetError::Io(_) => Error::ERR_FILE_CANT_WRITE,
+        PresetError::Toml(_) => Error::ERR_PARSE_ERROR,
+    }
+}
+
 pub fn parse_hexseed(s: &str) -> Result<u64, std::num::ParseIntError> {
     u64::from_str_radix(s, 16)
 }
+
+impl AsyncNode for AudioState {
+    fn set_executor(&mut self, executor: Option<Rc<LocalExecutor<'static>>>) {
+        self.executor = executor;
+    }
+
+    fn get_executor(&self) -> &Option<Rc<LocalExecutor<'static>>> {
+        &self.executor
+    }
+}
@@ -1,12 +1,23 @@
 use std::{
-    sync::LazyLock,
-    thread::{self},
+    collections::VecDeque,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
 
-use tokio::sync::broadcast;
+use itertools::Itertools as _;
+use tokio::{select, sync::broadcast};
+use tokio_util::sync::CancellationToken;
 use tracing::instrument;
 
+use crate::{
+    flags::{BPM_RAMPING, Flag},
+    util::AtomicF32,
+};
+
 #[derive(Debug, Clone, Copy)]
 pub struct Tick {
     pub tick: usize, // 0..(ticks_per_beat - 1)
@@ -17,85 +28,754 @@ pub struct Tick {
     pub beats_per_bar: usize,  // Usually 4, 3, etc.
 
     pub total_ticks: usize,
+
+    /// When the tick thread sent this tick, see `Tick::lag`.
+    pub emitted_at: Instant,
+}
+
+impl Tick {
+    /// How late we are to handle this tick at `now`, e.g. because we were waiting for the next frame.
+    pub fn lag(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.emitted_at)
+    }
+
+    /// Whether this is the first tick of a beat.
+    pub fn is_beat(&self) -> bool {
+        self.tick == 0
+    }
+
+    /// Whether this is the first tick of a bar.
+    pub fn is_bar(&self) -> bool {
+        self.is_beat() && self.beat == 0
+    }
+
+    /// Index of this tick within its bar, 0..(beats_per_bar * ticks_per_beat - 1)
+    pub fn tick_in_bar(&self) -> usize {
+        self.beat * self.ticks_per_beat + self.tick
+    }
 }
 
-static TICK_CHANNEL: LazyLock<broadcast::Sender<Tick>> = LazyLock::new(|| {
-    let (tx, _rx) = broadcast::channel(128); // 128 = capacity per-receiver
-    let tx2 = tx.clone();
-    thread::spawn(move || beat_emitter(tx2));
-    tx // Use subscribe() to get a new receiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub beats_per_bar: usize,
+    pub ticks_per_beat: usize,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            beats_per_bar: 4,
+            ticks_per_beat: 4,
+        }
+    }
+}
+
+/// Counts ticks, beats and bars for `beat_emitter`.
+/// Time signature changes wait for the next bar boundary, so bars stay aligned.
+#[derive(Debug, Clone)]
+pub struct TickCounter {
+    tick: usize,
+    beat: usize,
+    bar: usize,
+    total_ticks: usize,
+    signature: TimeSignature,
+    pending: Option<TimeSignature>,
+}
+
+impl TickCounter {
+    pub fn new(signature: TimeSignature) -> Self {
+        Self {
+            tick: 0,
+            beat: 0,
+            bar: 0,
+            total_ticks: 0,
+            signature,
+            pending: None,
+        }
+    }
+
+    /// The tick that will be sent next.
+    pub fn current(&self) -> Tick {
+        Tick {
+            tick: self.tick,
+            beat: self.beat,
+            bar: self.bar,
+            ticks_per_beat: self.signature.ticks_per_beat,
+            beats_per_bar: self.signature.beats_per_bar,
+            total_ticks: self.total_ticks,
+            emitted_at: Instant::now(), // Set again right before sending
+        }
+    }
+
+    pub fn signature(&self) -> TimeSignature {
+        self.signature
+    }
+
+    /// Applies `signature` right away if the next tick starts a bar, otherwise at the start of the next bar.
+    pub fn set_time_signature(&mut self, signature: TimeSignature) {
+        if self.tick == 0 && self.beat == 0 {
+            self.signature = signature;
+            self.pending = None;
+        } else {
+            self.pending = Some(signature);
+        }
+    }
+
+    /// Goes back to the first tick of bar 0, applying any pending time signature.
+    pub fn reset(&mut self) {
+        if let Some(signature) = self.pending.take() {
+            self.signature = signature;
+        }
+        self.tick = 0;
+        self.beat = 0;
+        self.bar = 0;
+        self.total_ticks = 0;
+    }
+
+    /// Moves on to the next tick.
+    pub fn advance(&mut self) {
+        self.total_ticks += 1;
+        self.tick += 1;
+
+        if self.tick >= self.signature.ticks_per_beat {
+            self.tick = 0;
+            self.beat += 1;
+
+            if self.beat >= self.signature.beats_per_bar {
+                self.beat = 0;
+                self.bar += 1;
+
+                if let Some(signature) = self.pending.take() {
+                    self.signature = signature;
+                }
+            }
+        }
+    }
+}
+
+static TICK_CHANNEL: LazyLock<broadcast::WeakSender<Tick>> = LazyLock::new(|| {
+    let (tx, _rx) = broadcast::channel(TICK_CHANNEL_CAPACITY);
+    let weak_tx = tx.downgrade();
+    let handle = thread::spawn(move || beat_emitter(tx, &TickInputs::global(), &TICK_SHUTDOWN));
+    *TICK_THREAD.lock().unwrap() = Some(handle);
+    weak_tx // Only the tick thread keeps the sender alive, so receivers see it close when the thread exits
 });
 
+/// How many ticks a receiver can fall behind before it misses some, see `TICK_LAG`.
+/// A receiver that's behind gets all of its backlog at once, so a bigger buffer means fewer missed ticks but longer bursts of stale ones.
+/// At the fastest we go (300 BPM with 12 ticks per beat, 60 ticks per second) this is still 17 seconds, more than any hitch should take.
+/// Every tick is stored once, not per receiver, so the memory doesn't matter.
+pub const TICK_CHANNEL_CAPACITY: usize = 1024;
+
+/// Set by `shutdown_tick_thread`, makes `beat_emitter` return.
+static TICK_SHUTDOWN: Flag = Flag::new(false);
+
+/// The thread running `beat_emitter`, None until the first `subscribe_to_ticks`.
+static TICK_THREAD: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+/// How often `beat_emitter` checks for shutdown while it's blocked waiting for a message.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Stops the tick thread and waits up to `timeout` for it to exit, logging if it doesn't. Called when the extension unloads.
+/// Subscribers don't get any ticks after this.
+pub fn shutdown_tick_thread(timeout: Duration) {
+    TICK_SHUTDOWN.set(true);
+    let Some(handle) = TICK_THREAD.lock().unwrap().take() else {
+        return; // Never started
+    };
+
+    let deadline = Instant::now() + timeout;
+    while !handle.is_finished() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(1));
+    }
+    if handle.is_finished() {
+        if handle.join().is_err() {
+            tracing::error!("tick thread panicked");
+        }
+        tracing::info!("tick thread stopped");
+    } else {
+        tracing::warn!("tick thread didn't stop within {timeout:?}, leaking it");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BpmMessage {
+    /// Jump to this BPM on the next tick.
+    Set(f64),
+    /// Linearly go to `target` over `bars` bars, starting on the next tick.
+    Ramp { target: f64, bars: usize },
+    /// Shift the ticks once so the next beat lands at `next_beat`, see `TickClock::nudge`. Doesn't change the BPM.
+    Nudge { next_beat: Instant },
+}
+
+impl BpmMessage {
+    /// The BPM this message goes to, None if it doesn't change the BPM.
+    pub fn target(&self) -> Option<f64> {
+        match *self {
+            BpmMessage::Set(bpm) => Some(bpm),
+            BpmMessage::Ramp { target, .. } => Some(target),
+            BpmMessage::Nudge { .. } => None,
+        }
+    }
+}
+
+/// The BPM the tick thread is ticking at right now, this lags behind `AudioState::bpm` during a ramp.
+pub static CURRENT_BPM: AtomicF32 = AtomicF32::new(0.0);
+
+#[derive(Debug, Clone, Copy)]
+struct BpmRamp {
+    from: f64,
+    target: f64,
+    ticks: usize,
+    elapsed: usize,
+}
+
+/// Tempo of `beat_emitter`, which may be ramping towards another BPM.
+#[derive(Debug, Clone)]
+pub struct Tempo {
+    bpm: f64,
+    ramp: Option<BpmRamp>,
+}
+
+impl Tempo {
+    pub fn new(bpm: f64) -> Self {
+        Self { bpm, ramp: None }
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    pub fn is_ramping(&self) -> bool {
+        self.ramp.is_some()
+    }
+
+    /// Replaces any ongoing ramp. `ticks_per_bar` converts the length of a ramp to ticks. Nudges are ignored.
+    pub fn apply(&mut self, message: BpmMessage, ticks_per_bar: usize) {
+        self.ramp = match message {
+            BpmMessage::Ramp { target, bars } if bars > 0 => Some(BpmRamp {
+                from: self.bpm,
+                target,
+                ticks: bars * ticks_per_bar,
+                elapsed: 0,
+            }),
+            BpmMessage::Nudge { .. } => return,
+            BpmMessage::Set(bpm) | BpmMessage::Ramp { target: bpm, .. } => {
+                self.bpm = bpm;
+                None
+            }
+        };
+    }
+
+    /// Moves on to the next tick, and returns how long it takes. During a ramp, the last tick is at the target BPM.
+    pub fn next_interval(&mut self, ticks_per_beat: usize) -> Duration {
+        let bpm = self.next_bpm();
+        Duration::from_secs_f64(60.0 / bpm / ticks_per_beat as f64)
+    }
+
+    /// Moves on to the next tick, and returns its BPM, see `next_interval`.
+    pub fn next_bpm(&mut self) -> f64 {
+        if let Some(ramp) = &mut self.ramp {
+            ramp.elapsed += 1;
+            let t = ramp.elapsed as f64 / ramp.ticks as f64;
+            self.bpm = ramp.from + (ramp.target - ramp.from) * t;
+
+            if ramp.elapsed >= ramp.ticks {
+                self.ramp = None;
+            }
+        }
+
+        self.bpm
+    }
+}
+
+/// Tap tempo only looks at this many of the last taps...
+const MAX_TAPS: usize = 32;
+/// ...and only at the ones from the last 30 seconds.
+const TAP_WINDOW: Duration = Duration::from_secs(30);
+/// Fewer taps than this don't set the BPM, 3 intervals are the least the median can reject an outlier from.
+const MIN_TAPS: usize = 4;
+/// Intervals that differ more than this from the median (as a fraction of it) are sloppy taps, and get ignored.
+const TAP_OUTLIER_TOLERANCE: f64 = 0.4;
+const MIN_TAP_BPM: f64 = 30.0;
+const MAX_TAP_BPM: f64 = 300.0;
+
+/// What `TapTempo` makes of the taps so far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TapEstimate {
+    pub bpm: f64,
+    /// When the next tap would be if the tapping goes on, so the beat can be nudged there.
+    pub next_tap: Instant,
+}
+
+/// Turns taps into a BPM and a phase, see `AudioGraph::perform_bpm_tap`.
+#[derive(Debug, Default, Clone)]
+pub struct TapTempo {
+    taps: VecDeque<Instant>,
+}
+
+impl TapTempo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many taps are still in the window.
+    pub fn tap_count(&self) -> usize {
+        self.taps.len()
+    }
+
+    /// Adds a tap at `now`, and returns the new estimate once there are at least `MIN_TAPS` taps.
+    /// The BPM comes from the mean of the intervals close enough to the median, so one sloppy tap doesn't skew it.
+    pub fn tap(&mut self, now: Instant) -> Option<TapEstimate> {
+        self.taps.push_back(now);
+        while self.taps.len() > MAX_TAPS {
+            self.taps.pop_front();
+        }
+        self.taps.retain(|&tap| now - tap < TAP_WINDOW);
+        if self.taps.len() < MIN_TAPS {
+            return None;
+        }
+
+        let intervals = self
+            .taps
+            .iter()
+            .tuple_windows()
+            .map(|(a, b)| (*b - *a).as_secs_f64())
+            .collect_vec();
+        let median_interval = median(intervals.clone());
+        let is_inlier = |interval: f64| {
+            (interval - median_interval).abs() <= median_interval * TAP_OUTLIER_TOLERANCE
+        };
+        let inliers = intervals
+            .iter()
+            .copied()
+            .filter(|&interval| is_inlier(interval))
+            .collect_vec();
+        let interval = inliers.iter().sum::<f64>() / inliers.len() as f64; // Never empty, the median is an inlier
+        let bpm = (60.0 / interval).clamp(MIN_TAP_BPM, MAX_TAP_BPM);
+        let interval = 60.0 / bpm;
+
+        // Every tap since the last sloppy one predicts the next tap, the median of those is the phase
+        let last = *self.taps.back()?;
+        let run_start = intervals
+            .iter()
+            .rposition(|&interval| !is_inlier(interval))
+            .map_or(0, |index| index + 1);
+        let predictions = self
+            .taps
+            .iter()
+            .enumerate()
+            .skip(run_start)
+            .map(|(index, &tap)| {
+                let taps_to_go = (self.taps.len() - index) as f64;
+                taps_to_go * interval - (last - tap).as_secs_f64()
+            })
+            .collect_vec();
+        let next_tap = last + Duration::from_secs_f64(median(predictions).max(0.0));
+
+        Some(TapEstimate { bpm, next_tap })
+    }
+}
+
+/// Median of `values`, the mean of the middle two if there's an even number of them. Panics if empty.
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+/// Where we are in the music (in beats), and when that is (in seconds since the start).
+/// Times are derived from the last tempo change (the anchor) instead of adding up tick intervals,
+/// so rounding errors can't pile up over many tempo changes and shift the bars.
+#[derive(Debug, Clone)]
+pub struct MusicalTimeline {
+    anchor_secs: f64,
+    anchor_beats: f64,
+    ticks_since_anchor: usize,
+    bpm: f64,
+    ticks_per_beat: usize,
+}
+
+impl MusicalTimeline {
+    pub fn new(bpm: f64, ticks_per_beat: usize) -> Self {
+        Self {
+            anchor_secs: 0.0,
+            anchor_beats: 0.0,
+            ticks_since_anchor: 0,
+            bpm,
+            ticks_per_beat,
+        }
+    }
+
+    pub fn beats(&self) -> f64 {
+        self.anchor_beats + self.ticks_since_anchor as f64 / self.ticks_per_beat as f64
+    }
+
+    pub fn secs(&self) -> f64 {
+        self.anchor_secs
+            + self.ticks_since_anchor as f64 * 60.0 / self.bpm / self.ticks_per_beat as f64
+    }
+
+    /// Changes the tempo from the current position on, re-anchoring here so the time we already spent stays exact.
+    pub fn set_tempo(&mut self, bpm: f64, ticks_per_beat: usize) {
+        if (bpm, ticks_per_beat) != (self.bpm, self.ticks_per_beat) {
+            self.anchor_secs = self.secs();
+            self.anchor_beats = self.beats();
+            self.ticks_since_anchor = 0;
+            self.bpm = bpm;
+            self.ticks_per_beat = ticks_per_beat;
+        }
+    }
+
+    /// Moves one tick forward, and returns when that is.
+    pub fn advance_tick(&mut self) -> f64 {
+        self.ticks_since_anchor += 1;
+        self.secs()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Play,
+    /// Stops the ticks, without losing our place in the bar.
+    Stop,
+    /// Goes back to bar 0, without stopping or starting.
+    Reset,
+}
+
+/// Everything `beat_emitter` keeps track of, without the thread and the sleeping.
+#[derive(Debug, Clone)]
+pub struct TickClock {
+    counter: TickCounter,
+    tempo: Tempo,
+    timeline: MusicalTimeline,
+    /// Added to the timeline, see `nudge`.
+    offset_secs: f64,
+    playing: bool,
+}
+
+impl TickClock {
+    pub fn new(bpm: f64) -> Self {
+        Self {
+            counter: TickCounter::new(TimeSignature::default()),
+            tempo: Tempo::new(bpm),
+            timeline: MusicalTimeline::new(bpm, TimeSignature::default().ticks_per_beat),
+            offset_secs: 0.0,
+            playing: true,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn tempo(&self) -> &Tempo {
+        &self.tempo
+    }
+
+    /// How much musical time has passed since the start, not counting the time we were stopped.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64((self.timeline.secs() + self.offset_secs).max(0.0))
+    }
+
+    /// When the next beat starts (see `elapsed`), if the tempo doesn't change until then.
+    pub fn next_beat(&self) -> Duration {
+        let ticks_per_beat = self.counter.signature().ticks_per_beat;
+        let ticks = (ticks_per_beat - self.counter.current().tick) % ticks_per_beat + 1;
+        self.elapsed() + self.tempo_tick_interval().mul_f64(ticks as f64)
+    }
+
+    /// Shifts all following ticks so the next beat starts at `next_beat` (see `elapsed`) instead of `TickClock::next_beat`.
+    /// Never by more than half a beat either way, so we don't skip or repeat a beat. Ticks that end up in the past are due right away.
+    pub fn nudge(&mut self, next_beat: Duration) {
+        let beat_secs = 60.0 / self.tempo.bpm();
+        let shift = next_beat.as_secs_f64() - self.next_beat().as_secs_f64();
+        let shift = (shift + beat_secs / 2.0).rem_euclid(beat_secs) - beat_secs / 2.0;
+        self.offset_secs += shift;
+    }
+
+    fn tempo_tick_interval(&self) -> Duration {
+        Duration::from_secs_f64(
+            60.0 / self.tempo.bpm() / self.counter.signature().ticks_per_beat as f64,
+        )
+    }
+
+    pub fn set_bpm(&mut self, message: BpmMessage) {
+        let signature = self.counter.signature();
+        self.tempo
+            .apply(message, signature.beats_per_bar * signature.ticks_per_beat);
+    }
+
+    pub fn set_time_signature(&mut self, signature: TimeSignature) {
+        self.counter.set_time_signature(signature);
+    }
+
+    pub fn transport(&mut self, transport: Transport) {
+        match transport {
+            Transport::Play => self.playing = true,
+            Transport::Stop => self.playing = false,
+            Transport::Reset => self.counter.reset(),
+        }
+    }
+
+    /// When to send the next tick (see `elapsed`), and the tick itself. None while stopped.
+    pub fn next_tick(&mut self) -> Option<(Duration, Tick)> {
+        if !self.playing {
+            return None;
+        }
+
+        let bpm = self.tempo.next_bpm();
+        self.timeline
+            .set_tempo(bpm, self.counter.signature().ticks_per_beat);
+        let at =
+            Duration::from_secs_f64((self.timeline.advance_tick() + self.offset_secs).max(0.0));
+        let tick = self.counter.current();
+        self.counter.advance();
+        Some((at, tick))
+    }
+}
+
 /// Use `set_bpm_internal` to send a message on this thread to change the BPM on the next tick.
-static BPM_CHANNEL: LazyLock<(flume::Sender<f64>, flume::Receiver<f64>)> =
+static BPM_CHANNEL: LazyLock<(flume::Sender<BpmMessage>, flume::Receiver<BpmMessage>)> =
     LazyLock::new(flume::unbounded);
 
-pub(super) fn set_bpm_internal(new_bpm: f64) {
-    let _ = BPM_CHANNEL.0.send(new_bpm);
+pub(crate) fn set_bpm_internal(message: BpmMessage) {
+    let _ = BPM_CHANNEL.0.send(message);
 }
 
-// Synchronous high-precision ticker
+/// Use `set_time_signature_internal` to send a message on this thread to change the time signature on the next bar.
+static TIME_SIGNATURE_CHANNEL: LazyLock<(
+    flume::Sender<TimeSignature>,
+    flume::Receiver<TimeSignature>,
+)> = LazyLock::new(flume::unbounded);
+
+pub(super) fn set_time_signature_internal(signature: TimeSignature) {
+    let _ = TIME_SIGNATURE_CHANNEL.0.send(signature);
+}
+
+/// Use `set_transport_internal` to send a message on this thread to start, stop or reset the ticks.
+static TRANSPORT_CHANNEL: LazyLock<(flume::Sender<Transport>, flume::Receiver<Transport>)> =
+    LazyLock::new(flume::unbounded);
+
+pub(super) fn set_transport_internal(transport: Transport) {
+    let _ = TRANSPORT_CHANNEL.0.send(transport);
+}
+
+/// What `beat_emitter` listens to. Normally the global channels, see `set_bpm_internal` and friends.
+#[derive(Debug, Clone)]
+pub struct TickInputs {
+    pub bpm: flume::Receiver<BpmMessage>,
+    pub time_signature: flume::Receiver<TimeSignature>,
+    pub transport: flume::Receiver<Transport>,
+}
+
+impl TickInputs {
+    fn global() -> Self {
+        Self {
+            bpm: BPM_CHANNEL.1.clone(),
+            time_signature: TIME_SIGNATURE_CHANNEL.1.clone(),
+            transport: TRANSPORT_CHANNEL.1.clone(),
+        }
+    }
+}
+
+/// Blocks until `rx` has a message, or returns None once `shutdown` is set.
+fn recv_until_shutdown<T>(rx: &flume::Receiver<T>, shutdown: &Flag) -> Option<T> {
+    while !shutdown.get() {
+        match rx.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+            Ok(message) => return Some(message),
+            Err(flume::RecvTimeoutError::Timeout) => {}
+            Err(flume::RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+    None
+}
+
+/// Synchronous high-precision ticker, sends ticks on `tx` until `shutdown` is set.
+/// Only public so tests can run it with their own channels, everything else should use `subscribe_to_ticks`.
 #[cfg_attr(feature = "enable-tracing", instrument(skip_all))]
-fn beat_emitter(tx: broadcast::Sender<Tick>) {
-    let bpm_rx = &BPM_CHANNEL.1;
+pub fn beat_emitter(tx: broadcast::Sender<Tick>, inputs: &TickInputs, shutdown: &Flag) {
+    let bpm_rx = &inputs.bpm;
+    let time_signature_rx = &inputs.time_signature;
+    let transport_rx = &inputs.transport;
 
-    let bpm = {
+    let mut clock = {
         //Note - The ticker won't start until you call set_bpm_internal at least once
         let span = tracing::info_span!("waiting_for_initial_bpm");
         let _guard = span.enter();
 
-        let result = bpm_rx.recv().unwrap();
+        let result = loop {
+            let Some(message) = recv_until_shutdown(bpm_rx, shutdown) else {
+                return;
+            };
+            if let Some(bpm) = message.target() {
+                break bpm;
+            }
+        };
         tracing::info!(initial_bpm = result);
-        result
+        TickClock::new(result)
     };
+    CURRENT_BPM.store(clock.tempo().bpm() as f32, Ordering::Relaxed);
 
-    let ticks_per_beat = 4;
+    // Deadlines are `start + clock.elapsed()`, see `MusicalTimeline`
+    let mut start = Instant::now();
 
-    let mut interval = Duration::from_secs_f64(60.0 / bpm / ticks_per_beat as f64);
-    let mut deadline = Instant::now();
+    while !shutdown.get() {
+        // Handle every transport message in order, a stop followed by a reset should do both
+        for transport in transport_rx.try_iter() {
+            clock.transport(transport);
+            tracing::info!("transport: {transport:?}");
+        }
 
-    let beats_per_bar = 4;
+        if !clock.is_playing() {
+            // Park until we're told to play again, instead of spin-sleeping
+            while !clock.is_playing() {
+                let Some(transport) = recv_until_shutdown(transport_rx, shutdown) else {
+                    return;
+                };
+                clock.transport(transport);
+                tracing::info!("transport: {transport:?}");
+            }
+            start = Instant::now() - clock.elapsed(); // Don't try to catch up on the ticks we missed
+        }
 
-    let mut total_ticks = 0;
-    let mut tick = 0;
-    let mut beat = 0;
-    let mut bar = 0;
+        // Check for BPM change, throwing away all stale messages. Nudges come after, since they depend on the new BPM.
+        let (nudges, changes): (Vec<_>, Vec<_>) = bpm_rx
+            .try_iter()
+            .partition(|message| matches!(message, BpmMessage::Nudge { .. }));
+        if let Some(message) = changes.last() {
+            clock.set_bpm(*message);
+            tracing::info!("BPM changed: {message:?}");
+        }
+        if let Some(&BpmMessage::Nudge { next_beat }) = nudges.last() {
+            clock.nudge(next_beat.saturating_duration_since(start));
+            tracing::info!("nudged the next beat to {next_beat:?}");
+        }
 
-    loop {
-        // Check for BPM change, throwing away all stale messages
-        if let Some(bpm) = bpm_rx.try_iter().last() {
-            interval = Duration::from_secs_f64(60.0 / bpm / ticks_per_beat as f64);
-            tracing::info!("BPM changed to {bpm}");
+        // Same for the time signature, which waits for the next bar
+        if let Some(signature) = time_signature_rx.try_iter().last() {
+            clock.set_time_signature(signature);
+            tracing::info!("time signature changed to {signature:?}");
         }
 
-        deadline += interval;
+        let was_ramping = clock.tempo().is_ramping();
+        let Some((at, tick)) = clock.next_tick() else {
+            continue;
+        };
+        let deadline = start + at;
+        CURRENT_BPM.store(clock.tempo().bpm() as f32, Ordering::Relaxed);
+        if was_ramping && !clock.tempo().is_ramping() {
+            BPM_RAMPING.set(false);
+            tracing::info!("BPM ramp finished at {}", clock.tempo().bpm());
+        }
         spin_sleep::sleep_until(deadline);
 
         // Send ticks synchronized to the beat
         let _ = tx.send(Tick {
-            tick,
-            beat,
-            bar,
-            total_ticks,
-            ticks_per_beat,
-            beats_per_bar,
+            emitted_at: Instant::now(),
+            ..tick
         });
+    }
+    tracing::info!("tick thread shutting down");
+}
 
-        total_ticks += 1;
-        tick += 1;
+/// How long `TickLagCounter` counts before starting over.
+const TICK_LAG_WINDOW: Duration = Duration::from_secs(60);
 
-        if tick >= ticks_per_beat {
-            tick = 0;
-            beat += 1;
+/// How many ticks receivers missed by falling behind, over the current minute. Unlike `TickLatency` this only counts ticks that never arrived.
+#[derive(Debug)]
+pub struct TickLagCounter {
+    missed: AtomicU64,
+    /// The most ticks a single receiver missed at once.
+    max: AtomicU64,
+    window_start: Mutex<Option<Instant>>,
+}
 
-            if beat >= beats_per_bar {
-                beat = 0;
-                bar += 1;
-            }
+impl Default for TickLagCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TickLagCounter {
+    pub const fn new() -> Self {
+        Self {
+            missed: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+            window_start: Mutex::new(None),
+        }
+    }
+
+    pub fn record(&self, missed: u64) {
+        self.missed.fetch_add(missed, Ordering::Relaxed);
+        self.max.fetch_max(missed, Ordering::Relaxed);
+    }
+
+    /// Missed ticks and the max at once since the current window started, starting a new one if it's older than `TICK_LAG_WINDOW` at `now`.
+    pub fn stats(&self, now: Instant) -> (u64, u64) {
+        let mut window_start = self.window_start.lock().unwrap();
+        if window_start.is_none_or(|start| now.saturating_duration_since(start) >= TICK_LAG_WINDOW)
+        {
+            *window_start = Some(now);
+            self.missed.store(0, Ordering::Relaxed);
+            self.max.store(0, Ordering::Relaxed);
         }
+        (
+            self.missed.load(Ordering::Relaxed),
+            self.max.load(Ordering::Relaxed),
+        )
     }
 }
 
+/// Fed by every `TickReceiver` that falls behind.
+pub static TICK_LAG: TickLagCounter = TickLagCounter::new();
+
+/// How far back `TickLatency` looks.
+const TICK_LATENCY_WINDOW: Duration = Duration::from_secs(1);
+
+/// How late ticks were delivered during the last `TICK_LATENCY_WINDOW`, see `Tick::lag`.
+#[derive(Debug, Default)]
+pub struct TickLatency {
+    samples: VecDeque<(Instant, Duration)>,
+}
+
+impl TickLatency {
+    pub const fn new() -> Self {
+        Self {
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Records that `tick` got handled at `now`.
+    pub fn record(&mut self, tick: &Tick, now: Instant) {
+        self.samples.push_back((now, tick.lag(now)));
+        self.forget_old(now);
+    }
+
+    fn forget_old(&mut self, now: Instant) {
+        while let Some(&(at, _)) = self.samples.front()
+            && now.saturating_duration_since(at) > TICK_LATENCY_WINDOW
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Average and max latency over the last `TICK_LATENCY_WINDOW` before `now`, None if no ticks arrived.
+    pub fn stats(&mut self, now: Instant) -> Option<(Duration, Duration)> {
+        self.forget_old(now);
+        let max = self.samples.iter().map(|&(_, lag)| lag).max()?;
+        let total = self.samples.iter().map(|&(_, lag)| lag).sum::<Duration>();
+        Some((total / self.samples.len() as u32, max))
+    }
+}
+
+/// Fed by `AudioUI`, which picks up the newest tick every frame on the main thread, just like the walks handle theirs.
+pub static TICK_LATENCY: Mutex<TickLatency> = Mutex::new(TickLatency::new());
+
 pub struct TickReceiver(broadcast::Receiver<Tick>);
 
 impl TickReceiver {
@@ -105,24 +785,126 @@ impl TickReceiver {
         // So, subscribe as early as possible to increate your synchronicity.
     }
 
-    //TODO: maybe impl TickReceiver::clone() with self.0.resubscribe?
+    /// A new receiver that starts at the next tick, like `subscribe_to_ticks`, but for the same sender as this one.
+    pub fn resubscribe(&self) -> Self {
+        Self(self.0.resubscribe())
+    }
 
+    /// Never returns once the tick thread shut down (see `shutdown_tick_thread`), so use it together with a cancellation token.
     pub async fn wait(&mut self) -> Tick {
         // Normally this would only loop once, unless we lagged
         loop {
             match self.0.recv().await {
                 Ok(tick) => return tick,
                 Err(broadcast::error::RecvError::Closed) => {
-                    panic!("Tick sender dropped, this should never happen")
-                }
-                Err(broadcast::error::RecvError::Lagged(n)) => {
-                    tracing::warn!("Tick receiver lagged and missed {n} ticks, catching up...")
+                    tracing::debug!("tick sender dropped, no more ticks");
+                    return std::future::pending().await;
                 }
+                Err(broadcast::error::RecvError::Lagged(n)) => lagged(n),
             }
         }
     }
+
+    /// Waits for the first tick that matches `pred`, skipping the others.
+    pub async fn next_matching(&mut self, mut pred: impl FnMut(&Tick) -> bool) -> Tick {
+        loop {
+            let tick = self.wait().await;
+            if pred(&tick) {
+                return tick;
+            }
+        }
+    }
+
+    /// Waits for the first tick of the next beat.
+    pub async fn next_beat(&mut self) -> Tick {
+        self.next_matching(Tick::is_beat).await
+    }
+
+    /// Waits for the first tick of the next bar.
+    pub async fn next_bar(&mut self) -> Tick {
+        self.next_matching(Tick::is_bar).await
+    }
+
+    /// The newest tick that arrived since the last call, without waiting. Only for code that can't await, like `process`.
+    /// Always None once the tick thread shut down.
+    pub fn try_latest(&mut self) -> Option<Tick> {
+        let mut latest = None;
+        loop {
+            match self.0.try_recv() {
+                Ok(tick) => latest = Some(tick),
+                Err(broadcast::error::TryRecvError::Empty) => return latest,
+                Err(broadcast::error::TryRecvError::Closed) => return latest,
+                Err(broadcast::error::TryRecvError::Lagged(n)) => lagged(n),
+            }
+        }
+    }
+
+    /// Like `wait`, but blocks the thread instead. Only for threads without an async runtime, like the MIDI clock.
+    /// None once the tick thread shut down, so the calling thread can exit too.
+    pub fn blocking_wait(&mut self) -> Option<Tick> {
+        loop {
+            match self.0.blocking_recv() {
+                Ok(tick) => return Some(tick),
+                Err(broadcast::error::RecvError::Closed) => return None,
+                Err(broadcast::error::RecvError::Lagged(n)) => lagged(n),
+            }
+        }
+    }
+}
+
+/// Keeps track of the task behind `AudioState::enable_tick_signal`, so there's at most one and disabling actually stops it.
+#[derive(Debug, Default)]
+pub struct TickForwarding {
+    cancel: Option<CancellationToken>,
+}
+
+impl TickForwarding {
+    pub fn is_enabled(&self) -> bool {
+        self.cancel.is_some()
+    }
+
+    /// The token to hand to a new `forward_ticks` task, None if one is already running.
+    pub fn enable(&mut self) -> Option<CancellationToken> {
+        if self.is_enabled() {
+            return None;
+        }
+        let cancel = CancellationToken::new();
+        self.cancel = Some(cancel.clone());
+        Some(cancel)
+    }
+
+    /// Stops the running task, if any.
+    pub fn disable(&mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            cancel.cancel();
+        }
+    }
+}
+
+/// Calls `emit` for every tick until `cancel` is cancelled, see `TickForwarding`.
+pub async fn forward_ticks(
+    mut ticks: TickReceiver,
+    cancel: CancellationToken,
+    mut emit: impl FnMut(Tick),
+) {
+    loop {
+        let tick = select! {
+            biased;
+            _ = cancel.cancelled() => return,
+            tick = ticks.wait() => tick,
+        };
+        emit(tick);
+    }
+}
+
+fn lagged(missed: u64) {
+    tracing::warn!("Tick receiver lagged and missed {missed} ticks, catching up...");
+    TICK_LAG.record(missed);
 }
 
 pub fn subscribe_to_ticks() -> TickReceiver {
-    TickReceiver::new(&TICK_CHANNEL)
+    match TICK_CHANNEL.upgrade() {
+        Some(tx) => TickReceiver::new(&tx),
+        None => TickReceiver::new(&broadcast::channel(1).0), // Already shut down, so a receiver that's closed right away
+    }
 }
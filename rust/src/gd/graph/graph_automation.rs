@@ -0,0 +1,61 @@
+//! BPM automation: the tempo follows a curve over a number of bars, see `AudioGraph::toggle_bpm_automation`.
+
+/// BPMs closer than this count as the same, so we don't mistake rounding for someone moving the slider.
+const BPM_EPSILON: f64 = 1e-6;
+
+/// Where on the automation curve (0..1) we are after `bars_elapsed` bars, the curve loops every `length_bars` bars.
+pub fn automation_position(bars_elapsed: usize, length_bars: usize) -> f32 {
+    let length_bars = length_bars.max(1);
+    (bars_elapsed % length_bars) as f32 / length_bars as f32
+}
+
+/// Maps a value of the automation curve (0..1, clamped) to a BPM between `min_bpm` and `max_bpm`.
+pub fn automation_bpm(value: f32, min_bpm: f64, max_bpm: f64) -> f64 {
+    min_bpm + (max_bpm - min_bpm) * value.clamp(0.0, 1.0) as f64
+}
+
+/// Keeps track of how far the BPM automation got, and pauses it when the BPM changes behind its back (e.g. the slider or tap tempo).
+#[derive(Debug, Default, Clone)]
+pub struct BpmAutomation {
+    bars_elapsed: usize,
+    /// The BPM at the last bar (or what we set it to since), to notice someone else changing it.
+    last_bpm: Option<f64>,
+    paused_bars_left: usize,
+}
+
+impl BpmAutomation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_bars_left > 0
+    }
+
+    /// Call this on every bar with the BPM right now. Returns where to sample the curve (see `automation_position`),
+    /// or None for `pause_bars` bars after someone else changed the BPM. The curve keeps moving while paused.
+    pub fn bar(&mut self, current_bpm: f64, length_bars: usize, pause_bars: usize) -> Option<f32> {
+        let position = automation_position(self.bars_elapsed, length_bars);
+        self.bars_elapsed += 1;
+
+        if self
+            .last_bpm
+            .is_some_and(|last_bpm| (current_bpm - last_bpm).abs() > BPM_EPSILON)
+        {
+            tracing::info!("BPM changed by hand, pausing BPM automation for {pause_bars} bars");
+            self.paused_bars_left = pause_bars;
+        }
+        self.last_bpm = Some(current_bpm);
+
+        if self.is_paused() {
+            self.paused_bars_left -= 1;
+            return None;
+        }
+        Some(position)
+    }
+
+    /// Call this after setting the BPM to what `bar` asked for, so it doesn't count as a change by hand.
+    pub fn set_bpm(&mut self, bpm: f64) {
+        self.last_bpm = Some(bpm);
+    }
+}
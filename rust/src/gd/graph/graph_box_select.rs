@@ -0,0 +1,57 @@
+//! Box selection: dragging over empty space selects the nodes inside the rectangle, which all play together on the next bar.
+//! See `AudioGraph::end_box_selection`.
+
+use godot::prelude::*;
+use petgraph::graph::NodeIndex;
+
+/// How far (in pixels) the mouse has to move before a left-click on empty space becomes a box selection.
+pub const MIN_BOX_SELECTION_DRAG: f32 = 8.0;
+
+/// A rectangle being dragged in screen space, from where the left mouse button went down to where the mouse is now.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxSelection {
+    pub start: Vector2,
+    pub end: Vector2,
+}
+
+impl BoxSelection {
+    pub fn new(start: Vector2) -> Self {
+        Self { start, end: start }
+    }
+
+    /// With a positive size, whichever way it was dragged.
+    pub fn rect(&self) -> Rect2 {
+        Rect2::new(
+            self.start.coord_min(self.end),
+            (self.end - self.start).abs(),
+        )
+    }
+
+    /// Whether the mouse moved far enough for this to be a drag instead of a click, see `MIN_BOX_SELECTION_DRAG`.
+    pub fn is_drag(&self) -> bool {
+        self.start.distance_to(self.end) >= MIN_BOX_SELECTION_DRAG
+    }
+}
+
+/// The nodes whose `projected` screen position is inside `rect`, sorted by index.
+/// Only the first `max_count` of them, so a huge selection doesn't overload the mixer.
+pub fn nodes_in_rect(
+    projected: impl IntoIterator<Item = (NodeIndex, Vector2)>,
+    rect: Rect2,
+    max_count: usize,
+) -> Vec<NodeIndex> {
+    let mut selected = projected
+        .into_iter()
+        .filter(|&(_, pos)| rect.contains_point(pos))
+        .map(|(node_idx, _)| node_idx)
+        .collect::<Vec<_>>();
+    selected.sort();
+    if selected.len() > max_count {
+        tracing::info!(
+            "selected {} nodes, only playing the first {max_count}",
+            selected.len()
+        );
+        selected.truncate(max_count);
+    }
+    selected
+}
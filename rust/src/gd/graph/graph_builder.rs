@@ -0,0 +1,151 @@
+//! A builder for `ConstellationGraph`, so new options don't keep piling up as positional parameters.
+
+use std::time::Duration;
+
+use godot::builtin::Vector3;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::gd::graph::{
+    graph_generate::{
+        Connectivity, ConstellationGraph, ConstellationParams, EdgeStrategy, GenerationBudget,
+        GenerationError, GenerationProgress, PointDistribution,
+    },
+    graph_import::PointCloudError,
+};
+
+/// The full configuration of a generated constellation. It's serializable, so it can be stored alongside a saved constellation:
+/// building it again with an rng in the same state gives the exact same graph.
+///
+/// The defaults match the ones `AudioGraph` uses, apart from `num_points`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConstellationBuilder {
+    pub num_points: usize,
+    pub radius: f32,
+    pub max_neighbor_count: usize,
+    pub params: ConstellationParams,
+
+    /// Not part of the configuration, so it's not serialized.
+    #[serde(skip)]
+    progress: Option<flume::Sender<GenerationProgress>>,
+}
+
+impl Default for ConstellationBuilder {
+    fn default() -> Self {
+        Self {
+            num_points: 500,
+            radius: 5.0,
+            max_neighbor_count: 3,
+            params: ConstellationParams::default(),
+            progress: None,
+        }
+    }
+}
+
+impl ConstellationBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_num_points(mut self, num_points: usize) -> Self {
+        self.num_points = num_points;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Every node gets connected to 1..=`max_neighbor_count` of its nearest neighbors within its cluster.
+    pub fn with_max_neighbors(mut self, max_neighbor_count: usize) -> Self {
+        self.max_neighbor_count = max_neighbor_count;
+        self
+    }
+
+    pub fn with_distribution(mut self, distribution: PointDistribution) -> Self {
+        self.params.distribution = distribution;
+        self
+    }
+
+    pub fn with_edge_strategy(mut self, edge_strategy: EdgeStrategy) -> Self {
+        self.params.edge_strategy = edge_strategy;
+        self
+    }
+
+    pub fn with_topology(mut self, connectivity: Connectivity) -> Self {
+        self.params.connectivity = connectivity;
+        self
+    }
+
+    pub fn with_bridges_per_island(mut self, bridges_per_island: usize) -> Self {
+        self.params.bridges_per_island = bridges_per_island;
+        self
+    }
+
+    pub fn with_min_island_size(mut self, min_island_size: usize) -> Self {
+        self.params.min_island_size = min_island_size;
+        self
+    }
+
+    pub fn with_island_adjacency_distance(mut self, distance: f32) -> Self {
+        self.params.island_adjacency_distance = distance;
+        self
+    }
+
+    pub fn with_lloyd_iterations(mut self, lloyd_iterations: usize) -> Self {
+        self.params.lloyd_iterations = lloyd_iterations;
+        self
+    }
+
+    pub fn with_radial_jitter(mut self, radial_jitter: f32) -> Self {
+        self.params.radial_jitter = radial_jitter;
+        self
+    }
+
+    pub fn with_budget(mut self, budget: GenerationBudget) -> Self {
+        self.params.budget = budget;
+        self
+    }
+
+    /// Shorthand for a budget that only limits the duration.
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.params.budget.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Every generation phase reports its progress on `progress`.
+    pub fn with_progress(mut self, progress: flume::Sender<GenerationProgress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// See `ConstellationGraph::with_params`.
+    pub fn build<R: Rng>(self, rng: &mut R) -> Result<ConstellationGraph, GenerationError> {
+        ConstellationGraph::with_params(
+            self.num_points,
+            self.radius,
+            self.max_neighbor_count,
+            &self.params,
+            self.progress,
+            rng,
+        )
+    }
+
+    /// Uses the given points instead of generating them, so `num_points` and the distribution are ignored.
+    /// See `ConstellationGraph::from_points`.
+    pub fn build_from_points<R: Rng>(
+        self,
+        points: Vec<Vector3>,
+        rng: &mut R,
+    ) -> Result<ConstellationGraph, PointCloudError> {
+        ConstellationGraph::from_points(
+            points,
+            self.radius,
+            self.max_neighbor_count,
+            &self.params,
+            self.progress,
+            rng,
+        )
+    }
+}
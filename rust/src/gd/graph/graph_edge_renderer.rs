@@ -0,0 +1,388 @@
+//! The edges MultiMesh, written once per frame instead of a Godot call for every change. See `EdgeRenderer`.
+
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+use godot::{classes::MultiMesh, prelude::*};
+
+/// Floats per instance in the MultiMesh buffer: a 3x4 transform, the color and the custom data.
+pub const EDGE_BUFFER_STRIDE: usize = 12 + 4 + 4;
+
+/// Above this many pending `set_instance_*` calls, `EdgeRenderer::flush` replaces the whole buffer in one call instead.
+pub const BUFFER_FLUSH_CALLS: usize = 64;
+
+/// What the custom data starts out as, `x` is the tween progress of the edge. This hides the progress indicator in the shader.
+pub const DEFAULT_EDGE_TWEEN_PROGRESS: f32 = -999999.0;
+
+/// One edge cylinder in the edges MultiMesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeInstance {
+    pub transform: Transform3D,
+    pub color: Color,
+    /// `r` is the tween progress, `a` the glow, see `AudioGraph::lerp_edge`
+    pub custom: Color,
+}
+
+impl EdgeInstance {
+    /// Collapsed to a point, for free slots.
+    pub fn hidden() -> Self {
+        Self {
+            transform: Transform3D::new(Basis::from_scale(Vector3::ZERO), Vector3::ZERO),
+            color: Color::BLACK,
+            custom: Color::from_rgba(DEFAULT_EDGE_TWEEN_PROGRESS, 0.0, 0.0, 0.0),
+        }
+    }
+
+    /// The cylinder from `a` to `b`, black and without glow.
+    pub fn between(a: Vector3, b: Vector3) -> Self {
+        let direction = b - a;
+        let length = direction.length();
+        let midpoint = a + direction * 0.5;
+
+        //Create basis that rotates +Y to the direction vector
+        let up = Vector3::UP;
+        let angle = up.angle_to(direction);
+
+        let rotation = if angle.abs() < f32::EPSILON {
+            Basis::IDENTITY
+        } else {
+            // Straight down has no cross product with up, any horizontal axis will do
+            let axis = up
+                .cross(direction)
+                .try_normalized()
+                .unwrap_or(Vector3::RIGHT);
+            Basis::from_axis_angle(axis, angle)
+        };
+
+        //Stretch the cylinder (assumes original height = 1.0)
+        let scale = Vector3::new(1.0, length, 1.0);
+        let basis = rotation * Basis::from_scale(scale);
+        Self {
+            transform: Transform3D::new(basis, midpoint),
+            ..Self::hidden()
+        }
+    }
+
+    /// The instance as it's laid out in `MultiMesh::set_buffer`, see `EDGE_BUFFER_STRIDE`.
+    pub fn write_buffer(&self, out: &mut [f32]) {
+        let Transform3D { basis, origin } = self.transform;
+        let rows = basis.rows;
+        out[0..4].copy_from_slice(&[rows[0].x, rows[0].y, rows[0].z, origin.x]);
+        out[4..8].copy_from_slice(&[rows[1].x, rows[1].y, rows[1].z, origin.y]);
+        out[8..12].copy_from_slice(&[rows[2].x, rows[2].y, rows[2].z, origin.z]);
+        let Color { r, g, b, a } = self.color;
+        out[12..16].copy_from_slice(&[r, g, b, a]);
+        let Color { r, g, b, a } = self.custom;
+        out[16..20].copy_from_slice(&[r, g, b, a]);
+    }
+}
+
+/// Which parts of an instance changed since the last flush.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EdgeDirty {
+    pub transform: bool,
+    pub color: bool,
+    pub custom: bool,
+}
+
+impl EdgeDirty {
+    const ALL: Self = Self {
+        transform: true,
+        color: true,
+        custom: true,
+    };
+
+    /// How many `set_instance_*` calls writing these takes.
+    pub fn calls(&self) -> usize {
+        [self.transform, self.color, self.custom]
+            .into_iter()
+            .filter(|dirty| *dirty)
+            .count()
+    }
+}
+
+/// How `EdgeRenderer::flush` writes the changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeFlush {
+    Nothing,
+    /// One `set_instance_*` call per changed part of an instance
+    Instances,
+    /// The whole buffer in one call, when there are lots of changes or the instance count changed
+    Buffer,
+}
+
+/// The instances of the edges MultiMesh, indexed by slot (see `EdgeSlots`). AudioGraph owns it, and shares it with the edge tweens.
+///
+/// Everything that changes the edges writes in here, and AudioGraph writes the changes to the MultiMesh once per frame, see `flush`.
+/// Writing the same edge more than once a frame (like trails and tweens do) only costs one Godot call, or none at all with `EdgeFlush::Buffer`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EdgeRenderer {
+    instances: Vec<EdgeInstance>,
+    dirty: BTreeMap<usize, EdgeDirty>,
+    resized: bool,
+    /// The newest tween of every slot that has one, only that one may write the custom data, see `start_tween`
+    tween_owners: BTreeMap<usize, EdgeTweenId>,
+    next_tween: u64,
+}
+
+/// A tween of the custom data of an edge (the progress indicator), see `EdgeRenderer::start_tween`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EdgeTweenId(u64);
+
+/// Ends its tween when dropped, so the progress indicator gets reset however the tween stops: finished, cancelled,
+/// or its task dropped along with the AudioGraph. See `EdgeRenderer::end_tween`.
+pub struct EdgeTweenGuard {
+    edges: Rc<RefCell<EdgeRenderer>>,
+    slot: usize,
+    id: EdgeTweenId,
+}
+
+impl EdgeTweenGuard {
+    /// Starts a tween on `slot`, taking it over from any tween that's still running there.
+    pub fn new(edges: Rc<RefCell<EdgeRenderer>>, slot: usize) -> Self {
+        let id = edges.borrow_mut().start_tween(slot);
+        Self { edges, slot, id }
+    }
+
+    /// Does nothing once a newer tween took over the edge.
+    pub fn set_custom(&self, custom: Color) {
+        self.edges
+            .borrow_mut()
+            .set_tween_custom(self.id, self.slot, custom);
+    }
+}
+
+impl Drop for EdgeTweenGuard {
+    fn drop(&mut self) {
+        // Can't panic in drop, and the renderer is only borrowed for single calls, so this shouldn't fail
+        match self.edges.try_borrow_mut() {
+            Ok(mut edges) => edges.end_tween(self.id, self.slot),
+            Err(err) => tracing::error!(slot = self.slot, "couldn't end edge tween: {err}"),
+        }
+    }
+}
+
+impl EdgeRenderer {
+    /// One instance per edge, `edges` yields the endpoints of every slot in order.
+    pub fn reset(&mut self, edges: impl IntoIterator<Item = (Vector3, Vector3)>) {
+        self.instances = edges
+            .into_iter()
+            .map(|(a, b)| EdgeInstance::between(a, b))
+            .collect();
+        self.dirty.clear();
+        self.resized = true;
+        self.tween_owners.clear();
+    }
+
+    /// Removes all instances, e.g. while regenerating.
+    pub fn clear(&mut self) {
+        self.reset([]);
+    }
+
+    /// Amount of instances the MultiMesh gets.
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Grows (with hidden instances) or shrinks to `len` instances.
+    pub fn resize(&mut self, len: usize) {
+        if len != self.instances.len() {
+            self.instances.resize(len, EdgeInstance::hidden());
+            self.dirty.retain(|&slot, _| slot < len);
+            self.tween_owners.retain(|&slot, _| slot < len);
+            self.resized = true;
+        }
+    }
+
+    /// Black and default custom data if the slot wasn't in use, growing if needed.
+    pub fn set_edge(&mut self, slot: usize, a: Vector3, b: Vector3) {
+        if slot >= self.instances.len() {
+            self.resize((slot + 1).next_power_of_two());
+        }
+        self.instances[slot] = EdgeInstance::between(a, b);
+        self.tween_owners.remove(&slot); // A tween of the edge that was here before
+        self.mark(slot, EdgeDirty::ALL);
+    }
+
+    /// Collapses the instance to a point, for free slots.
+    pub fn hide(&mut self, slot: usize) {
+        self.tween_owners.remove(&slot);
+        self.update(slot, EdgeDirty::ALL, |instance| {
+            *instance = EdgeInstance::hidden()
+        });
+    }
+
+    /// Moves the instance of `from` to `to`, e.g. when compacting the slots.
+    /// A tween of `from` keeps writing to its old slot, so it loses the edge, and the progress indicator gets reset.
+    pub fn copy(&mut self, from: usize, to: usize) {
+        if let Some(&instance) = self.instances.get(from) {
+            let tweened = self.tween_owners.remove(&from).is_some();
+            self.tween_owners.remove(&to);
+            self.update(to, EdgeDirty::ALL, |to| {
+                *to = instance;
+                if tweened {
+                    to.custom = EdgeInstance::hidden().custom;
+                }
+            });
+        }
+    }
+
+    pub fn get(&self, slot: usize) -> Option<EdgeInstance> {
+        self.instances.get(slot).copied()
+    }
+
+    /// Black if there's no instance.
+    pub fn color(&self, slot: usize) -> Color {
+        self.get(slot)
+            .map_or(Color::BLACK, |instance| instance.color)
+    }
+
+    /// Does nothing if there's no instance, like the setters below.
+    pub fn set_color(&mut self, slot: usize, color: Color) {
+        let dirty = EdgeDirty {
+            color: true,
+            ..Default::default()
+        };
+        self.update(slot, dirty, |instance| instance.color = color);
+    }
+
+    pub fn set_custom(&mut self, slot: usize, custom: Color) {
+        let dirty = EdgeDirty {
+            custom: true,
+            ..Default::default()
+        };
+        self.update(slot, dirty, |instance| instance.custom = custom);
+    }
+
+    pub fn set_transform(&mut self, slot: usize, transform: Transform3D) {
+        let dirty = EdgeDirty {
+            transform: true,
+            ..Default::default()
+        };
+        self.update(slot, dirty, |instance| instance.transform = transform);
+    }
+
+    /// Makes a new tween the owner of the custom data of `slot`. Any older tween on the slot can't write it anymore,
+    /// so overlapping tweens don't fight over the progress indicator, and only the newest one resets it. Use `EdgeTweenGuard`.
+    pub fn start_tween(&mut self, slot: usize) -> EdgeTweenId {
+        let id = EdgeTweenId(self.next_tween);
+        self.next_tween += 1;
+        self.tween_owners.insert(slot, id);
+        id
+    }
+
+    /// Whether `id` is the newest tween of `slot`, and hasn't ended.
+    pub fn owns_tween(&self, id: EdgeTweenId, slot: usize) -> bool {
+        self.tween_owners.get(&slot) == Some(&id)
+    }
+
+    /// Like `set_custom`, but only if `id` still owns the slot. Returns whether it did.
+    pub fn set_tween_custom(&mut self, id: EdgeTweenId, slot: usize, custom: Color) -> bool {
+        let owns = self.owns_tween(id, slot);
+        if owns {
+            self.set_custom(slot, custom);
+        }
+        owns
+    }
+
+    /// Resets the progress indicator, if `id` still owns the slot.
+    pub fn end_tween(&mut self, id: EdgeTweenId, slot: usize) {
+        if self.owns_tween(id, slot) {
+            self.tween_owners.remove(&slot);
+            self.set_custom(slot, EdgeInstance::hidden().custom);
+        }
+    }
+
+    fn update(&mut self, slot: usize, dirty: EdgeDirty, f: impl FnOnce(&mut EdgeInstance)) {
+        if let Some(instance) = self.instances.get_mut(slot) {
+            let before = *instance;
+            f(instance);
+            if *instance != before {
+                self.mark(slot, dirty);
+            }
+        }
+    }
+
+    fn mark(&mut self, slot: usize, dirty: EdgeDirty) {
+        let entry = self.dirty.entry(slot).or_default();
+        entry.transform |= dirty.transform;
+        entry.color |= dirty.color;
+        entry.custom |= dirty.custom;
+    }
+
+    /// How many `set_instance_*` calls writing the changes one by one would take.
+    pub fn pending_calls(&self) -> usize {
+        self.dirty.values().map(EdgeDirty::calls).sum()
+    }
+
+    /// How the next `flush` writes the changes, see `BUFFER_FLUSH_CALLS`.
+    pub fn flush_mode(&self) -> EdgeFlush {
+        if self.resized {
+            EdgeFlush::Buffer // Changing the instance count clears the MultiMesh anyway
+        } else if self.dirty.is_empty() {
+            EdgeFlush::Nothing
+        } else if self.pending_calls() > BUFFER_FLUSH_CALLS {
+            EdgeFlush::Buffer
+        } else {
+            EdgeFlush::Instances
+        }
+    }
+
+    /// All instances, laid out like `MultiMesh::set_buffer` wants them.
+    pub fn buffer(&self) -> Vec<f32> {
+        let mut buffer = vec![0.0; self.instances.len() * EDGE_BUFFER_STRIDE];
+        for (instance, out) in self
+            .instances
+            .iter()
+            .zip(buffer.chunks_exact_mut(EDGE_BUFFER_STRIDE))
+        {
+            instance.write_buffer(out);
+        }
+        buffer
+    }
+
+    /// The changes since the last call (and what changed about them), and forgets them.
+    pub fn take_dirty(&mut self) -> Vec<(usize, EdgeDirty)> {
+        self.resized = false;
+        std::mem::take(&mut self.dirty).into_iter().collect()
+    }
+
+    /// Writes the changes to `multi`. Returns how many Godot calls that took, see `EdgeFlush`.
+    pub fn flush(&mut self, multi: &mut Gd<MultiMesh>) -> usize {
+        match self.flush_mode() {
+            EdgeFlush::Nothing => 0,
+            EdgeFlush::Buffer => {
+                let mut calls = 1;
+                if self.resized {
+                    multi.set_instance_count(self.instances.len() as i32);
+                    calls += 1;
+                }
+                if !self.instances.is_empty() {
+                    multi.set_buffer(&PackedFloat32Array::from(self.buffer().as_slice()));
+                }
+                self.take_dirty();
+                calls
+            }
+            EdgeFlush::Instances => {
+                let dirty = self.take_dirty();
+                for &(slot, parts) in &dirty {
+                    let instance = self.instances[slot];
+                    let i = slot as i32;
+                    if parts.transform {
+                        multi.set_instance_transform(i, instance.transform);
+                    }
+                    if parts.color {
+                        multi.set_instance_color(i, instance.color);
+                    }
+                    if parts.custom {
+                        multi.set_instance_custom_data(i, instance.custom);
+                    }
+                }
+                dirty.iter().map(|(_, parts)| parts.calls()).sum()
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use petgraph::graph::EdgeIndex;
+
+/// Maps every edge of the graph to its instance slot in the edges MultiMesh.
+///
+/// We can't use the EdgeIndex as the slot directly, since `Graph::remove_edge` moves the last edge into the index of the removed one,
+/// while the MultiMesh instances should stay where they are (they may be in the middle of a tween).
+/// Freed slots should be hidden, and get re-used for new edges. Use `compact` to get rid of the free slots again.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EdgeSlots {
+    slots: Vec<usize>, // Indexed by EdgeIndex
+    free: Vec<usize>,  // Used as a stack, so the most recently freed slot is re-used first
+    slot_count: usize,
+}
+
+impl EdgeSlots {
+    /// Maps every edge to the slot with the same index, like `setup_multimesh` does for a freshly generated graph.
+    pub fn new(edge_count: usize) -> Self {
+        Self {
+            slots: (0..edge_count).collect(),
+            free: vec![],
+            slot_count: edge_count,
+        }
+    }
+
+    pub fn slot(&self, edge: EdgeIndex) -> usize {
+        self.slots[edge.index()]
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Amount of slots, including the free ones. The MultiMesh needs at least this many instances.
+    pub fn slot_count(&self) -> usize {
+        self.slot_count
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Call this right after `Graph::add_edge` returned `edge`. Returns its slot, re-using a free slot if possible.
+    pub fn insert(&mut self, edge: EdgeIndex) -> usize {
+        assert_eq!(
+            edge.index(),
+            self.slots.len(),
+            "edges must be inserted in order"
+        );
+
+        let slot = self.free.pop().unwrap_or_else(|| {
+            self.slot_count += 1;
+            self.slot_count - 1
+        });
+        self.slots.push(slot);
+        slot
+    }
+
+    /// Call this right after `Graph::remove_edge(edge)`. Returns the freed slot, which should be hidden.
+    /// Just like petgraph, the last edge takes over the index of the removed edge (but keeps its slot).
+    pub fn remove(&mut self, edge: EdgeIndex) -> usize {
+        let slot = self.slots.swap_remove(edge.index());
+        self.free.push(slot);
+        slot
+    }
+
+    /// Moves the edges in the highest slots into the free slots, so afterwards `slot_count() == edge_count()` and there are no free slots left.
+    /// Returns the moves as `(from_slot, to_slot)`, the caller should copy the MultiMesh instances accordingly.
+    pub fn compact(&mut self) -> Vec<(usize, usize)> {
+        let edge_count = self.slots.len();
+
+        let mut holes = self
+            .free
+            .iter()
+            .copied()
+            .filter(|slot| *slot < edge_count)
+            .collect::<Vec<_>>();
+        holes.sort_unstable();
+
+        let mut out_of_range = self
+            .slots
+            .iter_mut()
+            .filter(|slot| **slot >= edge_count)
+            .collect::<Vec<_>>();
+        out_of_range.sort_unstable_by_key(|slot| **slot);
+
+        debug_assert_eq!(holes.len(), out_of_range.len());
+
+        let moves = out_of_range
+            .into_iter()
+            .zip(holes)
+            .map(|(slot, hole)| {
+                let from = *slot;
+                *slot = hole;
+                (from, hole)
+            })
+            .collect();
+
+        self.free.clear();
+        self.slot_count = edge_count;
+        moves
+    }
+}
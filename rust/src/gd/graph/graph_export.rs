@@ -0,0 +1,183 @@
+use std::fmt::Write as _;
+
+use godot::prelude::*;
+use petgraph::graph::NodeIndex;
+
+use crate::gd::graph::{graph_generate::ConstellationGraph, graph_main::IslandData};
+
+#[derive(Clone, Copy, GodotConvert, Var, Export, Default, Debug, Eq, PartialEq)]
+#[godot(via = i64)]
+pub enum GraphExportFormat {
+    #[default]
+    Dot,
+    GraphMl,
+}
+
+/// Per-node attributes shared by both export formats.
+struct NodeAttributes {
+    position: Vector3,
+    island: usize,
+    extras: Option<IslandData>, // Only if the island data was passed
+}
+
+impl ConstellationGraph {
+    /// Serialize to GraphViz DOT. Pass `island_data` (see `AudioGraph::generate_island_data`) to include the waveform/octave of every node.
+    pub fn to_dot(&self, island_data: Option<&[IslandData]>) -> String {
+        let mut out = String::new();
+
+        writeln!(out, "graph constellation {{").unwrap();
+        writeln!(
+            out,
+            "    graph [chord=\"{:?}\", semitone_offset={}];",
+            self.chord, self.semitone_offset
+        )
+        .unwrap();
+
+        for (idx, attrs) in self.node_attributes(island_data) {
+            let Vector3 { x, y, z } = attrs.position;
+            write!(
+                out,
+                "    {} [x={x}, y={y}, z={z}, island={}",
+                idx.index(),
+                attrs.island
+            )
+            .unwrap();
+            if let Some(IslandData {
+                waveform,
+                is_pad,
+                octave_base,
+                ..
+            }) = attrs.extras
+            {
+                write!(
+                    out,
+                    ", waveform=\"{waveform:?}\", pad={is_pad}, octave_base={octave_base}"
+                )
+                .unwrap();
+            }
+            writeln!(out, "];").unwrap();
+        }
+
+        for edge in self.graph.raw_edges() {
+            writeln!(
+                out,
+                "    {} -- {};",
+                edge.source().index(),
+                edge.target().index()
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    /// Serialize to GraphML. Pass `island_data` (see `AudioGraph::generate_island_data`) to include the waveform/octave of every node.
+    pub fn to_graphml(&self, island_data: Option<&[IslandData]>) -> String {
+        let mut out = String::new();
+
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(
+            out,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )
+        .unwrap();
+
+        let mut keys = vec![
+            ("x", "float"),
+            ("y", "float"),
+            ("z", "float"),
+            ("island", "int"),
+        ];
+        if island_data.is_some() {
+            keys.extend([
+                ("waveform", "string"),
+                ("pad", "boolean"),
+                ("octave_base", "double"),
+            ]);
+        }
+        for (name, ty) in keys {
+            writeln!(
+                out,
+                r#"  <key id="{name}" for="node" attr.name="{name}" attr.type="{ty}"/>"#
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            r#"  <graph id="constellation" edgedefault="undirected">"#
+        )
+        .unwrap();
+
+        for (idx, attrs) in self.node_attributes(island_data) {
+            let Vector3 { x, y, z } = attrs.position;
+            writeln!(out, r#"    <node id="n{}">"#, idx.index()).unwrap();
+            writeln!(out, r#"      <data key="x">{x}</data>"#).unwrap();
+            writeln!(out, r#"      <data key="y">{y}</data>"#).unwrap();
+            writeln!(out, r#"      <data key="z">{z}</data>"#).unwrap();
+            writeln!(out, r#"      <data key="island">{}</data>"#, attrs.island).unwrap();
+            if let Some(IslandData {
+                waveform,
+                is_pad,
+                octave_base,
+                ..
+            }) = attrs.extras
+            {
+                writeln!(out, r#"      <data key="waveform">{waveform:?}</data>"#).unwrap();
+                writeln!(out, r#"      <data key="pad">{is_pad}</data>"#).unwrap();
+                writeln!(out, r#"      <data key="octave_base">{octave_base}</data>"#).unwrap();
+            }
+            writeln!(out, r#"    </node>"#).unwrap();
+        }
+
+        for edge in self.graph.raw_edges() {
+            writeln!(
+                out,
+                r#"    <edge source="n{}" target="n{}"/>"#,
+                edge.source().index(),
+                edge.target().index()
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "  </graph>").unwrap();
+        writeln!(out, "</graphml>").unwrap();
+        out
+    }
+
+    pub fn to_format(
+        &self,
+        format: GraphExportFormat,
+        island_data: Option<&[IslandData]>,
+    ) -> String {
+        match format {
+            GraphExportFormat::Dot => self.to_dot(island_data),
+            GraphExportFormat::GraphMl => self.to_graphml(island_data),
+        }
+    }
+
+    fn node_attributes(
+        &self,
+        island_data: Option<&[IslandData]>,
+    ) -> impl Iterator<Item = (NodeIndex, NodeAttributes)> {
+        let mut island_of = vec![usize::MAX; self.graph.node_count()];
+        for (island_idx, island) in self.islands.iter().enumerate() {
+            for node in island {
+                island_of[node.index()] = island_idx;
+            }
+        }
+
+        self.graph.node_indices().map(move |idx| {
+            let island = island_of[idx.index()];
+            (
+                idx,
+                NodeAttributes {
+                    position: self.graph[idx],
+                    island,
+                    extras: island_data.map(|data| data[island]),
+                },
+            )
+        })
+    }
+}
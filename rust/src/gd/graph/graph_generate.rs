@@ -1,7 +1,22 @@
-use std::{any::type_name, collections::BTreeMap, f64::consts::TAU, num::NonZero};
+use std::{
+    any::type_name,
+    collections::{BTreeMap, BTreeSet},
+    f64::consts::TAU,
+    fmt,
+    num::NonZero,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use godot::prelude::*;
-use kiddo::{NearestNeighbour, SquaredEuclidean, float::kdtree::KdTree};
+use itertools::Itertools as _;
+use kiddo::{
+    NearestNeighbour, SquaredEuclidean, float::kdtree::KdTree,
+    immutable::float::kdtree::ImmutableKdTree,
+};
 use nalgebra::{Point3, Unit, UnitQuaternion, Vector3 as NVector3};
 use ordered_float::OrderedFloat;
 use petgraph::{
@@ -11,60 +26,616 @@ use petgraph::{
 };
 use rand::{Rng, SeedableRng as _, seq::IndexedRandom as _};
 use rand_xoshiro::Xoshiro256Plus;
+use rayon::iter::{
+    IndexedParallelIterator as _, IntoParallelIterator as _, IntoParallelRefIterator as _,
+    ParallelIterator as _,
+};
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator as _;
 use tracing::instrument;
 
-use crate::{chords::Chord, gd::graph::graph_main::GraphTypedef, profile, util::random_unit_axis};
+use crate::{
+    chords::Chord,
+    gd::graph::{
+        graph_builder::ConstellationBuilder,
+        graph_import::{PointCloudError, normalize_points},
+        graph_main::GraphTypedef,
+        graph_metrics::GraphMetrics,
+    },
+    profile,
+    util::random_unit_axis,
+};
+
+/// Islands smaller than this are musically useless (they show up in red in the stats), so they get merged into a neighboring island.
+pub const DEFAULT_MIN_ISLAND_SIZE: usize = 3;
+
+/// About twice the typical distance between neighboring points, for the default radius of 5.
+pub const DEFAULT_ISLAND_ADJACENCY_DISTANCE: f32 = 1.0;
+
+/// Enough to even out most of the Voronoi clusters, more iterations barely change anything.
+pub const DEFAULT_LLOYD_ITERATIONS: usize = 2;
+
+/// Relative to the radius, see `ConstellationGraph::from_points`.
+const POINT_JITTER: f32 = 1e-3;
+
+/// How the islands are connected to each other after generation.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export, Serialize, Deserialize,
+)]
+#[godot(via = i64)]
+pub enum Connectivity {
+    /// Leave the islands separated (apart from merging tiny islands)
+    #[default]
+    Islands,
+    /// Add the `bridges_per_island` shortest edges from every island to its neighboring islands
+    Bridged,
+    /// Keep adding the shortest bridges until there is only a single island left
+    FullyConnected,
+}
+
+/// How the points are spread over the sphere.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export, Serialize, Deserialize,
+)]
+#[godot(via = i64)]
+pub enum PointDistribution {
+    /// Evenly spaced, see `ConstellationGraph::generate_points`
+    #[default]
+    Poisson,
+    /// Completely random, so there are dense clumps and big gaps (fewer, larger islands)
+    Uniform,
+}
+
+/// How the points within a cluster get connected to each other.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export, Serialize, Deserialize,
+)]
+#[godot(via = i64)]
+pub enum EdgeStrategy {
+    /// Connect every point to 1..=`max_neighbor_count` of its nearest neighbors
+    #[default]
+    Knn,
+    /// Delaunay triangulation of the cluster (on the sphere), without its longest edges. Ignores `max_neighbor_count`.
+    Triangulated,
+}
+
+/// Edges longer than this percentile of the edge lengths within a cluster get pruned from a triangulation.
+const TRIANGULATION_PRUNE_PERCENTILE: f32 = 0.8;
+
+/// Tweakable generation parameters that aren't passed to `ConstellationGraph::new` directly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConstellationParams {
+    pub distribution: PointDistribution,
+    pub edge_strategy: EdgeStrategy,
+    /// Islands with fewer nodes than this get connected to their nearest neighboring island. 0 or 1 disables merging.
+    pub min_island_size: usize,
+    pub connectivity: Connectivity,
+    /// Only used for `Connectivity::Bridged`.
+    pub bridges_per_island: usize,
+    /// Islands whose closest points are at most this far apart are adjacent in the `island_graph`.
+    pub island_adjacency_distance: f32,
+    /// Lloyd relaxation passes over the Voronoi clusters, to even out their sizes. 0 disables relaxation.
+    pub lloyd_iterations: usize,
+    /// Every cluster gets moved radially by a random amount of up to this fraction of the radius, so islands sit at different depths.
+    /// Only happens after clustering (the Poisson sampling still spaces the points out on the sphere). 0 disables it.
+    pub radial_jitter: f32,
+    pub budget: GenerationBudget,
+}
+
+impl Default for ConstellationParams {
+    fn default() -> Self {
+        Self {
+            distribution: PointDistribution::default(),
+            edge_strategy: EdgeStrategy::default(),
+            min_island_size: DEFAULT_MIN_ISLAND_SIZE,
+            connectivity: Connectivity::default(),
+            bridges_per_island: 1,
+            island_adjacency_distance: DEFAULT_ISLAND_ADJACENCY_DISTANCE,
+            lloyd_iterations: DEFAULT_LLOYD_ITERATIONS,
+            radial_jitter: 0.0,
+            budget: GenerationBudget::default(),
+        }
+    }
+}
+
+/// Limits how long the Poisson point generation may take, so a huge `n` can't freeze the rayon worker forever.
+/// If either limit is reached, generation stops with the points placed so far, see `GenerationError::Budget`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GenerationBudget {
+    /// Total amount of candidate points tried. Deterministic, unlike `max_duration`.
+    pub max_iterations: Option<usize>,
+    pub max_duration: Option<Duration>,
+}
+
+impl Default for GenerationBudget {
+    fn default() -> Self {
+        Self {
+            max_iterations: None,
+            max_duration: Some(Duration::from_secs(30)), // Normal generation takes less than a second
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GenerationError {
+    /// The `GenerationBudget` ran out after placing `placed` out of `requested` points.
+    /// `partial` is the (still fully clustered and connected) graph of the points that were placed.
+    Budget {
+        placed: usize,
+        requested: usize,
+        partial: Box<ConstellationGraph>,
+    },
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerationError::Budget {
+                placed, requested, ..
+            } => write!(
+                f,
+                "generation budget exhausted, only placed {placed} out of {requested} points"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
+
+/// Statistics about the structure of a graph, see `ConstellationGraph::validate`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphReport {
+    /// Degree -> amount of nodes with that degree.
+    pub degree_histogram: BTreeMap<usize, usize>,
+    pub isolated_vertex_count: usize,
+    pub self_loop_count: usize,
+    /// Amount of edges that connect an already connected pair of nodes.
+    pub duplicate_edge_count: usize,
+    /// Largest first.
+    pub component_sizes: Vec<usize>,
+}
+
+/// Structural problems that would break graph walks. If a graph has several problems, the first one in this order is returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphError {
+    SelfLoop(NodeIndex),
+    DuplicateEdge(NodeIndex, NodeIndex),
+    /// Walks can't go anywhere from a node without edges.
+    IsolatedVertex(NodeIndex),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::SelfLoop(node) => write!(f, "node {} has a self-loop", node.index()),
+            GraphError::DuplicateEdge(a, b) => write!(
+                f,
+                "nodes {} and {} are connected more than once",
+                a.index(),
+                b.index()
+            ),
+            GraphError::IsolatedVertex(node) => write!(f, "node {} has no edges", node.index()),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+pub type NodeKdTree = KdTree<f32, usize, 3, 32, u32>;
+
+/// For trees that don't change after construction. Bulk-built, so they're balanced, and faster to build than inserting the points one by one.
+/// The items are indices into the slice the tree was built from.
+type StaticKdTree = ImmutableKdTree<f32, u32, 3, 32>;
+
+fn build_static_kdtree(points: &[Vector3]) -> StaticKdTree {
+    let points = points.iter().map(|p| [p.x, p.y, p.z]).collect::<Vec<_>>();
+    StaticKdTree::new_from_slice(&points)
+}
+
+/// Amount of nearest neighbors per node to look at when searching for the closest other island.
+const ISLAND_QUERY_SIZE: usize = 16;
+
+/// Points get added one by one while sampling, so this can't be a `StaticKdTree`.
+type PoissonKdTree = KdTree<f64, usize, 3, 32, u32>;
+
+/// Amount of candidate batches proposed in parallel per Poisson round. Changing this changes the output for a given seed!
+const POISSON_BATCH_COUNT: usize = 32;
+/// Amount of candidates every batch tries per round.
+const POISSON_ATTEMPTS_PER_BATCH: usize = 32;
+/// If a round needs more iterations than this per committed point, the angle between points gets reduced.
+const POISSON_MAX_ITERATIONS_PER_POINT: usize = 50;
+
+/// Progress of a single generation phase, sent from the rayon worker so the loading indicator can show a percentage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenerationProgress {
+    pub phase: &'static str,
+    pub fraction: f32, // 0..1
+}
+
+/// How long every phase of a single generation took, in the order they ran. See `profile!`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerationTimings {
+    pub phases: Vec<(&'static str, Duration)>,
+}
+
+impl GenerationTimings {
+    pub fn record(&mut self, phase: &'static str, duration: Duration) {
+        self.phases.push((phase, duration));
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// Formats several generations as a table, one column per generation in the given order.
+    /// Phases that didn't run in a generation (e.g. points from a file) show up as `-`.
+    pub fn format_table(history: &[GenerationTimings]) -> String {
+        let mut phases: Vec<&'static str> = vec![];
+        for timings in history {
+            for (phase, _) in &timings.phases {
+                if !phases.contains(phase) {
+                    phases.push(phase);
+                }
+            }
+        }
+
+        let format_row = |label: &str, durations: Vec<Option<Duration>>| {
+            let cells = durations
+                .into_iter()
+                .map(|duration| match duration {
+                    Some(duration) => format!("{:>9.1}", duration.as_millis_f64()),
+                    None => format!("{:>9}", "-"),
+                })
+                .collect::<String>();
+            format!("{label:<20}{cells}")
+        };
+
+        phases
+            .iter()
+            .map(|phase| {
+                let durations = history
+                    .iter()
+                    .map(|timings| {
+                        timings
+                            .phases
+                            .iter()
+                            .filter(|(p, _)| p == phase)
+                            .map(|(_, duration)| *duration)
+                            .reduce(|a, b| a + b)
+                    })
+                    .collect();
+                format_row(phase, durations)
+            })
+            .chain([format_row(
+                "total",
+                history
+                    .iter()
+                    .map(|timings| Some(timings.total()))
+                    .collect(),
+            )])
+            .join("\n")
+    }
+}
+
+/// Only send progress if it increased by at least this much since the last message (or if the phase is done).
+const PROGRESS_GRANULARITY: f32 = 0.01;
+
+/// Rate-limits `GenerationProgress` messages for a single phase.
+struct ProgressReporter<'a> {
+    tx: Option<&'a flume::Sender<GenerationProgress>>,
+    phase: &'static str,
+    last_sent: Option<f32>,
+}
+
+impl<'a> ProgressReporter<'a> {
+    fn new(tx: Option<&'a flume::Sender<GenerationProgress>>, phase: &'static str) -> Self {
+        Self {
+            tx,
+            phase,
+            last_sent: None,
+        }
+    }
+
+    fn report(&mut self, fraction: f32) {
+        let Some(tx) = self.tx else {
+            return;
+        };
+
+        if let Some(last_sent) = self.last_sent
+            && fraction < 1.0
+            && fraction - last_sent < PROGRESS_GRANULARITY
+        {
+            return;
+        }
+
+        self.last_sent = Some(fraction);
+
+        // Ignore the error - the receiver may have been dropped (e.g. the AudioGraph got freed during generation), that's fine
+        let _ = tx.send(GenerationProgress {
+            phase: self.phase,
+            fraction,
+        });
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConstellationGraph {
     pub chord: Chord,
     pub semitone_offset: i32,
     pub graph: GraphTypedef,
-    pub islands: Vec<Vec<NodeIndex>>, // Strongly Connected Components (aka "islands")
+    /// Strongly Connected Components (aka "islands"), in canonical order (see `canonicalize_islands`), so per-island data like
+    /// `AudioGraph::generate_island_data` doesn't depend on the order petgraph happens to find them in.
+    pub islands: Vec<Vec<NodeIndex>>,
+    pub bridge_edge_count: usize, // Amount of edges added between islands, see `Connectivity`
+    pub island_graph: IslandGraphTypedef,
+    pub metrics: GraphMetrics,
+    /// Only set if a non-default `EdgeStrategy` was used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge_strategy_comparison: Option<EdgeStrategyComparison>,
+    /// Not part of the graph itself, so it's not serialized or compared.
+    #[serde(skip)]
+    pub timings: GenerationTimings,
+}
+
+/// Compares the edges within the clusters (so before merging and bridging) to what `EdgeStrategy::Knn` would have given.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EdgeStrategyComparison {
+    pub edge_strategy: EdgeStrategy,
+    pub cluster_edge_count: usize,
+    pub knn_cluster_edge_count: usize,
 }
 
+/// Nodes are island indices (into `ConstellationGraph::islands`), edges connect spatially adjacent islands.
+/// The edge weight is the distance between the closest points of both islands.
+pub type IslandGraphTypedef = UnGraph<usize, f32>;
+
 impl ConstellationGraph {
-    /// Create the graph and its strongly connected components (islands)
-    pub fn new<R: Rng>(n: usize, radius: f32, max_neighbor_count: usize, rng: &mut R) -> Self {
+    /// Create the graph and its strongly connected components (islands), using the default `ConstellationParams`.
+    /// Use `ConstellationBuilder` for anything more involved.
+    pub fn new<R: Rng>(
+        n: usize,
+        radius: f32,
+        max_neighbor_count: usize,
+        rng: &mut R,
+    ) -> Result<Self, GenerationError> {
+        ConstellationBuilder::new()
+            .with_num_points(n)
+            .with_radius(radius)
+            .with_max_neighbors(max_neighbor_count)
+            .build(rng)
+    }
+
+    /// Create the graph and its strongly connected components (islands).
+    /// If `progress` is set, every phase reports its progress on it.
+    /// Fails if `params.budget` runs out, but the error still contains the graph of the points placed so far.
+    pub fn with_params<R: Rng>(
+        n: usize,
+        radius: f32,
+        max_neighbor_count: usize,
+        params: &ConstellationParams,
+        progress: Option<flume::Sender<GenerationProgress>>,
+        rng: &mut R,
+    ) -> Result<Self, GenerationError> {
+        let progress = progress.as_ref();
+
         tracing::info!(rng_type = type_name::<R>(), "generating ConstellationGraph");
 
         // Generate this first for rng reasons
+        let (chord, semitone_offset_base) = Self::choose_chord(rng);
+
+        let mut timings = GenerationTimings::default();
+        let points = profile!(
+            into timings,
+            "points",
+            match params.distribution {
+                PointDistribution::Poisson => {
+                    Self::generate_points(n, radius as f64, &params.budget, rng, progress)
+                }
+                PointDistribution::Uniform => Self::generate_points_uniform(n, radius as f64, rng),
+            }
+        );
+        let placed = points.len();
+
+        let constellation = Self::from_points_unchecked(
+            (chord, semitone_offset_base),
+            points,
+            max_neighbor_count,
+            params,
+            progress,
+            timings,
+            rng,
+        );
+
+        if placed < n {
+            Err(GenerationError::Budget {
+                placed,
+                requested: n,
+                partial: Box::new(constellation),
+            })
+        } else {
+            Ok(constellation)
+        }
+    }
+
+    /// Like `with_params`, but uses the given points instead of generating them, e.g. to spell out a shape.
+    /// The points are deduplicated, re-centered and scaled to `radius` first, see `normalize_points`.
+    pub fn from_points<R: Rng>(
+        points: Vec<Vector3>,
+        radius: f32,
+        max_neighbor_count: usize,
+        params: &ConstellationParams,
+        progress: Option<flume::Sender<GenerationProgress>>,
+        rng: &mut R,
+    ) -> Result<Self, PointCloudError> {
+        let progress = progress.as_ref();
+
+        let mut timings = GenerationTimings::default();
+        let points = profile!(into timings, "normalize_points", normalize_points(points, radius))?;
+        tracing::info!(
+            rng_type = type_name::<R>(),
+            point_count = points.len(),
+            "building ConstellationGraph from points"
+        );
+
+        let (chord, semitone_offset_base) = Self::choose_chord(rng);
+
+        // The kd-trees panic if more than their bucket size (32) points share a coordinate on one axis, which is
+        // very likely for hand-made shapes (e.g. a flat grid), so nudge every point by a tiny random amount
+        let mut jitter_rng = Xoshiro256Plus::from_rng(rng);
+        let jitter = radius * POINT_JITTER;
+        let points = points
+            .into_iter()
+            .map(|p| {
+                p + Vector3::new(
+                    jitter_rng.random_range(-jitter..jitter),
+                    jitter_rng.random_range(-jitter..jitter),
+                    jitter_rng.random_range(-jitter..jitter),
+                )
+            })
+            .collect();
+
+        Ok(Self::from_points_unchecked(
+            (chord, semitone_offset_base),
+            points,
+            max_neighbor_count,
+            params,
+            progress,
+            timings,
+            rng,
+        ))
+    }
+
+    fn choose_chord<R: Rng>(rng: &mut R) -> (Chord, i32) {
         let chords = Chord::iter().collect::<Vec<_>>();
         let chord = *chords
             .choose(&mut Xoshiro256Plus::from_rng(rng)) // Making a new rng here to avoid nondeterminism when we change the amount of chords
             .unwrap();
         let semitone_offset_base = rng.random_range(-11..12); // Equal for all notes to avoid dissonance
+        (chord, semitone_offset_base)
+    }
 
-        let points = Self::generate_points(n, radius as f64, rng);
+    /// Clusters and connects the points, and finds the islands.
+    fn from_points_unchecked<R: Rng>(
+        (chord, semitone_offset_base): (Chord, i32),
+        points: Vec<Vector3>,
+        max_neighbor_count: usize,
+        params: &ConstellationParams,
+        progress: Option<&flume::Sender<GenerationProgress>>,
+        mut timings: GenerationTimings,
+        rng: &mut R,
+    ) -> Self {
+        let n = points.len();
 
         let voronoi_rng = Xoshiro256Plus::from_rng(rng);
         let clusters = profile!(
+            into timings,
             "cluster_voronoi",
             Self::cluster_voronoi(
                 points,
                 (n as f64 / 15.0).ceil() as usize, // n / 15 means ~15 nodes per cluster
-                voronoi_rng
+                params.lloyd_iterations,
+                voronoi_rng,
+                progress
             )
         );
 
-        let supergraph = Self::connect_clusters_internally(&clusters, max_neighbor_count, rng);
-        let scc = tarjan_scc(&supergraph);
+        let mut supergraph = profile!(
+            into timings,
+            "connect_clusters",
+            Self::connect_clusters_internally(
+                &clusters,
+                params.edge_strategy,
+                max_neighbor_count,
+                params.radial_jitter,
+                rng,
+                progress,
+            )
+        );
+
+        let edge_strategy_comparison = (params.edge_strategy != EdgeStrategy::Knn).then(|| {
+            let knn_graph = Self::connect_clusters_internally(
+                &clusters,
+                EdgeStrategy::Knn,
+                max_neighbor_count,
+                params.radial_jitter,
+                &mut Xoshiro256Plus::from_rng(&mut *rng),
+                None,
+            );
+            EdgeStrategyComparison {
+                edge_strategy: params.edge_strategy,
+                cluster_edge_count: supergraph.edge_count(),
+                knn_cluster_edge_count: knn_graph.edge_count(),
+            }
+        });
+
+        let kdtree = Self::build_node_kdtree(&supergraph);
+        profile!(
+            into timings,
+            "merge_small_islands",
+            Self::merge_small_islands(&mut supergraph, &kdtree, params.min_island_size)
+        );
+
+        let edge_count_before_bridging = supergraph.edge_count();
+        profile!(
+            into timings,
+            "bridge_islands",
+            match params.connectivity {
+                Connectivity::Islands => {}
+                Connectivity::Bridged => {
+                    Self::bridge_islands(&mut supergraph, &kdtree, params.bridges_per_island)
+                }
+                Connectivity::FullyConnected => {
+                    Self::merge_small_islands(&mut supergraph, &kdtree, usize::MAX)
+                }
+            }
+        );
+        let bridge_edge_count = supergraph.edge_count() - edge_count_before_bridging;
+
+        let scc = profile!(
+            into timings,
+            "scc",
+            Self::canonicalize_islands(tarjan_scc(&supergraph))
+        );
+
+        let island_graph = profile!(
+            into timings,
+            "build_island_graph",
+            Self::build_island_graph(&supergraph, &kdtree, &scc, params.island_adjacency_distance)
+        );
+
+        let metrics = profile!(
+            into timings,
+            "metrics",
+            GraphMetrics::compute(&supergraph, &scc)
+        );
 
         ConstellationGraph {
             chord,
             semitone_offset: semitone_offset_base,
             graph: supergraph,
             islands: scc,
+            bridge_edge_count,
+            island_graph,
+            metrics,
+            edge_strategy_comparison,
+            timings,
         }
     }
 
-    /// Generate n random points on the unit sphere
-    fn generate_points<R: Rng>(n: usize, radius: f64, rng: &mut R) -> Vec<Vector3> {
+    /// Generate n random points on the unit sphere. Returns less than n points if the `budget` runs out.
+    pub fn generate_points<R: Rng>(
+        n: usize,
+        radius: f64,
+        budget: &GenerationBudget,
+        rng: &mut R,
+        progress: Option<&flume::Sender<GenerationProgress>>,
+    ) -> Vec<Vector3> {
         // Using a large value of `max_angle` here is safe now due to our new `leniency` algorithm
         let max_angle = TAU * 0.02;
-        let points_nalgebra = Self::generate_points_poisson(n, radius, max_angle, rng);
+        let points_nalgebra = profile!(
+            "generate_points_poisson",
+            Self::generate_points_poisson(n, radius, max_angle, budget, rng, progress)
+        );
 
         points_nalgebra
             .into_iter()
@@ -72,22 +643,38 @@ impl ConstellationGraph {
             .collect()
     }
 
+    /// Generates `n` uniformly random points on the surface of a sphere of radius `radius`. Unlike Poisson, this can't run out of budget.
+    pub fn generate_points_uniform<R: Rng>(n: usize, radius: f64, rng: &mut R) -> Vec<Vector3> {
+        (0..n)
+            .map(|_| {
+                let p = random_unit_axis(rng) * radius;
+                Vector3::new(p.x as f32, p.y as f32, p.z as f32)
+            })
+            .collect()
+    }
+
     /// Generates `n` points on the surface of a sphere of radius `radius` using a Poisson-disk-like algorithm.
     /// The points are roughly `max_angle` radians separated from each other, unless the algorithm reaches an iteration limit.
     /// Then it will slowly reduce the angle limit until it succeeds. This is called `leniency`.
-    #[cfg_attr(feature = "enable-tracing", instrument(skip(rng)))]
+    ///
+    /// Candidates are proposed in parallel in `POISSON_BATCH_COUNT` batches per round, every batch with its own forked rng,
+    /// against a read-only snapshot of the kd-tree. They are then validated and committed sequentially in batch order.
+    /// That way the output only depends on the seed, not on thread scheduling or the amount of threads.
+    ///
+    /// The `budget` is checked after every round, if it runs out the points placed so far are returned.
+    #[cfg_attr(feature = "enable-tracing", instrument(skip(rng, progress)))]
     fn generate_points_poisson<R: Rng>(
         n: usize,
         radius: f64,
         mut max_angle: f64,
+        budget: &GenerationBudget,
         rng: &mut R,
+        progress: Option<&flume::Sender<GenerationProgress>>,
     ) -> Vec<Point3<f64>> {
-        pub type KdTreeUsize<A, const K: usize> = KdTree<A, usize, K, 32, u32>;
+        let mut progress = ProgressReporter::new(progress, "poisson");
 
         let mut points = Vec::with_capacity(n);
-        let mut tree = KdTreeUsize::new();
-
-        let epsilon_mult = 0.9; // This is the "leniency factor"
+        let mut tree = PoissonKdTree::new();
 
         // Starting point: (0, radius, 0)
         let start = NVector3::new(0.0, radius, 0.0);
@@ -95,168 +682,654 @@ impl ConstellationGraph {
         tree.add(&start_point.into(), 0);
         points.push(start_point);
 
+        let start_time = Instant::now();
+        let mut total_iterations = 0;
+
         while points.len() < n {
-            let new_point;
+            let iterations_exhausted = budget
+                .max_iterations
+                .is_some_and(|max| total_iterations >= max);
+            let time_exhausted = budget
+                .max_duration
+                .is_some_and(|max| start_time.elapsed() >= max);
+            if iterations_exhausted || time_exhausted {
+                tracing::error!(
+                    placed = points.len(),
+                    requested = n,
+                    total_iterations,
+                    elapsed = ?start_time.elapsed(),
+                    "Poisson generation ran out of budget"
+                );
+                break;
+            }
 
-            // Random angle per point at 50%..150% of max_angle (this is important to ensure melody rest times are varied - they depend on inter-node distance)
-            let angle = rng.random_range(0.5..=1.5) * max_angle;
+            // Spread the remaining points over the batches, so we don't waste time proposing way too many candidates
+            let quota = (n - points.len()).div_ceil(POISSON_BATCH_COUNT);
 
-            // We want chord distance formula here, NOT arc length (we want an underestimation, not an overestimation)
-            let mut min_distance = 2.0 * radius * (angle / 2.0).sin() * epsilon_mult;
-
-            let mut iteration = 0;
-            loop {
-                // Select a previous point as a base
-                let parent_idx = rng.random_range(0..points.len());
-                let parent = points[parent_idx].coords;
-
-                // If iteration count goes beyond 1k or so, reduce minimum distance (becomes slightly more `lenient`)
-                if iteration >= 1_000 {
-                    iteration = 0;
-
-                    let leniency_factor = 0.9;
-                    min_distance *= leniency_factor;
-                    max_angle *= leniency_factor;
-
-                    tracing::warn!(
-                        "Reducing poisson min_distance to {:.4} due to reaching iteration limit",
-                        min_distance
-                    );
-                }
+            // Fork the rngs up front and sequentially, so every batch gets the same rng regardless of which thread runs it
+            let batch_rngs = (0..POISSON_BATCH_COUNT)
+                .map(|_| Xoshiro256Plus::from_rng(&mut *rng))
+                .collect::<Vec<_>>();
 
-                // Apply random axis rotation by fixed angle
-                let axis = random_unit_axis(rng);
-                let rot = UnitQuaternion::from_axis_angle(&Unit::new_normalize(axis), angle);
-                let candidate = rot * parent;
-
-                // Find closest neighbor
-                let neighbors = tree.nearest_n_within::<SquaredEuclidean>(
-                    &candidate.into(),
-                    min_distance * min_distance,
-                    NonZero::new(1).unwrap(), //Return up to 1 result
-                    false,
-                );
+            let batches = batch_rngs
+                .into_par_iter()
+                .map(|mut batch_rng| {
+                    Self::propose_poisson_candidates(
+                        &points,
+                        &tree,
+                        radius,
+                        max_angle,
+                        quota,
+                        &mut batch_rng,
+                    )
+                })
+                .collect::<Vec<_>>(); // Preserves batch order
 
-                // Reject if too close to neighbor
-                if neighbors.is_empty() {
-                    let p = Point3::from(candidate);
-                    tree.add(&p.into(), points.len());
-                    new_point = p;
+            // Candidates of different batches (or of the same batch) may be too close to each other, so check again against the live tree
+            let iterations = batches.iter().map(|batch| batch.len()).sum::<usize>();
+            total_iterations += iterations;
+            let mut committed = 0;
+            for (point, min_distance) in batches.into_iter().flatten().flatten() {
+                if points.len() >= n {
                     break;
                 }
 
-                iteration += 1;
+                if Self::is_far_enough(&tree, &point, min_distance) {
+                    tree.add(&point.into(), points.len());
+                    points.push(point);
+                    committed += 1;
+                }
+            }
+
+            // If we need too many iterations per point, reduce the angle for the next rounds (becomes slightly more `lenient`)
+            if committed * POISSON_MAX_ITERATIONS_PER_POINT < iterations {
+                let leniency_factor = 0.9;
+                max_angle *= leniency_factor;
+
+                tracing::warn!(
+                    committed,
+                    "Reducing poisson max_angle to {:.4} due to reaching iteration limit",
+                    max_angle
+                );
             }
 
-            points.push(new_point);
+            progress.report(points.len() as f32 / n as f32);
         }
 
+        progress.report(1.0);
         points
     }
 
+    /// Tries up to `POISSON_ATTEMPTS_PER_BATCH` candidates that are far enough from all points in the `tree` snapshot, and stops after `quota` successes.
+    /// Returns the candidates along with the minimum distance they were checked with, or None for every failed attempt.
+    fn propose_poisson_candidates<R: Rng>(
+        points: &[Point3<f64>],
+        tree: &PoissonKdTree,
+        radius: f64,
+        max_angle: f64,
+        quota: usize,
+        rng: &mut R,
+    ) -> Vec<Option<(Point3<f64>, f64)>> {
+        let epsilon_mult = 0.9; // This is the "leniency factor"
+
+        let mut attempts = vec![];
+        let mut candidate_count = 0;
+        for _ in 0..POISSON_ATTEMPTS_PER_BATCH {
+            // Random angle per point at 50%..150% of max_angle (this is important to ensure melody rest times are varied - they depend on inter-node distance)
+            let angle = rng.random_range(0.5..=1.5) * max_angle;
+
+            // We want chord distance formula here, NOT arc length (we want an underestimation, not an overestimation)
+            let min_distance = 2.0 * radius * (angle / 2.0).sin() * epsilon_mult;
+
+            // Select a previous point as a base
+            let parent = points[rng.random_range(0..points.len())].coords;
+
+            // Apply random axis rotation by fixed angle
+            let axis = random_unit_axis(rng);
+            let rot = UnitQuaternion::from_axis_angle(&Unit::new_normalize(axis), angle);
+            let point = Point3::from(rot * parent);
+
+            if Self::is_far_enough(tree, &point, min_distance) {
+                attempts.push(Some((point, min_distance)));
+                candidate_count += 1;
+
+                if candidate_count >= quota {
+                    break;
+                }
+            } else {
+                attempts.push(None);
+            }
+        }
+
+        attempts
+    }
+
+    /// Returns true if there's no point in the tree within `min_distance` of `candidate`.
+    fn is_far_enough(tree: &PoissonKdTree, candidate: &Point3<f64>, min_distance: f64) -> bool {
+        let neighbors = tree.nearest_n_within::<SquaredEuclidean>(
+            &(*candidate).into(),
+            min_distance * min_distance,
+            NonZero::new(1).unwrap(), //Return up to 1 result
+            false,
+        );
+
+        neighbors.is_empty()
+    }
+
     /// Cluster the points according to a voronoi-like algorithm, where `k` is the amount of clusters. Returns the clustered points and the cluster centroids.
-    #[cfg_attr(feature = "enable-tracing", instrument(skip(points, rng)))]
+    /// Every Lloyd iteration moves the centroids to the spherical mean of their cluster and reassigns the points, which evens out the cluster sizes.
+    #[cfg_attr(feature = "enable-tracing", instrument(skip(points, rng, progress)))]
     fn cluster_voronoi(
         points: Vec<Vector3>,
         k: usize,
+        lloyd_iterations: usize,
         mut rng: Xoshiro256Plus,
+        progress: Option<&flume::Sender<GenerationProgress>>,
     ) -> Vec<(Vec<Vector3>, Vector3)> {
-        pub type KdTreeUsize<A, const K: usize> = KdTree<A, usize, K, 32, u32>;
-        let mut kdtree = KdTreeUsize::new();
+        let mut progress = ProgressReporter::new(progress, "voronoi");
+        let passes = (lloyd_iterations + 1) as f32;
 
         // Pick k random clusters
-        let centroids = points.choose_multiple(&mut rng, k).collect::<Vec<_>>();
+        let centroids = points
+            .choose_multiple(&mut rng, k)
+            .copied()
+            .collect::<Vec<_>>();
+
+        let mut clusters = Self::assign_to_centroids(&points, &centroids, |fraction| {
+            progress.report(fraction / passes)
+        });
+        let variance_before = cluster_size_variance(&clusters);
 
-        // Add them to kd-tree
-        for (i, c) in centroids.iter().enumerate() {
-            kdtree.add(&[c.x, c.y, c.z], i);
+        for iteration in 1..=lloyd_iterations {
+            let centroids = clusters
+                .iter()
+                .map(|(members, centroid)| {
+                    spherical_mean(members, centroid.length()).unwrap_or(*centroid)
+                })
+                .collect::<Vec<_>>();
+
+            clusters = Self::assign_to_centroids(&points, &centroids, |fraction| {
+                progress.report((iteration as f32 + fraction) / passes)
+            });
+        }
+        progress.report(1.0);
+
+        if lloyd_iterations > 0 {
+            tracing::info!(
+                lloyd_iterations,
+                variance_before,
+                variance_after = cluster_size_variance(&clusters),
+                "relaxed voronoi clusters"
+            );
         }
 
+        //Now sort by centroid y (stable sort) for cool animation!
+        clusters.sort_by_key(|cluster| OrderedFloat(-cluster.1.y));
+
+        clusters
+    }
+
+    /// Assigns every point to the cluster of its closest centroid. Empty clusters are kept.
+    fn assign_to_centroids(
+        points: &[Vector3],
+        centroids: &[Vector3],
+        mut report: impl FnMut(f32),
+    ) -> Vec<(Vec<Vector3>, Vector3)> {
+        let kdtree = build_static_kdtree(centroids);
+
         let mut clusters = centroids
             .iter()
-            .map(|centroid| (vec![], **centroid))
+            .map(|centroid| (vec![], *centroid))
             .collect::<Vec<_>>();
 
         // Find closest centroid per point and assign the point to that cluster
-        for p in points {
+        for (i, p) in points.iter().enumerate() {
             let closest_centroid = kdtree
                 .nearest_one::<SquaredEuclidean>(&[p.x, p.y, p.z])
                 .item;
-            clusters[closest_centroid].0.push(p);
+            clusters[closest_centroid as usize].0.push(*p);
+            report(i as f32 / points.len() as f32);
         }
 
-        //Now sort by centroid y (stable sort) for cool animation!
-        clusters.sort_by_key(|cluster| OrderedFloat(-cluster.1.y));
-
         clusters
     }
 
     fn connect_clusters_internally<R: Rng>(
         clusters: &[(Vec<Vector3>, Vector3)],
+        edge_strategy: EdgeStrategy,
         max_neighbor_count: usize,
+        radial_jitter: f32,
         rng: &mut R,
+        progress: Option<&flume::Sender<GenerationProgress>>,
     ) -> GraphTypedef {
-        pub type KdTreeUsize<A, const K: usize> = KdTree<A, usize, K, 32, u32>;
+        let progress = Mutex::new(ProgressReporter::new(progress, "connect"));
+        let connected_count = AtomicUsize::new(0);
 
         let mut supergraph: GraphTypedef = Graph::new_undirected();
-        let mut graphs = vec![];
 
-        profile!(
-            "connect_clusters_internally", // This is not the bottleneck btw
-            for (cluster, _centroid) in clusters.iter() {
-                // Build the kd-tree for nearest neighbor search per-cluster
-                let mut kdtree = KdTreeUsize::new();
-                for (i, p) in cluster.iter().enumerate() {
-                    kdtree.add(&[p.x, p.y, p.z], i);
-                }
+        // Fork the rngs up front and sequentially (cluster i gets the i-th fork of `rng`), so every cluster gets the same rng regardless of which thread runs it
+        let cluster_rngs = clusters
+            .iter()
+            .map(|_| Xoshiro256Plus::from_rng(&mut *rng))
+            .collect::<Vec<_>>();
 
-                // Create a graph where each point is a node
-                let mut graph: GraphTypedef = Graph::new_undirected();
-                let node_indices: Vec<NodeIndex> =
-                    cluster.iter().map(|p| graph.add_node(*p)).collect();
-
-                // Add edges between each node and its 1..max_neighbor_count nearest neighbors
-                let max_neighbor_count = max_neighbor_count as i32;
-                let min_neighbor_count = 1;
-
-                for (i, point) in cluster.iter().enumerate() {
-                    let neighbor_count = *(min_neighbor_count..=max_neighbor_count)
-                        .collect::<Vec<_>>()
-                        .choose_weighted(rng, |w| match w {
-                            1 => 0.8,
-                            2 => 1.0,
-                            3 => 0.3,
-                            _ => 0.0,
-                        })
-                        .unwrap() as usize;
-
-                    // Find neighbors
-                    let query = [point.x, point.y, point.z];
-                    let neighbors =
-                        kdtree.nearest_n::<SquaredEuclidean>(&query, neighbor_count + 1); //+1 since we get the point itself too
-
-                    // Connect vertex i to neighbor j
-                    for NearestNeighbour { item: j, .. } in &neighbors {
-                        if i != *j {
-                            let a = node_indices[i];
-                            let b = node_indices[*j];
-
-                            //Only add edge if there isn't one already
-                            if !graph.contains_edge(a, b) {
-                                graph.add_edge(a, b, ());
-                            }
+        let graphs = profile!(
+            "connect_clusters_internally",
+            clusters
+                .par_iter()
+                .zip(cluster_rngs)
+                .map(|((cluster, _centroid), mut cluster_rng)| {
+                    // Jitter before connecting, so the edge lengths are the real distances. Only draws from the rng when enabled, to keep old seeds intact.
+                    let jittered;
+                    let cluster = if radial_jitter > 0.0 {
+                        let scale = 1.0 + cluster_rng.random_range(-radial_jitter..=radial_jitter);
+                        jittered = cluster.iter().map(|p| *p * scale).collect::<Vec<_>>();
+                        &jittered
+                    } else {
+                        cluster
+                    };
+
+                    let graph = match edge_strategy {
+                        EdgeStrategy::Knn => {
+                            Self::connect_cluster(cluster, max_neighbor_count, &mut cluster_rng)
                         }
-                    }
-                }
+                        EdgeStrategy::Triangulated => Self::triangulate_cluster(cluster),
+                    };
 
-                graphs.push(graph);
-            }
+                    let connected = connected_count.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress
+                        .lock()
+                        .unwrap()
+                        .report(connected as f32 / clusters.len() as f32);
+
+                    graph
+                })
+                .collect::<Vec<_>>() // Preserves cluster order
         );
+        progress.lock().unwrap().report(1.0);
 
         Self::merge_undirected_graphs(&mut supergraph, &graphs);
         supergraph
     }
 
+    /// Connects every point of the cluster to 1..max_neighbor_count of its nearest neighbors.
+    fn connect_cluster<R: Rng>(
+        cluster: &[Vector3],
+        max_neighbor_count: usize,
+        rng: &mut R,
+    ) -> GraphTypedef {
+        // Build the kd-tree for nearest neighbor search per-cluster
+        let kdtree = build_static_kdtree(cluster);
+
+        // Create a graph where each point is a node
+        let mut graph: GraphTypedef = Graph::new_undirected();
+        let node_indices: Vec<NodeIndex> = cluster.iter().map(|p| graph.add_node(*p)).collect();
+
+        // Add edges between each node and its 1..max_neighbor_count nearest neighbors
+        let max_neighbor_count = max_neighbor_count as i32;
+        let min_neighbor_count = 1;
+
+        for (i, point) in cluster.iter().enumerate() {
+            let neighbor_count = *(min_neighbor_count..=max_neighbor_count)
+                .collect::<Vec<_>>()
+                .choose_weighted(rng, |w| match w {
+                    1 => 0.8,
+                    2 => 1.0,
+                    3 => 0.3,
+                    _ => 0.0,
+                })
+                .unwrap() as usize;
+
+            // Find neighbors
+            let query = [point.x, point.y, point.z];
+            let neighbors = kdtree.nearest_n::<SquaredEuclidean>(
+                &query,
+                NonZero::new(neighbor_count + 1).unwrap(), //+1 since we get the point itself too
+            );
+
+            // Connect vertex i to neighbor j
+            for NearestNeighbour { item: j, .. } in &neighbors {
+                let j = *j as usize;
+                if i != j {
+                    let a = node_indices[i];
+                    let b = node_indices[j];
+
+                    //Only add edge if there isn't one already
+                    if !graph.contains_edge(a, b) {
+                        graph.add_edge(a, b, ());
+                    }
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Sorts the nodes within every island, and the islands by their smallest node (then by size, although the smallest nodes are unique anyway).
+    /// The order of `tarjan_scc` is an implementation detail of petgraph, this order only depends on the graph itself.
+    pub fn canonicalize_islands(mut islands: Vec<Vec<NodeIndex>>) -> Vec<Vec<NodeIndex>> {
+        for island in &mut islands {
+            island.sort_unstable();
+        }
+        islands.sort_unstable_by_key(|island| (island.first().copied(), island.len()));
+        islands
+    }
+
+    /// Checks that the graph has no self-loops, duplicate edges or isolated vertices, and returns some statistics about it.
+    pub fn validate(&self) -> Result<GraphReport, GraphError> {
+        Self::validate_graph(&self.graph)
+    }
+
+    /// See `validate`, but for any graph (e.g. a hand-built one).
+    pub fn validate_graph(graph: &GraphTypedef) -> Result<GraphReport, GraphError> {
+        let mut report = GraphReport::default();
+        let mut first_error = None;
+
+        let mut seen_edges = BTreeSet::new();
+        for edge in graph.raw_edges() {
+            let (a, b) = (edge.source(), edge.target());
+            if a == b {
+                report.self_loop_count += 1;
+                first_error.get_or_insert(GraphError::SelfLoop(a));
+            } else if !seen_edges.insert((a.min(b), a.max(b))) {
+                report.duplicate_edge_count += 1;
+                first_error.get_or_insert(GraphError::DuplicateEdge(a.min(b), a.max(b)));
+            }
+        }
+
+        for node in graph.node_indices() {
+            let degree = graph.edges(node).count();
+            *report.degree_histogram.entry(degree).or_default() += 1;
+            if degree == 0 {
+                report.isolated_vertex_count += 1;
+            }
+        }
+
+        report.component_sizes = tarjan_scc(graph)
+            .iter()
+            .map(|component| component.len())
+            .collect();
+        report.component_sizes.sort_unstable_by(|a, b| b.cmp(a));
+
+        // Only check this after the edges, so self-loops and duplicate edges take precedence
+        if let Some(node) = graph
+            .node_indices()
+            .find(|node| graph.edges(*node).next().is_none())
+        {
+            first_error.get_or_insert(GraphError::IsolatedVertex(node));
+        }
+
+        match first_error {
+            Some(error) => Err(error),
+            None => Ok(report),
+        }
+    }
+
+    /// Connects the points of the cluster with a Delaunay triangulation on the sphere, then prunes its longest edges.
+    ///
+    /// The convex hull of points on a sphere is their spherical Delaunay triangulation. The cluster only covers a small part of the sphere though,
+    /// so we add the center of the sphere as an extra point, and leave out the edges to it. That also gets rid of the hull's "bottom".
+    /// The hull is brute-forced (every triple of points is a face if no point lies outside its plane), which is fine for ~15 points.
+    fn triangulate_cluster(cluster: &[Vector3]) -> GraphTypedef {
+        let mut graph: GraphTypedef = Graph::new_undirected();
+        let node_indices: Vec<NodeIndex> = cluster.iter().map(|p| graph.add_node(*p)).collect();
+
+        if cluster.len() <= 3 {
+            // Too small to triangulate, just connect everything
+            for (i, j) in (0..cluster.len()).tuple_combinations() {
+                graph.add_edge(node_indices[i], node_indices[j], ());
+            }
+            return graph;
+        }
+
+        let center = Vector3::ZERO;
+        let hull_points = cluster.iter().copied().chain([center]).collect::<Vec<_>>();
+        let center_idx = cluster.len();
+        let epsilon = 1e-6
+            * cluster
+                .iter()
+                .map(|p| p.length_squared())
+                .fold(0.0, f32::max);
+
+        // BTreeSet, so the edges get added in a deterministic order
+        let mut edges = BTreeSet::new();
+        for (i, j, k) in (0..hull_points.len()).tuple_combinations() {
+            let (a, b, c) = (hull_points[i], hull_points[j], hull_points[k]);
+            let normal = (b - a).cross(c - a);
+            if normal.length_squared() <= epsilon * epsilon {
+                continue; // Degenerate triangle
+            }
+
+            let sides = hull_points
+                .iter()
+                .map(|p| normal.dot(*p - a))
+                .filter(|side| side.abs() > epsilon)
+                .collect::<Vec<_>>();
+            let is_face =
+                sides.iter().all(|side| *side > 0.0) || sides.iter().all(|side| *side < 0.0);
+
+            if is_face {
+                for (u, v) in [(i, j), (j, k), (i, k)] {
+                    if u != center_idx && v != center_idx {
+                        edges.insert((u, v));
+                    }
+                }
+            }
+        }
+
+        // Prune the longest edges, but never isolate a node
+        let mut edge_lengths = edges
+            .into_iter()
+            .map(|(u, v)| ((u, v), cluster[u].distance_to(cluster[v])))
+            .collect::<Vec<_>>();
+        let mut lengths = edge_lengths.iter().map(|(_, len)| *len).collect::<Vec<_>>();
+        lengths.sort_by_key(|len| OrderedFloat(*len));
+        let threshold = lengths
+            .get(
+                ((lengths.len() as f32 * TRIANGULATION_PRUNE_PERCENTILE) as usize)
+                    .min(lengths.len().saturating_sub(1)),
+            )
+            .copied()
+            .unwrap_or(f32::INFINITY);
+
+        let mut degrees = vec![0; cluster.len()];
+        for ((u, v), _) in &edge_lengths {
+            degrees[*u] += 1;
+            degrees[*v] += 1;
+        }
+
+        edge_lengths.sort_by_key(|((u, v), len)| (std::cmp::Reverse(OrderedFloat(*len)), *u, *v)); // Longest first
+        edge_lengths.retain(|((u, v), len)| {
+            let prune = *len > threshold && degrees[*u] > 1 && degrees[*v] > 1;
+            if prune {
+                degrees[*u] -= 1;
+                degrees[*v] -= 1;
+            }
+            !prune
+        });
+
+        edge_lengths.sort_by_key(|((u, v), _)| (*u, *v));
+        for ((u, v), _) in edge_lengths {
+            graph.add_edge(node_indices[u], node_indices[v], ());
+        }
+
+        graph
+    }
+
+    /// Which island every node belongs to, see `islands`.
+    pub fn node_islands(&self) -> BTreeMap<NodeIndex, usize> {
+        let mut node_islands = BTreeMap::new(); //BTreeMap is deterministic now
+        for (island_idx, island) in self.islands.iter().enumerate() {
+            for node in island {
+                let inserted = node_islands.insert(*node, island_idx);
+                assert_eq!(inserted, None);
+            }
+        }
+        node_islands
+    }
+
+    /// Builds a kd-tree over all nodes of the graph, where the item is the NodeIndex.
+    pub fn build_node_kdtree(graph: &GraphTypedef) -> NodeKdTree {
+        let mut kdtree = NodeKdTree::new();
+        for idx in graph.node_indices() {
+            let p = graph[idx];
+            kdtree.add(&[p.x, p.y, p.z], idx.index());
+        }
+        kdtree
+    }
+
+    /// Returns, for every node, the index of the island it belongs to.
+    fn island_membership(graph: &GraphTypedef, islands: &[Vec<NodeIndex>]) -> Vec<usize> {
+        let mut island_of = vec![0; graph.node_count()];
+        for (island_idx, island) in islands.iter().enumerate() {
+            for node in island {
+                island_of[node.index()] = island_idx;
+            }
+        }
+        island_of
+    }
+
+    /// Connects every pair of islands whose closest points are at most `max_distance` apart, see `IslandGraphTypedef`.
+    /// Node `i` of the result is island `i`. Deterministic: edges are added in (island, other island) order.
+    #[cfg_attr(feature = "enable-tracing", instrument(skip(graph, kdtree, islands)))]
+    fn build_island_graph(
+        graph: &GraphTypedef,
+        kdtree: &NodeKdTree,
+        islands: &[Vec<NodeIndex>],
+        max_distance: f32,
+    ) -> IslandGraphTypedef {
+        let island_of = Self::island_membership(graph, islands);
+
+        let mut island_graph = IslandGraphTypedef::with_capacity(islands.len(), 0);
+        for island_idx in 0..islands.len() {
+            island_graph.add_node(island_idx);
+        }
+
+        for (island_idx, island) in islands.iter().enumerate() {
+            // Only look at islands with a higher index, so every pair is only added once
+            let mut closest = BTreeMap::<usize, f32>::new();
+
+            for &a in island {
+                let p = graph[a];
+                for neigh in kdtree.within_unsorted::<SquaredEuclidean>(
+                    &[p.x, p.y, p.z],
+                    max_distance * max_distance,
+                ) {
+                    let other = island_of[neigh.item];
+                    if other > island_idx {
+                        let distance = neigh.distance.sqrt();
+                        closest
+                            .entry(other)
+                            .and_modify(|d| *d = d.min(distance))
+                            .or_insert(distance);
+                    }
+                }
+            }
+
+            for (other, distance) in closest {
+                island_graph.add_edge(NodeIndex::new(island_idx), NodeIndex::new(other), distance);
+            }
+        }
+
+        island_graph
+    }
+
+    /// Finds pairs `(distance, a, b)` where `a` is in the island and `b` is in another island, sorted by distance, then by NodeIndex.
+    /// Only looks at the nearest neighbors of every node, but always returns at least one pair (unless there's only one island).
+    fn closest_outside_pairs(
+        graph: &GraphTypedef,
+        kdtree: &NodeKdTree,
+        island: &[NodeIndex],
+        island_of: &[usize],
+    ) -> Vec<(OrderedFloat<f32>, NodeIndex, NodeIndex)> {
+        let island_idx = island_of[island[0].index()];
+
+        let mut query_size = ISLAND_QUERY_SIZE;
+        loop {
+            let mut pairs = island
+                .iter()
+                .flat_map(|&a| {
+                    let p = graph[a];
+                    kdtree
+                        .nearest_n::<SquaredEuclidean>(&[p.x, p.y, p.z], query_size)
+                        .into_iter()
+                        .filter(|neigh| island_of[neigh.item] != island_idx)
+                        .map(move |neigh| {
+                            (OrderedFloat(neigh.distance), a, NodeIndex::new(neigh.item))
+                        })
+                })
+                .collect::<Vec<_>>();
+
+            // If every node only sees its own island, look further (this only happens for big, isolated islands)
+            if !pairs.is_empty() || query_size >= graph.node_count() {
+                pairs.sort();
+                return pairs;
+            }
+            query_size *= 2;
+        }
+    }
+
+    /// Connects every island smaller than `min_size` to its nearest neighboring island, by adding an edge between the two closest points.
+    /// Repeats until no island is too small (or only one island is left).
+    /// Deterministic: islands are visited in index order, ties are broken by distance, then by NodeIndex.
+    /// Use `min_size = usize::MAX` to keep merging until the whole graph is connected.
+    #[cfg_attr(feature = "enable-tracing", instrument(skip(graph, kdtree)))]
+    fn merge_small_islands(graph: &mut GraphTypedef, kdtree: &NodeKdTree, min_size: usize) {
+        loop {
+            let islands = tarjan_scc(&*graph);
+
+            let small_island_count = islands.iter().filter(|i| i.len() < min_size).count();
+            if islands.len() <= 1 || small_island_count == 0 {
+                return;
+            }
+
+            tracing::debug!(small_island_count, "merging small islands");
+
+            let island_of = Self::island_membership(graph, &islands);
+
+            for island in &islands {
+                if island.len() >= min_size {
+                    continue;
+                }
+
+                let closest = Self::closest_outside_pairs(graph, kdtree, island, &island_of);
+
+                if let Some(&(_, a, b)) = closest.first() {
+                    // If the other island is small too, it may have already connected to us in this pass
+                    if !graph.contains_edge(a, b) {
+                        graph.add_edge(a, b, ());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds up to `bridges_per_island` of the shortest edges from every island to other islands (at most one bridge per other island).
+    /// Deterministic: islands are visited in index order, ties are broken by distance, then by NodeIndex.
+    #[cfg_attr(feature = "enable-tracing", instrument(skip(graph, kdtree)))]
+    fn bridge_islands(graph: &mut GraphTypedef, kdtree: &NodeKdTree, bridges_per_island: usize) {
+        let islands = tarjan_scc(&*graph);
+        if islands.len() <= 1 {
+            return;
+        }
+
+        let island_of = Self::island_membership(graph, &islands);
+
+        for island in &islands {
+            let mut bridged_islands = BTreeSet::new();
+
+            for (_, a, b) in Self::closest_outside_pairs(graph, kdtree, island, &island_of) {
+                if bridged_islands.len() >= bridges_per_island {
+                    break;
+                }
+
+                if bridged_islands.insert(island_of[b.index()]) && !graph.contains_edge(a, b) {
+                    graph.add_edge(a, b, ());
+                }
+            }
+        }
+    }
+
     /// Merges multiple undirected graphs together into one big graph.
     fn merge_undirected_graphs<N: Clone, E: Clone>(
         base: &mut UnGraph<N, E>,
@@ -284,7 +1357,9 @@ impl ConstellationGraph {
 
 impl PartialEq for ConstellationGraph {
     fn eq(&self, other: &Self) -> bool {
-        graph_eq(&self.graph, &other.graph) && self.islands == other.islands
+        graph_eq(&self.graph, &other.graph)
+            && self.islands == other.islands
+            && graph_eq(&self.island_graph, &other.island_graph)
     }
 }
 
@@ -312,3 +1387,32 @@ where
         .map(|e| (e.source(), e.target(), &e.weight));
     a_ns.eq(b_ns) && a_es.eq(b_es)
 }
+
+/// The mean of the points, projected back onto the sphere with the given `radius`.
+/// Returns `None` if there are no points, or if they cancel each other out (e.g. two antipodal points).
+fn spherical_mean(points: &[Vector3], radius: f32) -> Option<Vector3> {
+    let sum = points.iter().copied().fold(Vector3::ZERO, |acc, p| acc + p);
+    if sum.length_squared() < f32::EPSILON {
+        return None;
+    }
+    Some(sum.normalized() * radius)
+}
+
+/// Variance of the amount of points per cluster, for logging how uneven the clusters are.
+fn cluster_size_variance(clusters: &[(Vec<Vector3>, Vector3)]) -> f32 {
+    if clusters.is_empty() {
+        return 0.0;
+    }
+
+    let count = clusters.len() as f32;
+    let mean = clusters
+        .iter()
+        .map(|(members, _)| members.len() as f32)
+        .sum::<f32>()
+        / count;
+    clusters
+        .iter()
+        .map(|(members, _)| (members.len() as f32 - mean).powi(2))
+        .sum::<f32>()
+        / count
+}
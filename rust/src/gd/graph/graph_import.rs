@@ -0,0 +1,162 @@
+//! Loading user-provided point clouds, so artists can spell out shapes with the constellation (see `--points-file`).
+
+use std::{collections::BTreeSet, fmt, fs, path::Path};
+
+use godot::prelude::*;
+use serde::Deserialize;
+
+use crate::util::OrderedVector3;
+
+/// Need at least this many distinct points, otherwise we can't re-center and scale them.
+pub const MIN_POINT_COUNT: usize = 2;
+
+#[derive(Debug)]
+pub enum PointCloudError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Csv { line: usize, message: String },
+    NonFinite { index: usize },
+    TooFewPoints { count: usize },
+}
+
+impl fmt::Display for PointCloudError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PointCloudError::Io(err) => write!(f, "failed to read points file: {err}"),
+            PointCloudError::Json(err) => write!(f, "invalid JSON points file: {err}"),
+            PointCloudError::Csv { line, message } => {
+                write!(f, "invalid CSV points file at line {line}: {message}")
+            }
+            PointCloudError::NonFinite { index } => {
+                write!(f, "point {index} has a NaN or infinite coordinate")
+            }
+            PointCloudError::TooFewPoints { count } => write!(
+                f,
+                "need at least {MIN_POINT_COUNT} distinct points, got {count}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PointCloudError {}
+
+/// Either `[x, y, z]` or `{"x": x, "y": y, "z": z}`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonPoint {
+    Array([f32; 3]),
+    Object { x: f32, y: f32, z: f32 },
+}
+
+/// Loads points from a `.json` file (an array of points) or otherwise a CSV file (`x,y,z` per line).
+/// The points are returned as-is, `ConstellationGraph::from_points` takes care of normalizing them.
+pub fn load_points_file(path: &Path) -> Result<Vec<Vector3>, PointCloudError> {
+    let contents = fs::read_to_string(path).map_err(PointCloudError::Io)?;
+
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+    if is_json {
+        parse_points_json(&contents)
+    } else {
+        parse_points_csv(&contents)
+    }
+}
+
+pub fn parse_points_json(contents: &str) -> Result<Vec<Vector3>, PointCloudError> {
+    let points: Vec<JsonPoint> = serde_json::from_str(contents).map_err(PointCloudError::Json)?;
+
+    Ok(points
+        .into_iter()
+        .map(|p| match p {
+            JsonPoint::Array([x, y, z]) | JsonPoint::Object { x, y, z } => Vector3::new(x, y, z),
+        })
+        .collect())
+}
+
+/// Parses `x,y,z` per line. Empty lines, `#` comments and a header line (e.g. `x,y,z`) are skipped.
+pub fn parse_points_csv(contents: &str) -> Result<Vec<Vector3>, PointCloudError> {
+    let mut points = vec![];
+
+    for (line_idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+        let parsed = fields
+            .iter()
+            .map(|field| field.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>();
+
+        match (parsed, fields.len()) {
+            (Ok(coords), 3) => points.push(Vector3::new(coords[0], coords[1], coords[2])),
+            (Err(_), 3) if points.is_empty() => {} // Header
+            (Err(err), 3) => {
+                return Err(PointCloudError::Csv {
+                    line: line_idx + 1,
+                    message: err.to_string(),
+                });
+            }
+            (_, count) => {
+                return Err(PointCloudError::Csv {
+                    line: line_idx + 1,
+                    message: format!("expected 3 fields, got {count}"),
+                });
+            }
+        }
+    }
+
+    Ok(points)
+}
+
+/// Deduplicates the points (keeping the first occurrence), then centers them around the origin and scales them
+/// so the furthest point is exactly `radius` away, like the generated points. The note timing depends on the distances between points, so this matters.
+pub fn normalize_points(
+    points: Vec<Vector3>,
+    radius: f32,
+) -> Result<Vec<Vector3>, PointCloudError> {
+    if let Some(index) = points
+        .iter()
+        .position(|p| !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite()))
+    {
+        return Err(PointCloudError::NonFinite { index });
+    }
+
+    let mut seen = BTreeSet::new();
+    let points = points
+        .into_iter()
+        .filter(|p| seen.insert(OrderedVector3::from(*p)))
+        .collect::<Vec<_>>();
+
+    if points.len() < MIN_POINT_COUNT {
+        return Err(PointCloudError::TooFewPoints {
+            count: points.len(),
+        });
+    }
+
+    // Sum in f64 to avoid precision issues with large point clouds
+    let sum = points.iter().fold([0.0_f64; 3], |acc, p| {
+        [
+            acc[0] + p.x as f64,
+            acc[1] + p.y as f64,
+            acc[2] + p.z as f64,
+        ]
+    });
+    let count = points.len() as f64;
+    let center = Vector3::new(
+        (sum[0] / count) as f32,
+        (sum[1] / count) as f32,
+        (sum[2] / count) as f32,
+    );
+
+    let max_dist = points
+        .iter()
+        .map(|p| (*p - center).length())
+        .fold(0.0_f32, f32::max);
+    let scale = radius / max_dist; // max_dist > 0, since there are at least 2 distinct points
+
+    Ok(points.into_iter().map(|p| (p - center) * scale).collect())
+}
@@ -0,0 +1,31 @@
+//! Bookkeeping for tearing down a generated constellation at runtime, see `AudioGraph::regenerate`.
+
+use tokio_util::sync::CancellationToken;
+
+/// Owns the CancellationToken of the current constellation. Every task that belongs to a constellation
+/// (generation, intro animation, graph walks, metronome) should listen to `token()`, or a child token of it.
+/// Renewing cancels all of them at once, since a cancelled token can't be re-used.
+#[derive(Debug, Default)]
+pub struct ConstellationLifetime {
+    cancel: CancellationToken,
+    generation: u64,
+}
+
+impl ConstellationLifetime {
+    pub fn token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// How many times the lifetime was renewed, i.e. the amount of regenerations so far.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Cancels all tasks of the current constellation and returns the token for the next one.
+    pub fn renew(&mut self) -> CancellationToken {
+        self.cancel.cancel();
+        self.cancel = CancellationToken::new();
+        self.generation += 1;
+        self.token()
+    }
+}
@@ -1,179 +1,701 @@
 use std::{
-    collections::{BTreeMap, VecDeque},
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
+    ops::Range,
+    path::{Path, PathBuf},
+    pin::pin,
     rc::Rc,
+    sync::atomic::Ordering,
     time::{Duration, Instant},
 };
 
 use async_executor::LocalExecutor;
+use futures::future::join_all;
 use godot::{
     classes::{
-        AudioStreamPlayer, InputEvent, InputEventMouseButton, MeshInstance3D, MultiMesh,
-        MultiMeshInstance3D,
+        AudioStream, AudioStreamPlayer, Curve, FileAccess, InputEvent, InputEventMouseButton,
+        InputEventMouseMotion, InputEventWithModifiers, MeshInstance3D, MultiMesh,
+        MultiMeshInstance3D, ProjectSettings, file_access::ModeFlags,
     },
-    global::MouseButton,
+    global::{Error, MouseButton},
     prelude::*,
 };
 use itertools::Itertools as _;
-use petgraph::{Graph, Undirected, graph::NodeIndex, visit::EdgeRef as _};
+use kiddo::SquaredEuclidean;
+use petgraph::{
+    Graph, Undirected,
+    graph::{EdgeIndex, NodeIndex},
+    visit::EdgeRef as _,
+};
 use rand::{Rng, SeedableRng, seq::IndexedRandom};
 use rand_distr::{Distribution as _, Normal};
 use rand_xoshiro::Xoshiro256Plus;
+use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
+use time::OffsetDateTime;
+use tokio::select;
 use tokio_util::sync::CancellationToken;
 use tracing::{info_span, instrument};
 
 use crate::{
-    async_node::{AsyncNode, spawn_rayon_with_result},
-    flags::USE_METRONOME,
+    async_node::{AsyncNode, spawn_rayon_with_result, wait_for_next_frame},
+    chords::Chord,
+    flags::{EXTERNAL_CLOCK, PAUSED, USE_METRONOME, WALKS_PAUSED},
     format_gdobj,
     gd::{
-        autoload::{state_main::AudioState, state_tick::subscribe_to_ticks},
-        graph::graph_generate::ConstellationGraph,
-        node_main::AudioNode,
-        node_stream::Waveform,
+        autoload::{
+            cli::GAME_ARGS,
+            state_main::AudioState,
+            state_tick::{BpmMessage, TapTempo, Tick, set_bpm_internal, subscribe_to_ticks},
+        },
+        graph::{
+            graph_automation::{BpmAutomation, automation_bpm},
+            graph_box_select::{BoxSelection, nodes_in_rect},
+            graph_builder::ConstellationBuilder,
+            graph_edge_renderer::EdgeRenderer,
+            graph_edge_slots::EdgeSlots,
+            graph_export::GraphExportFormat,
+            graph_generate::{
+                Connectivity, ConstellationGraph, DEFAULT_LLOYD_ITERATIONS,
+                DEFAULT_MIN_ISLAND_SIZE, EdgeStrategy, EdgeStrategyComparison, GenerationError,
+                GenerationProgress, NodeKdTree, PointDistribution,
+            },
+            graph_import::load_points_file,
+            graph_lifetime::ConstellationLifetime,
+            graph_record::{WalkRecorder, WalkRecording},
+            graph_spheres::SphereBatch,
+            graph_stress::{STRESS_MEASURE_SECS, StressMeasurement},
+            graph_walk::{
+                ActiveWalks, ClickQueue, ClickQueueMode, DeadEndBehavior, EdgeEase,
+                LaunchQuantization, NodeOccupancy, RevisitPolicy, RhythmGrid, TrailState,
+                WalkConfig, WalkEnd, WalkId, WalkStats, WalkStrategy, WalkerOverflow,
+                deterministic_walk_rng, note_velocity, shortest_path, trail_lightening,
+                wait_for_launch,
+            },
+            graph_wave::bfs_layers,
+        },
+        node_main::{AudioNode, Envelope},
+        node_stream::{ACTIVE_STREAMS, Waveform},
     },
+    midi_sync::{MidiNoteAction, MidiNoteIndex, MidiNoteOn, incoming_midi_notes},
+    palette::NodeColors,
+    presets::GenerationParams,
     profile,
-    util::create_rng_from_seed_and_state,
+    session::BuildInfo,
+    stats_export::{STATS_DIR, StatsExport},
+    util::{color_to_html, create_rng_from_seed_and_state, ray_sphere_intersection, salted_seed},
 };
 
+thread_local! {
+    /// The AudioGraph in the scene right now (the focused one if there are several), see `AudioGraph::current`.
+    static CURRENT_AUDIOGRAPH: Cell<Option<InstanceId>> = const { Cell::new(None) };
+}
+
 pub type GraphTypedef = Graph<Vector3, (), Undirected>;
 
+const MAX_NEIGHBOR_COUNT: usize = 3; //3 is good, 2 is sparse, 1 is too sparse (4 is IRL max I think)
+const CONSTELLATION_RADIUS: f32 = 5.0; //Warning - if you change the radius it messes up the note timing!
+
+/// Every island gets a rest probability between 0 and this, see `AudioGraph::rest_probability`.
+const MAX_REST_PROBABILITY: f64 = 0.3;
+
+/// The tempo multipliers an island can get, with how likely they are, see `IslandData::tempo_mult`.
+const TEMPO_MULTS: [(f64, f64); 3] = [(0.5, 1.0), (1.0, 4.0), (2.0, 1.0)];
+
+/// How much the envelope of an island can differ from the default one, e.g. 0.2 means a release between 0.8 and 1.2.
+const ENVELOPE_VARIATION: f64 = 0.2;
+
+/// Longest attack of an island that's not a pad, in seconds for a duration of 1, so plucks stay plucky.
+const MAX_PLUCK_ATTACK: f64 = 0.03;
+
+/// How every node of an island sounds, see `AudioGraph::generate_island_data`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IslandData {
+    pub waveform: Waveform,
+    pub is_pad: bool,
+    /// The octave of the nodes varies a little around this
+    pub octave_base: f64,
+    /// See `AudioGraph::rest_probability`
+    pub rest_probability: f64,
+    /// Edges in this island take this many times as many ticks, so 0.5 plays at double speed and 2.0 at half speed.
+    /// See `island_edge_beats`.
+    pub tempo_mult: f64,
+    /// The envelope of every node, varies a little around `Envelope::PLUCK` or `Envelope::PAD`
+    pub envelope: Envelope,
+}
+
+/// How many islands have a key, see `AudioGraph::trigger_island`.
+pub const TRIGGER_ISLAND_KEYS: usize = 10;
+
+/// The islands biggest first (ties go to the lower island index), each with its anchor: its lowest NodeIndex.
+/// The anchor is where `AudioGraph::trigger_island` starts, so a key always plays the same node. Follows sculpting, like `IslandInfo::new`.
+pub fn island_anchors(node_islands: &BTreeMap<NodeIndex, usize>) -> Vec<(usize, NodeIndex)> {
+    let mut islands = BTreeMap::<usize, (usize, NodeIndex)>::new(); // island -> (size, anchor)
+    for (&node_idx, &island) in node_islands {
+        // Iterating in order, so the first node we see is the lowest one
+        islands.entry(island).or_insert((0, node_idx)).0 += 1;
+    }
+    islands
+        .into_iter()
+        .sorted_by_key(|&(island, (size, _))| (std::cmp::Reverse(size), island))
+        .map(|(island, (_, anchor))| (island, anchor))
+        .collect()
+}
+
+/// An island as `AudioGraph::get_island_info` shows it to GDScript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IslandInfo {
+    pub data: IslandData,
+    /// Sorted
+    pub nodes: Vec<NodeIndex>,
+    /// The average position of the nodes
+    pub centroid: Vector3,
+}
+
+impl IslandInfo {
+    /// None if there's no such island. Takes `node_islands` instead of `ConstellationGraph::islands`, so it follows sculpting.
+    pub fn new(
+        graph: &GraphTypedef,
+        island_data: &[IslandData],
+        node_islands: &BTreeMap<NodeIndex, usize>,
+        island: usize,
+    ) -> Option<Self> {
+        let data = *island_data.get(island)?;
+        let nodes = node_islands
+            .iter()
+            .filter(|&(_, &node_island)| node_island == island)
+            .map(|(&node_idx, _)| node_idx)
+            .collect_vec();
+        let centroid = if nodes.is_empty() {
+            Vector3::ZERO // Sculpting removed all of its nodes
+        } else {
+            nodes
+                .iter()
+                .map(|&node_idx| graph[node_idx])
+                .sum::<Vector3>()
+                / nodes.len() as f32
+        };
+        Some(Self {
+            data,
+            nodes,
+            centroid,
+        })
+    }
+
+    pub fn to_dictionary(&self) -> Dictionary {
+        dict! {
+            "waveform": self.data.waveform,
+            "is_pad": self.data.is_pad,
+            "octave_base": self.data.octave_base,
+            "node_count": self.nodes.len() as i64,
+            "nodes": self.nodes.iter().map(|node_idx| node_idx.index() as i32).collect::<PackedInt32Array>(),
+            "centroid": self.centroid,
+        }
+    }
+}
+
+/// How many AudioNodes are cancelling right now. AudioGraph shares it with its nodes, which count their own changes, see `AudioNode::set_cancelling`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellingCount(Rc<Cell<usize>>);
+
+impl CancellingCount {
+    pub fn get(&self) -> usize {
+        self.0.get()
+    }
+
+    /// Counts a node going from cancelling `was` to cancelling `now`.
+    pub fn update(&self, was: bool, now: bool) {
+        match (was, now) {
+            (false, true) => self.0.set(self.0.get() + 1),
+            (true, false) => self.0.set(self.0.get().saturating_sub(1)),
+            _ => {}
+        }
+    }
+}
+
+/// Whether AudioGraph reacts to input, see `AudioGraph::set_accepting_input`. Off while generating and during the intro.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AcceptingInput {
+    accepting: bool,
+    announced: bool,
+}
+
+impl AcceptingInput {
+    pub fn get(&self) -> bool {
+        self.accepting
+    }
+
+    /// Returns what to emit in `accepting_input_changed`: only changes, but the first call always, so the UI starts out right.
+    pub fn set(&mut self, accepting: bool) -> Option<bool> {
+        let changed = !self.announced || self.accepting != accepting;
+        self.accepting = accepting;
+        self.announced = true;
+        changed.then_some(accepting)
+    }
+}
+
+/// How long the hovered island has to stay the same before it gets highlighted, so sweeping the mouse over lots of nodes doesn't rewrite the MultiMesh every frame.
+pub const ISLAND_HOVER_DEBOUNCE: Duration = Duration::from_millis(80);
+
+/// How much lighter the edges of the hovered island get, see `AudioGraph::highlight_island`.
+const HIGHLIGHT_LIGHTENING: f64 = 0.3;
+
+/// Which island the mouse is on, and which one is highlighted. See `AudioGraph::highlight_island`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IslandHover {
+    hovered: Option<usize>,
+    hovered_since: Option<Instant>, //None once `update` handled it
+    highlighted: Option<usize>,
+}
+
+impl IslandHover {
+    /// The mouse went onto a node of `island` at `now`, or off a node if it's None.
+    pub fn hover(&mut self, island: Option<usize>, now: Instant) {
+        if island != self.hovered {
+            self.hovered = island;
+            self.hovered_since = Some(now);
+        }
+    }
+
+    /// The island to highlight instead (None to only take the highlight away), once the hover didn't change for `ISLAND_HOVER_DEBOUNCE`.
+    pub fn update(&mut self, now: Instant) -> Option<Option<usize>> {
+        let since = self.hovered_since?;
+        if now.duration_since(since) < ISLAND_HOVER_DEBOUNCE {
+            return None;
+        }
+        self.hovered_since = None;
+        if self.hovered == self.highlighted {
+            return None; // Went off and back on within the debounce
+        }
+        self.highlighted = self.hovered;
+        Some(self.highlighted)
+    }
+
+    pub fn highlighted(&self) -> Option<usize> {
+        self.highlighted
+    }
+}
+
+/// The islands that are soloed (ctrl-right-click), nodes on other islands are silent while there are any. See `AudioNode::muted`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SoloIslands {
+    islands: BTreeSet<usize>,
+}
+
+impl SoloIslands {
+    /// Returns true if the island is soloed now.
+    pub fn toggle(&mut self, island: usize) -> bool {
+        if self.islands.remove(&island) {
+            false
+        } else {
+            self.islands.insert(island)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.islands.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.islands.clear();
+    }
+
+    /// True if nodes on `island` (None if it's unknown) are silenced by the solo.
+    pub fn silences(&self, island: Option<usize>) -> bool {
+        !self.is_empty() && island.is_none_or(|island| !self.islands.contains(&island))
+    }
+}
+
+/// Whether a node on `island` ends up silent, `muted` being its own `AudioNode::muted`.
+pub fn is_effectively_muted(muted: bool, island: Option<usize>, solo: &SoloIslands) -> bool {
+    muted || solo.silences(island)
+}
+
+/// The envelope of an island: `Envelope::PLUCK` or `Envelope::PAD`, a little different, see `ENVELOPE_VARIATION`.
+pub fn island_envelope<R: Rng>(is_pad: bool, rng: &mut R) -> Envelope {
+    let vary = |value: f64, rng: &mut R| {
+        value * rng.random_range(1.0 - ENVELOPE_VARIATION..=1.0 + ENVELOPE_VARIATION)
+    };
+    if is_pad {
+        Envelope {
+            attack: vary(Envelope::PAD.attack, rng),
+            sustain_level: vary(Envelope::PAD.sustain_level, rng),
+            release: vary(Envelope::PAD.release, rng),
+        }
+    } else {
+        Envelope {
+            attack: rng.random_range(0.0..=MAX_PLUCK_ATTACK),
+            sustain_level: Envelope::PLUCK.sustain_level, // Already the loudest
+            release: vary(Envelope::PLUCK.release, rng),
+        }
+    }
+}
+
+/// How often a waveform occurs on islands, see `ConstellationStats`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WaveformCount {
+    pub waveform: Waveform,
+    /// Islands that aren't pads
+    pub islands: usize,
+    pub pad_islands: usize,
+}
+
+/// The numbers behind the Statistics tab, see `AudioGraph::generate_stats` and `AudioState::get_stats_dict`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConstellationStats {
+    pub chord: Chord,
+    pub semitone_offset: i32,
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub bridge_edge_count: usize,
+    /// In the order of `ConstellationGraph::islands`
+    pub island_sizes: Vec<usize>,
+    pub pad_island_count: usize,
+    /// In the order of `Waveform::iter`
+    pub waveform_counts: Vec<WaveformCount>,
+}
+
+impl ConstellationStats {
+    pub fn new(constellation: &ConstellationGraph, island_data: &[IslandData]) -> Self {
+        let waveform_counts = Waveform::iter()
+            .map(|waveform| {
+                let count = |is_pad: bool| {
+                    island_data
+                        .iter()
+                        .filter(|island| island.waveform == waveform && island.is_pad == is_pad)
+                        .count()
+                };
+                WaveformCount {
+                    waveform,
+                    islands: count(false),
+                    pad_islands: count(true),
+                }
+            })
+            .collect();
+
+        Self {
+            chord: constellation.chord,
+            semitone_offset: constellation.semitone_offset,
+            node_count: constellation.graph.node_count(),
+            edge_count: constellation.graph.edge_count(),
+            bridge_edge_count: constellation.bridge_edge_count,
+            island_sizes: constellation.islands.iter().map(Vec::len).collect(),
+            pad_island_count: island_data.iter().filter(|island| island.is_pad).count(),
+            waveform_counts,
+        }
+    }
+
+    pub fn island_count(&self) -> usize {
+        self.island_sizes.len()
+    }
+
+    /// Waveform counts are keyed by the waveform name, e.g. `{"Sine": 3, ...}`.
+    pub fn to_dictionary(&self) -> Dictionary {
+        let waveform_counts = |count: fn(&WaveformCount) -> usize| {
+            self.waveform_counts
+                .iter()
+                .map(|waveform_count| {
+                    (
+                        format!("{:?}", waveform_count.waveform),
+                        count(waveform_count) as i64,
+                    )
+                })
+                .collect::<Dictionary>()
+        };
+        dict! {
+            "chord": format!("{:?}", self.chord),
+            "semitone_offset": self.semitone_offset,
+            "node_count": self.node_count as i64,
+            "edge_count": self.edge_count as i64,
+            "bridge_edge_count": self.bridge_edge_count as i64,
+            "island_count": self.island_count() as i64,
+            "island_sizes": self.island_sizes.iter().map(|&size| size as i64).collect::<PackedInt64Array>(),
+            "pad_island_count": self.pad_island_count as i64,
+            "waveform_counts": waveform_counts(|count| count.islands),
+            "pad_waveform_counts": waveform_counts(|count| count.pad_islands),
+        }
+    }
+}
+
+/// Where `toggle_recording` saves to, and `replay_recording` loads from.
+const RECORDING_PATH: &str = "user://recording.json";
+
+/// Shift-clicking closer than this to an existing node counts as clicking the node, instead of adding a new point.
+const MIN_ADD_POINT_DISTANCE: f32 = 0.2;
+/// Compact the edges MultiMesh if it has at least this many free slots (and they're at least 25% of all slots).
+const MIN_COMPACT_FREE_SLOTS: usize = 64;
+
+/// Metronome volumes without an `accent_pattern`, see `metronome_volume`.
+const BAR_CLICK_VOLUME: f32 = 1.0;
+const BEAT_CLICK_VOLUME: f32 = 0.3;
+const TICK_CLICK_VOLUME: f32 = 0.1;
+
 #[derive(GodotClass, Debug)]
 #[class(init,base=Node3D)]
 pub struct AudioGraph {
     #[base]
     base: Base<Node3D>,
 
+    /// Only the default for `AudioState::num_points`, which is what generation uses.
     #[export]
     #[init(val = 20)]
     num_points: i32,
+    /// Mixed into the seed, so graphs in the same scene get different constellations from the same seed. 0 uses the seed as is.
+    #[export]
+    graph_salt: u32,
+    /// Whether key actions, clicks on empty space, MIDI notes and the metronome go to this graph, and its stats are shown.
+    /// Only one graph has it at a time, so with several graphs in a scene, turn it off on all but one. See `set_input_focus`.
+    #[export]
+    #[var(get, set = set_input_focus)]
+    #[init(val = true)]
+    input_focus: bool,
+
+    /// How walks choose the next node. Only affects walks that start afterwards.
+    #[export]
+    #[var(get, set = set_walk_strategy_enum)]
+    walk_strategy: WalkStrategy,
+
+    /// Whether walks avoid nodes they already played. Only affects walks that start afterwards.
+    #[export]
+    avoid_revisits: RevisitPolicy,
+    /// Only used if `max_walk_steps` isn't unlimited.
+    #[export]
+    dead_end_behavior: DeadEndBehavior,
+    /// Which note lengths edges get rounded to.
+    #[export]
+    rhythm_grid: RhythmGrid,
+    /// How many nodes a single click may play in total, 0 is unlimited.
+    #[export]
+    #[init(val = 64)]
+    max_walk_steps: u32,
+    /// How many branches a walk may split into at once, 0 is unlimited.
+    #[export]
+    #[init(val = 4)]
+    max_concurrent_branches: u32,
+    /// How much `WalkStrategy::DirectionPreserving` walks wander, 0 always goes the straightest way.
+    /// Higher temperatures pick less straight neighbors more often, see `softmax_choice`.
+    #[export(range = (0.0, 2.0, or_greater))]
+    direction_temperature: f64,
+    /// How many walks may run at once, 0 is unlimited.
+    #[export]
+    #[init(val = 8)]
+    max_active_walkers: u32,
+    /// What happens when clicking while `max_active_walkers` are running.
+    #[export]
+    walker_overflow: WalkerOverflow,
+    /// How many bars of metronome to play before a walk starts, when no other walks are running. 0 disables it.
+    #[export(range = (0.0, 2.0))]
+    count_in_bars: u32,
+    /// Whether left-clicking launches right away, or queues the node so walks launch one per bar.
+    #[export]
+    click_queue_mode: ClickQueueMode,
+    /// When walks and replays start after clicking.
+    #[export]
+    launch_quantization: LaunchQuantization,
+    /// How the progress indicators of walked edges move.
+    #[export]
+    edge_ease: EdgeEase,
+    /// How many beats a wave expands for, 0 is unlimited (the whole island).
+    #[export]
+    #[init(val = 8)]
+    max_wave_depth: i64,
+    /// How fast the nodes appear when a constellation gets generated, 0 skips the animation (like `--skip-intro`).
+    #[export]
+    #[init(val = 1000)]
+    intro_nodes_per_second: u32,
+    /// How many of the last edges a walk traversed light up behind it, 0 disables trails.
+    #[export]
+    #[init(val = 6)]
+    trail_length: u32,
+
+    /// At most this many nodes play when box selecting, to protect the mixer, see `end_box_selection`.
+    #[export]
+    #[init(val = 128)]
+    max_box_selection: u32,
+
+    /// How many nodes the stress test plays at once, see `start_stress_test`. `--stress` overrides it.
+    #[export]
+    #[init(val = 256)]
+    stress_note_count: u32,
+    /// Pads play this much longer in waves, so they end on a sustained chord.
+    #[export]
+    #[init(val = 3.0)]
+    wave_pad_duration_mult: f32,
+    /// Scales how often walks rest on every island, 0 disables rests.
+    #[export]
+    #[init(val = 1.0)]
+    rest_probability_mult: f64,
+    /// How much louder notes on junctions (nodes with 3 or more neighbors) are, see `note_velocity`.
+    #[export]
+    #[init(val = 0.25)]
+    junction_accent: f32,
+    /// How much louder notes after long edges are, see `note_velocity`.
+    #[export]
+    #[init(val = 0.15)]
+    long_edge_accent: f32,
+
+    #[export]
+    distribution: PointDistribution,
+    #[export]
+    edge_strategy: EdgeStrategy,
+    /// How the islands get connected to each other, see `Connectivity`.
+    #[export]
+    connectivity: Connectivity,
+    /// Only used if `connectivity` is `Bridged`.
+    #[export]
+    #[init(val = 1)]
+    bridges_per_island: u32,
+    /// Evens out the sizes of the Voronoi clusters, 0 disables it.
+    #[export]
+    #[init(val = DEFAULT_LLOYD_ITERATIONS as u32)]
+    lloyd_iterations: u32,
 
     #[init(node = "EdgesMultiMesh")]
     #[var]
     multimesh_instance: OnReady<Gd<MultiMeshInstance3D>>,
+    /// Renders the spheres of all AudioNodes, see `SphereBatch`.
+    #[init(node = "SpheresMultiMesh")]
+    spheres_instance: OnReady<Gd<MultiMeshInstance3D>>,
+
+    /// Metronome volume of every beat in a bar, the first one is the downbeat. Repeats if the bar has more beats than this.
+    /// Empty is the default: a loud downbeat and quieter other beats. Changes apply from the next bar.
+    #[export]
+    accent_pattern: PackedFloat32Array,
+    /// Metronome sound on the downbeat, the Metronome node's own sound if unset.
+    #[export]
+    metronome_bar_stream: Option<Gd<AudioStream>>,
+    /// Metronome sound on all other ticks, the Metronome node's own sound if unset.
+    #[export]
+    metronome_beat_stream: Option<Gd<AudioStream>>,
+
+    /// The BPM follows this curve while BPM automation is on, see `toggle_bpm_automation`.
+    /// Its whole domain is stretched over `automation_length_bars`, and its value range over `automation_min_bpm..=automation_max_bpm`.
+    #[export]
+    bpm_automation: Option<Gd<Curve>>,
+    /// How many bars the automation takes to go through the curve once, after which it loops.
+    #[export]
+    #[init(val = 32)]
+    automation_length_bars: u32,
+    #[export]
+    #[init(val = 80.0)]
+    automation_min_bpm: f64,
+    #[export]
+    #[init(val = 160.0)]
+    automation_max_bpm: f64,
+    /// Changing the BPM by hand (e.g. with the slider) pauses the automation for this many bars.
+    #[export]
+    #[init(val = 8)]
+    automation_pause_bars: u32,
+    /// Set while BPM automation is on.
+    bpm_automation_cancel: Option<CancellationToken>,
 
     #[init(node = "Metronome")]
     metronome: OnReady<Gd<AudioStreamPlayer>>,
+    /// The stream the Metronome node came with, for when the streams above aren't set.
+    metronome_default_stream: Option<Gd<AudioStream>>,
 
     #[init(node = "IndicatorLoading")]
     indicator_loading: OnReady<Gd<MeshInstance3D>>,
 
-    #[init(val = OnReady::manual())]
-    graph: OnReady<Rc<GraphTypedef>>,
+    graph: Option<Rc<GraphTypedef>>, //None while (re)generating
+    /// Never contains dead instances: a node that leaves the tree gets removed right away, see `on_audio_node_exiting`.
+    /// Walks hold on to a snapshot of it though, which can, so they look nodes up with `live_node`.
     #[init]
     graph_godot_nodes: Rc<BTreeMap<NodeIndex, Gd<AudioNode>>>, //Use BTreeMap instead of HashMap for determinism
+    constellation: Option<Rc<ConstellationGraph>>, //Only kept around for `export_graph`
+    island_data: Vec<IslandData>,
+    node_scene: Option<Gd<PackedScene>>,
 
-    executor: Option<Rc<LocalExecutor<'static>>>,
-    is_accepting_input: bool,
-    panic_button_cancel: CancellationToken,
+    // Sculpting state, see `add_point` and `remove_node`
+    node_islands: BTreeMap<NodeIndex, usize>,
+    node_kdtree: NodeKdTree, //Items are NodeIndex::index()
+    edge_slots: EdgeSlots,
 
-    bpm_taps: VecDeque<Instant>,
+    executor: Option<Rc<LocalExecutor<'static>>>,
+    accepting_input: AcceptingInput, //Only change it with `set_accepting_input`
+    lifetime: ConstellationLifetime,
+    panic_button_cancel: CancellationToken, //Child token of `lifetime`
+    active_walks: ActiveWalks,              //Their tokens are children of `panic_button_cancel`
+    recorder: Option<WalkRecorder>,         //Only set while recording
+    last_recording: Option<WalkRecording>,  //The one `toggle_recording` saved last
+    generation_warning: Option<String>,     //Shown above the stats, see `recolor_nodes`
+    stress_summary: Option<String>,         //Of the last stress test, shown above the stats too
+    stats_export_error: Option<String>, //Of the last failed `export_stats`, shown below the stats
+    solo_islands: SoloIslands,
+    node_pool: Vec<Gd<AudioNode>>, //Hidden AudioNodes to reuse instead of instantiating new ones, see `AudioNode::reset_for_reuse`
+    spheres: Rc<RefCell<SphereBatch>>, //Shared with every AudioNode
+    edges: Rc<RefCell<EdgeRenderer>>, //Shared with the edge tweens, see `lerp_edge`
+    edge_flush_calls: usize,       //Godot calls the last flush of `edges` took, shown in the stats
+    cancelling_count: CancellingCount, //Shared with every AudioNode too
+    path_start: Option<NodeIndex>, //The first node that was ctrl-clicked, see `walk_path`
+    walk_click_count: u32, //Walks started on this constellation, see `deterministic_walk_rng`
+    occupied_nodes: NodeOccupancy,
+    click_queue: ClickQueue, //Only used in `ClickQueueMode::Queued`
+    trail_colors: BTreeMap<EdgeIndex, (Color, usize)>, //The original colors of the edges on trails, and how many trails they're on
+    island_hover: IslandHover,
+    highlight_colors: BTreeMap<EdgeIndex, Color>, //The original colors of the edges of the highlighted island, see `highlight_island`
+    highlighted_nodes: Vec<NodeIndex>,
+    box_selection: Option<BoxSelection>, //While dragging over empty space with the left mouse button
+    midi_note_index: Option<MidiNoteIndex>, //None when nodes changed since it was built, see `play_midi_note`
+
+    bpm_taps: TapTempo,
 }
 
 #[godot_api]
 impl INode3D for AudioGraph {
     #[cfg_attr(feature = "enable-tracing",  instrument(fields(self = format_gdobj!(self.base()))))]
     fn ready(&mut self) {
-        self.start_metronome_task();
-
         //load() becomes much faster if you call it outside the async executor? Weird...
-        let node_scene = profile!(load::<PackedScene>("res://scenes/audio_node.tscn"));
-
-        self.spawn_local_task(
-            true,
-            info_span!("spawn_all_nodes"),
-            async move |mut this| {
-                this.bind_mut().indicator_loading.show();
-                reset_multimesh(this.bind().multimesh_instance.get_multimesh().unwrap());
-
-                let num_points = this.bind().num_points;
-
-                tracing::info!("audio graph ready, spawning {} points...", num_points);
-                let global_seed = AudioState::autoload().bind().get_seed();
-                let mut root_rng = create_rng_from_seed_and_state(0xA0A0BE63, global_seed);
-
-                let max_neighbor_count = 3; //3 is good, 2 is sparse, 1 is too sparse (4 is IRL max I think)
-                let radius = 5.0; //Warning - if you change the radius it messes up the note timing!
-                let mut point_rng = Xoshiro256Plus::from_rng(&mut root_rng); //Forks the rng, so nondeterminism caused by parallellism shouldn't influence the root rng
-
-                let constellation = spawn_rayon_with_result(move || {
-                    profile!(
-                        "generate_constellation_graph",
-                        ConstellationGraph::new(
-                            num_points as usize,
-                            radius,
-                            max_neighbor_count,
-                            &mut point_rng
-                        )
-                    )
-                })
-                .await
-                .expect("generate_points_and_edges panicked");
-
-                let island_count = constellation.islands.len();
-
-                let scc_assoc = {
-                    let mut scc_assoc = BTreeMap::<NodeIndex, usize>::default(); //BTreeMap is deterministic now
-                    for (island_idx, island) in constellation.islands.iter().enumerate() {
-                        for node in island {
-                            let inserted = scc_assoc.insert(*node, island_idx);
-                            assert_eq!(inserted, None);
-                        }
-                    }
-                    scc_assoc
-                };
-
-                let ConstellationGraph {
-                    ref graph,
-                    ref islands,
-                    ref chord,
-                    semitone_offset: semitone_offset_base,
-                } = constellation;
-
-                tracing::info!(?chord, semitone_offset_base); //Poisson has about ~250 islands, non-poisson about ~90
-                tracing::info!(
-                    island_count,
-                    smallest_island = ?islands.iter().map(|island| island.len()).min().unwrap(), //Should be >=2, I don't want loose points
-                    largest_island = ?islands.iter().map(|island| island.len()).max().unwrap()
-                );
-
-                ////////////////////
-
-                profile!("setup_multimesh", {
-                    let multi = this.bind().multimesh_instance.get_multimesh().unwrap();
-                    setup_multimesh(multi, graph);
-                });
-
-                this.bind_mut().indicator_loading.hide();
-
-                ////////////////////
-
-                let island_data = Self::generate_island_data(&constellation, &mut root_rng);
-                let stats = Self::generate_stats(&constellation, &island_data);
-                AudioState::autoload()
-                    .bind_mut()
-                    .set_graph_debug_str(stats.into());
-
-                let graph_godot_nodes = Self::play_intro_animation(
-                    &mut this,
-                    &constellation,
-                    &island_data,
-                    &scc_assoc,
-                    node_scene,
-                    &mut root_rng,
-                )
-                .await;
+        self.set_accepting_input(false); // Until the intro animation is done
+        self.node_scene = Some(profile!(load::<PackedScene>(
+            "res://scenes/audio_node.tscn"
+        )));
+        self.panic_button_cancel = self.lifetime.token().child_token();
+        self.metronome_default_stream = self.metronome.get_stream();
+        let mut state = AudioState::autoload();
+        self.walk_strategy = state.bind().settings().walk_strategy;
+        state.bind_mut().init_num_points(self.num_points as i64);
+        if self.input_focus {
+            self.set_input_focus(true);
+        } else if AudioGraph::current().is_none() {
+            CURRENT_AUDIOGRAPH.set(Some(self.base().instance_id())); // Something has to show up in the UI
+        }
 
-                this.bind_mut().is_accepting_input = true;
-                this.bind_mut().graph.init(Rc::new(constellation.graph));
-                this.bind_mut().graph_godot_nodes = Rc::new(graph_godot_nodes);
-            },
-        );
+        self.start_metronome_task();
+        self.start_click_queue_task();
+        self.spawn_generation_task(vec![]);
+
+        self.signals()
+            .node_hovered()
+            .connect_self(Self::on_node_hovered);
+        self.signals()
+            .node_unhovered()
+            .connect_self(Self::on_node_unhovered);
     }
 
     fn process(&mut self, _delta: f32) {
         self.tick_deferred();
+
+        if self.input_focus {
+            for note_on in incoming_midi_notes() {
+                self.play_midi_note(note_on);
+            }
+        }
+        // Not during the intro, which colors the edges itself
+        if self.accepting_input.get()
+            && let Some(island) = self.island_hover.update(Instant::now())
+        {
+            self.highlight_island(island);
+        }
+
+        //The AudioNodes process after us, so their changes show up a frame later
+        let mut multi = self.spheres_instance.get_multimesh().unwrap();
+        self.spheres.borrow_mut().flush(&mut multi);
+
+        // Ours change during the frame too (trails, tweens), the tweens that run after us show up a frame later
+        let mut multi = self.multimesh_instance.get_multimesh().unwrap();
+        self.edge_flush_calls = self.edges.borrow_mut().flush(&mut multi);
     }
 
     #[cfg_attr(feature = "enable-tracing", instrument(skip(self)))]
@@ -181,159 +703,1755 @@ impl INode3D for AudioGraph {
         //Note - while a textbox is selected, unhandled_input triggers anyway, but pressed() is always false.
         //So be sure to use is_action_pressed() instead of is_action().
 
-        if !self.is_accepting_input {
+        // Other graphs in the scene get the same events
+        if !self.accepting_input.get() || !self.input_focus {
             return;
         }
 
         if event.is_action_pressed("toggle_metronome") {
             USE_METRONOME.toggle();
         }
+        if event.is_action_pressed("toggle_bpm_automation") {
+            self.toggle_bpm_automation();
+        }
+        if event.is_action_pressed("cycle_color_mode") {
+            let mut state = AudioState::autoload();
+            let color_mode = state.bind().color_mode().next();
+            state.bind_mut().set_color_mode(color_mode);
+        }
+        if event.is_action_pressed("pause_walks") {
+            AudioState::autoload().bind_mut().toggle_walks_paused();
+        }
         if event.is_action_pressed("panic") {
             //Panic button
-            self.panic_button_cancel.cancel();
-            self.panic_button_cancel = CancellationToken::new(); //Create a new token, since we can't re-use it after cancelling
+            self.stop_walks();
+        }
+        if event.is_action_pressed("clear_all_cancelling") {
+            self.clear_all_cancelling();
         }
         if event.is_action_pressed("stress") {
-            //Performance stress test - play the first 256 notes simultaneously
-            //A modern PC should easily be able to handle this
-            let debug_play_nodes = 256;
-
-            let subnodes = self
-                .base_mut()
-                .get_children()
-                .iter_shared()
-                .filter_map(|node| node.try_cast::<AudioNode>().ok())
-                .collect::<Vec<_>>();
-
-            for mut node in &mut subnodes.into_iter().take(debug_play_nodes) {
-                let panic_button_cancel = self.panic_button_cancel.clone();
-                self.spawn_local_task(false, info_span!("play_debug"), async move |_this| {
-                    AudioNode::play(&mut node, 20.0, panic_button_cancel).await;
-                });
+            self.start_stress_test();
+        }
+        if event.is_action_pressed("export_stats") {
+            self.export_stats();
+        }
+        for key in (0..TRIGGER_ISLAND_KEYS).filter(|_| event.is_pressed()) {
+            if event.is_action_pressed(&format!("trigger_island_{}", key + 1)) {
+                // Shift works like shift-clicking the node
+                let wave = event
+                    .clone()
+                    .try_cast::<InputEventWithModifiers>()
+                    .is_ok_and(|event| event.is_shift_pressed());
+                self.trigger_island_anchor(key, wave);
             }
         }
         if event.is_action_pressed("bpm_tap") {
             self.perform_bpm_tap();
         }
+        if event.is_action_pressed("regenerate") {
+            self.regenerate();
+        }
+        if event.is_action_pressed("toggle_recording") {
+            self.toggle_recording();
+        }
+        if event.is_action_pressed("replay_recording") {
+            self.replay_recording(RECORDING_PATH.into());
+        }
+        if let Ok(motion) = event.clone().try_cast::<InputEventMouseMotion>()
+            && let Some(selection) = &mut self.box_selection
+        {
+            selection.end = motion.get_position();
+        }
+        if let Ok(mb) = event.try_cast::<InputEventMouseButton>()
+            && mb.get_button_index() == MouseButton::LEFT
+        {
+            if !mb.is_pressed() {
+                if let Some(selection) = self.box_selection.take() {
+                    self.end_box_selection(selection);
+                }
+            } else if mb.is_shift_pressed() {
+                self.add_point_at_screen_pos(mb.get_position());
+            } else if !mb.is_ctrl_pressed() {
+                // Cancelled again if it turns out a node was clicked, see `on_node_input_event`
+                self.box_selection = Some(BoxSelection::new(mb.get_position()));
+            }
+        }
     }
 }
 
 impl AudioGraph {
-    pub fn start_metronome_task(&mut self) {
-        tracing::info!("starting metronome task...");
-        self.spawn_local_task(false, info_span!("metronome"), async move |mut this| {
-            let mut ticks = subscribe_to_ticks();
+    /// Runs the whole pipeline: generating the constellation, spawning the nodes with the intro animation, and enabling input.
+    /// Stops as soon as the current `lifetime` gets cancelled. `old_nodes` are put in the pool first, see `regenerate`.
+    fn spawn_generation_task(&mut self, old_nodes: Vec<Gd<AudioNode>>) {
+        let node_scene = self
+            .node_scene
+            .clone()
+            .expect("spawn_generation_task called before ready");
+        let lifetime = self.lifetime.token();
 
-            tracing::info!("started metronome task");
+        self.spawn_local_task(
+            true,
+            info_span!("spawn_all_nodes"),
+            async move |mut this| {
+                if !old_nodes.is_empty() {
+                    // Give the cancelled tasks a frame to wind down, they may still touch the old nodes and edges
+                    wait_for_next_frame().await;
+
+                    // Instantiating (and freeing) thousands of nodes takes seconds, so reuse them instead.
+                    // They stay children of `this`, with their signals still connected
+                    tracing::info!("pooling {} old nodes", old_nodes.len());
+                    let mut this = this.bind_mut();
+                    for mut node in old_nodes {
+                        node.bind_mut().reset_for_reuse();
+                        this.node_pool.push(node);
+                    }
+                }
 
-            loop {
-                let tick = ticks.wait().await;
+                select! {
+                    biased;
+                    _ = lifetime.cancelled() => {
+                        tracing::info!("generation cancelled");
+                    }
+                    _ = Self::generate_and_spawn(&mut this, node_scene) => {}
+                }
+            },
+        );
+    }
+
+    async fn generate_and_spawn(this: &mut Gd<Self>, node_scene: Gd<PackedScene>) {
+        this.bind_mut().indicator_loading.show();
+        this.bind().edges.borrow_mut().clear();
+
+        let num_points = AudioState::autoload().bind().get_num_points();
+        let (progress_tx, progress_rx) = flume::unbounded::<GenerationProgress>();
+        let builder = ConstellationBuilder::new()
+            .with_num_points(num_points as usize)
+            .with_radius(CONSTELLATION_RADIUS)
+            .with_max_neighbors(MAX_NEIGHBOR_COUNT)
+            .with_distribution(this.bind().distribution)
+            .with_edge_strategy(this.bind().edge_strategy)
+            .with_topology(this.bind().connectivity)
+            .with_bridges_per_island(this.bind().bridges_per_island as usize)
+            .with_lloyd_iterations(this.bind().lloyd_iterations as usize)
+            .with_radial_jitter(GAME_ARGS.radial_jitter)
+            .with_progress(progress_tx);
+
+        tracing::info!("audio graph ready, spawning {} points...", num_points);
+        let graph_seed = this.bind().graph_seed();
+        let mut root_rng = create_rng_from_seed_and_state(0xA0A0BE63, graph_seed);
+
+        let mut point_rng = Xoshiro256Plus::from_rng(&mut root_rng); //Forks the rng, so nondeterminism caused by parallellism shouldn't influence the root rng
+
+        // Resolve res:// and user:// here, ProjectSettings can't be used from the rayon worker
+        let points_file = GAME_ARGS.points_file.as_ref().map(|path| {
+            PathBuf::from(
+                ProjectSettings::singleton()
+                    .globalize_path(path)
+                    .to_string(),
+            )
+        });
+
+        let constellation_future = spawn_rayon_with_result(move || {
+            let from_file = points_file.and_then(|path| {
+                tracing::info!("loading points from {path:?}");
+                load_points_file(&path)
+                    .and_then(|points| builder.clone().build_from_points(points, &mut point_rng))
+                    .inspect_err(|err| {
+                        tracing::error!(
+                            "can't use points file {path:?}, generating points instead: {err}"
+                        )
+                    })
+                    .ok()
+            });
+            if let Some(constellation) = from_file {
+                return (constellation, None);
+            }
+
+            let result = profile!(
+                "generate_constellation_graph",
+                builder.build(&mut point_rng)
+            );
+
+            // If we ran out of budget, just continue with the points we have, but make it visible in the stats
+            match result {
+                Ok(constellation) => (constellation, None),
+                Err(err @ GenerationError::Budget { .. }) => {
+                    tracing::warn!("{err}, continuing with a partial constellation");
+                    let warning = format!("[color=red]WARNING: {err}[/color]");
+                    let GenerationError::Budget { partial, .. } = err;
+                    (*partial, Some(warning))
+                }
+            }
+        });
+
+        // Forward the progress to AudioState while we wait for the rayon worker
+        let mut constellation_future = pin!(constellation_future);
+        let (constellation, generation_warning) = loop {
+            select! {
+                result = &mut constellation_future => {
+                    break result.expect("generate_points_and_edges panicked");
+                }
+                Ok(progress) = progress_rx.recv_async() => {
+                    if let Some(mut state) = AudioState::try_autoload() {
+                        state
+                            .bind_mut()
+                            .set_generation_progress(progress.phase.into(), progress.fraction as f64);
+                    }
+                }
+            }
+        };
+
+        if cfg!(debug_assertions) {
+            match constellation.validate() {
+                Ok(report) => tracing::debug!(?report, "graph is healthy"),
+                Err(error) => tracing::error!(%error, "generated graph is broken"),
+            }
+        }
+
+        let island_count = constellation.islands.len();
+
+        let scc_assoc = constellation.node_islands();
+
+        let ConstellationGraph {
+            ref graph,
+            ref islands,
+            ref chord,
+            semitone_offset: semitone_offset_base,
+            ..
+        } = constellation;
+
+        tracing::info!(?chord, semitone_offset_base); //Poisson has about ~250 islands, non-poisson about ~90
+        tracing::info!(
+            island_count,
+            smallest_island = ?islands.iter().map(|island| island.len()).min().unwrap(), //Should be >= DEFAULT_MIN_ISLAND_SIZE, I don't want loose points
+            largest_island = ?islands.iter().map(|island| island.len()).max().unwrap()
+        );
+
+        ////////////////////
+
+        let mut timings = constellation.timings.clone();
+        profile!(into timings, "setup_multimesh", {
+            // One instance per edge, where the slot of every edge is its EdgeIndex (see `EdgeSlots::new`)
+            let endpoints = graph
+                .edge_references()
+                .map(|edge| (graph[edge.source()], graph[edge.target()]));
+            this.bind().edges.borrow_mut().reset(endpoints);
+        });
+        this.bind_mut().edge_slots = EdgeSlots::new(graph.edge_count());
+
+        this.bind_mut().indicator_loading.hide();
+
+        ////////////////////
+
+        let island_data = Self::generate_island_data(&constellation, &mut root_rng);
+        let walk_config = this.bind().walk_config();
+        let stats = ConstellationStats::new(&constellation, &island_data);
+        let colors = AudioState::autoload().bind().node_colors();
+        let mut stats_str =
+            Self::generate_stats(&stats, &constellation, &island_data, &walk_config, &colors);
+        if let Some(warning) = &generation_warning {
+            stats_str = format!("{warning}\n{stats_str}");
+        }
+        this.bind_mut().generation_warning = generation_warning;
+        if let Some(mut state) = AudioState::try_autoload() {
+            let id = this.instance_id();
+            state
+                .bind_mut()
+                .set_graph_stats(id, stats_str.into(), stats);
+        }
+
+        // Includes the intro animation itself, so this mostly shows whether spawning keeps up with it
+        let graph_godot_nodes = profile!(
+            into timings,
+            "spawn_nodes",
+            Self::play_intro_animation(
+                this,
+                &constellation,
+                &island_data,
+                &scc_assoc,
+                node_scene,
+                &mut root_rng,
+            )
+            .await
+        );
+        if let Some(mut state) = AudioState::try_autoload() {
+            state.bind_mut().push_generation_timings(timings);
+        }
+
+        this.bind_mut().set_accepting_input(true);
+        this.bind_mut().node_kdtree = ConstellationGraph::build_node_kdtree(graph);
+        this.bind_mut().node_islands = scc_assoc;
+        this.bind_mut().graph = Some(Rc::new(constellation.graph.clone()));
+        // Nodes that left the tree during the intro weren't in `graph_godot_nodes` yet, so `on_audio_node_exiting` missed them
+        let mut graph_godot_nodes = graph_godot_nodes;
+        remove_dead_nodes(&mut graph_godot_nodes);
+        this.bind_mut().graph_godot_nodes = Rc::new(graph_godot_nodes);
+        this.bind_mut().constellation = Some(Rc::new(constellation));
+        this.bind_mut().island_data = island_data;
+        this.bind_mut().rebuild_midi_note_index();
+
+        if let Some(note_count) = GAME_ARGS.stress
+            && this.bind().lifetime.generation() == 0
+        {
+            this.bind_mut().stress_note_count = note_count;
+            this.bind_mut().run_stress_test(true);
+        }
+    }
+
+    /// Replays a recording from the next beat on. It counts as a walk, so it can be stopped like one.
+    pub fn replay(&mut self, recording: WalkRecording) -> Error {
+        let Some(graph) = self.graph.clone() else {
+            tracing::warn!("can't replay, the graph hasn't been generated yet");
+            return Error::ERR_UNCONFIGURED;
+        };
+        let Some(first_event) = recording.events.first() else {
+            tracing::warn!("can't replay, the recording is empty");
+            return Error::ERR_INVALID_DATA;
+        };
+        if recording.events.iter().any(|event| {
+            event.node.index() >= graph.node_count()
+                || event
+                    .edge
+                    .is_some_and(|edge| edge.index() >= graph.edge_count())
+        }) {
+            tracing::error!("can't replay, the recording doesn't fit this constellation");
+            return Error::ERR_INVALID_DATA;
+        }
+        if recording.seed != self.graph_seed() {
+            tracing::warn!("replaying a recording of another seed, it won't sound the same");
+        }
+
+        let Some((walk_id, walk_cancel)) = self.active_walks.try_start(
+            first_event.node,
+            &self.panic_button_cancel,
+            self.max_active_walkers as usize,
+            self.walker_overflow,
+        ) else {
+            tracing::info!("too many active walkers, not replaying");
+            return Error::ERR_BUSY;
+        };
+
+        let ticks = subscribe_to_ticks();
+        let launch_quantization = self.launch_quantization;
+        let schedule = recording.schedule();
+        let graph_godot_nodes = Rc::clone(&self.graph_godot_nodes);
+        self.spawn_local_task(false, info_span!("replay"), async move |mut this| {
+            Self::replay_schedule(
+                Gd::clone(&this),
+                &graph,
+                &graph_godot_nodes,
+                schedule,
+                ticks,
+                launch_quantization,
+                walk_cancel,
+            )
+            .await;
+            this.bind_mut().active_walks.finish(walk_id);
+        });
+        Error::OK
+    }
+
+    fn save_recording(recording: &WalkRecording, path: &str) {
+        let json = serde_json::to_string(recording).expect("failed to serialize recording");
+        let Some(mut file) = FileAccess::open(path, ModeFlags::WRITE) else {
+            tracing::error!(
+                "failed to open {path} for writing: {:?}",
+                FileAccess::get_open_error()
+            );
+            return;
+        };
+        file.store_string(&json);
+        file.close();
+        tracing::info!("saved recording to {path}");
+    }
+
+    /// Walks the shortest path from `from` to `to`, playing every node on the way. Both have to be on the same island.
+    pub fn walk_path(&mut self, from: NodeIndex, to: NodeIndex) {
+        let ticks = subscribe_to_ticks(); //Call this as early as possible, to improve synchronicity
+
+        let Some(mut to_node) = self.graph_godot_nodes.get(&to).cloned() else {
+            tracing::warn!("can't walk to node {to:?}, it was freed");
+            return;
+        };
+        let Some(graph) = self.graph.clone() else {
+            return;
+        };
+        let same_island = self.node_islands.get(&from) == self.node_islands.get(&to);
+        let Some(path) = same_island
+            .then(|| shortest_path(&graph, from, to))
+            .flatten()
+        else {
+            tracing::info!("can't walk from {from:?} to {to:?}, they're on different islands");
+            if let Some(from_node) = self.graph_godot_nodes.get(&from) {
+                Gd::clone(from_node).bind_mut().set_pending(false);
+            }
+            self.spawn_local_task(false, info_span!("flash_cancelling"), async move |_this| {
+                AudioNode::flash_cancelling(&mut to_node).await;
+            });
+            return;
+        };
+
+        let Some((walk_id, walk_cancel)) = self.active_walks.try_start(
+            from,
+            &self.panic_button_cancel,
+            self.max_active_walkers as usize,
+            self.walker_overflow,
+        ) else {
+            tracing::info!("too many active walkers, not walking the path");
+            if let Some(from_node) = self.graph_godot_nodes.get(&from) {
+                Gd::clone(from_node).bind_mut().set_pending(false);
+            }
+            self.spawn_local_task(false, info_span!("flash_rejected"), async move |_this| {
+                AudioNode::flash_rejected(&mut to_node).await;
+            });
+            return;
+        };
+
+        tracing::info!("walking {} nodes from {from:?} to {to:?}", path.len());
+        let graph_godot_nodes = Rc::clone(&self.graph_godot_nodes);
+        let rhythm_grid = self.rhythm_grid;
+        let launch_quantization = self.launch_quantization;
+        let recorder = self.recorder.as_ref().map(WalkRecorder::sender);
+        self.spawn_local_task(false, info_span!("path_walk"), async move |mut this| {
+            Self::path_walk(
+                Gd::clone(&this),
+                path,
+                graph,
+                graph_godot_nodes,
+                ticks,
+                (rhythm_grid, launch_quantization),
+                recorder,
+                walk_cancel,
+            )
+            .await;
+            this.bind_mut().active_walks.finish(walk_id);
+        });
+    }
+
+    /// Plays the island of `node_idx` as a wave expanding from it, see `AudioGraph::wave`.
+    pub fn wave_from(&mut self, node_idx: NodeIndex) {
+        let ticks = subscribe_to_ticks(); //Call this as early as possible, to improve synchronicity
+
+        let Some(graph) = self.graph.clone() else {
+            return;
+        };
+        let Some((walk_id, walk_cancel)) = self.active_walks.try_start(
+            node_idx,
+            &self.panic_button_cancel,
+            self.max_active_walkers as usize,
+            self.walker_overflow,
+        ) else {
+            tracing::info!("too many active walkers, not starting a wave");
+            if let Some(node) = self.graph_godot_nodes.get(&node_idx) {
+                let mut node = Gd::clone(node);
+                self.spawn_local_task(false, info_span!("flash_rejected"), async move |_this| {
+                    AudioNode::flash_rejected(&mut node).await;
+                });
+            }
+            return;
+        };
+
+        let island = self.node_islands.get(&node_idx);
+        let layers = bfs_layers(
+            &graph,
+            node_idx,
+            self.max_wave_depth.max(0) as usize,
+            |other_idx| self.node_islands.get(&other_idx) == island,
+        );
+        tracing::info!(
+            "starting a wave of {} layers on node {node_idx:?}",
+            layers.len()
+        );
+        let graph_godot_nodes = Rc::clone(&self.graph_godot_nodes);
+        let launch_quantization = self.launch_quantization;
+        let pad_duration_mult = self.wave_pad_duration_mult;
+        self.spawn_local_task(false, info_span!("wave"), async move |mut this| {
+            Self::wave(
+                Gd::clone(&this),
+                layers,
+                graph,
+                graph_godot_nodes,
+                ticks,
+                launch_quantization,
+                pad_duration_mult,
+                walk_cancel,
+            )
+            .await;
+            this.bind_mut().active_walks.finish(walk_id);
+        });
+    }
+
+    /// Stops all walks and playing notes.
+    fn stop_walks(&mut self) {
+        self.panic_button_cancel.cancel();
+        self.panic_button_cancel = self.lifetime.token().child_token(); //Create a new token, since we can't re-use it after cancelling
+        self.active_walks.clear();
+        self.restore_trails();
+        self.clear_click_queue();
+    }
+
+    /// Clears the queue of `ClickQueueMode::Queued`, and the pending indicators of the queued nodes.
+    fn clear_click_queue(&mut self) {
+        for node_idx in self.click_queue.clear() {
+            if let Some(node) = self.graph_godot_nodes.get(&node_idx) {
+                Gd::clone(node).bind_mut().set_pending(false);
+            }
+        }
+        self.emit_click_queue_changed();
+    }
+
+    fn set_accepting_input(&mut self, accepting: bool) {
+        if let Some(accepting) = self.accepting_input.set(accepting) {
+            tracing::debug!(accepting, "accepting input changed");
+            self.signals().accepting_input_changed().emit(accepting);
+        }
+    }
+
+    fn emit_click_queue_changed(&mut self) {
+        let queue_length = self.click_queue.len() as i64;
+        self.signals().click_queue_changed().emit(queue_length);
+    }
+
+    /// Sculpting: adds a point where the mouse ray hits the sphere of the constellation, unless that's right next to an existing node.
+    fn add_point_at_screen_pos(&mut self, screen_pos: Vector2) {
+        let Some(camera) = self
+            .base()
+            .get_viewport()
+            .and_then(|viewport| viewport.get_camera_3d())
+        else {
+            return;
+        };
+
+        let to_local = self.base().get_global_transform().affine_inverse();
+        let origin = to_local * camera.project_ray_origin(screen_pos);
+        let direction = (to_local.basis * camera.project_ray_normal(screen_pos)).normalized();
+
+        let Some(pos) = ray_sphere_intersection(origin, direction, CONSTELLATION_RADIUS) else {
+            return;
+        };
+
+        // Probably a shift-click on a node instead, the node handles that itself
+        if self.node_kdtree.size() > 0 {
+            let nearest = self
+                .node_kdtree
+                .nearest_one::<SquaredEuclidean>(&[pos.x, pos.y, pos.z]);
+            if nearest.distance < MIN_ADD_POINT_DISTANCE * MIN_ADD_POINT_DISTANCE {
+                return;
+            }
+        }
+
+        self.add_point(pos);
+    }
+
+    /// Moves the edges out of the free slots at the end of the MultiMesh, and shrinks it.
+    fn compact_edge_slots(&mut self) {
+        let moves = self.edge_slots.compact();
+        tracing::info!(moved = moves.len(), "compacting edges multimesh");
+
+        let mut edges = self.edges.borrow_mut();
+        for (from, to) in moves {
+            edges.copy(from, to);
+        }
+        edges.resize(self.edge_slots.slot_count());
+    }
+
+    /// The MultiMesh instance of the edge, see `EdgeSlots`.
+    pub fn edge_slot(&self, edge: EdgeIndex) -> usize {
+        self.edge_slots.slot(edge)
+    }
+
+    /// Where the edges are written to, the MultiMesh gets the changes at the end of `process`.
+    pub fn edge_renderer(&self) -> Rc<RefCell<EdgeRenderer>> {
+        self.edges.clone()
+    }
+
+    /// How many Godot calls writing the edges to the MultiMesh took last frame, see `EdgeRenderer::flush`.
+    pub fn edge_flush_calls(&self) -> usize {
+        self.edge_flush_calls
+    }
+
+    /// All the AudioNodes that are currently spawned, including those of an unfinished intro animation, but not those in the pool.
+    fn audio_node_children(&mut self) -> Vec<Gd<AudioNode>> {
+        self.base_mut()
+            .get_children()
+            .iter_shared()
+            .filter_map(|node| node.try_cast::<AudioNode>().ok())
+            .filter(|node| !node.bind().is_in_pool())
+            .collect()
+    }
+}
+
+#[godot_api]
+impl AudioGraph {
+    /// Emitted when a walk started by clicking ends. `stats` has `notes_played`, `islands_visited`, `total_beats` (in ticks),
+    /// `longest_run` (most notes a branch played in a row) and `end_reason` (`dead_end`, `cancelled` or `step_budget`).
+    #[signal]
+    fn walk_finished(walk_id: i64, stats: Dictionary);
+
+    /// Emitted every time a walk started by clicking (see `walk_finished`) reaches a node, e.g. for a camera that follows it.
+    /// With branches, that's every node any branch reaches. `position` is relative to this node, like `get_node_position`.
+    #[signal]
+    pub(super) fn walk_moved(walk_id: i64, node_idx: i64, position: Vector3);
+
+    /// Emitted when two walkers play the same node in the same tick, which plays an accent.
+    #[signal]
+    pub(super) fn walkers_collided(node_idx: i64);
+
+    /// Emitted when a node gets added to or removed from the queue of `ClickQueueMode::Queued`, or a queued walk launches.
+    #[signal]
+    fn click_queue_changed(queue_length: i64);
+
+    /// Emitted when AudioGraph starts or stops reacting to input (clicks, keys and actions like `start_walk_from`),
+    /// e.g. to grey out controls or show a loading indicator. See `is_accepting_input`.
+    #[signal]
+    fn accepting_input_changed(accepting: bool);
+
+    /// The mouse entered a node, e.g. to show `AudioNode::get_node_info` in a tooltip.
+    #[signal]
+    fn node_hovered(node_idx: i64);
+    #[signal]
+    fn node_unhovered(node_idx: i64);
+
+    /// Writes the constellation to `user://<path>` (or `path` itself if it's already a `user://` path), for debugging the island structure.
+    #[func]
+    pub fn export_graph(&self, path: GString, format: GraphExportFormat) -> Error {
+        let Some(constellation) = &self.constellation else {
+            tracing::warn!("can't export the graph, it hasn't been generated yet");
+            return Error::ERR_UNCONFIGURED;
+        };
+
+        let path = user_path(&path.to_string());
+        let contents = constellation.to_format(format, Some(&self.island_data));
+
+        let Some(mut file) = FileAccess::open(&path, ModeFlags::WRITE) else {
+            let err = FileAccess::get_open_error();
+            tracing::error!("failed to open {path} for writing: {err:?}");
+            return err;
+        };
+        file.store_string(&contents);
+        file.close();
+
+        tracing::info!(
+            "exported graph as {format:?} to {path} ({} bytes)",
+            contents.len()
+        );
+        Error::OK
+    }
+
+    /// Writes the stats of the constellation, with the seed, BPM, generation parameters and build, as JSON to `STATS_DIR`.
+    /// Returns the path of the file, or an empty string if it failed (which also shows up below the stats).
+    #[func]
+    pub fn export_stats(&mut self) -> GString {
+        let Some(constellation) = &self.constellation else {
+            tracing::warn!("can't export the stats, the constellation hasn't been generated yet");
+            return GString::new();
+        };
+        let (seed, bpm, num_points) = {
+            let state = AudioState::autoload();
+            let state = state.bind();
+            (state.get_seed(), state.get_bpm(), state.get_num_points())
+        };
+        let export = StatsExport {
+            build: BuildInfo::current(),
+            seed: seed as u64, // Bitwise conversion
+            graph_salt: self.graph_salt,
+            bpm,
+            generation: self.generation_params(num_points),
+            stats: ConstellationStats::new(constellation, &self.island_data),
+        };
+
+        let dir = ProjectSettings::singleton()
+            .globalize_path(STATS_DIR)
+            .to_string();
+        let result = export.save(Path::new(&dir), OffsetDateTime::now_utc());
+        let had_error = self.stats_export_error.take().is_some();
+        let path = match result {
+            Ok(path) => {
+                tracing::info!("exported stats to {}", path.display());
+                path.display().to_string().into()
+            }
+            Err(err) => {
+                tracing::error!("failed to export the stats to {dir}: {err}");
+                self.stats_export_error = Some(format!("Exporting stats failed: {err}"));
+                GString::new()
+            }
+        };
+        if had_error || self.stats_export_error.is_some() {
+            let colors = AudioState::autoload().bind().node_colors();
+            self.refresh_graph_debug_str(&colors);
+        }
+        path
+    }
+
+    /// Starts recording the walks that get started from now on, or stops recording and saves it to `RECORDING_PATH`.
+    #[func]
+    pub fn toggle_recording(&mut self) {
+        match self.recorder.take() {
+            None => {
+                self.recorder = Some(WalkRecorder::new(self.graph_seed()));
+                tracing::info!("started recording");
+            }
+            Some(recorder) => {
+                let recording = recorder.finish();
+                tracing::info!("stopped recording, {} events", recording.events.len());
+                Self::save_recording(&recording, RECORDING_PATH);
+                self.last_recording = Some(recording);
+            }
+        }
+    }
+
+    /// Loads a recording from `user://<path>` (see `toggle_recording`) and replays it from the next beat on.
+    #[func]
+    pub fn replay_recording(&mut self, path: GString) -> Error {
+        let path = user_path(&path.to_string());
+        if !FileAccess::file_exists(&path) {
+            tracing::warn!("there is no recording at {path}");
+            return Error::ERR_FILE_NOT_FOUND;
+        }
+
+        let json = FileAccess::get_file_as_string(&path).to_string();
+        match serde_json::from_str::<WalkRecording>(&json) {
+            Ok(recording) => self.replay(recording),
+            Err(err) => {
+                tracing::error!("failed to parse recording {path}: {err}");
+                Error::ERR_PARSE_ERROR
+            }
+        }
+    }
+
+    /// Throws away the current constellation and generates a new one (using the current seed of AudioState), without reloading the scene.
+    /// Cancels all running tasks (generation, intro animation, walks, notes and the metronome) and frees all AudioNodes.
+    #[func]
+    pub fn regenerate(&mut self) {
+        tracing::info!(
+            generation = self.lifetime.generation(),
+            "regenerating constellation"
+        );
+
+        // Stays false until the new intro animation is done
+        self.set_accepting_input(false);
+
+        self.lifetime.renew();
+        self.panic_button_cancel = self.lifetime.token().child_token();
+        self.active_walks.clear();
+        self.occupied_nodes.clear();
+        self.trail_colors.clear(); // The MultiMesh gets reset anyway
+        self.highlight_colors.clear();
+        self.highlighted_nodes.clear(); // Pooled nodes get reset anyway
+        self.island_hover = IslandHover::default();
+        self.click_queue.clear(); // The nodes get freed anyway
+        self.emit_click_queue_changed();
+        self.path_start = None;
+        self.walk_click_count = 0;
+        if self.recorder.is_some() {
+            // Recordings only make sense on the graph they were recorded on
+            self.toggle_recording();
+        }
+        self.last_recording = None;
+        self.stress_summary = None; // Measured on another constellation
+        self.solo_islands.clear(); // The new nodes aren't soloed out, and island indices change anyway
+
+        // Running walks may still hold a clone of these until they notice the cancellation
+        self.graph = None;
+        self.graph_godot_nodes = Rc::default();
+        self.spheres.borrow_mut().clear();
+        self.constellation = None;
+        self.island_data = vec![];
+        self.node_islands = BTreeMap::new();
+        self.node_kdtree = NodeKdTree::new();
+        self.edge_slots = EdgeSlots::default();
+        self.midi_note_index = None;
+
+        let old_nodes = self.audio_node_children();
+
+        self.start_metronome_task();
+        self.start_click_queue_task();
+        self.spawn_generation_task(old_nodes);
+    }
+
+    /// Sculpting: adds a node at `pos` (in local space), connected to its nearest neighbors. It joins the island of its nearest neighbor.
+    /// Stops all walks, since they would still be walking the old graph.
+    #[func]
+    pub fn add_point(&mut self, pos: Vector3) {
+        let (Some(constellation), Some(node_scene)) =
+            (self.constellation.clone(), self.node_scene.clone())
+        else {
+            tracing::warn!("can't add a point, the graph hasn't been generated yet");
+            return;
+        };
+        if self.graph.is_none() || self.node_kdtree.size() == 0 {
+            return;
+        }
+
+        self.stop_walks();
+        self.unhighlight_island(); // The edges are about to change
+        let mut this = self.to_gd();
+
+        let neighbors = self
+            .node_kdtree
+            .nearest_n::<SquaredEuclidean>(&[pos.x, pos.y, pos.z], MAX_NEIGHBOR_COUNT)
+            .into_iter()
+            .map(|neigh| NodeIndex::new(neigh.item))
+            .collect::<Vec<_>>();
+        let island = self.node_islands[&neighbors[0]];
+
+        let graph = Rc::make_mut(self.graph.as_mut().unwrap());
+        let idx = graph.add_node(pos);
+        self.node_kdtree.add(&[pos.x, pos.y, pos.z], idx.index());
+        self.node_islands.insert(idx, island);
+
+        let node_rng = Xoshiro256Plus::from_rng(&mut rand::rng()); //Sculpting is nondeterministic anyway
+        let pooled = self.node_pool.pop();
+        let mut audionode = Self::spawn_audio_node(
+            &mut this,
+            pooled,
+            &node_scene,
+            &constellation,
+            island,
+            self.island_data[island],
+            idx,
+            pos,
+            node_rng,
+        );
+        audionode
+            .bind_mut()
+            .set_soloed_out(self.solo_islands.silences(Some(island)));
+        let color = audionode.bind().get_color();
+        Rc::make_mut(&mut self.graph_godot_nodes).insert(idx, audionode);
+        self.midi_note_index = None;
+
+        let mut edges = self.edges.borrow_mut();
+        for neighbor in neighbors {
+            let edge = graph.add_edge(idx, neighbor, ());
+            let slot = self.edge_slots.insert(edge);
+            edges.set_edge(slot, pos, graph[neighbor]);
+            edges.set_color(slot, color);
+        }
+        drop(edges);
+
+        for node_idx in graph.neighbors(idx).chain([idx]).collect_vec() {
+            self.update_node_graph_info(node_idx);
+        }
+        tracing::info!(?idx, island, "added point at {pos}");
+    }
+
+    /// Sculpting: removes the node and its edges. The last node takes over its index (that's how petgraph removes nodes).
+    /// Stops all walks, since they would still be walking the old graph.
+    #[func]
+    pub fn remove_node(&mut self, node_idx: u32) {
+        let idx = NodeIndex::new(node_idx as usize);
+        if self
+            .graph
+            .as_ref()
+            .is_none_or(|graph| idx.index() >= graph.node_count())
+        {
+            tracing::warn!("can't remove node {idx:?}, it doesn't exist");
+            return;
+        }
+
+        self.stop_walks();
+        self.unhighlight_island(); // Edge and node indices are about to shift
+
+        let graph = Rc::make_mut(self.graph.as_mut().unwrap());
+        let neighbors = graph.neighbors(idx).collect_vec();
+
+        // Remove the edges one by one, so the slots can follow petgraph moving the last edge into the removed index
+        while let Some(edge) = graph.edges(idx).next().map(|edge| edge.id()) {
+            graph.remove_edge(edge);
+            let slot = self.edge_slots.remove(edge);
+            self.edges.borrow_mut().hide(slot);
+        }
+
+        let last = NodeIndex::new(graph.node_count() - 1);
+        let pos = graph[idx];
+        self.node_kdtree.remove(&[pos.x, pos.y, pos.z], idx.index());
+        if last != idx {
+            let last_pos = graph[last];
+            self.node_kdtree
+                .remove(&[last_pos.x, last_pos.y, last_pos.z], last.index());
+            self.node_kdtree
+                .add(&[last_pos.x, last_pos.y, last_pos.z], idx.index());
+        }
+        graph.remove_node(idx);
+        self.spheres.borrow_mut().remove(idx.index());
+
+        let nodes = Rc::make_mut(&mut self.graph_godot_nodes);
+        let removed = nodes.remove(&idx);
+        self.node_islands.remove(&idx);
+        if last != idx {
+            let mut moved = nodes.remove(&last).unwrap();
+            moved
+                .bind_mut()
+                .set_node_idx(idx.index().try_into().unwrap());
+            nodes.insert(idx, moved);
+
+            let island = self.node_islands.remove(&last).unwrap();
+            self.node_islands.insert(idx, island);
+        }
+        if let Some(mut removed) = removed {
+            removed.bind_mut().reset_for_reuse();
+            self.node_pool.push(removed);
+        }
+        self.midi_note_index = None;
+
+        for neighbor in neighbors {
+            // The last node took over the index of the removed one
+            self.update_node_graph_info(if neighbor == last { idx } else { neighbor });
+        }
+        tracing::info!(?idx, "removed node");
+
+        let free_count = self.edge_slots.free_count();
+        if free_count >= MIN_COMPACT_FREE_SLOTS && free_count * 4 >= self.edge_slots.slot_count() {
+            self.compact_edge_slots();
+        }
+    }
+
+    /// Gives the node the next waveform (see `AudioNode::cycle_waveform`), and its edges its new color.
+    fn cycle_node_waveform(&mut self, node: &mut Gd<AudioNode>, node_index: NodeIndex) {
+        let color = node.bind_mut().cycle_waveform();
+        tracing::info!(waveform = ?node.bind().get_waveform(), "cycled waveform of node {node_index:?}");
+        self.recolor_node_edges(node_index, color);
+    }
+
+    /// Makes the node cancel the next walk that gets there (or not anymore), see `AudioNode::cancelling`.
+    fn toggle_node_cancelling(&mut self, node: &mut Gd<AudioNode>, node_index: NodeIndex) {
+        node.bind_mut().toggle_cancelling();
+        tracing::info!(
+            cancelling = node.bind().get_cancelling(),
+            count = self.cancelling_count.get(),
+            "toggled cancelling of node {node_index:?}"
+        );
+    }
+
+    /// Shifts the node `by` octaves (see `AudioNode::shift_octave`), and gives its edges its new color.
+    fn shift_node_octave(&mut self, node: &mut Gd<AudioNode>, node_index: NodeIndex, by: i32) {
+        let Some(color) = node.bind_mut().shift_octave(by) else {
+            return;
+        };
+        self.midi_note_index = None; // It plays another note now
+        tracing::info!(
+            octave = node.bind().get_octave(),
+            "shifted octave of node {node_index:?}"
+        );
+        self.recolor_node_edges(node_index, color);
+    }
+
+    /// Recolors all edges of `node_index` with `color`. Edges on a trail get it once they're off the trail.
+    fn recolor_node_edges(&mut self, node_index: NodeIndex, color: Color) {
+        let Some(graph) = self.graph.clone() else {
+            return;
+        };
+        let color = Color { a: 1.0, ..color }; // Only the node fades, like when it was spawned
+        for edge in graph.edges(node_index) {
+            match self.trail_colors.get_mut(&edge.id()) {
+                Some((trail_color, _)) => {
+                    *trail_color = color;
+                    if let Some(highlight_color) = self.highlight_colors.get_mut(&edge.id()) {
+                        *highlight_color = color;
+                    }
+                }
+                None => self.set_edge_color(edge.id(), color),
+            }
+        }
+    }
+
+    /// AudioGraph never takes its nodes out of the tree itself (see `node_pool`), so a node that leaves is about to be freed.
+    /// Forgets it, and blacks out its sphere and edges. The graph keeps the node, so walks that get there end, see `live_node`.
+    fn on_audio_node_exiting(&mut self, node: Gd<AudioNode>) {
+        self.node_pool.retain(|pooled| *pooled != node);
+        self.cancelling_count
+            .update(node.bind().get_cancelling(), false);
+        let idx = NodeIndex::new(node.bind().get_node_idx() as usize);
+        if !forget_node(&mut self.graph_godot_nodes, idx, &node) {
+            return; // Pooled, or not spawned yet
+        }
+        tracing::debug!(?idx, "node is leaving the tree, forgot it");
+        self.midi_note_index = None;
+
+        self.spheres.borrow_mut().hide(idx.index());
+        let Some(graph) = self.graph.clone() else {
+            return;
+        };
+        let mut edges = self.edges.borrow_mut();
+        for edge in graph.edges(idx) {
+            self.trail_colors.remove(&edge.id()); // Otherwise the trail restores the color
+            self.highlight_colors.remove(&edge.id()); // Same for the highlight
+            edges.set_color(self.edge_slots.slot(edge.id()), Color::BLACK);
+        }
+    }
+
+    /// Tells the AudioNode its island and degree again, after sculpting changed them. See `AudioNode::set_graph_info`.
+    fn update_node_graph_info(&self, node_idx: NodeIndex) {
+        let (Some(graph), Some(node)) = (&self.graph, self.graph_godot_nodes.get(&node_idx)) else {
+            return;
+        };
+        Gd::clone(node).bind_mut().set_graph_info(
+            self.node_islands.get(&node_idx).copied(),
+            graph.edges(node_idx).count(),
+        );
+    }
+
+    pub fn walk_config(&self) -> WalkConfig {
+        WalkConfig {
+            strategy: self.walk_strategy,
+            revisits: self.avoid_revisits,
+            dead_ends: self.dead_end_behavior,
+            max_steps: self.max_walk_steps as usize,
+            max_concurrent_branches: self.max_concurrent_branches as usize,
+            direction_temperature: self.direction_temperature,
+            rhythm_grid: self.rhythm_grid,
+        }
+    }
+
+    pub fn edge_ease(&self) -> EdgeEase {
+        self.edge_ease
+    }
+
+    pub fn walk_strategy(&self) -> WalkStrategy {
+        self.walk_strategy
+    }
+
+    /// The AudioGraph in the scene right now (the focused one, see `input_focus`), None while the audio world is reloading.
+    /// Only works on the main thread.
+    pub fn current() -> Option<Gd<Self>> {
+        let instance_id = CURRENT_AUDIOGRAPH.get()?;
+        Gd::try_from_instance_id(instance_id).ok()
+    }
+
+    /// The global seed of AudioState with `graph_salt` mixed in, see `salted_seed`.
+    pub fn graph_seed(&self) -> i64 {
+        salted_seed(AudioState::autoload().bind().get_seed(), self.graph_salt)
+    }
+
+    /// `num_points` comes from AudioState, see `AudioState::num_points`.
+    pub fn generation_params(&self, num_points: i64) -> GenerationParams {
+        GenerationParams {
+            num_points,
+            distribution: self.distribution,
+            edge_strategy: self.edge_strategy,
+            connectivity: self.connectivity,
+            bridges_per_island: self.bridges_per_island,
+            lloyd_iterations: self.lloyd_iterations,
+        }
+    }
+
+    /// Applies a preset loaded by `AudioState::load_preset` (or the part of a session that `AudioState::import_session` could read).
+    /// Call `regenerate` afterwards to see the new constellation.
+    /// Doesn't go through `set_walk_strategy_enum`, AudioState already updated its settings (and `num_points`).
+    pub fn apply_preset(
+        &mut self,
+        generation: Option<&GenerationParams>,
+        walk_strategy: Option<WalkStrategy>,
+    ) {
+        if let Some(generation) = generation {
+            self.distribution = generation.distribution;
+            self.edge_strategy = generation.edge_strategy;
+            self.connectivity = generation.connectivity;
+            self.bridges_per_island = generation.bridges_per_island;
+            self.lloyd_iterations = generation.lloyd_iterations;
+        }
+        if let Some(walk_strategy) = walk_strategy {
+            self.walk_strategy = walk_strategy;
+        }
+    }
+
+    /// The recording `toggle_recording` saved last, None after regenerating.
+    pub fn last_recording(&self) -> Option<&WalkRecording> {
+        self.last_recording.as_ref()
+    }
+
+    #[func]
+    pub fn set_walk_strategy_enum(&mut self, walk_strategy: WalkStrategy) {
+        tracing::info!(?walk_strategy, "walk strategy changed");
+        self.walk_strategy = walk_strategy;
+        if let Some(mut state) = AudioState::try_autoload() {
+            state
+                .bind_mut()
+                .update_settings(|settings| settings.walk_strategy = walk_strategy);
+        }
+    }
+
+    /// Sets the walk strategy by its index in `WalkStrategy`, e.g. from a dropdown.
+    #[func]
+    pub fn set_walk_strategy(&mut self, index: i64) {
+        match WalkStrategy::try_from_godot(index) {
+            Ok(walk_strategy) => self.set_walk_strategy_enum(walk_strategy),
+            Err(err) => tracing::warn!("invalid walk strategy {index}: {err}"),
+        }
+    }
+
+    /// Stops a single walk and its notes, unlike the panic button. Returns false if the walk already finished.
+    #[func]
+    pub fn stop_walk(&mut self, id: i64) -> bool {
+        let Ok(id) = WalkId::try_from(id) else {
+            return false;
+        };
+        self.active_walks.stop(id)
+    }
+
+    /// Starts a walk on node `node_idx` (0 until `get_node_count`), like left-clicking it in `ClickQueueMode::Immediate`.
+    /// It ignores `click_queue_mode` and always launches on the next `launch_quantization` boundary, since it has to return the id of the walk.
+    /// Node indices are stable for a given seed, until nodes get added or removed.
+    /// Returns the id of the walk (see `stop_walk`), or -1 if the index is invalid (see `script_node`), input is disabled (e.g. while generating) or there are too many walkers.
+    #[func]
+    pub fn start_walk_from(&mut self, node_idx: i64) -> i64 {
+        if !self.accepting_input.get() {
+            return -1;
+        }
+        let Some((node_index, node)) = script_node(&self.graph_godot_nodes, node_idx) else {
+            tracing::warn!("can't start a walk on node {node_idx}, it's invalid or freed");
+            return -1;
+        };
+        self.start_walk(node, node_index, self.launch_quantization)
+            .map_or(-1, |walk_id| walk_id as i64)
+    }
+
+    /// Like `start_walk_from`, on a random node. Returns -1 if there are no nodes.
+    #[func]
+    pub fn start_random_walk(&mut self) -> i64 {
+        let node_indices = self.graph_godot_nodes.keys().copied().collect::<Vec<_>>();
+        let Some(node_index) = node_indices.choose(&mut rand::rng()) else {
+            return -1;
+        };
+        self.start_walk_from(node_index.index() as i64)
+    }
+
+    /// Plays the anchor of the `island_idx`th biggest island (see `island_anchors`) like clicking it. Returns false if there's no such island.
+    /// Keys 1 to 0 do this for the first 10 islands.
+    #[func]
+    pub fn trigger_island(&mut self, island_idx: i64) -> bool {
+        self.accepting_input.get()
+            && usize::try_from(island_idx)
+                .is_ok_and(|island_idx| self.trigger_island_anchor(island_idx, false))
+    }
+
+    /// Like `trigger_island`, but starts a wave instead of a walk (like shift-clicking).
+    #[func]
+    pub fn trigger_island_wave(&mut self, island_idx: i64) -> bool {
+        self.accepting_input.get()
+            && usize::try_from(island_idx)
+                .is_ok_and(|island_idx| self.trigger_island_anchor(island_idx, true))
+    }
+
+    /// How many nodes there are, 0 while generating.
+    #[func]
+    pub fn get_node_count(&self) -> i64 {
+        self.graph_godot_nodes.len() as i64
+    }
+
+    /// The position of node `node_idx` (see `start_walk_from`), relative to this node. Zero if the index is invalid.
+    #[func]
+    pub fn get_node_position(&self, node_idx: i64) -> Vector3 {
+        match (&self.graph, script_node(&self.graph_godot_nodes, node_idx)) {
+            (Some(graph), Some((node_index, _))) => graph[node_index],
+            _ => Vector3::ZERO,
+        }
+    }
+
+    /// The position of the node the walk reached last (see `walk_moved`), relative to this node.
+    /// Zero if there's no such walk, see `get_active_walk_ids`.
+    #[func]
+    pub fn get_walk_head_position(&self, walk_id: i64) -> Vector3 {
+        let head = u64::try_from(walk_id)
+            .ok()
+            .and_then(|walk_id| self.active_walks.head(walk_id));
+        match (&self.graph, head) {
+            (Some(graph), Some(head)) if head.index() < graph.node_count() => graph[head],
+            _ => Vector3::ZERO,
+        }
+    }
+
+    /// The ids of the walks that are still running, oldest first.
+    #[func]
+    pub fn get_active_walk_ids(&self) -> PackedInt64Array {
+        self.active_walks.ids().map(|id| id as i64).collect()
+    }
+
+    /// Returns the indices of the islands that are spatially adjacent to `island_idx`, see `ConstellationGraph::island_graph`.
+    /// Empty if the graph hasn't been generated yet or `island_idx` is out of range.
+    #[func]
+    pub fn get_adjacent_islands(&self, island_idx: i32) -> PackedInt32Array {
+        let Some(constellation) = &self.constellation else {
+            return PackedInt32Array::new();
+        };
+        let Ok(island_idx) = usize::try_from(island_idx) else {
+            return PackedInt32Array::new();
+        };
+        if island_idx >= constellation.island_graph.node_count() {
+            return PackedInt32Array::new();
+        }
+
+        constellation
+            .island_graph
+            .neighbors(NodeIndex::new(island_idx))
+            .map(|neighbor| constellation.island_graph[neighbor] as i32)
+            .sorted()
+            .collect()
+    }
+
+    pub fn start_metronome_task(&mut self) {
+        tracing::info!("starting metronome task...");
+        let lifetime = self.lifetime.token();
+        self.spawn_local_task(false, info_span!("metronome"), async move |mut this| {
+            let mut ticks = subscribe_to_ticks();
+
+            tracing::info!("started metronome task");
+
+            // Only read on the downbeat, so a bar doesn't get half of the old pattern and half of the new one
+            let mut accent_pattern = this.bind().accent_pattern.to_vec();
+            loop {
+                let tick = select! {
+                    biased;
+                    _ = lifetime.cancelled() => {
+                        tracing::info!("metronome task cancelled");
+                        return;
+                    }
+                    tick = ticks.wait() => tick,
+                };
+
+                if tick.is_bar() {
+                    accent_pattern = this.bind().accent_pattern.to_vec();
+                }
+                // One click per beat, even with several graphs
+                if USE_METRONOME.get() && !PAUSED.get() && this.bind().input_focus {
+                    this.bind_mut().play_metronome_click(&tick, &accent_pattern);
+                }
+            }
+        });
+    }
+
+    /// Plays the metronome sound for `tick` once, also used for count-ins. See `metronome_volume`.
+    pub fn play_metronome_click(&mut self, tick: &Tick, accent_pattern: &[f32]) {
+        let stream = if tick.is_bar() {
+            &self.metronome_bar_stream
+        } else {
+            &self.metronome_beat_stream
+        };
+        let stream = stream.as_ref().or(self.metronome_default_stream.as_ref());
+        if self.metronome.get_stream().as_ref() != stream {
+            self.metronome.set_stream(stream);
+        }
+        self.metronome
+            .set_volume_linear(metronome_volume(tick, accent_pattern));
+        self.metronome.play();
+    }
+
+    /// Launches the walks queued in `ClickQueueMode::Queued`, one on every bar.
+    fn start_click_queue_task(&mut self) {
+        let lifetime = self.lifetime.token();
+        self.spawn_local_task(false, info_span!("click_queue"), async move |mut this| {
+            let mut ticks = subscribe_to_ticks();
+
+            // Start on the last tick of the bar, so the walk launches on the first tick of the next one
+            let last_tick_of_bar = |tick: &Tick| {
+                tick.tick + 1 == tick.ticks_per_beat && tick.beat + 1 == tick.beats_per_bar
+            };
+            loop {
+                select! {
+                    biased;
+                    _ = lifetime.cancelled() => return,
+                    _ = ticks.next_matching(last_tick_of_bar) => {},
+                };
+
+                if !WALKS_PAUSED.get() {
+                    this.bind_mut().launch_queued_walk();
+                }
+            }
+        });
+    }
+
+    /// Starts or stops changing the BPM every bar according to `bpm_automation`, see `BpmAutomation`.
+    /// Does nothing while following MIDI clock.
+    #[func]
+    pub fn toggle_bpm_automation(&mut self) {
+        if let Some(cancel) = self.bpm_automation_cancel.take() {
+            cancel.cancel();
+            tracing::info!("stopped BPM automation");
+            return;
+        }
+        if self.bpm_automation.is_none() {
+            tracing::warn!("bpm_automation isn't set, not starting BPM automation");
+            return;
+        }
+
+        let cancel = CancellationToken::new();
+        self.bpm_automation_cancel = Some(cancel.clone());
+        tracing::info!("started BPM automation");
+        self.spawn_local_task(false, info_span!("bpm_automation"), async move |this| {
+            let mut ticks = subscribe_to_ticks();
+            let mut automation = BpmAutomation::new();
+
+            loop {
+                select! {
+                    biased;
+                    _ = cancel.cancelled() => return,
+                    _ = ticks.next_bar() => {},
+                };
+                if EXTERNAL_CLOCK.get() {
+                    continue;
+                }
+
+                // Read every bar, so changes in the inspector apply right away
+                let this = this.bind();
+                let Some(curve) = this.bpm_automation.clone() else {
+                    continue;
+                };
+                let length_bars = this.automation_length_bars as usize;
+                let pause_bars = this.automation_pause_bars as usize;
+                let (min_bpm, max_bpm) = (this.automation_min_bpm, this.automation_max_bpm);
+                drop(this);
+
+                let mut state = AudioState::autoload();
+                let current_bpm = state.bind().get_bpm();
+                if let Some(position) = automation.bar(current_bpm, length_bars, pause_bars) {
+                    let bpm = automation_bpm(sample_normalized(&curve, position), min_bpm, max_bpm);
+                    state.bind_mut().set_bpm(bpm); // This updates the slider UI as well
+                    automation.set_bpm(bpm);
+                }
+            }
+        });
+    }
+
+    fn launch_queued_walk(&mut self) {
+        let Some(node_index) = self.click_queue.pop() else {
+            return;
+        };
+        self.emit_click_queue_changed();
+        if let Some(node) = self.graph_godot_nodes.get(&node_index) {
+            let node = Gd::clone(node);
+            self.start_walk(node, node_index, LaunchQuantization::NextBar);
+        }
+    }
+
+    pub fn on_node_input_event(
+        &mut self,
+        mut node: Gd<AudioNode>,
+        node_index: NodeIndex,
+        event: Gd<InputEvent>,
+    ) {
+        if !self.accepting_input.get() {
+            return;
+        }
+
+        if let Ok(mb) = event.clone().try_cast::<InputEventMouseButton>()
+            && mb.is_pressed()
+            && mb.get_button_index() == MouseButton::LEFT
+        {
+            // Picking happens after `unhandled_input`, which took this click for the start of a box selection
+            self.box_selection = None;
+        }
+
+        match event.try_cast::<InputEventMouseButton>() {
+            Ok(mb)
+                if mb.is_pressed()
+                    && mb.is_shift_pressed()
+                    && mb.get_button_index() == MouseButton::RIGHT =>
+            {
+                self.remove_node(node_index.index().try_into().unwrap());
+            }
+            Ok(mb)
+                if mb.is_pressed()
+                    && mb.is_shift_pressed()
+                    && mb.get_button_index() == MouseButton::LEFT =>
+            {
+                self.wave_from(node_index);
+            }
+            Ok(mb) if mb.is_shift_pressed() => {
+                // Shift-left-click next to a node is for adding points, see `unhandled_input`
+            }
+            Ok(mb)
+                if mb.is_pressed()
+                    && mb.is_ctrl_pressed()
+                    && mb.get_button_index() == MouseButton::LEFT =>
+            {
+                match self.path_start.take() {
+                    None => {
+                        tracing::info!(
+                            "walking a path from node {node_index:?}, ctrl-click the destination"
+                        );
+                        self.path_start = Some(node_index);
+                        node.bind_mut().set_pending(true);
+                    }
+                    Some(from) if from == node_index => {
+                        node.bind_mut().set_pending(false);
+                    }
+                    Some(from) => self.walk_path(from, node_index),
+                }
+            }
+            Ok(mb)
+                if mb.is_pressed()
+                    && mb.is_ctrl_pressed()
+                    && mb.get_button_index() == MouseButton::RIGHT =>
+            {
+                if let Some(&island) = self.node_islands.get(&node_index) {
+                    self.toggle_island_solo(island);
+                }
+            }
+            Ok(mb) if mb.is_pressed() && mb.get_button_index() == MouseButton::RIGHT => {
+                if self.click_queue.remove(node_index) {
+                    tracing::info!("removed node {node_index:?} from the click queue");
+                    node.bind_mut().set_pending(false);
+                    self.emit_click_queue_changed();
+                } else {
+                    self.toggle_node_cancelling(&mut node, node_index);
+                }
+            }
+            Ok(mb)
+                if mb.is_pressed()
+                    && mb.is_ctrl_pressed()
+                    && mb.get_button_index() == MouseButton::MIDDLE =>
+            {
+                self.cycle_node_waveform(&mut node, node_index);
+            }
+            Ok(mb)
+                if mb.is_pressed()
+                    && (mb.get_button_index() == MouseButton::WHEEL_UP
+                        || mb.get_button_index() == MouseButton::WHEEL_DOWN) =>
+            {
+                let by = if mb.get_button_index() == MouseButton::WHEEL_UP {
+                    1
+                } else {
+                    -1
+                };
+                self.shift_node_octave(&mut node, node_index, by);
+            }
+            Ok(mb) if mb.is_pressed() && mb.get_button_index() == MouseButton::MIDDLE => {
+                let stopped = self.active_walks.stop_started_at(node_index);
+                tracing::info!("stopped {stopped} walk(s) started on node {node_index:?}");
+            }
+            Ok(mb) if mb.is_pressed() && mb.get_button_index() == MouseButton::LEFT => {
+                self.click_node(node, node_index);
+            }
+
+            _ => {}
+        };
+    }
+
+    /// See `trigger_island`.
+    fn trigger_island_anchor(&mut self, island_idx: usize, wave: bool) -> bool {
+        let Some(&(island, anchor)) = island_anchors(&self.node_islands).get(island_idx) else {
+            tracing::info!("can't trigger island {island_idx}, there are fewer islands");
+            return false;
+        };
+        let Some(node) = self.graph_godot_nodes.get(&anchor).cloned() else {
+            return false;
+        };
+        tracing::info!(island, ?anchor, wave, "triggered island {island_idx}");
+        if wave {
+            self.wave_from(anchor);
+        } else {
+            self.click_node(node, anchor);
+        }
+        true
+    }
+
+    /// Plays the free node closest in pitch to a note from `--midi-notes`. Loud notes start a walk on it, see `MidiNoteOn::action`.
+    fn play_midi_note(&mut self, note_on: MidiNoteOn) {
+        if !self.accepting_input.get() {
+            return;
+        }
+        if self.midi_note_index.is_none() {
+            self.rebuild_midi_note_index();
+        }
+        let nodes = &self.graph_godot_nodes;
+        let Some(node_index) = self.midi_note_index.as_ref().and_then(|index| {
+            index.nearest(note_on.note as i32, |idx| {
+                nodes
+                    .get(&idx)
+                    .is_some_and(|node| !node.bind().get_active())
+            })
+        }) else {
+            tracing::debug!(?note_on, "no free node to play the MIDI note on");
+            return;
+        };
+        let mut node = Gd::clone(&nodes[&node_index]);
+        tracing::info!(?note_on, ?node_index, "playing MIDI note");
+
+        match note_on.action(GAME_ARGS.midi_walk_velocity) {
+            MidiNoteAction::Walk => {
+                // Whoever plays the keyboard is in time already
+                self.start_walk(node, node_index, LaunchQuantization::NextTick);
+            }
+            MidiNoteAction::Play => {
+                let panic_button_cancel = self.panic_button_cancel.clone();
+                self.spawn_local_task(false, info_span!("play_midi_note"), async move |_this| {
+                    AudioNode::play(&mut node, 1.0, note_on.play_velocity(), panic_button_cancel)
+                        .await;
+                });
+            }
+        }
+    }
+
+    /// See `MidiNoteIndex`.
+    fn rebuild_midi_note_index(&mut self) {
+        self.midi_note_index = Some(MidiNoteIndex::new(
+            self.graph_godot_nodes
+                .iter()
+                .map(|(&idx, node)| (idx, node.bind().midi_note())),
+        ));
+    }
+
+    /// The rectangle being dragged, if it's far enough to be a box selection (see `BoxSelection::is_drag`). AudioUI draws it.
+    pub fn box_selection_rect(&self) -> Option<Rect2> {
+        self.box_selection
+            .filter(BoxSelection::is_drag)
+            .map(|selection| selection.rect())
+    }
+
+    /// Plays every node inside the dragged rectangle together, on the next bar. Clicks without dragging do nothing.
+    fn end_box_selection(&mut self, selection: BoxSelection) {
+        if !selection.is_drag() {
+            return;
+        }
+        let Some(camera) = self.base().get_viewport().and_then(|vp| vp.get_camera_3d()) else {
+            return;
+        };
+        let projected = self
+            .graph_godot_nodes
+            .iter()
+            .filter_map(|(&node_idx, node)| {
+                let pos = node.get_global_position();
+                (!camera.is_position_behind(pos))
+                    .then(|| (node_idx, camera.unproject_position(pos)))
+            });
+        let selected = nodes_in_rect(projected, selection.rect(), self.max_box_selection as usize);
+        if selected.is_empty() {
+            return;
+        }
+        tracing::info!(
+            "box selected {} nodes, playing them on the next bar",
+            selected.len()
+        );
+
+        let ticks = subscribe_to_ticks();
+        let graph_godot_nodes = Rc::clone(&self.graph_godot_nodes);
+        let cancel = self.panic_button_cancel.clone();
+        for node_idx in &selected {
+            Gd::clone(&graph_godot_nodes[node_idx])
+                .bind_mut()
+                .set_pending(true);
+        }
+        self.spawn_local_task(false, info_span!("chord_stab"), async move |_this| {
+            let mut ticks = ticks;
+            let launched = wait_for_launch(&mut ticks, LaunchQuantization::NextBar, &cancel)
+                .await
+                .is_some();
+            let nodes = selected
+                .iter()
+                .filter_map(|&node_idx| live_node(&graph_godot_nodes, node_idx))
+                .collect_vec();
+            for mut node in nodes.iter().cloned() {
+                node.bind_mut().set_pending(false);
+            }
+            if !launched {
+                return;
+            }
+            join_all(nodes.into_iter().map(|mut node| {
+                let cancel = cancel.clone();
+                async move { AudioNode::play(&mut node, 1.0, 1.0, cancel).await }
+            }))
+            .await;
+        });
+    }
+
+    /// Stops every node from cancelling walks, instead of right-clicking them one by one.
+    #[func]
+    pub fn clear_all_cancelling(&mut self) {
+        let count = self.cancelling_count.get();
+        for node in self.graph_godot_nodes.values() {
+            Gd::clone(node).bind_mut().set_cancelling(false);
+        }
+        tracing::info!("cleared {count} cancelling node(s)");
+    }
+
+    /// Focusing this graph takes the focus away from the one that had it, and shows the stats of this one. See `input_focus`.
+    #[func]
+    pub fn set_input_focus(&mut self, input_focus: bool) {
+        self.input_focus = input_focus;
+        if !input_focus || !self.base().is_inside_tree() {
+            return; // `ready` takes the focus
+        }
+        let id = self.base().instance_id();
+        if let Some(mut previous) = AudioGraph::current()
+            && previous.instance_id() != id
+        {
+            previous.bind_mut().input_focus = false;
+        }
+        CURRENT_AUDIOGRAPH.set(Some(id));
+        if let Some(mut state) = AudioState::try_autoload() {
+            state.bind_mut().show_graph_stats(id);
+        }
+    }
+
+    /// False while generating and during the intro animation, see `accepting_input_changed`.
+    #[func]
+    pub fn is_accepting_input(&self) -> bool {
+        self.accepting_input.get()
+    }
+
+    /// How many nodes are cancelling right now, see `CancellingCount`.
+    #[func]
+    pub fn get_cancelling_count(&self) -> i64 {
+        self.cancelling_count.get() as i64
+    }
 
-                if USE_METRONOME.get() {
-                    let metronome = &mut this.bind_mut().metronome;
+    /// Performance stress test: plays `stress_note_count` random nodes at once (a modern PC should easily handle 256),
+    /// and shows the FPS and stream count over the next `STRESS_MEASURE_SECS` above the stats.
+    /// The nodes only depend on the seed, so runs are comparable.
+    #[func]
+    pub fn start_stress_test(&mut self) {
+        if !self.accepting_input.get() {
+            return;
+        }
+        self.run_stress_test(false);
+    }
 
-                    let volume = if tick.beat == 0 && tick.tick == 0 {
-                        1.0
-                    } else if tick.tick == 0 {
-                        0.3
-                    } else {
-                        0.1
-                    };
+    /// See `start_stress_test`. With `quit`, prints the summary and quits the game afterwards, see `--stress`.
+    fn run_stress_test(&mut self, quit: bool) {
+        let mut rng = create_rng_from_seed_and_state(0x57BE55, self.graph_seed());
+        let nodes = self
+            .graph_godot_nodes
+            .values()
+            .cloned()
+            .collect_vec()
+            .choose_multiple(&mut rng, self.stress_note_count as usize)
+            .cloned()
+            .collect_vec();
+        let note_count = nodes.len();
+        tracing::info!("starting stress test with {note_count} notes");
+
+        for mut node in nodes {
+            let panic_button_cancel = self.panic_button_cancel.clone();
+            self.spawn_local_task(false, info_span!("play_debug"), async move |_this| {
+                AudioNode::play(&mut node, 20.0, 1.0, panic_button_cancel).await;
+            });
+        }
 
-                    metronome.set_volume_linear(volume);
-                    metronome.play();
+        let cancel = self.lifetime.token();
+        self.spawn_local_task(
+            false,
+            info_span!("measure_stress"),
+            async move |mut this| {
+                let mut measurement = StressMeasurement::new(STRESS_MEASURE_SECS);
+                loop {
+                    wait_for_next_frame().await;
+                    if cancel.is_cancelled() {
+                        return;
+                    }
+                    let delta = this.bind().base().get_process_delta_time();
+                    if measurement.frame(delta, ACTIVE_STREAMS.load(Ordering::Relaxed)) {
+                        break;
+                    }
+                }
+                let Some(summary) = measurement.summary(note_count) else {
+                    return;
+                };
+                tracing::info!("{summary}");
+                this.bind_mut().stress_summary = Some(summary.to_string());
+                let colors = AudioState::autoload().bind().node_colors();
+                this.bind().refresh_graph_debug_str(&colors);
+                if quit {
+                    godot_print!("{summary}");
+                    this.get_tree().unwrap().quit();
                 }
+            },
+        );
+    }
+
+    /// What left-clicking a node does, see `ClickQueueMode`.
+    fn click_node(&mut self, mut node: Gd<AudioNode>, node_index: NodeIndex) {
+        match self.click_queue_mode {
+            ClickQueueMode::Immediate => {
+                self.start_walk(node, node_index, self.launch_quantization);
             }
-        });
+            ClickQueueMode::Queued if self.click_queue.push(node_index) => {
+                tracing::info!("queued node {node_index:?}");
+                node.bind_mut().set_pending(true);
+                self.emit_click_queue_changed();
+            }
+            ClickQueueMode::Queued => {}
+        }
     }
 
-    pub fn on_node_input_event(
+    /// Starts a walk on `node_index` when `launch` says so, like left-clicking it. Returns None if there are too many walkers already.
+    pub fn start_walk(
         &mut self,
         mut node: Gd<AudioNode>,
         node_index: NodeIndex,
-        event: Gd<InputEvent>,
-    ) {
-        if !self.is_accepting_input {
-            return;
-        }
-
-        match event.try_cast::<InputEventMouseButton>() {
-            Ok(mb) if mb.is_pressed() && mb.get_button_index() == MouseButton::RIGHT => {
-                node.bind_mut().toggle_cancelling();
-            }
-            Ok(mb) if mb.is_pressed() && mb.get_button_index() == MouseButton::LEFT => {
-                tracing::info!("start playing on node {node_index:?}");
-
-                let ticks = subscribe_to_ticks(); //Call this as early as possible, to improve synchronicity
-
-                let graph = Rc::clone(&self.graph);
-                let graph_godot_nodes = Rc::clone(&self.graph_godot_nodes);
-                let mut rng = rand::rng(); //Graph walk direction is nondeterministic
-
-                let panic_button_cancel = self.panic_button_cancel.clone();
-                self.spawn_local_task(false, info_span!("graph_walk"), async move |this| {
-                    Self::graph_walk(
-                        this,
-                        node,
-                        node_index,
-                        graph,
-                        graph_godot_nodes,
-                        ticks,
-                        panic_button_cancel,
-                        &mut rng,
-                    )
-                    .await;
-                });
-            }
+        launch: LaunchQuantization,
+    ) -> Option<WalkId> {
+        tracing::info!("start playing on node {node_index:?}");
+
+        let ticks = subscribe_to_ticks(); //Call this as early as possible, to improve synchronicity
+
+        let graph = self.graph.clone()?;
+        let graph_godot_nodes = Rc::clone(&self.graph_godot_nodes);
+        let mut rng = self.walk_rng(node_index);
+        let walk_config = self.walk_config();
+        let recorder = self.recorder.as_ref().map(WalkRecorder::sender);
+        // Only when it's quiet, otherwise the other walks are enough of a reference
+        let count_in_bars = if self.active_walks.is_empty() {
+            self.count_in_bars as usize
+        } else {
+            0
+        };
 
-            _ => {}
+        let Some((walk_id, walk_cancel)) = self.active_walks.try_start(
+            node_index,
+            &self.panic_button_cancel,
+            self.max_active_walkers as usize,
+            self.walker_overflow,
+        ) else {
+            tracing::info!("too many active walkers, ignoring click");
+            self.spawn_local_task(false, info_span!("flash_rejected"), async move |_this| {
+                AudioNode::flash_rejected(&mut node).await;
+            });
+            return None;
         };
+        self.spawn_local_task(false, info_span!("graph_walk"), async move |mut this| {
+            let (stats, end) = Self::graph_walk(
+                Gd::clone(&this),
+                node,
+                node_index,
+                graph,
+                graph_godot_nodes,
+                ticks,
+                walk_config,
+                (launch, count_in_bars),
+                recorder,
+                (walk_id, walk_cancel),
+                &mut rng,
+            )
+            .await;
+            this.bind_mut().active_walks.finish(walk_id);
+
+            let stats = this.bind().walk_stats_dict(&stats, end);
+            this.signals().walk_finished().emit(walk_id as i64, &stats);
+        });
+        Some(walk_id)
     }
 
+    /// Sets the BPM from the taps so far (see `TapTempo`), and nudges the beat so the next one lands where the next tap would.
+    /// Ignored while generating, like the other actions.
     pub fn perform_bpm_tap(&mut self) {
-        let now = Instant::now();
-
-        // Calculate avg bpm
-        if self.bpm_taps.len() > 2 {
-            let intervals: Vec<Duration> = self
-                .bpm_taps
-                .iter()
-                .tuple_windows()
-                .map(|(a, b)| *b - *a)
-                .collect();
-
-            let total_secs: f64 = intervals.iter().map(|dur| dur.as_secs_f64()).sum();
-            let avg_secs = total_secs as f64 / intervals.len() as f64;
-            let new_bpm = (60.0 / avg_secs).clamp(30.0, 300.0);
-
-            tracing::info!("bpm tap set bpm to {new_bpm:.5}");
-            AudioState::autoload().bind_mut().set_bpm(new_bpm); // This updates the slider UI as well
+        if !self.accepting_input.get() {
+            return;
         }
-
-        // Add new tap
-        self.bpm_taps.push_back(now);
-
-        // Limit tap count
-        while self.bpm_taps.len() > 32 {
-            self.bpm_taps.pop_front();
+        if let Some(estimate) = self.bpm_taps.tap(Instant::now()) {
+            tracing::info!("bpm tap set bpm to {:.5}", estimate.bpm);
+            AudioState::autoload().bind_mut().set_bpm(estimate.bpm); // This updates the slider UI as well
+            set_bpm_internal(BpmMessage::Nudge {
+                next_beat: estimate.next_tap,
+            });
         }
 
-        // Delete old taps
-        let timeout = 30.0; // seconds
-        self.bpm_taps = self
-            .bpm_taps
-            .iter()
-            .cloned()
-            .filter(|tap| (now - *tap).as_secs_f64() < timeout)
-            .collect();
-
-        tracing::info!("bpm tap count = {}", self.bpm_taps.len());
+        tracing::info!("bpm tap count = {}", self.bpm_taps.tap_count());
     }
 
     pub async fn play_intro_animation<R: Rng>(
         this: &mut Gd<Self>,
         constellation: &ConstellationGraph,
-        island_data: &[(Waveform, bool, f64)],
+        island_data: &[IslandData],
         scc_assoc: &BTreeMap<NodeIndex, usize>,
         node_scene: Gd<PackedScene>,
         root_rng: &mut R,
@@ -341,114 +2459,234 @@ impl AudioGraph {
         //Start spawning nodes
         let mut graph_godot_nodes = BTreeMap::default();
 
-        let ConstellationGraph {
-            chord,
-            semitone_offset: semitone_offset_base,
-            graph,
-            ..
-        } = constellation;
-
-        //Use precise timing here from another thread to evenly spread the node spawning over time, even with low FPS.
-        let (tx, rx) = flume::unbounded();
+        let ConstellationGraph { graph, .. } = constellation;
         let node_indices = graph.node_indices().collect::<Vec<_>>();
-        tokio::task::spawn_blocking(move || {
-            //Don't use rayon here, also don't block inside of a tokio::task::spawn!
-
-            let nodes_per_second = 1000;
-            let interval = Duration::from_secs_f64(1.0 / nodes_per_second as f64);
-            let mut deadline = Instant::now();
-
-            for i in node_indices {
-                deadline += interval;
-                spin_sleep::sleep_until(deadline);
-
-                //Send tick
-                let Ok(_) = tx.send(i) else {
-                    tracing::info!("spawning_start animation cancelled");
-                    break; //Important - stop the task if the channel is closed (otherwise it stalls the next animation)
-                };
-            }
-        });
 
-        let spawning_start = Instant::now();
-        while let Ok(idx) = rx.recv_async().await {
-            let mut node_rng = Xoshiro256Plus::from_rng(root_rng);
+        //Spawns a single node. Both branches spawn in the same order, so the nodes come out the same either way.
+        let mut spawn_node = |this: &mut Gd<Self>, idx: NodeIndex| {
+            let node_rng = Xoshiro256Plus::from_rng(&mut *root_rng);
             //From now on use node_rng instead of root_rng!
 
-            let pos = graph[idx];
-            let instance = node_scene
-                .instantiate()
-                .expect("failed to instantiate node_scene");
-
-            let mut audionode = instance.cast::<AudioNode>();
-            let island_idx = scc_assoc[&idx] as i64;
-            let (waveform, is_pad, octave_base) = island_data[usize::try_from(island_idx).unwrap()];
+            let island = scc_assoc[&idx];
+            let pooled = this.bind_mut().node_pool.pop();
+            let mut audionode = Self::spawn_audio_node(
+                this,
+                pooled,
+                &node_scene,
+                constellation,
+                island,
+                island_data[island],
+                idx,
+                graph[idx],
+                node_rng,
+            );
+            audionode
+                .bind_mut()
+                .set_graph_info(Some(scc_assoc[&idx]), graph.edges(idx).count());
+            audionode
+        };
 
-            //Need a little bit of variation of octaves within an island, otherwise it becomes boring
-            let octave = (octave_base + Normal::new(0.0_f64, 1.0).unwrap().sample(&mut node_rng))
-                .clamp(2.0, 8.0)
-                .round() as i32;
+        let spawning_start = Instant::now();
+        let intro_nodes_per_second = this.bind().intro_nodes_per_second;
+        match IntroPacing::new(GAME_ARGS.skip_intro, intro_nodes_per_second) {
+            IntroPacing::Skipped => {
+                //Spawn as fast as possible, but give the engine a frame now and then so it doesn't freeze
+                //Only here spawning isn't paced by the animation, so the profile shows what spawning a node actually costs
+                for chunk in intro_chunks(node_indices.len(), SKIPPED_INTRO_NODES_PER_FRAME) {
+                    profile!("spawn_chunk", {
+                        for &idx in &node_indices[chunk] {
+                            let audionode = spawn_node(this, idx);
+                            graph_godot_nodes.insert(idx, audionode);
+                        }
+                    });
+                    wait_for_next_frame().await;
+                }
 
-            let detune = 0.07; //1.0 = full semitone offset
-            let semitone_offset =
-                *semitone_offset_base as f32 + node_rng.random_range(-detune..detune);
+                //All edges at once, in the color they'd get from the animation (of the node that got spawned last)
+                let this_ref = this.bind();
+                let mut edges = this_ref.edges.borrow_mut();
+                for edge in graph.edge_references() {
+                    let last_spawned = edge.source().max(edge.target());
+                    let slot = this_ref.edge_slots.slot(edge.id());
+                    edges.set_color(slot, graph_godot_nodes[&last_spawned].bind().get_color());
+                }
+            }
+            IntroPacing::Animated { nodes_per_second } => {
+                //Use precise timing here from another thread to evenly spread the node spawning over time, even with low FPS.
+                let (tx, rx) = flume::unbounded();
+                let node_indices = node_indices.clone();
+                tokio::task::spawn_blocking(move || {
+                    //Don't use rayon here, also don't block inside of a tokio::task::spawn!
+
+                    let interval = Duration::from_secs_f64(1.0 / nodes_per_second as f64);
+                    let mut deadline = Instant::now();
+
+                    for i in node_indices {
+                        deadline += interval;
+                        spin_sleep::sleep_until(deadline);
+
+                        //Send tick
+                        let Ok(_) = tx.send(i) else {
+                            tracing::info!("spawning_start animation cancelled");
+                            break; //Important - stop the task if the channel is closed (otherwise it stalls the next animation)
+                        };
+                    }
+                });
 
-            {
-                let mut audionode = audionode.bind_mut();
-                audionode.set_chord(chord.to_godot());
-                audionode.set_semitone_offset(semitone_offset);
-                audionode.set_octave(octave);
-                audionode.set_waveform(waveform.to_godot());
+                while let Ok(idx) = rx.recv_async().await {
+                    let audionode = spawn_node(this, idx);
+                    graph_godot_nodes.insert(idx, audionode.clone());
+
+                    //Animation: make edges gradually visible, at the moment both connected nodes have been spawned
+                    {
+                        let others = graph.edges(idx);
+                        for edge in others {
+                            if graph_godot_nodes.contains_key(&edge.target()) {
+                                let this_ref = this.bind();
+                                let slot = this_ref.edge_slots.slot(edge.id());
+                                this_ref.edges.borrow_mut().set_color(
+                                    slot,
+                                    audionode.bind().get_color(), //NOTE - this may introduce edges that have brightness > 1.0 (breaks MSAA)
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
 
-                audionode.set_duration(node_rng.random_range(0.3..1.5));
-                audionode.set_node_idx(idx.index().try_into().unwrap());
-                audionode.set_is_pad(is_pad);
+        tracing::info!("`spawning_start` took {:?}", spawning_start.elapsed()); //When animated, this should take node_count / intro_nodes_per_second exactly, regardless of framerate
 
-                audionode.set_rng(node_rng);
-            }
+        graph_godot_nodes
+    }
 
-            audionode.set_position(pos); //Do this BEFORE add_child! (prevent re-calculating collision BVH twice)
-            this.add_child(&audionode);
+    /// Instantiates an AudioNode at `pos` for graph node `idx`, configures it using the island it belongs to, and adds it as a child.
+    /// Reuses `pooled` instead if it's a node from the pool, which is already a child.
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn_audio_node(
+        this: &mut Gd<Self>,
+        pooled: Option<Gd<AudioNode>>,
+        node_scene: &Gd<PackedScene>,
+        constellation: &ConstellationGraph,
+        island: usize,
+        island_data: IslandData,
+        idx: NodeIndex,
+        pos: Vector3,
+        mut node_rng: Xoshiro256Plus,
+    ) -> Gd<AudioNode> {
+        let IslandData {
+            waveform,
+            is_pad,
+            octave_base,
+            envelope,
+            ..
+        } = island_data;
+        let is_reused = pooled.is_some();
+        let mut audionode = pooled.unwrap_or_else(|| {
+            node_scene
+                .instantiate()
+                .expect("failed to instantiate node_scene")
+                .cast::<AudioNode>()
+        });
 
-            graph_godot_nodes.insert(idx, audionode.clone());
+        //Need a little bit of variation of octaves within an island, otherwise it becomes boring
+        let octave = (octave_base + Normal::new(0.0_f64, 1.0).unwrap().sample(&mut node_rng))
+            .clamp(2.0, 8.0)
+            .round() as i32;
+
+        let detune = 0.07; //1.0 = full semitone offset
+        let semitone_offset =
+            constellation.semitone_offset as f32 + node_rng.random_range(-detune..detune);
+
+        {
+            let mut audionode = audionode.bind_mut();
+            audionode.set_chord(constellation.chord.to_godot());
+            audionode.set_semitone_offset(semitone_offset);
+            audionode.set_octave(octave);
+            audionode.set_waveform(waveform.to_godot());
+
+            audionode.set_duration(node_rng.random_range(0.3..1.5));
+            audionode.set_node_idx(idx.index().try_into().unwrap());
+            audionode.set_is_pad(is_pad);
+            audionode.set_envelope(envelope);
+            audionode.set_island(island);
+
+            audionode.set_rng(node_rng);
+        }
 
-            //Animation: make edges gradually visible, at the moment both connected nodes have been spawned
+        audionode.set_position(pos); //Do this BEFORE add_child! (prevent re-calculating collision BVH twice)
+        if is_reused {
+            //Its `ready` already ran, and its signals are still connected
             {
-                let others = graph.edges(idx);
-                for edge in others {
-                    if graph_godot_nodes.contains_key(&edge.target()) {
-                        this.bind_mut()
-                            .multimesh_instance
-                            .get_multimesh()
-                            .unwrap()
-                            .set_instance_color(
-                                edge.id().index() as i32,
-                                audionode.bind().get_color(), //NOTE - this may introduce edges that have brightness > 1.0 (breaks MSAA)
-                            );
-                    }
-                }
+                let mut audionode = audionode.bind_mut();
+                audionode.take_from_pool();
+                audionode.configure();
             }
+            return audionode;
+        }
+        let spheres = Rc::clone(&this.bind().spheres);
+        audionode.bind_mut().set_sphere_batch(spheres);
+        let cancelling_count = this.bind().cancelling_count.clone();
+        audionode.bind_mut().set_cancelling_count(cancelling_count);
+        this.add_child(&audionode);
+
+        //Setup input events
+        {
+            let mut this = Gd::clone(this); //Clone it so we can move into the closure below
+            audionode.signals().input_event().builder().connect_self_gd(
+                move |node, _, event, _, _, _| {
+                    //Don't capture the NodeIndex, it changes if another node gets removed (see `remove_node`)
+                    let idx = NodeIndex::new(node.bind().get_node_idx() as usize);
+                    this.bind_mut().on_node_input_event(node, idx, event);
+                },
+            );
+        }
 
-            //Setup input events
-            {
-                let mut this = Gd::clone(this); //Clone it so we can move into the closure below
-                audionode.signals().input_event().builder().connect_self_gd(
-                    move |node, _, event, _, _, _| {
-                        this.bind_mut().on_node_input_event(node, idx, event);
-                    },
-                );
-            }
+        //Keep `graph_godot_nodes` free of dead nodes
+        {
+            let mut this = Gd::clone(this);
+            audionode
+                .signals()
+                .tree_exiting()
+                .builder()
+                .connect_self_gd(move |node| {
+                    this.bind_mut().on_audio_node_exiting(node);
+                });
         }
 
-        tracing::info!("`spawning_start` took {:?}", spawning_start.elapsed()); //This should now take 2s exactly, regardless of framerate
+        //Setup hover events, for tooltips
+        {
+            let this = Gd::clone(this);
+            audionode
+                .signals()
+                .mouse_entered()
+                .builder()
+                .connect_self_gd(move |mut node| {
+                    node.bind_mut().wake(); // Whatever reacts to the hover may change it
+                    let idx = node.bind().get_node_idx() as i64;
+                    this.signals().node_hovered().emit(idx);
+                });
+        }
+        {
+            let this = Gd::clone(this);
+            audionode
+                .signals()
+                .mouse_exited()
+                .builder()
+                .connect_self_gd(move |node| {
+                    let idx = node.bind().get_node_idx() as i64;
+                    this.signals().node_unhovered().emit(idx);
+                });
+        }
 
-        graph_godot_nodes
+        audionode
     }
 
+    /// Returns how every island sounds. `island_data[i]` belongs to `constellation.islands[i]`.
     pub fn generate_island_data<R: Rng>(
         constellation: &ConstellationGraph,
         root_rng: &mut R,
-    ) -> Vec<(Waveform, bool, f64)> {
+    ) -> Vec<IslandData> {
         let ConstellationGraph { islands, .. } = constellation;
 
         let island_count = islands.len();
@@ -456,7 +2694,7 @@ impl AudioGraph {
         //Use this to generate stuff for every island, to ensure we remain deterministic, even if the amount of islands changes
         let mut island_rng = Xoshiro256Plus::from_rng(root_rng);
 
-        let island_data: Vec<_> = (0..island_count)
+        let island_data: Vec<(Waveform, bool, f64)> = (0..island_count)
             .map(|_island_idx| {
                 let waveform = *Waveform::iter()
                     .collect::<Vec<_>>()
@@ -481,44 +2719,468 @@ impl AudioGraph {
             })
             .collect();
 
+        // Forked after the rest, so adding these didn't change how existing seeds sound
+        let mut rest_rng = Xoshiro256Plus::from_rng(&mut island_rng);
+        let mut tempo_rng = Xoshiro256Plus::from_rng(&mut island_rng);
+        let mut envelope_rng = Xoshiro256Plus::from_rng(&mut island_rng);
+
         island_data
+            .into_iter()
+            .map(|(waveform, is_pad, octave_base)| {
+                let rest_probability = rest_rng.random_range(0.0..=MAX_REST_PROBABILITY);
+                let (tempo_mult, _) = *TEMPO_MULTS
+                    .choose_weighted(&mut tempo_rng, |(_, weight)| *weight)
+                    .unwrap();
+                IslandData {
+                    waveform,
+                    is_pad,
+                    octave_base,
+                    rest_probability,
+                    tempo_mult,
+                    envelope: island_envelope(is_pad, &mut envelope_rng),
+                }
+            })
+            .collect()
+    }
+
+    /// How likely a walk rests (skips playing) at `node_idx`, see `rest_probability_mult`.
+    pub fn rest_probability(&self, node_idx: NodeIndex) -> f64 {
+        let Some(island) = self.node_island_data(node_idx) else {
+            return 0.0;
+        };
+        (island.rest_probability * self.rest_probability_mult).clamp(0.0, 1.0)
+    }
+
+    /// The `IslandData::tempo_mult` of the island of `node_idx`.
+    pub fn tempo_mult(&self, node_idx: NodeIndex) -> f64 {
+        self.node_island_data(node_idx)
+            .map_or(1.0, |island| island.tempo_mult)
+    }
+
+    fn node_island_data(&self, node_idx: NodeIndex) -> Option<&IslandData> {
+        self.node_islands
+            .get(&node_idx)
+            .and_then(|island_idx| self.island_data.get(*island_idx))
+    }
+
+    /// The rng for a walk started by clicking `node_idx`. Nondeterministic, unless `AudioState::deterministic_walks` is on.
+    fn walk_rng(&mut self, node_idx: NodeIndex) -> Xoshiro256Plus {
+        let click_count = self.walk_click_count;
+        self.walk_click_count += 1;
+
+        if AudioState::autoload().bind().get_deterministic_walks() {
+            deterministic_walk_rng(self.graph_seed(), node_idx, click_count)
+        } else {
+            Xoshiro256Plus::from_rng(&mut rand::rng())
+        }
+    }
+
+    /// Call when a walk reaches `node_idx`, returns false if the walk isn't running anymore. See `ActiveWalks::moved`.
+    pub fn move_walk_head(&mut self, walk_id: WalkId, node_idx: NodeIndex) -> bool {
+        self.active_walks.moved(walk_id, node_idx)
+    }
+
+    /// Call when a walker plays `node_idx` at `tick`, returns true if it collided with another walker, see `NodeOccupancy::arrive`.
+    pub fn arrive_at(&mut self, node_idx: NodeIndex, tick: usize) -> bool {
+        self.occupied_nodes.arrive(node_idx, tick)
+    }
+
+    pub fn trail_length(&self) -> usize {
+        self.trail_length as usize
+    }
+
+    /// Adds `edge` to `trail` and lights up every edge on it, see `TrailState`.
+    /// Uses the color of the edges, the progress indicator has the custom data to itself.
+    pub fn trail_edge(&mut self, trail: &mut TrailState, edge: EdgeIndex) {
+        let slot = self.edge_slot(edge);
+        let original = match self.highlight_colors.get(&edge) {
+            Some(&highlight_color) => highlight_color,
+            None => self.edges.borrow().color(slot),
+        };
+        self.trail_colors.entry(edge).or_insert((original, 0)).1 += 1;
+
+        if let Some(old_edge) = trail.push(edge) {
+            self.untrail_edge(old_edge);
+        }
+
+        // Oldest first, so an edge that's on the trail twice gets the brightness of its newest position.
+        // Only the last color of the frame reaches the MultiMesh, see `EdgeRenderer`
+        let mut edges = self.edges.borrow_mut();
+        for (age, edge) in trail.edges() {
+            let (color, _) = self.trail_colors[&edge];
+            edges.set_color(
+                self.edge_slots.slot(edge),
+                color.lightened(trail_lightening(age) as f64),
+            );
+        }
+    }
+
+    /// Empties `trail`, call this when its walk ends.
+    pub fn clear_trail(&mut self, trail: &mut TrailState) {
+        for edge in trail.clear() {
+            self.untrail_edge(edge);
+        }
+    }
+
+    /// Takes `edge` off a trail. It gets its original color back once it's on no trails at all.
+    fn untrail_edge(&mut self, edge: EdgeIndex) {
+        let Some((color, count)) = self.trail_colors.get_mut(&edge) else {
+            return; // Restored already, see `restore_trails`
+        };
+        *count -= 1;
+        if *count == 0 {
+            let color = *color;
+            self.trail_colors.remove(&edge);
+            self.set_edge_color(edge, color);
+        }
+    }
+
+    /// Gives every edge on a trail its original color back, without waiting for the walks to notice they were stopped.
+    fn restore_trails(&mut self) {
+        for (edge, (color, _)) in std::mem::take(&mut self.trail_colors) {
+            self.set_edge_color(edge, color);
+        }
+    }
+
+    /// Sets the color of an edge that's not on a trail, lightened if it's on the highlighted island.
+    fn set_edge_color(&mut self, edge: EdgeIndex, color: Color) {
+        let slot = self.edge_slot(edge);
+        let color = match self.highlight_colors.get_mut(&edge) {
+            Some(original) => {
+                *original = color;
+                color.lightened(HIGHLIGHT_LIGHTENING)
+            }
+            None => color,
+        };
+        self.edges.borrow_mut().set_color(slot, color);
+    }
+
+    /// Subtly lights up the edges and nodes of `island`, so you can see what clicking would set off. Only one island at a time,
+    /// None takes the highlight away. Edges on trails keep their trail color, and get highlighted once they're off the trail.
+    fn highlight_island(&mut self, island: Option<usize>) {
+        let Some(graph) = self.graph.clone() else {
+            return;
+        };
+        profile!("highlight_island", {
+            self.clear_island_highlight();
+            let edges = self.edges.clone();
+
+            if let Some(island) = island {
+                let island_nodes = self
+                    .node_islands
+                    .iter()
+                    .filter(|&(_, &node_island)| node_island == island)
+                    .map(|(&node_idx, _)| node_idx)
+                    .collect_vec();
+                for &node_idx in &island_nodes {
+                    if let Some(node) = self.graph_godot_nodes.get(&node_idx) {
+                        Gd::clone(node).bind_mut().set_highlighted(true);
+                        self.highlighted_nodes.push(node_idx);
+                    }
+                    for edge in graph.edges(node_idx) {
+                        let within = self.node_islands.get(&edge.target()) == Some(&island)
+                            && self.node_islands.get(&edge.source()) == Some(&island);
+                        if !within || self.highlight_colors.contains_key(&edge.id()) {
+                            continue;
+                        }
+                        match self.trail_colors.get(&edge.id()) {
+                            // Gets lightened when it's off the trail, see `untrail_edge`
+                            Some(&(color, _)) => {
+                                self.highlight_colors.insert(edge.id(), color);
+                            }
+                            None => {
+                                let slot = self.edge_slot(edge.id());
+                                let mut edges = edges.borrow_mut();
+                                let color = edges.color(slot);
+                                self.highlight_colors.insert(edge.id(), color);
+                                edges.set_color(slot, color.lightened(HIGHLIGHT_LIGHTENING));
+                            }
+                        }
+                    }
+                }
+                tracing::debug!(
+                    island,
+                    nodes = island_nodes.len(),
+                    edges = self.highlight_colors.len(),
+                    "highlighted island"
+                );
+            }
+        });
+    }
+
+    /// Takes the highlight away until the mouse goes onto another node, e.g. before sculpting.
+    fn unhighlight_island(&mut self) {
+        self.highlight_island(None);
+        self.island_hover = IslandHover::default();
+    }
+
+    /// Gives the highlighted edges and nodes their original colors back, see `highlight_island`.
+    fn clear_island_highlight(&mut self) {
+        let mut edges = self.edges.borrow_mut();
+        for (edge, color) in std::mem::take(&mut self.highlight_colors) {
+            if !self.trail_colors.contains_key(&edge) {
+                edges.set_color(self.edge_slots.slot(edge), color);
+            }
+        }
+        drop(edges);
+        for node_idx in std::mem::take(&mut self.highlighted_nodes) {
+            if let Some(node) = self.graph_godot_nodes.get(&node_idx) {
+                Gd::clone(node).bind_mut().set_highlighted(false);
+            }
+        }
+    }
+
+    fn on_node_hovered(&mut self, node_idx: i64) {
+        let island = self
+            .node_islands
+            .get(&NodeIndex::new(node_idx as usize))
+            .copied();
+        self.island_hover.hover(island, Instant::now());
+    }
+
+    fn on_node_unhovered(&mut self, _node_idx: i64) {
+        self.island_hover.hover(None, Instant::now());
+    }
+
+    /// How many islands the constellation has, 0 while it's generating.
+    #[func]
+    pub fn get_island_count(&self) -> i64 {
+        self.island_data.len() as i64
+    }
+
+    /// How the island sounds, which nodes it has and where it is, see `IslandInfo::to_dictionary`. Empty if there's no such island.
+    #[func]
+    pub fn get_island_info(&self, island_idx: i64) -> Dictionary {
+        let island_info = self.graph.as_ref().and_then(|graph| {
+            let island = usize::try_from(island_idx).ok()?;
+            IslandInfo::new(graph, &self.island_data, &self.node_islands, island)
+        });
+        match island_info {
+            Some(island_info) => island_info.to_dictionary(),
+            None => {
+                tracing::warn!("there is no island {island_idx}");
+                Dictionary::new()
+            }
+        }
+    }
+
+    /// The average position of the nodes of the island, relative to this node (like `get_node_position`), e.g. for the camera to fly to.
+    /// Zero if there's no such island.
+    #[func]
+    pub fn get_island_centroid(&self, island_idx: i64) -> Vector3 {
+        self.graph
+            .as_ref()
+            .zip(usize::try_from(island_idx).ok())
+            .and_then(|(graph, island)| {
+                IslandInfo::new(graph, &self.island_data, &self.node_islands, island)
+            })
+            .map_or(Vector3::ZERO, |island_info| island_info.centroid)
+    }
+
+    /// The island `node_idx` belongs to, or -1 if there's no such node.
+    #[func]
+    pub fn get_island_of_node(&self, node_idx: i64) -> i64 {
+        usize::try_from(node_idx)
+            .ok()
+            .and_then(|node_idx| self.node_islands.get(&NodeIndex::new(node_idx)))
+            .map_or(-1, |&island| island as i64)
+    }
+
+    /// Recolors every node and edge, and the stats, for the current `AudioState::color_mode`.
+    #[func]
+    pub fn recolor_nodes(&mut self) {
+        let colors = AudioState::autoload().bind().node_colors();
+        tracing::info!(mode = ?colors.mode(), "recoloring nodes");
+
+        // Edges get the color of the node with the highest index, like in the intro animation
+        for (idx, node) in Rc::clone(&self.graph_godot_nodes).iter() {
+            let color = Gd::clone(node).bind_mut().recolor(&colors);
+            self.recolor_node_edges(*idx, color);
+        }
+        self.refresh_graph_debug_str(&colors);
+    }
+
+    /// Shows the stats of the current constellation, with the generation warning and the last stress test on top.
+    fn refresh_graph_debug_str(&self, colors: &NodeColors) {
+        if let Some(constellation) = self.constellation.clone() {
+            let stats = ConstellationStats::new(&constellation, &self.island_data);
+            let mut stats_str = Self::generate_stats(
+                &stats,
+                &constellation,
+                &self.island_data,
+                &self.walk_config(),
+                colors,
+            );
+            if let Some(warning) = &self.generation_warning {
+                stats_str = format!("{warning}\n{stats_str}");
+            }
+            if let Some(summary) = &self.stress_summary {
+                stats_str = format!("{summary}\n{stats_str}");
+            }
+            if let Some(error) = &self.stats_export_error {
+                stats_str = format!("{stats_str}\n{error}");
+            }
+            AudioState::autoload().bind_mut().set_graph_stats(
+                self.base().instance_id(),
+                stats_str.into(),
+                stats,
+            );
+        }
+    }
+
+    /// Mutes (or unmutes) every node on the island, see `AudioNode::muted`.
+    #[func]
+    pub fn set_island_muted(&mut self, island_idx: i64, muted: bool) {
+        let Some(island) = usize::try_from(island_idx)
+            .ok()
+            .filter(|&island| island < self.island_data.len())
+        else {
+            tracing::warn!("can't mute island {island_idx}, there is no such island");
+            return;
+        };
+        for (node_idx, _) in self
+            .node_islands
+            .iter()
+            .filter(|&(_, &node_island)| node_island == island)
+        {
+            if let Some(node) = self.graph_godot_nodes.get(node_idx) {
+                Gd::clone(node).bind_mut().set_muted(muted);
+            }
+        }
+        tracing::info!(island, muted, "island muted");
+    }
+
+    /// Solos the island, or unsolos it if it was soloed already. See `SoloIslands`.
+    fn toggle_island_solo(&mut self, island: usize) {
+        let soloed = self.solo_islands.toggle(island);
+        tracing::info!(island, soloed, "island solo toggled");
+        for (node_idx, node) in self.graph_godot_nodes.iter() {
+            let island = self.node_islands.get(node_idx).copied();
+            Gd::clone(node)
+                .bind_mut()
+                .set_soloed_out(self.solo_islands.silences(island));
+        }
+    }
+
+    /// The `stats` of the `walk_finished` signal.
+    fn walk_stats_dict(&self, stats: &WalkStats, end: WalkEnd) -> Dictionary {
+        let islands_visited = stats
+            .walked
+            .iter()
+            .filter_map(|node_idx| self.node_islands.get(node_idx))
+            .unique()
+            .count();
+        dict! {
+            "notes_played": stats.notes_played as i64,
+            "islands_visited": islands_visited as i64,
+            "total_beats": stats.total_ticks() as i64,
+            "longest_run": stats.longest_run as i64,
+            "end_reason": end.name(),
+        }
+    }
+
+    /// The velocity of `node_idx` when a walk reaches it after `beats_waited` beats, see `note_velocity`.
+    pub fn note_velocity(
+        &self,
+        graph: &GraphTypedef,
+        node_idx: NodeIndex,
+        beats_waited: usize,
+    ) -> f32 {
+        note_velocity(
+            graph.neighbors(node_idx).count(),
+            beats_waited,
+            self.junction_accent,
+            self.long_edge_accent,
+        )
     }
 
+    /// Renders `stats` as BBCode for the Statistics tab, with whatever else of the constellation is worth showing there.
     pub fn generate_stats(
+        stats: &ConstellationStats,
         constellation: &ConstellationGraph,
-        island_data: &[(Waveform, bool, f64)],
+        island_data: &[IslandData],
+        walk_config: &WalkConfig,
+        colors: &NodeColors,
     ) -> String {
-        let ConstellationGraph {
+        let ConstellationStats {
             chord,
             semitone_offset: semitone_offset_base,
+            node_count,
+            edge_count,
+            bridge_edge_count,
+            island_sizes,
+            pad_island_count,
+            waveform_counts,
+        } = stats;
+        let ConstellationGraph {
             graph,
             islands,
+            island_graph,
+            metrics,
+            edge_strategy_comparison,
             ..
         } = constellation;
 
-        let pad_island_count = island_data.iter().filter(|(_, pad, _)| *pad).count();
+        let island_count = stats.island_count();
 
-        let island_sizes = islands
+        // Colored like the nodes (see `ColorMode`), using the octave the nodes of the island vary around
+        let island_colors = island_data
             .iter()
-            .map(|island| island.len())
-            .collect::<Vec<_>>();
+            .enumerate()
+            .map(|(island, data)| {
+                let octave = data.octave_base.round() as i32;
+                (
+                    colors.base_color(data.waveform, octave, Some(island)),
+                    data.is_pad,
+                )
+            })
+            .collect_vec();
 
-        let island_count = islands.len();
+        // Every node is colored like its island
+        let (degrees, degree_colors): (Vec<_>, Vec<_>) = islands
+            .iter()
+            .zip_eq(&island_colors)
+            .flat_map(|(island, color)| {
+                island
+                    .iter()
+                    .map(move |node| (graph.edges(*node).count(), *color))
+            })
+            .unzip();
+
+        let avg_degree_nodes = (*node_count).max(1) as f32;
+        let edge_strategy = match edge_strategy_comparison {
+            Some(EdgeStrategyComparison {
+                edge_strategy,
+                cluster_edge_count,
+                knn_cluster_edge_count,
+            }) => format!(
+                "{edge_strategy:?}, {cluster_edge_count} cluster edges, avg degree {:.2} (Knn: {knn_cluster_edge_count}, {:.2})",
+                2.0 * *cluster_edge_count as f32 / avg_degree_nodes,
+                2.0 * *knn_cluster_edge_count as f32 / avg_degree_nodes,
+            ),
+            None => format!(
+                "{:?}, avg degree {:.2}",
+                EdgeStrategy::Knn,
+                2.0 * *edge_count as f32 / avg_degree_nodes
+            ),
+        };
 
         let waveform_occurrences = [false, true]
             .into_iter()
             .map(|is_pad| {
                 let symbol = if is_pad { '▲' } else { '■' };
 
-                Waveform::iter()
-                    .map(|wav| {
-                        let occurrences = island_data
-                            .iter()
-                            .filter(|(w, p, _)| *w == wav && *p == is_pad)
-                            .count();
+                waveform_counts
+                    .iter()
+                    .map(|count| {
+                        let occurrences = if is_pad {
+                            count.pad_islands
+                        } else {
+                            count.islands
+                        };
                         format!(
                             "[color={}]{symbol}×{occurrences:02}[/color]",
-                            wav.as_color().to_html_without_alpha()
+                            color_to_html(count.waveform.as_color())
                         )
                     })
                     .collect::<Vec<_>>()
@@ -526,23 +3188,66 @@ impl AudioGraph {
             })
             .join("\n");
 
+        let tempo_mults = TEMPO_MULTS
+            .iter()
+            .map(|(tempo_mult, _)| {
+                let occurrences = island_data
+                    .iter()
+                    .filter(|island| island.tempo_mult == *tempo_mult)
+                    .count();
+                format!("×{tempo_mult}: {occurrences}")
+            })
+            .join(", ");
+
+        let limit = |limit: usize| match limit {
+            0 => "unlimited".to_string(),
+            limit => limit.to_string(),
+        };
+        let walks = format!(
+            "{:?}, revisits {:?}, dead ends {:?}, max steps {}, max branches {}",
+            walk_config.strategy,
+            walk_config.revisits,
+            walk_config.dead_ends,
+            limit(walk_config.max_steps),
+            limit(walk_config.max_concurrent_branches),
+        );
+
         format!(
             r#"Chord: {chord:?} ({semitone_offset_base:+} semitones)
-Vertex/edge count: {}, {}
+Vertex/edge count: {node_count}, {edge_count}
 Island count: {island_count}
+Bridge edge count: {bridge_edge_count}
+Edge strategy: {edge_strategy}
+Metrics: avg degree {:.2}, max island diameter {}, avg path length {:.1}, clustering {:.2}
+Island adjacency count: {}
+Walks: {walks}
 Pad island count: {pad_island_count}/{island_count} ({:.1}%)
+Island tempo multipliers: {tempo_mults}
+Colors: {}
 Waveform occurrences:
 {waveform_occurrences}
 Island size histogram:
+{}
+Degree histogram:
 {}"#,
-            graph.node_count(),
-            graph.edge_count(),
-            pad_island_count as f32 / island_count as f32 * 100.0,
-            Self::generate_histogram(&island_sizes, island_data)
+            metrics.average_degree,
+            metrics.max_island_diameter(),
+            metrics.average_path_length,
+            metrics.clustering_coefficient,
+            island_graph.edge_count(),
+            *pad_island_count as f32 / island_count as f32 * 100.0,
+            colors.legend(),
+            Self::generate_histogram(island_sizes, &island_colors, DEFAULT_MIN_ISLAND_SIZE - 1),
+            Self::generate_histogram(&degrees, &degree_colors, 0),
         )
     }
 
-    pub fn generate_histogram(data: &[usize], extra_data: &[(Waveform, bool, f64)]) -> String {
+    /// Bins up to and including `max_bad_bin` get a red outline. `extra_data` has the color of every item, and whether it's a pad.
+    pub fn generate_histogram(
+        data: &[usize],
+        extra_data: &[(Color, bool)],
+        max_bad_bin: usize,
+    ) -> String {
         //Count occurrences of each number using a BTreeMap (sorted keys)
         let mut counts = BTreeMap::new();
         for (num, extra) in data.iter().zip_eq(extra_data) {
@@ -567,20 +3272,18 @@ Island size histogram:
                 //Invisible dummy string to avoid the line height changing
                 histogram_bar.push_str("[color=transparent]■[/color]");
             } else {
-                for (waveform, is_pad, _) in extras {
-                    let color = waveform.as_color();
-
+                for (color, is_pad) in extras {
                     //▮█ are both too wide, so use ■ instead
                     histogram_bar.push_str(&format!(
                         "[color={}]{}[/color]",
-                        color.to_html_without_alpha(),
+                        color_to_html(*color),
                         if *is_pad { '▲' } else { '■' } //Alternatively '△' and '○'
                     ));
                 }
             }
-            let formatted_num = if num <= 2 {
+            let formatted_num = if num <= max_bad_bin {
                 format!(
-                    //Island too small - give it a red outline
+                    //Island too small (or similar) - give it a red outline
                     "[outline_size=5][outline_color=red]{num:>3}[/outline_color][/outline_size]",
                 )
             } else {
@@ -594,50 +3297,130 @@ Island size histogram:
     }
 }
 
-pub const DEFAULT_EDGE_TWEEN_PROGRESS: f32 = -999999.0; //Ensures the edge hides the progress indicator in the shader
-
-fn setup_multimesh(mut multi: Gd<MultiMesh>, graph: &GraphTypedef) {
-    let edge_count = graph.edge_count();
+/// Volume of the metronome click at `tick`. Beats take their volume from `accent_pattern` (repeating it if the bar is longer),
+/// the ticks in between are always quiet. An empty pattern accents only the downbeat.
+pub fn metronome_volume(tick: &Tick, accent_pattern: &[f32]) -> f32 {
+    if !tick.is_beat() {
+        TICK_CLICK_VOLUME
+    } else if !accent_pattern.is_empty() {
+        accent_pattern[tick.beat % accent_pattern.len()]
+    } else if tick.is_bar() {
+        BAR_CLICK_VOLUME
+    } else {
+        BEAT_CLICK_VOLUME
+    }
+}
 
-    multi.set_instance_count(edge_count as i32);
+/// Samples `curve` at `position` (0..1) along its domain, and returns the value as a fraction (0..1) of its value range.
+fn sample_normalized(curve: &Gd<Curve>, position: f32) -> f32 {
+    let (min_domain, max_domain) = (curve.get_min_domain(), curve.get_max_domain());
+    let (min_value, max_value) = (curve.get_min_value(), curve.get_max_value());
+    let value = curve.sample(min_domain + (max_domain - min_domain) * position);
+    (value - min_value) / (max_value - min_value).max(f32::EPSILON)
+}
 
-    //NOTE - this will only work correctly if you don't add new edges after deleting them (else edge ids will no longer be consecutive)
-    for (i, edge) in graph.edge_references().enumerate() {
-        let i = i as i32;
-        let a = graph[edge.source()];
-        let b = graph[edge.target()];
+/// `path` as a `user://` path, unless it already is one.
+fn user_path(path: &str) -> String {
+    if path.starts_with("user://") {
+        path.to_string()
+    } else {
+        format!("user://{path}")
+    }
+}
 
-        let direction = b - a;
-        let length = direction.length();
-        let midpoint = a + direction * 0.5;
+/// How many nodes `AudioGraph::play_intro_animation` spawns per frame when the animation is skipped.
+pub const SKIPPED_INTRO_NODES_PER_FRAME: usize = 500;
 
-        //Create basis that rotates +Y to the direction vector
-        let up = Vector3::UP;
-        let axis = up.cross(direction).normalized();
-        let angle = up.angle_to(direction);
+/// How `AudioGraph::play_intro_animation` spawns the nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntroPacing {
+    /// Evenly spread over time
+    Animated { nodes_per_second: u32 },
+    /// As fast as possible, see `SKIPPED_INTRO_NODES_PER_FRAME`
+    Skipped,
+}
 
-        let rotation = if angle.abs() < f32::EPSILON {
-            Basis::IDENTITY
+impl IntroPacing {
+    /// `nodes_per_second` is `AudioGraph::intro_nodes_per_second`, where 0 skips the animation too.
+    pub fn new(skip_intro: bool, nodes_per_second: u32) -> Self {
+        if skip_intro || nodes_per_second == 0 {
+            IntroPacing::Skipped
         } else {
-            Basis::from_axis_angle(axis, angle)
-        };
+            IntroPacing::Animated { nodes_per_second }
+        }
+    }
+}
 
-        //Stretch the cylinder (assumes original height = 1.0)
-        let scale = Vector3::new(1.0, length, 1.0);
-        let basis = rotation * Basis::from_scale(scale);
-        let transform = Transform3D::new(basis, midpoint);
+/// Splits `0..count` into ranges of at most `chunk_size` (at least 1), e.g. the nodes to spawn per frame.
+pub fn intro_chunks(count: usize, chunk_size: usize) -> impl Iterator<Item = Range<usize>> {
+    let chunk_size = chunk_size.max(1);
+    (0..count)
+        .step_by(chunk_size)
+        .map(move |start| start..(start + chunk_size).min(count))
+}
 
-        multi.set_instance_transform(i, transform);
-        multi.set_instance_color(i, Color::BLACK);
-        multi.set_instance_custom_data(
-            i,
-            Color::from_rgba(DEFAULT_EDGE_TWEEN_PROGRESS, 0.0, 0.0, 0.0),
-        );
+/// Whether an object is still alive, like `Gd::is_instance_valid`. Lets the helpers below be tested without an engine.
+pub trait Liveness {
+    fn is_alive(&self) -> bool;
+}
+
+impl<T: GodotClass> Liveness for Gd<T> {
+    fn is_alive(&self) -> bool {
+        self.is_instance_valid()
+    }
+}
+
+/// The node at `idx`, None if it's missing or dead. Use this instead of indexing a snapshot of `graph_godot_nodes` after an await.
+pub fn live_node<N: Liveness + Clone>(nodes: &BTreeMap<NodeIndex, N>, idx: NodeIndex) -> Option<N> {
+    nodes.get(&idx).filter(|node| node.is_alive()).cloned()
+}
+
+/// Validates a node index coming from GDScript, see `AudioGraph::start_walk_from`. None if it's negative, out of range or freed.
+pub fn script_node<N: Liveness + Clone>(
+    nodes: &BTreeMap<NodeIndex, N>,
+    node_idx: i64,
+) -> Option<(NodeIndex, N)> {
+    let idx = NodeIndex::new(usize::try_from(node_idx).ok()?);
+    live_node(nodes, idx).map(|node| (idx, node))
+}
+
+/// Removes the dead nodes, returns their indices.
+pub fn remove_dead_nodes<N: Liveness>(nodes: &mut BTreeMap<NodeIndex, N>) -> Vec<NodeIndex> {
+    let dead = nodes
+        .iter()
+        .filter(|(_, node)| !node.is_alive())
+        .map(|(&idx, _)| idx)
+        .collect_vec();
+    for idx in &dead {
+        nodes.remove(idx);
     }
+    dead
 }
 
-fn reset_multimesh(mut multi: Gd<MultiMesh>) {
-    multi.set_instance_count(0);
+/// Removes `node` from `nodes`, but only if it's the one at `idx` (a pooled node keeps its old index).
+/// Doesn't clone `nodes` if there's nothing to remove. Returns whether it was removed.
+pub fn forget_node<N: PartialEq + Clone>(
+    nodes: &mut Rc<BTreeMap<NodeIndex, N>>,
+    idx: NodeIndex,
+    node: &N,
+) -> bool {
+    if nodes.get(&idx) != Some(node) {
+        return false;
+    }
+    Rc::make_mut(nodes).remove(&idx);
+    true
+}
+
+/// Changes the instance count, but keeps the data of the first instances (set_instance_count alone clears everything).
+/// New instances are all-zero, so they're hidden.
+pub(crate) fn resize_multimesh(multi: &mut Gd<MultiMesh>, instance_count: usize) {
+    let old_buffer = multi.get_buffer();
+    multi.set_instance_count(instance_count as i32);
+
+    let mut buffer = multi.get_buffer();
+    let len = old_buffer.len().min(buffer.len());
+    buffer.as_mut_slice()[..len].copy_from_slice(&old_buffer.as_slice()[..len]);
+    multi.set_buffer(&buffer);
 }
 
 impl AsyncNode for AudioGraph {
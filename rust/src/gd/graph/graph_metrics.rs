@@ -0,0 +1,123 @@
+//! Connectivity metrics of a generated constellation, shown in the statistics tab.
+
+use std::collections::VecDeque;
+
+use petgraph::graph::NodeIndex;
+use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator as _};
+use serde::{Deserialize, Serialize};
+
+use crate::gd::graph::graph_main::GraphTypedef;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GraphMetrics {
+    /// Longest shortest path (in edges) within every island, indexed like `ConstellationGraph::islands`.
+    /// This bounds how long a walk can keep going somewhere new.
+    pub island_diameters: Vec<usize>,
+    /// Mean shortest path length over all pairs of nodes that are in the same island.
+    pub average_path_length: f32,
+    pub average_degree: f32,
+    /// Mean local clustering coefficient, nodes with fewer than 2 neighbors count as 0.
+    pub clustering_coefficient: f32,
+}
+
+impl GraphMetrics {
+    /// BFS from every node, so this is quadratic in the island size. That's fine for islands of up to ~60 nodes.
+    pub fn compute(graph: &GraphTypedef, islands: &[Vec<NodeIndex>]) -> Self {
+        let node_count = graph.node_count();
+        if node_count == 0 {
+            return Self::default();
+        }
+
+        // Per island: (diameter, sum of path lengths, amount of pairs)
+        let island_paths = islands
+            .par_iter()
+            .map(|island| {
+                island
+                    .iter()
+                    .map(|start| bfs_distances(graph, *start))
+                    .fold((0, 0, 0), |(diameter, sum, pairs), distances| {
+                        let others = distances.iter().filter(|d| **d > 0);
+                        (
+                            diameter.max(distances.iter().copied().max().unwrap_or(0)),
+                            sum + others.clone().sum::<usize>(),
+                            pairs + others.count(),
+                        )
+                    })
+            })
+            .collect::<Vec<_>>(); // Preserves island order, so the sums below are deterministic
+
+        let (path_sum, pair_count) = island_paths
+            .iter()
+            .fold((0, 0), |(sum, pairs), (_, s, p)| (sum + s, pairs + p));
+        let average_path_length = if pair_count > 0 {
+            (path_sum as f64 / pair_count as f64) as f32
+        } else {
+            0.0
+        };
+
+        let clustering_sum = graph
+            .node_indices()
+            .map(|node| local_clustering_coefficient(graph, node))
+            .sum::<f64>();
+
+        Self {
+            island_diameters: island_paths
+                .iter()
+                .map(|(diameter, _, _)| *diameter)
+                .collect(),
+            average_path_length,
+            average_degree: (2.0 * graph.edge_count() as f64 / node_count as f64) as f32,
+            clustering_coefficient: (clustering_sum / node_count as f64) as f32,
+        }
+    }
+
+    pub fn max_island_diameter(&self) -> usize {
+        self.island_diameters.iter().copied().max().unwrap_or(0)
+    }
+}
+
+/// Distances (in edges) from `start` to every node in its island, in no particular order. Includes `start` itself (0).
+fn bfs_distances(graph: &GraphTypedef, start: NodeIndex) -> Vec<usize> {
+    let mut distance_by_node = vec![None; graph.node_count()];
+    distance_by_node[start.index()] = Some(0);
+
+    let mut distances = vec![];
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        let distance = distance_by_node[node.index()].unwrap();
+        distances.push(distance);
+
+        for neighbor in graph.neighbors(node) {
+            if distance_by_node[neighbor.index()].is_none() {
+                distance_by_node[neighbor.index()] = Some(distance + 1);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    distances
+}
+
+/// Fraction of the pairs of neighbors of `node` that are neighbors of each other.
+fn local_clustering_coefficient(graph: &GraphTypedef, node: NodeIndex) -> f64 {
+    let mut neighbors = graph
+        .neighbors(node)
+        .filter(|n| *n != node)
+        .collect::<Vec<_>>();
+    neighbors.sort_unstable();
+    neighbors.dedup();
+
+    let k = neighbors.len();
+    if k < 2 {
+        return 0.0;
+    }
+
+    let mut linked_pairs = 0;
+    for (i, a) in neighbors.iter().enumerate() {
+        for b in &neighbors[i + 1..] {
+            if graph.contains_edge(*a, *b) {
+                linked_pairs += 1;
+            }
+        }
+    }
+    linked_pairs as f64 / (k * (k - 1) / 2) as f64
+}
@@ -0,0 +1,209 @@
+//! Recording walks, so a nice take can be saved and replayed later. See `AudioGraph::replay`.
+
+use std::collections::BTreeMap;
+
+use godot::prelude::*;
+use petgraph::{
+    Direction,
+    graph::{EdgeIndex, NodeIndex},
+};
+use serde::{Deserialize, Serialize};
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+use tracing::info_span;
+
+use crate::{
+    async_node::AsyncNode as _,
+    flags::WALKS_PAUSED,
+    gd::{
+        autoload::state_tick::TickReceiver,
+        graph::{
+            graph_main::{AudioGraph, GraphTypedef, live_node},
+            graph_walk::{LaunchQuantization, wait_for_launch},
+        },
+        node_main::AudioNode,
+    },
+};
+
+/// A node played by a walk.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WalkEvent {
+    /// `Tick::total_ticks` when the node was played. Relative to the start of the recording once it's finished.
+    /// Ticks while walks were paused count too, so a pause ends up as a rest in the replay.
+    pub tick: usize,
+    pub node: NodeIndex,
+    /// The edge that was walked to get here. None for the first node of a walk, or after wrapping around at a dead end.
+    pub edge: Option<EdgeIndex>,
+    /// How many ticks it took to get here (and how long the edge animation took).
+    pub beats_waited: usize,
+    /// See `note_velocity`. Recordings made before velocity existed play at full velocity.
+    #[serde(default = "full_velocity")]
+    pub velocity: f32,
+}
+
+fn full_velocity() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WalkRecording {
+    /// The seed of the constellation (see `AudioGraph::graph_seed`), a recording only makes sense on the graph it was recorded on.
+    pub seed: i64,
+    /// Sorted by tick, the first one is at tick 0.
+    pub events: Vec<WalkEvent>,
+}
+
+/// What to do at a tick of a replay, see `WalkRecording::schedule`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayAction {
+    /// Animate `edge` towards `to_node`, taking `beats` ticks.
+    LerpEdge {
+        edge: EdgeIndex,
+        to_node: NodeIndex,
+        beats: usize,
+    },
+    Play {
+        node: NodeIndex,
+        velocity: f32,
+    },
+}
+
+impl WalkRecording {
+    /// The actions of a replay, keyed by tick relative to the start of the replay.
+    /// These are ticks and not seconds, so replays follow the current BPM instead of the one it was recorded at.
+    pub fn schedule(&self) -> BTreeMap<usize, Vec<ReplayAction>> {
+        let mut schedule = BTreeMap::<usize, Vec<ReplayAction>>::new();
+        for event in &self.events {
+            if let Some(edge) = event.edge {
+                schedule
+                    .entry(event.tick.saturating_sub(event.beats_waited))
+                    .or_default()
+                    .push(ReplayAction::LerpEdge {
+                        edge,
+                        to_node: event.node,
+                        beats: event.beats_waited,
+                    });
+            }
+            schedule
+                .entry(event.tick)
+                .or_default()
+                .push(ReplayAction::Play {
+                    node: event.node,
+                    velocity: event.velocity,
+                });
+        }
+        schedule
+    }
+}
+
+/// Collects the events of all walks that were started while recording, see `WalkState::recorder`.
+#[derive(Debug)]
+pub struct WalkRecorder {
+    seed: i64,
+    tx: flume::Sender<WalkEvent>,
+    rx: flume::Receiver<WalkEvent>,
+}
+
+impl WalkRecorder {
+    pub fn new(seed: i64) -> Self {
+        let (tx, rx) = flume::unbounded();
+        Self { seed, tx, rx }
+    }
+
+    /// Give this to every walk that should be recorded.
+    pub fn sender(&self) -> flume::Sender<WalkEvent> {
+        self.tx.clone()
+    }
+
+    /// Stops recording. Walks that are still running keep their sender, but whatever they send from now on is ignored.
+    pub fn finish(self) -> WalkRecording {
+        let mut events = self.rx.drain().collect::<Vec<_>>();
+        events.sort_by_key(|event| event.tick); // Stable, so branches keep their order
+
+        let start_tick = events.first().map_or(0, |event| event.tick);
+        for event in &mut events {
+            event.tick -= start_tick;
+        }
+
+        WalkRecording {
+            seed: self.seed,
+            events,
+        }
+    }
+}
+
+impl AudioGraph {
+    /// Runs the actions of `WalkRecording::schedule`, starting when `launch` says so, until they're done or `cancel` gets cancelled.
+    /// Ticks that arrive while walks are paused (see `WALKS_PAUSED`) don't count, like with walks.
+    pub async fn replay_schedule(
+        mut this: Gd<Self>,
+        graph: &GraphTypedef,
+        graph_assoc: &BTreeMap<NodeIndex, Gd<AudioNode>>,
+        schedule: BTreeMap<usize, Vec<ReplayAction>>,
+        mut ticks: TickReceiver,
+        launch: LaunchQuantization,
+        cancel: CancellationToken,
+    ) {
+        // Like walks
+        if wait_for_launch(&mut ticks, launch, &cancel).await.is_none() {
+            tracing::info!("replay cancelled before launching");
+            return;
+        }
+
+        let mut elapsed_ticks = 0;
+        for (relative_tick, actions) in schedule {
+            while elapsed_ticks < relative_tick {
+                select! {
+                    _ = ticks.wait() => {
+                        if !WALKS_PAUSED.get() {
+                            elapsed_ticks += 1;
+                        }
+                    }
+                    _ = cancel.cancelled() => {
+                        tracing::info!("replay cancelled");
+                        return;
+                    }
+                };
+            }
+
+            for action in actions {
+                match action {
+                    ReplayAction::LerpEdge {
+                        edge,
+                        to_node,
+                        beats,
+                    } => {
+                        let (_, target) = graph.edge_endpoints(edge).unwrap();
+                        let edge_dir = if target == to_node {
+                            Direction::Outgoing
+                        } else {
+                            Direction::Incoming
+                        };
+                        Self::lerp_edge(
+                            &mut this,
+                            (0.0, beats as f64),
+                            ((edge, edge_dir), 1.0),
+                            cancel.clone(),
+                        );
+                    }
+                    ReplayAction::Play { node, velocity } => {
+                        let Some(mut node) = live_node(graph_assoc, node) else {
+                            tracing::warn!("node {node:?} was freed, skipping it in the replay");
+                            continue;
+                        };
+                        let cancel = cancel.clone();
+                        this.bind_mut().spawn_local_task(
+                            false,
+                            info_span!("play"),
+                            async move |_this| {
+                                AudioNode::play(&mut node, 1.0, velocity, cancel).await;
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        tracing::info!("replay finished");
+    }
+}
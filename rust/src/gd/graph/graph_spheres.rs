@@ -0,0 +1,145 @@
+use std::collections::BTreeSet;
+
+use godot::{classes::MultiMesh, prelude::*};
+
+use crate::gd::graph::graph_main::resize_multimesh;
+
+/// One node sphere in the spheres MultiMesh, see `SphereBatch`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SphereInstance {
+    pub position: Vector3,
+    /// Includes the alpha, which fades the sphere in and out
+    pub color: Color,
+    pub scale: f32,
+    /// Pads use a different texture
+    pub is_pad: bool,
+}
+
+/// The spheres of all AudioNodes, rendered by a single MultiMesh instead of a MeshInstance3D (and material) per node.
+///
+/// The instances are indexed by node_idx, just like the graph nodes. AudioNodes write into the batch (see `AudioNode::set_mat_color`),
+/// and AudioGraph writes only the changed instances to the MultiMesh once per frame, see `flush`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SphereBatch {
+    instances: Vec<Option<SphereInstance>>, // Indexed by node_idx, None if there's no node (yet)
+    dirty: BTreeSet<usize>,
+}
+
+impl SphereBatch {
+    /// Sets the whole instance, e.g. when a node gets (re)configured.
+    pub fn set(&mut self, node_idx: usize, instance: SphereInstance) {
+        if node_idx >= self.instances.len() {
+            self.instances.resize(node_idx + 1, None);
+        }
+        self.instances[node_idx] = Some(instance);
+        self.dirty.insert(node_idx);
+    }
+
+    pub fn get(&self, node_idx: usize) -> Option<SphereInstance> {
+        self.instances.get(node_idx).copied().flatten()
+    }
+
+    /// Does nothing if the node has no instance.
+    pub fn set_color(&mut self, node_idx: usize, color: Color) {
+        self.update(node_idx, |instance| instance.color = color);
+    }
+
+    /// Does nothing if the node has no instance.
+    pub fn set_scale(&mut self, node_idx: usize, scale: f32) {
+        self.update(node_idx, |instance| instance.scale = scale);
+    }
+
+    fn update(&mut self, node_idx: usize, f: impl FnOnce(&mut SphereInstance)) {
+        if let Some(Some(instance)) = self.instances.get_mut(node_idx) {
+            f(instance);
+            self.dirty.insert(node_idx);
+        }
+    }
+
+    /// Call this right after `Graph::remove_node(node_idx)`. Just like petgraph, the last node takes over the index of the removed one.
+    pub fn remove(&mut self, node_idx: usize) {
+        if node_idx >= self.instances.len() {
+            return;
+        }
+        let last = self.instances.len() - 1;
+        self.instances.swap_remove(node_idx);
+        self.dirty.extend([node_idx, last]);
+    }
+
+    /// Hides the instance, without moving the other ones like `remove` does.
+    pub fn hide(&mut self, node_idx: usize) {
+        if let Some(instance) = self.instances.get_mut(node_idx) {
+            *instance = None;
+            self.dirty.insert(node_idx);
+        }
+    }
+
+    /// Removes all instances, the ones that were set get hidden on the next `flush`.
+    pub fn clear(&mut self) {
+        self.dirty.extend(0..self.instances.len());
+        self.instances.clear();
+    }
+
+    /// Amount of instances the MultiMesh needs.
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn dirty_count(&self) -> usize {
+        self.dirty.len()
+    }
+
+    /// The instances that changed since the last call, None for the ones that should be hidden.
+    pub fn take_dirty(&mut self) -> Vec<(usize, Option<SphereInstance>)> {
+        std::mem::take(&mut self.dirty)
+            .into_iter()
+            .map(|node_idx| (node_idx, self.get(node_idx)))
+            .collect()
+    }
+
+    /// Writes the changed instances to `multi`, growing it if needed. Returns how many instances were written.
+    pub fn flush(&mut self, multi: &mut Gd<MultiMesh>) -> usize {
+        if self.dirty.is_empty() {
+            return 0;
+        }
+
+        let instance_count = multi.get_instance_count() as usize;
+        if self.instances.len() > instance_count {
+            resize_multimesh(multi, self.instances.len().next_power_of_two());
+        }
+        let instance_count = multi.get_instance_count() as usize;
+
+        let dirty = self.take_dirty();
+        for &(node_idx, instance) in &dirty {
+            if node_idx >= instance_count {
+                continue; // Removed before it was ever written, so there's nothing to hide
+            }
+            let i = node_idx as i32;
+            match instance {
+                Some(SphereInstance {
+                    position,
+                    color,
+                    scale,
+                    is_pad,
+                }) => {
+                    multi.set_instance_transform(i, Transform3D::new(Basis::IDENTITY, position));
+                    multi.set_instance_color(i, color);
+                    multi.set_instance_custom_data(
+                        i,
+                        Color::from_rgba(scale, if is_pad { 1.0 } else { 0.0 }, 0.0, 0.0),
+                    );
+                }
+                None => {
+                    // A scale of zero hides it, see node_sphere.gdshader
+                    multi.set_instance_color(i, Color::from_rgba(0.0, 0.0, 0.0, 0.0));
+                    multi.set_instance_custom_data(i, Color::from_rgba(0.0, 0.0, 0.0, 0.0));
+                }
+            }
+        }
+        dirty.len()
+    }
+}
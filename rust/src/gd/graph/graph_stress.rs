@@ -0,0 +1,81 @@
+//! The stress test: a lot of nodes playing at once, measuring how the frame rate holds up. See `AudioGraph::start_stress_test`.
+
+use std::fmt;
+
+/// How long after starting the notes the stress test measures.
+pub const STRESS_MEASURE_SECS: f64 = 5.0;
+
+/// Collects the frames during a stress test, until `duration` seconds have passed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StressMeasurement {
+    duration: f64,
+    elapsed: f64,
+    frames: u32,
+    max_delta: f64,
+    peak_streams: u32,
+}
+
+impl StressMeasurement {
+    pub fn new(duration: f64) -> Self {
+        Self {
+            duration,
+            elapsed: 0.0,
+            frames: 0,
+            max_delta: 0.0,
+            peak_streams: 0,
+        }
+    }
+
+    /// Records a frame that took `delta` seconds, with `active_streams` streams playing (see `ACTIVE_STREAMS`).
+    /// Returns true once the measurement is done, later frames are ignored.
+    pub fn frame(&mut self, delta: f64, active_streams: u32) -> bool {
+        if self.is_done() {
+            return true;
+        }
+        self.elapsed += delta;
+        self.frames += 1;
+        self.max_delta = self.max_delta.max(delta);
+        self.peak_streams = self.peak_streams.max(active_streams);
+        self.is_done()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// None if there were no frames (or they took no time).
+    pub fn summary(&self, note_count: usize) -> Option<StressSummary> {
+        if self.frames == 0 || self.elapsed <= 0.0 || self.max_delta <= 0.0 {
+            return None;
+        }
+        Some(StressSummary {
+            note_count,
+            min_fps: 1.0 / self.max_delta,
+            avg_fps: self.frames as f64 / self.elapsed,
+            peak_streams: self.peak_streams,
+            secs: self.elapsed,
+        })
+    }
+}
+
+/// The result of a stress test, see `StressMeasurement::summary`. Displays as a single line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StressSummary {
+    pub note_count: usize,
+    /// Of the slowest frame
+    pub min_fps: f64,
+    /// Over the whole measurement, so frames count by how long they took
+    pub avg_fps: f64,
+    pub peak_streams: u32,
+    pub secs: f64,
+}
+
+impl fmt::Display for StressSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Stress test: {} notes, {:.1} min FPS, {:.1} avg FPS, {} peak streams over {:.1}s",
+            self.note_count, self.min_fps, self.avg_fps, self.peak_streams, self.secs
+        )
+    }
+}
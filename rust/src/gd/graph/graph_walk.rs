@@ -1,41 +1,1084 @@
-use std::{collections::BTreeMap, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
 
 use futures::future::join_all;
 use godot::{obj::Gd, prelude::*};
+use itertools::Itertools as _;
 use ordered_float::OrderedFloat;
 use petgraph::{
     Direction,
+    algo::astar,
     graph::{EdgeIndex, NodeIndex},
+    visit::{Bfs, EdgeRef as _},
 };
-use rand::Rng;
+use rand::{Rng, seq::IndexedRandom as _};
+use rand_xoshiro::Xoshiro256Plus;
+use serde::{Deserialize, Serialize};
 use tokio::select;
 use tokio_util::sync::CancellationToken;
 use tracing::info_span;
 
 use crate::{
-    async_node::{AsyncNode as _, wait_for_next_frame},
+    async_node::{AsyncNode as _, wait_for_duration, wait_for_next_frame},
+    flags::WALKS_PAUSED,
     gd::{
         autoload::{
             state_main::AudioState,
-            state_tick::{TickReceiver, subscribe_to_ticks},
+            state_tick::{Tick, TickReceiver, subscribe_to_ticks},
+        },
+        graph::{
+            graph_edge_renderer::EdgeTweenGuard,
+            graph_main::{AudioGraph, GraphTypedef, live_node},
+            graph_record::WalkEvent,
         },
-        graph::graph_main::{AudioGraph, DEFAULT_EDGE_TWEEN_PROGRESS, GraphTypedef},
-        node_main::AudioNode,
+        node_main::{AudioNode, PlayAction},
+    },
+    util::{
+        create_rng_from_seed_and_state, ease_in_out_sine, ease_out_quad, round_to_nearest_pow2_f64,
     },
-    util::round_to_nearest_pow2_f64,
 };
 
+/// How a walk picks the next node. The first step always branches out to every neighbor of the clicked node,
+/// and every walk ends when it reaches a node without other neighbors than the one it came from.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export, Serialize, Deserialize,
+)]
+#[godot(via = i64)]
+pub enum WalkStrategy {
+    /// The neighbor that best preserves the direction of the walk, so melodies run in straight-ish lines
+    #[default]
+    DirectionPreserving,
+    /// Any neighbor, including the one we just came from
+    RandomNeighbor,
+    /// Any neighbor, but the shorter the edge the more likely (shorter edges mean faster rhythms)
+    WeightedByDistance,
+    /// Any neighbor, except the one we just came from
+    AvoidBacktrack,
+}
+
+/// What a walk does with nodes it (or one of its branches) already visited.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export)]
+#[godot(via = i64)]
+pub enum RevisitPolicy {
+    /// Visited nodes are treated like any other node
+    Allow,
+    /// Prefer unvisited neighbors, only go back to visited ones if there are no unvisited neighbors left
+    #[default]
+    FallBack,
+    /// Stop the walk once all neighbors have been visited
+    Stop,
+}
+
+/// What a walk does when it reaches a dead end (a node without other neighbors than the one it came from).
+/// Anything but `Stop` only applies if the walk has a step budget, otherwise two dead ends would ping-pong forever.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export)]
+#[godot(via = i64)]
+pub enum DeadEndBehavior {
+    /// End the walk
+    #[default]
+    Stop,
+    /// Walk back the way we came
+    Bounce,
+    /// Jump to a random node on the same island, without walking an edge
+    Wrap,
+}
+
+/// Which note lengths (in ticks) edges get rounded to, see `RhythmGrid::quantize`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export)]
+#[godot(via = i64)]
+pub enum RhythmGrid {
+    /// 1, 2, 4, 8 or 16 ticks
+    #[default]
+    PowersOfTwo,
+    /// Powers of two plus 3 and 6 ticks
+    WithTriplets,
+    /// Powers of two plus 3 and 12 ticks (dotted eighths and dotted halves)
+    WithDotted,
+    /// Any whole number of ticks
+    Free,
+}
+
+impl RhythmGrid {
+    /// Rounds `ticks` to the nearest length on this grid. Except for `Free`, "nearest" is on a log2 scale, like `round_to_nearest_pow2_f64`.
+    pub fn quantize(self, ticks: f64) -> f64 {
+        let extra_lengths: &[f64] = match self {
+            RhythmGrid::PowersOfTwo => &[],
+            RhythmGrid::WithTriplets => &[3.0, 6.0],
+            RhythmGrid::WithDotted => &[3.0, 12.0],
+            RhythmGrid::Free => return ticks.round(),
+        };
+
+        let pow2 = round_to_nearest_pow2_f64(ticks);
+        if ticks <= 0.0 {
+            return pow2;
+        }
+        extra_lengths
+            .iter()
+            .copied()
+            .chain([pow2])
+            .min_by_key(|length| OrderedFloat((length.log2() - ticks.log2()).abs()))
+            .unwrap()
+    }
+}
+
+/// The settings a walk is started with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WalkConfig {
+    pub strategy: WalkStrategy,
+    pub revisits: RevisitPolicy,
+    pub dead_ends: DeadEndBehavior,
+    /// How many nodes a single click may play in total (over all branches), 0 is unlimited.
+    pub max_steps: usize,
+    /// How many branches a walk may split into at once, 0 is unlimited.
+    pub max_concurrent_branches: usize,
+    pub rhythm_grid: RhythmGrid,
+    /// How much `WalkStrategy::DirectionPreserving` may turn, see `softmax_choice`. 0 always goes the straightest way.
+    pub direction_temperature: f64,
+}
+
+/// How a walk ended, see `WalkState::end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkEnd {
+    /// Every branch reached a dead end
+    DeadEnd,
+    /// Stopped by the user (panic button, middle-click, right-clicking a node, regenerating...)
+    Cancelled,
+    /// Ran out of `WalkConfig::max_steps`
+    StepBudget,
+}
+
+impl WalkEnd {
+    /// How this shows up in the `walk_finished` signal.
+    pub fn name(self) -> &'static str {
+        match self {
+            WalkEnd::DeadEnd => "dead_end",
+            WalkEnd::Cancelled => "cancelled",
+            WalkEnd::StepBudget => "step_budget",
+        }
+    }
+}
+
+/// Statistics of a walk, accumulated by all its branches. Surfaced by the `walk_finished` signal of `AudioGraph`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WalkStats {
+    /// Rests don't count
+    pub notes_played: usize,
+    /// Every node a walk reached, including rests
+    pub walked: BTreeSet<NodeIndex>,
+    /// `Tick::total_ticks` of the first and last node reached
+    pub ticks: Option<(usize, usize)>,
+    /// Most notes a single branch played in a row, without resting
+    pub longest_run: usize,
+    /// Whether a branch was stopped by a right-clicked node
+    pub stopped_by_node: bool,
+}
+
+impl WalkStats {
+    /// Call for every node a walk reaches at `tick`. `run` is how many notes in a row its branch played, including this one.
+    pub fn visit(&mut self, node_idx: NodeIndex, tick: usize, played: bool, run: usize) {
+        if played {
+            self.notes_played += 1;
+        }
+        self.walked.insert(node_idx);
+        self.ticks = Some(match self.ticks {
+            Some((first, last)) => (first.min(tick), last.max(tick)),
+            None => (tick, tick),
+        });
+        self.longest_run = self.longest_run.max(run);
+    }
+
+    /// How long the walk took, from the first to the last node.
+    pub fn total_ticks(&self) -> usize {
+        self.ticks.map_or(0, |(first, last)| last - first)
+    }
+}
+
+/// State shared by all branches of a single walk.
+#[derive(Debug)]
+pub struct WalkState {
+    /// The nodes played (or about to be played) by any branch
+    pub visited: RefCell<BTreeSet<NodeIndex>>,
+    /// How many more nodes may be played
+    pub remaining_steps: Cell<usize>,
+    /// Only set while recording, see `WalkRecorder`
+    pub recorder: Option<flume::Sender<WalkEvent>>,
+    pub stats: RefCell<WalkStats>,
+    /// Empty unless set with `with_trail`
+    pub trail: RefCell<TrailState>,
+    /// Only set for walks in `ActiveWalks`, see `with_walk_id`
+    pub walk_id: Option<WalkId>,
+}
+
+impl WalkState {
+    /// The state of a walk that just played `start_idx`.
+    pub fn new(start_idx: NodeIndex, config: &WalkConfig) -> Self {
+        let max_steps = match config.max_steps {
+            0 => usize::MAX,
+            max_steps => max_steps,
+        };
+        Self {
+            visited: RefCell::new(BTreeSet::from([start_idx])),
+            remaining_steps: Cell::new(max_steps - 1),
+            recorder: None,
+            stats: RefCell::default(),
+            trail: RefCell::default(),
+            walk_id: None,
+        }
+    }
+
+    pub fn with_recorder(self, recorder: Option<flume::Sender<WalkEvent>>) -> Self {
+        Self { recorder, ..self }
+    }
+
+    /// Lets the walk report where it is, see `AudioGraph::walk_moved`.
+    pub fn with_walk_id(self, walk_id: WalkId) -> Self {
+        Self {
+            walk_id: Some(walk_id),
+            ..self
+        }
+    }
+
+    pub fn with_trail(self, trail_length: usize) -> Self {
+        Self {
+            trail: RefCell::new(TrailState::new(trail_length)),
+            ..self
+        }
+    }
+
+    pub fn record(&self, event: WalkEvent) {
+        if let Some(recorder) = &self.recorder {
+            let _ = recorder.send(event); // The recording may have been stopped already
+        }
+    }
+
+    /// Why the walk ended, call this once all branches are done.
+    pub fn end(&self, cancel: &CancellationToken) -> WalkEnd {
+        if cancel.is_cancelled() || self.stats.borrow().stopped_by_node {
+            WalkEnd::Cancelled
+        } else if self.remaining_steps.get() == 0 {
+            WalkEnd::StepBudget
+        } else {
+            WalkEnd::DeadEnd
+        }
+    }
+}
+
+impl WalkConfig {
+    /// Like `WalkStrategy::next_nodes`, but within the limits of this config.
+    /// The returned nodes are marked visited and their steps are taken from the budget right away,
+    /// so other branches won't walk to them, and every walked edge ends in a played note.
+    pub fn next_nodes<R: Rng>(
+        &self,
+        graph: &GraphTypedef,
+        node_idx: NodeIndex,
+        previous_idx: Option<NodeIndex>,
+        state: &WalkState,
+        rng: &mut R,
+    ) -> Vec<NodeIndex> {
+        let mut next_node_idxes = match previous_idx {
+            Some(previous_idx) if self.max_steps > 0 && graph.neighbors(node_idx).count() <= 1 => {
+                self.dead_end_nodes(graph, node_idx, previous_idx, rng)
+            }
+            _ => self.strategy.next_nodes(
+                graph,
+                node_idx,
+                previous_idx,
+                &state.visited.borrow(),
+                self.revisits,
+                self.direction_temperature,
+                rng,
+            ),
+        };
+
+        // Prefer the branches that best preserve the direction of the walk.
+        // The first step has no direction yet, so it keeps the order of the graph.
+        if let Some(previous_idx) = previous_idx {
+            let node_pos = graph[node_idx];
+            let last_dir = (node_pos - graph[previous_idx]).normalized();
+            next_node_idxes.sort_by_key(|next_idx| {
+                let dir = (graph[*next_idx] - node_pos).normalized();
+                Reverse(OrderedFloat::from(last_dir.dot(dir)))
+            });
+        }
+        if self.max_concurrent_branches > 0 {
+            next_node_idxes.truncate(self.max_concurrent_branches);
+        }
+
+        let remaining_steps = state.remaining_steps.get();
+        next_node_idxes.truncate(remaining_steps);
+        state
+            .remaining_steps
+            .set(remaining_steps - next_node_idxes.len());
+
+        state.visited.borrow_mut().extend(&next_node_idxes);
+        next_node_idxes
+    }
+
+    /// Where to go from the dead end `node_idx`. Ignores the revisit policy, since everything around a dead end is visited.
+    fn dead_end_nodes<R: Rng>(
+        &self,
+        graph: &GraphTypedef,
+        node_idx: NodeIndex,
+        previous_idx: NodeIndex,
+        rng: &mut R,
+    ) -> Vec<NodeIndex> {
+        match self.dead_ends {
+            DeadEndBehavior::Stop => vec![],
+            DeadEndBehavior::Bounce => vec![previous_idx],
+            DeadEndBehavior::Wrap => {
+                let mut bfs = Bfs::new(graph, node_idx);
+                let mut island = vec![];
+                while let Some(island_node_idx) = bfs.next(graph) {
+                    if island_node_idx != node_idx {
+                        island.push(island_node_idx);
+                    }
+                }
+                island.choose(rng).into_iter().copied().collect()
+            }
+        }
+    }
+}
+
+impl WalkStrategy {
+    /// Returns the node(s) to walk to from `node_idx`, if we came from `previous_idx`. Empty if the walk should end.
+    /// `direction_temperature` is only used by `DirectionPreserving`, see `WalkConfig::direction_temperature`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn next_nodes<R: Rng>(
+        self,
+        graph: &GraphTypedef,
+        node_idx: NodeIndex,
+        previous_idx: Option<NodeIndex>,
+        visited: &BTreeSet<NodeIndex>,
+        revisits: RevisitPolicy,
+        direction_temperature: f64,
+        rng: &mut R,
+    ) -> Vec<NodeIndex> {
+        let all_neighs = graph.neighbors(node_idx).collect::<Vec<_>>();
+        let node_pos = graph[node_idx];
+
+        // If there is only 1 neighbor (the one we came from), we reached end of the graph, so stop instantly.
+        if previous_idx.is_some() && all_neighs.len() <= 1 {
+            return vec![];
+        }
+
+        let unvisited = all_neighs
+            .iter()
+            .filter(|neigh_idx| !visited.contains(neigh_idx))
+            .copied()
+            .collect::<Vec<_>>();
+        let neighs = match revisits {
+            RevisitPolicy::Allow => all_neighs,
+            RevisitPolicy::FallBack if unvisited.is_empty() => all_neighs,
+            RevisitPolicy::Stop if unvisited.is_empty() => return vec![],
+            RevisitPolicy::FallBack | RevisitPolicy::Stop => unvisited,
+        };
+
+        // Multiple neighbors if the user clicks on a node with a degree of 2 or higher
+        let Some(previous_idx) = previous_idx else {
+            return neighs;
+        };
+
+        let next = match self {
+            WalkStrategy::DirectionPreserving => {
+                let last_dir = (node_pos - graph[previous_idx]).normalized();
+                let dots = neighs
+                    .iter()
+                    .map(|neigh_idx| {
+                        let dir = (graph[*neigh_idx] - node_pos).normalized();
+                        last_dir.dot(dir) as f64
+                    })
+                    .collect::<Vec<_>>();
+                softmax_choice(&dots, direction_temperature, rng).map(|i| &neighs[i])
+            }
+            WalkStrategy::RandomNeighbor => neighs.choose(rng),
+            WalkStrategy::WeightedByDistance => neighs
+                .choose_weighted(rng, |neigh_idx| {
+                    1.0 / graph[*neigh_idx].distance_to(node_pos).max(1e-3)
+                })
+                .ok(),
+            WalkStrategy::AvoidBacktrack => neighs
+                .iter()
+                .filter(|neigh_idx| **neigh_idx != previous_idx)
+                .collect::<Vec<_>>()
+                .choose(rng)
+                .copied(),
+        };
+
+        next.into_iter().copied().collect()
+    }
+}
+
+/// Picks the index of one of `scores` with a softmax at `temperature`, None if there are none.
+/// Temperature 0 (or less) always picks the highest score (the last one on ties) without touching `rng`,
+/// higher temperatures get closer and closer to picking uniformly.
+pub fn softmax_choice<R: Rng>(scores: &[f64], temperature: f64, rng: &mut R) -> Option<usize> {
+    let best = scores.iter().copied().map(OrderedFloat).position_max()?;
+    if temperature <= 0.0 {
+        return Some(best);
+    }
+
+    // Relative to the best score, so exp() can't overflow. All-equal scores all get weight 1.
+    let weights = scores
+        .iter()
+        .map(|score| ((score - scores[best]) / temperature).exp())
+        .collect::<Vec<_>>();
+    let indices = (0..scores.len()).collect::<Vec<_>>();
+    indices
+        .choose_weighted(rng, |i| weights[*i])
+        .ok()
+        .copied()
+        .or(Some(best)) // NaN scores
+}
+
+/// How bright the progress indicator of an edge is, when walking towards a node that rests. Normal edges are 1.0.
+const REST_EDGE_GLOW: f32 = 0.25;
+
+/// Until the first tick arrives, after that we use `Tick::ticks_per_beat`.
+fn default_ticks_per_beat() -> usize {
+    AudioState::autoload().bind().get_ticks_per_beat() as usize
+}
+
+/// How long a tick takes in seconds, at the current BPM.
+fn tick_interval(ticks_per_beat: usize) -> f64 {
+    60.0 / AudioState::autoload().bind().get_bpm() / ticks_per_beat as f64
+}
+
+/// Longest an edge can take, in beats.
+const MAX_EDGE_BEATS: usize = 16;
+
+/// Velocity of a note that isn't accented at all, see `note_velocity`.
+pub const BASE_VELOCITY: f32 = 0.6;
+
+/// The velocity (0 to 1) of a note played by a walk, after waiting `beats_waited` beats to reach a node with `degree` neighbors:
+/// - `BASE_VELOCITY` to start with.
+/// - Plus `junction_accent` if the node has 3 or more neighbors, so junctions feel like accents.
+/// - Plus `long_edge_accent`, scaled by the length of the edge on a log2 scale (edges are powers of 2 beats long):
+///   nothing for edges of 1 beat or less, half for 4 beats, all of it for `MAX_EDGE_BEATS` beats.
+pub fn note_velocity(
+    degree: usize,
+    beats_waited: usize,
+    junction_accent: f32,
+    long_edge_accent: f32,
+) -> f32 {
+    let junction = if degree >= 3 { junction_accent } else { 0.0 };
+    let edge_length =
+        (beats_waited.clamp(1, MAX_EDGE_BEATS) as f32).log2() / (MAX_EDGE_BEATS as f32).log2();
+
+    (BASE_VELOCITY + junction + long_edge_accent * edge_length).clamp(0.0, 1.0)
+}
+
+/// How many ticks a walk waits to walk from `from` to `to`, on `rhythm_grid`.
+pub fn edge_beats(rhythm_grid: RhythmGrid, from: Vector3, to: Vector3) -> usize {
+    rhythm_grid
+        .quantize((to - from).length() as f64 * 8.0)
+        .clamp(0.0, MAX_EDGE_BEATS as f64) as usize
+}
+
+/// How many ticks an edge of `beats` (see `edge_beats`) takes in an island with `tempo_mult`, see `IslandData::tempo_mult`.
+/// Clamped to 1..=`MAX_EDGE_BEATS`, so powers of two stay powers of two. Edges of 0 beats stay 0.
+pub fn island_edge_beats(beats: usize, tempo_mult: f64) -> usize {
+    ((beats as f64 * tempo_mult).round() as usize).clamp(beats.min(1), MAX_EDGE_BEATS)
+}
+
+/// The shortest path from `from` to `to` (both included) over the edge lengths, None if they aren't connected.
+pub fn shortest_path(
+    graph: &GraphTypedef,
+    from: NodeIndex,
+    to: NodeIndex,
+) -> Option<Vec<NodeIndex>> {
+    let target = graph[to];
+    astar(
+        graph,
+        from,
+        |node_idx| node_idx == to,
+        |edge| graph[edge.source()].distance_to(graph[edge.target()]),
+        |node_idx| graph[node_idx].distance_to(target),
+    )
+    .map(|(_, path)| path)
+}
+
+/// Whether a walk rests (walks to a node without playing it), given the rest probability of the node.
+/// Doesn't touch `rng` if `rest_probability` is 0, so disabling rests doesn't change where walks go.
+pub fn should_rest<R: Rng>(rest_probability: f64, rng: &mut R) -> bool {
+    rest_probability > 0.0 && rng.random_bool(rest_probability.min(1.0))
+}
+
+/// Humanizing: how many seconds to delay a note, uniformly up to `humanize_ms`. The delay is measured from the note's own tick,
+/// so it never accumulates over a walk. None if humanizing is off, in which case `rng` isn't touched either.
+pub fn humanize_delay<R: Rng>(humanize_ms: f64, rng: &mut R) -> Option<f64> {
+    (humanize_ms > 0.0).then(|| rng.random_range(0.0..humanize_ms) / 1000.0)
+}
+
+/// Shortens a delay of `delay` seconds by how late we already are (`lag`, see `Tick::lag`), so a delayed note still lands where it should.
+/// Never negative, if we're later than the delay we just play right away.
+pub fn compensate_delay(delay: f64, lag: Duration) -> f64 {
+    (delay - lag.as_secs_f64()).max(0.0)
+}
+
+/// How late we still are after `compensate_delay` used up as much of `lag` as it could.
+pub fn remaining_lag(delay: f64, lag: Duration) -> Duration {
+    lag.saturating_sub(Duration::from_secs_f64(delay.max(0.0)))
+}
+
+/// Swing: how many ticks a note landing on `total_ticks` gets delayed. Only off-beat sixteenths (odd ticks) are delayed.
+pub fn swing_delay_ticks(total_ticks: usize, swing: f64) -> f64 {
+    if total_ticks % 2 == 1 { swing } else { 0.0 }
+}
+
+/// How many ticks an edge animation from `from_tick` to `from_tick + beats` takes with swing.
+/// It starts late if the note it starts at was delayed, and ends late if the note it ends at will be.
+pub fn swung_duration_ticks(from_tick: usize, beats: usize, swing: f64) -> f64 {
+    beats as f64 + swing_delay_ticks(from_tick + beats, swing) - swing_delay_ticks(from_tick, swing)
+}
+
+/// How far an edge animation is, see `AudioGraph::lerp_edge`.
+/// Driven by the ticks that arrived plus how far we are into the current tick, instead of integrating the BPM over time,
+/// so it stays locked to the beat when the BPM changes halfway through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeProgress {
+    /// How far into the current tick the animation started, only non-zero when starting on a swung note.
+    start_offset: f64,
+    duration_ticks: f64,
+    ticks_consumed: usize,
+}
+
+impl EdgeProgress {
+    pub fn new(start_offset: f64, duration_ticks: f64) -> Self {
+        Self {
+            start_offset,
+            duration_ticks,
+            ticks_consumed: 0,
+        }
+    }
+
+    /// Call this every time a tick arrives.
+    pub fn tick(&mut self) {
+        self.ticks_consumed += 1;
+    }
+
+    /// The progress from 0 to 1. `since_last_tick` is the time since the last tick arrived (or since the animation started if none did),
+    /// `tick_interval` is how long a tick takes at the current BPM.
+    /// Never runs ahead of a tick that didn't arrive yet.
+    pub fn progress(&self, since_last_tick: f64, tick_interval: f64) -> f64 {
+        let intra_tick = since_last_tick / tick_interval;
+        let position = if self.ticks_consumed == 0 {
+            intra_tick.min(1.0 - self.start_offset)
+        } else {
+            self.ticks_consumed as f64 + intra_tick.min(1.0) - self.start_offset
+        };
+        if self.duration_ticks <= 0.0 {
+            return 1.0;
+        }
+        (position / self.duration_ticks).clamp(0.0, 1.0)
+    }
+}
+
+/// Counts down the ticks a walk waits for. Ticks that arrive while walks are paused (see `WALKS_PAUSED`) don't count,
+/// so a paused walk picks up exactly where it left off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickCountdown {
+    remaining: usize,
+    landed_at: Option<usize>,
+}
+
+impl TickCountdown {
+    /// Waits `ticks` ticks after `from_tick` (a `Tick::total_ticks`).
+    pub fn new(from_tick: usize, ticks: usize) -> Self {
+        Self {
+            remaining: ticks,
+            landed_at: (ticks == 0).then_some(from_tick),
+        }
+    }
+
+    /// Call this every time a tick arrives, with its `Tick::total_ticks`. Returns true once all ticks were counted.
+    pub fn tick(&mut self, total_ticks: usize, paused: bool) -> bool {
+        if !paused && !self.is_done() {
+            self.remaining -= 1;
+            if self.is_done() {
+                self.landed_at = Some(total_ticks);
+            }
+        }
+        self.is_done()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// The `Tick::total_ticks` of the tick the wait ended on, None until it's done.
+    /// Not always `from_tick + ticks`, paused ticks (and a transport reset) are in between.
+    pub fn landed_at(&self) -> Option<usize> {
+        self.landed_at
+    }
+}
+
+/// How the progress indicator of an edge moves, see `AudioGraph::lerp_edge`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export)]
+#[godot(via = i64)]
+pub enum EdgeEase {
+    #[default]
+    Linear,
+    InOutSine,
+    OutQuad,
+    /// Jumps ahead on every tick, instead of moving in between them
+    SteppedPerTick,
+}
+
+impl EdgeEase {
+    /// Maps the linear `progress` (see `EdgeProgress::progress`) onto this curve.
+    /// `tick_progress` is the progress as of the last tick, only used by `SteppedPerTick`.
+    pub fn apply(self, progress: f64, tick_progress: f64) -> f64 {
+        match self {
+            EdgeEase::Linear => progress,
+            EdgeEase::InOutSine => ease_in_out_sine(progress),
+            EdgeEase::OutQuad => ease_out_quad(progress),
+            EdgeEase::SteppedPerTick if progress >= 1.0 => 1.0, // Landing after a swing delay is a step too
+            EdgeEase::SteppedPerTick => tick_progress,
+        }
+    }
+}
+
+/// How much the newest edge of a trail gets lightened, see `trail_lightening`.
+const TRAIL_BRIGHTNESS: f32 = 0.6;
+/// Every edge of a trail gets lightened this much less than the one after it.
+const TRAIL_FALLOFF: f32 = 0.6;
+
+/// The last edges a walk traversed, so they can light up as a trail behind it, see `AudioGraph::trail_edge`.
+/// An edge can be on it more than once, e.g. after bouncing at a dead end.
+#[derive(Debug, Default, Clone)]
+pub struct TrailState {
+    /// Newest first
+    edges: VecDeque<EdgeIndex>,
+    max_len: usize,
+}
+
+impl TrailState {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            edges: VecDeque::with_capacity(max_len + 1),
+            max_len,
+        }
+    }
+
+    /// Adds `edge` as the newest edge. Returns the edge that fell off the end of the trail, if any.
+    pub fn push(&mut self, edge: EdgeIndex) -> Option<EdgeIndex> {
+        self.edges.push_front(edge);
+        if self.edges.len() > self.max_len {
+            self.edges.pop_back()
+        } else {
+            None
+        }
+    }
+
+    /// The edges on the trail with their age (0 is the newest), oldest first.
+    pub fn edges(&self) -> impl Iterator<Item = (usize, EdgeIndex)> + '_ {
+        self.edges.iter().copied().enumerate().rev()
+    }
+
+    /// Empties the trail, returns the edges that were on it.
+    pub fn clear(&mut self) -> Vec<EdgeIndex> {
+        self.edges.drain(..).collect()
+    }
+}
+
+/// How much an edge of a trail gets lightened (0 to 1), `age` is 0 for the newest edge.
+pub fn trail_lightening(age: usize) -> f32 {
+    TRAIL_BRIGHTNESS * TRAIL_FALLOFF.powi(age as i32)
+}
+
+/// Which nodes were just played by a walk, so walkers arriving at the same node in the same tick can be detected, see `NodeOccupancy::arrive`.
+/// Shared by all walks.
+#[derive(Debug, Default, Clone)]
+pub struct NodeOccupancy {
+    /// The last tick every node is occupied
+    occupied_until: BTreeMap<NodeIndex, usize>,
+    /// The tick of the latest arrival
+    latest: usize,
+}
+
+impl NodeOccupancy {
+    /// Call when a walker plays `node_idx` at `tick`. Returns true if another walker got there in the same tick.
+    /// Forgets everything from before `tick`, so this doesn't keep growing.
+    /// Walks land on the real `Tick::total_ticks` (see `TickCountdown::landed_at`), so an earlier tick than before means
+    /// the transport was reset, and it forgets everything.
+    pub fn arrive(&mut self, node_idx: NodeIndex, tick: usize) -> bool {
+        if tick < self.latest {
+            self.occupied_until.clear();
+        }
+        self.latest = tick;
+        self.occupied_until.retain(|_, until| *until >= tick);
+        let collided = self.occupied_until.contains_key(&node_idx);
+        self.occupied_until.insert(node_idx, tick);
+        collided
+    }
+
+    pub fn len(&self) -> usize {
+        self.occupied_until.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.occupied_until.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.occupied_until.clear();
+        self.latest = 0;
+    }
+}
+
+/// The rng of a walk in deterministic mode (see `AudioState::deterministic_walks`), so the same clicks on the same seed
+/// make the same branching decisions. `click_count` is how many walks were started on this constellation before.
+pub fn deterministic_walk_rng(
+    global_seed: i64,
+    node_idx: NodeIndex,
+    click_count: u32,
+) -> Xoshiro256Plus {
+    let mut click_rng = create_rng_from_seed_and_state(click_count, global_seed);
+    create_rng_from_seed_and_state(node_idx.index() as u32, click_rng.random())
+}
+
+/// Identifies a walk, see `ActiveWalks`. Never reused, even across regenerations.
+pub type WalkId = u64;
+
+/// How many walks are running, shown in the performance stats.
+pub static ACTIVE_WALKERS: AtomicUsize = AtomicUsize::new(0);
+
+/// What happens if a walk gets started while there are already too many, see `ActiveWalks::try_start`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export)]
+#[godot(via = i64)]
+pub enum WalkerOverflow {
+    /// Don't start the new walk
+    #[default]
+    IgnoreClick,
+    /// Stop the oldest walk(s) to make room for the new one
+    StopOldest,
+}
+
+/// What left-clicking a node does while another walk is still waiting to launch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export)]
+#[godot(via = i64)]
+pub enum ClickQueueMode {
+    /// Start walking right away, so quick clicks launch together
+    #[default]
+    Immediate,
+    /// Add the node to the `ClickQueue`, which launches one walk per bar
+    Queued,
+}
+
+/// The nodes that were clicked in `ClickQueueMode::Queued`, oldest first. A node is queued at most once.
+#[derive(Debug, Default, Clone)]
+pub struct ClickQueue {
+    queue: VecDeque<NodeIndex>,
+}
+
+impl ClickQueue {
+    /// Returns false if `node_idx` was queued already.
+    pub fn push(&mut self, node_idx: NodeIndex) -> bool {
+        if self.queue.contains(&node_idx) {
+            return false;
+        }
+        self.queue.push_back(node_idx);
+        true
+    }
+
+    /// The node that was queued first.
+    pub fn pop(&mut self) -> Option<NodeIndex> {
+        self.queue.pop_front()
+    }
+
+    /// Returns false if `node_idx` wasn't queued.
+    pub fn remove(&mut self, node_idx: NodeIndex) -> bool {
+        let Some(position) = self.queue.iter().position(|queued| *queued == node_idx) else {
+            return false;
+        };
+        self.queue.remove(position);
+        true
+    }
+
+    /// Empties the queue, returns the nodes that were on it.
+    pub fn clear(&mut self) -> Vec<NodeIndex> {
+        self.queue.drain(..).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+/// What to do at a tick of a count-in, see `CountIn::tick`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountInStep {
+    Wait,
+    /// Play the metronome, `accent` on the first beat of a bar
+    Click {
+        accent: bool,
+    },
+    /// The count-in is over, start walking on this tick
+    Done,
+}
+
+/// Counts in `bars` bars before a walk starts, starting at the next bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountIn {
+    bars_left: usize,
+    started: bool,
+}
+
+impl CountIn {
+    pub fn new(bars: usize) -> Self {
+        Self {
+            bars_left: bars,
+            started: false,
+        }
+    }
+
+    /// Call this every time a tick arrives.
+    pub fn tick(&mut self, tick: &Tick) -> CountInStep {
+        let on_beat = tick.is_beat();
+        let on_bar = tick.is_bar();
+        if on_bar {
+            if self.bars_left == 0 {
+                return CountInStep::Done;
+            }
+            self.started = true;
+            self.bars_left -= 1;
+        }
+        if self.started && on_beat {
+            CountInStep::Click { accent: on_bar }
+        } else {
+            CountInStep::Wait
+        }
+    }
+}
+
+/// When a walk (or replay) starts after clicking, see `wait_for_launch`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export)]
+#[godot(via = i64)]
+pub enum LaunchQuantization {
+    NextTick,
+    #[default]
+    NextBeat,
+    /// The first or middle beat of a bar. Bars with an odd number of beats round the middle down.
+    NextHalfBar,
+    NextBar,
+}
+
+impl LaunchQuantization {
+    /// Whether a walk waiting to start should start at `tick`.
+    pub fn is_launch_tick(self, tick: &Tick) -> bool {
+        match self {
+            LaunchQuantization::NextTick => true,
+            LaunchQuantization::NextBeat => tick.is_beat(),
+            LaunchQuantization::NextHalfBar => {
+                tick.is_beat() && (tick.beat == 0 || tick.beat == tick.beats_per_bar / 2)
+            }
+            LaunchQuantization::NextBar => tick.is_bar(),
+        }
+    }
+}
+
+/// Waits `delay` seconds after `tick` arrived (e.g. for swing), minus how late we already are if `AudioState::compensate_tick_lag` is on.
+/// Returns how late we still are after that (always zero without lag compensation), or None if cancelled.
+async fn wait_after_tick(tick: &Tick, delay: f64, cancel: &CancellationToken) -> Option<Duration> {
+    let lag = if AudioState::autoload().bind().get_compensate_tick_lag() {
+        tick.lag(Instant::now())
+    } else {
+        Duration::ZERO
+    };
+
+    let wait = compensate_delay(delay, lag);
+    if wait > 0.0 {
+        select! {
+            _ = wait_for_duration(wait, false) => { /* continue */ }
+            _ = cancel.cancelled() => return None,
+        }
+    }
+
+    Some(remaining_lag(delay, lag))
+}
+
+/// Waits until `launch` says a walk can start, and returns the tick it started at and how late we are (see `wait_after_tick`).
+/// None if cancelled before that.
+/// Off-beat launches (only possible with `LaunchQuantization::NextTick`) are swung like any other note.
+/// Nothing launches while walks are paused, see `WALKS_PAUSED`.
+pub async fn wait_for_launch(
+    ticks: &mut TickReceiver,
+    launch: LaunchQuantization,
+    cancel: &CancellationToken,
+) -> Option<(Tick, Duration)> {
+    let tick = select! {
+        tick = ticks.next_matching(|tick| !WALKS_PAUSED.get() && launch.is_launch_tick(tick)) => tick,
+        _ = cancel.cancelled() => return None,
+    };
+
+    let swing = AudioState::autoload().bind().get_swing();
+    let delay = swing_delay_ticks(tick.total_ticks, swing) * tick_interval(tick.ticks_per_beat);
+    let late = wait_after_tick(&tick, delay, cancel).await?;
+
+    Some((tick, late))
+}
+
+#[derive(Debug, Clone)]
+struct ActiveWalk {
+    start_idx: NodeIndex,
+    /// The node it reached last (with any branch), see `ActiveWalks::moved`
+    head: NodeIndex,
+    cancel: CancellationToken,
+}
+
+/// The walks that are currently running, so they can be stopped one by one instead of all at once with the panic button.
+#[derive(Debug, Default)]
+pub struct ActiveWalks {
+    next_id: WalkId,
+    walks: BTreeMap<WalkId, ActiveWalk>,
+}
+
+impl ActiveWalks {
+    /// Registers a walk starting at `start_idx`. Its token is a child of `parent`, so cancelling `parent` stops it too.
+    pub fn start(
+        &mut self,
+        start_idx: NodeIndex,
+        parent: &CancellationToken,
+    ) -> (WalkId, CancellationToken) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let cancel = parent.child_token();
+        self.walks.insert(
+            id,
+            ActiveWalk {
+                start_idx,
+                head: start_idx,
+                cancel: cancel.clone(),
+            },
+        );
+        self.publish_len();
+        (id, cancel)
+    }
+
+    /// Like `start`, but keeps the number of walks at `max_active` (0 is unlimited).
+    /// Returns None if the walk didn't start, see `WalkerOverflow`.
+    pub fn try_start(
+        &mut self,
+        start_idx: NodeIndex,
+        parent: &CancellationToken,
+        max_active: usize,
+        overflow: WalkerOverflow,
+    ) -> Option<(WalkId, CancellationToken)> {
+        if max_active > 0 && self.len() >= max_active {
+            match overflow {
+                WalkerOverflow::IgnoreClick => return None,
+                WalkerOverflow::StopOldest => {
+                    let oldest = self.ids().take(self.len() + 1 - max_active).collect_vec();
+                    for id in oldest {
+                        self.stop(id);
+                    }
+                }
+            }
+        }
+        Some(self.start(start_idx, parent))
+    }
+
+    /// Called by the walk itself once it's done. Doesn't cancel anything, notes that are still sounding keep playing.
+    pub fn finish(&mut self, id: WalkId) {
+        self.walks.remove(&id);
+        self.publish_len();
+    }
+
+    /// Cancels the walk, returns false if there is no such walk.
+    pub fn stop(&mut self, id: WalkId) -> bool {
+        let Some(walk) = self.walks.remove(&id) else {
+            return false;
+        };
+        walk.cancel.cancel();
+        self.publish_len();
+        true
+    }
+
+    /// Cancels all walks that started at `start_idx`, returns how many.
+    pub fn stop_started_at(&mut self, start_idx: NodeIndex) -> usize {
+        let ids = self
+            .walks
+            .iter()
+            .filter(|(_, walk)| walk.start_idx == start_idx)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in &ids {
+            self.stop(*id);
+        }
+        ids.len()
+    }
+
+    /// Called by the walk when it reaches `node_idx`. Returns false if it's not running anymore (e.g. it was stopped).
+    pub fn moved(&mut self, id: WalkId, node_idx: NodeIndex) -> bool {
+        match self.walks.get_mut(&id) {
+            Some(walk) => {
+                walk.head = node_idx;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The node the walk reached last, None if there's no such walk.
+    pub fn head(&self, id: WalkId) -> Option<NodeIndex> {
+        self.walks.get(&id).map(|walk| walk.head)
+    }
+
+    /// Forgets all walks, for when their parent token got cancelled anyway.
+    pub fn clear(&mut self) {
+        self.walks.clear();
+        self.publish_len();
+    }
+
+    /// In the order they were started.
+    pub fn ids(&self) -> impl Iterator<Item = WalkId> + '_ {
+        self.walks.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.walks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.walks.is_empty()
+    }
+
+    fn publish_len(&self) {
+        ACTIVE_WALKERS.store(self.len(), Ordering::Relaxed);
+    }
+}
+
 impl AudioGraph {
+    #[allow(clippy::too_many_arguments)]
+    /// Plays `node_idx` at `tick` with `velocity` (or rests if None), and continues the walk from there.
+    /// `run` is how many notes in a row this branch played before this one, see `WalkStats::longest_run`.
     pub async fn walk_node<R: Rng + Clone>(
         this: &mut Gd<Self>,
-        node_idx: NodeIndex,
+        (node_idx, tick, velocity, late): (NodeIndex, usize, Option<f32>, Duration),
+        run: usize,
         graph: &Rc<GraphTypedef>,
         graph_assoc: &Rc<BTreeMap<NodeIndex, Gd<AudioNode>>>,
-        last_diff: Option<Vector3>,
-        panic_button_cancel: CancellationToken,
+        previous_idx: Option<NodeIndex>,
+        config: WalkConfig,
+        state: &WalkState,
+        cancel: CancellationToken,
         rng: &mut R,
     ) {
-        let mut node = Gd::clone(graph_assoc.get(&node_idx).unwrap());
+        let Some(mut node) = live_node(graph_assoc, node_idx) else {
+            tracing::warn!("node {node_idx:?} was freed mid-walk, ending this branch");
+            return;
+        };
         let node_pos = graph[node_idx];
 
         let mut cancelling = false;
@@ -44,42 +1087,63 @@ impl AudioGraph {
         if node.bind().get_cancelling() {
             node.bind_mut().set_cancelling(false);
             cancelling = true;
+            state.stats.borrow_mut().stopped_by_node = true;
         }
 
-        // Play the node without waiting for it (send to "background" (not actually, still on main thread))
-        let panic_button_cancel2 = panic_button_cancel.clone();
-        this.bind_mut()
-            .spawn_local_task(false, info_span!("play"), async move |_this| {
-                AudioNode::play(&mut node, 1.0, panic_button_cancel2).await;
-            });
+        let run = if velocity.is_some() { run + 1 } else { 0 };
+        state
+            .stats
+            .borrow_mut()
+            .visit(node_idx, tick, velocity.is_some(), run);
+        if let Some(walk_id) = state.walk_id
+            && this.bind_mut().move_walk_head(walk_id, node_idx)
+        {
+            this.signals()
+                .walk_moved()
+                .emit(walk_id as i64, node_idx.index() as i64, node_pos);
+        }
 
-        // Find neighbor(s) to move to (this can be multiple neighbors, if the user clicks on a node with a degree of 2 or higher)
-        let next_node_idxes = {
-            let neighs = graph.neighbors(node_idx).collect::<Vec<_>>();
-
-            if let Some(last_diff) = last_diff {
-                let last_dir = last_diff.normalized();
-
-                // If there is only 1 neighbor, we reached end of the graph, so stop instantly.
-                if neighs.len() <= 1 {
-                    vec![]
-                } else {
-                    // Else, find the neighbor that is best at preserving the direction of the current walk
-                    vec![
-                        *neighs
-                            .iter()
-                            .max_by_key(|neigh_idx| {
-                                let dir = (graph[**neigh_idx] - node_pos).normalized();
-                                let neigh_dot = last_dir.dot(dir);
-                                OrderedFloat::from(neigh_dot)
-                            })
-                            .unwrap(),
-                    ]
-                }
+        // Play the node without waiting for it (send to "background" (not actually, still on main thread))
+        if let Some(velocity) = velocity {
+            let collided = this.bind_mut().arrive_at(node_idx, tick);
+            let cancel2 = cancel.clone();
+            if collided {
+                // Another walker got here in the same tick, so accent it instead of just retriggering
+                tracing::info!("walkers collided on node {node_idx:?}");
+                this.signals()
+                    .walkers_collided()
+                    .emit(node_idx.index() as i64);
+                let mut node = Gd::clone(&node);
+                let cancel = cancel.clone();
+                this.bind_mut().spawn_local_task(
+                    false,
+                    info_span!("play_accent"),
+                    async move |_this| {
+                        AudioNode::play_octave_up(&mut node, 1.0, cancel).await;
+                    },
+                );
+            }
+            let velocity = if collided { 1.0 } else { velocity };
+            let humanize_ms = AudioState::autoload().bind().get_humanize_ms();
+            let delay = humanize_delay(humanize_ms, rng).map(|delay| compensate_delay(delay, late));
+            if node.bind().play_action() == PlayAction::Skip {
+                tracing::debug!("node {node_idx:?} is still sounding, not retriggering it");
             } else {
-                neighs
+                this.bind_mut()
+                    .spawn_local_task(false, info_span!("play"), async move |_this| {
+                        // Only the note is late, the walk (and the edge animation) stays on the grid
+                        if let Some(delay) = delay {
+                            select! {
+                                _ = wait_for_duration(delay, false) => {}
+                                _ = cancel2.cancelled() => return,
+                            }
+                        }
+                        AudioNode::play(&mut node, 1.0, velocity, cancel2).await;
+                    });
             }
-        };
+        }
+
+        let next_node_idxes = config.next_nodes(graph, node_idx, previous_idx, state, rng);
 
         let reached_end_of_graph = next_node_idxes.is_empty();
         if cancelling || reached_end_of_graph {
@@ -87,46 +1151,73 @@ impl AudioGraph {
         }
 
         let mut futures = vec![];
+        let branch_ticks = subscribe_to_ticks();
 
         // Now recurse for every node in next_node_idxes
         for next_node_idx in next_node_idxes {
-            let last_diff = graph[next_node_idx] - node_pos;
-            let dist_rounded = round_to_nearest_pow2_f64(last_diff.length() as f64 * 8.0)
-                .clamp(0.0, 16.0) as usize;
-            let edge = graph.find_edge_undirected(node_idx, next_node_idx).unwrap();
+            let dist_rounded = island_edge_beats(
+                edge_beats(config.rhythm_grid, node_pos, graph[next_node_idx]),
+                this.bind().tempo_mult(node_idx),
+            );
+            // No edge if we wrapped around at a dead end
+            let edge = graph.find_edge_undirected(node_idx, next_node_idx);
+            let next_rest = should_rest(this.bind().rest_probability(next_node_idx), rng);
+            let next_velocity = (!next_rest).then(|| {
+                this.bind()
+                    .note_velocity(graph, next_node_idx, dist_rounded)
+            });
+            let glow = if next_rest { REST_EDGE_GLOW } else { 1.0 };
 
             // Every branch gets their own tick receiver to avoid consuming each other's ticks
-            let mut ticks = subscribe_to_ticks();
+            let mut ticks = branch_ticks.resubscribe();
 
             let mut rng2 = rng.clone();
             let mut this2 = Gd::clone(this);
 
-            let panic_button_cancel = panic_button_cancel.clone();
+            let cancel = cancel.clone();
 
             futures.push(async move {
-                let panic_button_cancel2 = panic_button_cancel.clone();
-                let should_continue = Self::wait_for_ticks_and_lerp_edge(
+                let cancel2 = cancel.clone();
+                let Some((next_tick, late)) = Self::wait_for_ticks_and_lerp_edge(
                     &mut this2,
-                    dist_rounded,
-                    edge,
+                    (tick, dist_rounded),
+                    (edge, glow),
                     &mut ticks,
-                    panic_button_cancel2,
+                    cancel2,
                 )
-                .await;
-
-                if !should_continue {
-                    // Cancelled via panic button, so stop walking
+                .await
+                else {
+                    // Cancelled via panic button or `AudioGraph::stop_walk`, so stop walking
                     tracing::info!("walker cancelled");
                     return;
+                };
+                if let Some((edge, _)) = edge {
+                    this2
+                        .bind_mut()
+                        .trail_edge(&mut state.trail.borrow_mut(), edge);
+                }
+
+                if let Some(velocity) = next_velocity {
+                    // Rests are silent, so there's nothing to replay
+                    state.record(WalkEvent {
+                        tick: next_tick,
+                        node: next_node_idx,
+                        edge: edge.map(|(edge, _)| edge),
+                        beats_waited: dist_rounded,
+                        velocity,
+                    });
                 }
 
                 Self::walk_node(
                     &mut this2,
-                    next_node_idx,
+                    (next_node_idx, next_tick, next_velocity, late),
+                    run,
                     graph,
                     graph_assoc,
-                    Some(last_diff),
-                    panic_button_cancel,
+                    Some(node_idx),
+                    config,
+                    state,
+                    cancel,
                     &mut rng2,
                 )
                 .await;
@@ -137,6 +1228,42 @@ impl AudioGraph {
         join_all(futures).await;
     }
 
+    /// Plays the metronome for `bars` bars starting at the next bar. The pending indicator pulses along by itself, see `AudioNode::set_pending`.
+    /// Returns the first tick after the count-in, None if cancelled before that. Ticks while paused don't count, see `WALKS_PAUSED`.
+    async fn count_in(
+        this: &mut Gd<Self>,
+        ticks: &mut TickReceiver,
+        bars: usize,
+        cancel: &CancellationToken,
+    ) -> Option<(Tick, Duration)> {
+        let mut count_in = CountIn::new(bars);
+        loop {
+            let tick = select! {
+                tick = ticks.wait() => tick,
+                _ = cancel.cancelled() => return None,
+            };
+            if WALKS_PAUSED.get() {
+                continue;
+            }
+            match count_in.tick(&tick) {
+                CountInStep::Wait => {}
+                CountInStep::Click { .. } => {
+                    let accent_pattern = this.bind().get_accent_pattern();
+                    this.bind_mut()
+                        .play_metronome_click(&tick, accent_pattern.as_slice());
+                }
+                CountInStep::Done => {
+                    let late = wait_after_tick(&tick, 0.0, cancel).await?;
+                    return Some((tick, late));
+                }
+            }
+        }
+    }
+
+    /// Walks the graph starting at `node_index`, until it ends or `cancel` (the token of this walk, see `ActiveWalks`) gets cancelled.
+    /// Counts in first if `count_in_bars` isn't 0, otherwise it starts when `launch` says so.
+    /// Returns what the walk did and why it ended.
+    #[allow(clippy::too_many_arguments)]
     pub async fn graph_walk<R>(
         mut this: Gd<Self>,
         mut node: Gd<AudioNode>,
@@ -144,109 +1271,291 @@ impl AudioGraph {
         graph: Rc<GraphTypedef>,
         graph_assoc: Rc<BTreeMap<NodeIndex, Gd<AudioNode>>>,
         mut ticks: TickReceiver,
-        panic_button_cancel: CancellationToken,
+        config: WalkConfig,
+        (launch, count_in_bars): (LaunchQuantization, usize),
+        recorder: Option<flume::Sender<WalkEvent>>,
+        (walk_id, cancel): (WalkId, CancellationToken),
         rng: &mut R,
-    ) where
+    ) -> (WalkStats, WalkEnd)
+    where
         R: Rng + Clone,
     {
-        // For the first step, wait until the next beat.
+        // For the first step, wait until `launch` says so (or the count-in is over).
         node.bind_mut().set_pending(true);
-        loop {
-            let tick = ticks.wait().await;
-            if tick.tick == 0 {
-                break;
+        let launch_tick = if count_in_bars > 0 {
+            Self::count_in(&mut this, &mut ticks, count_in_bars, &cancel).await
+        } else {
+            wait_for_launch(&mut ticks, launch, &cancel).await
+        };
+        let Some((start_tick, late)) = launch_tick.map(|(tick, late)| (tick.total_ticks, late))
+        else {
+            // The node may be freed already, see `AudioGraph::regenerate`
+            if node.is_instance_valid() {
+                node.bind_mut().set_pending(false);
             }
-        }
+            tracing::info!("walker cancelled before launching");
+            return (WalkStats::default(), WalkEnd::Cancelled);
+        };
 
         // Then start the walk.
+        let trail_length = this.bind().trail_length();
+        let state = WalkState::new(node_index, &config)
+            .with_recorder(recorder)
+            .with_trail(trail_length)
+            .with_walk_id(walk_id);
+        state.record(WalkEvent {
+            tick: start_tick,
+            node: node_index,
+            edge: None,
+            beats_waited: 0,
+            velocity: 1.0,
+        });
         Self::walk_node(
             &mut this,
-            node_index,
+            (node_index, start_tick, Some(1.0), late), // The clicked node always plays at full velocity
+            0,
             &graph,
             &graph_assoc,
             None,
-            panic_button_cancel,
+            config,
+            &state,
+            cancel.clone(),
             rng,
         )
         .await;
 
-        tracing::info!("walker reached end of the graph");
+        this.bind_mut().clear_trail(&mut state.trail.borrow_mut());
+        let end = state.end(&cancel);
+        tracing::info!(?end, "walker reached end of the graph");
+        (state.stats.into_inner(), end)
     }
 
-    /// This method waits for ticks and drives the edge-lerping animation. Returns true if successful, false if cancelled.
+    /// Walks `path` hop by hop (so it never branches), see `AudioGraph::walk_path`.
+    /// The endpoints show the pending indicator until the walk plays them.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn path_walk(
+        mut this: Gd<Self>,
+        path: Vec<NodeIndex>,
+        graph: Rc<GraphTypedef>,
+        graph_assoc: Rc<BTreeMap<NodeIndex, Gd<AudioNode>>>,
+        mut ticks: TickReceiver,
+        (rhythm_grid, launch): (RhythmGrid, LaunchQuantization),
+        recorder: Option<flume::Sender<WalkEvent>>,
+        cancel: CancellationToken,
+    ) {
+        let (Some(&from_idx), Some(&to_idx)) = (path.first(), path.last()) else {
+            return;
+        };
+        let clear_pending = |node_idxes: &[NodeIndex]| {
+            for node_idx in node_idxes {
+                // The node may be freed already, see `AudioGraph::on_audio_node_exiting`
+                if let Some(mut node) = live_node(&graph_assoc, *node_idx) {
+                    node.bind_mut().set_pending(false);
+                }
+            }
+        };
+        for node_idx in [from_idx, to_idx] {
+            if let Some(mut node) = live_node(&graph_assoc, node_idx) {
+                node.bind_mut().set_pending(true);
+            }
+        }
+
+        let Some(mut tick) = wait_for_launch(&mut ticks, launch, &cancel)
+            .await
+            .map(|(tick, _)| tick.total_ticks)
+        else {
+            clear_pending(&[from_idx, to_idx]);
+            tracing::info!("path walk cancelled before launching");
+            return;
+        };
+
+        let mut trail = TrailState::new(this.bind().trail_length());
+        let mut previous_idx = None;
+        for &node_idx in &path {
+            let mut velocity = 1.0; // The clicked node always plays at full velocity
+            let mut beats_waited = 0;
+            let mut edge = None;
+            if let Some(previous_idx) = previous_idx {
+                beats_waited = island_edge_beats(
+                    edge_beats(rhythm_grid, graph[previous_idx], graph[node_idx]),
+                    this.bind().tempo_mult(previous_idx),
+                );
+                edge = graph.find_edge_undirected(previous_idx, node_idx);
+                let Some((landed_at, _)) = Self::wait_for_ticks_and_lerp_edge(
+                    &mut this,
+                    (tick, beats_waited),
+                    (edge, 1.0),
+                    &mut ticks,
+                    cancel.clone(),
+                )
+                .await
+                else {
+                    clear_pending(&[to_idx]);
+                    this.bind_mut().clear_trail(&mut trail);
+                    tracing::info!("path walk cancelled");
+                    return;
+                };
+                if let Some((edge, _)) = edge {
+                    this.bind_mut().trail_edge(&mut trail, edge);
+                }
+                tick = landed_at;
+                velocity = this.bind().note_velocity(&graph, node_idx, beats_waited);
+            }
+
+            if let Some(recorder) = &recorder {
+                let _ = recorder.send(WalkEvent {
+                    tick,
+                    node: node_idx,
+                    edge: edge.map(|(edge, _)| edge),
+                    beats_waited,
+                    velocity,
+                });
+            }
+
+            let Some(mut node) = live_node(&graph_assoc, node_idx) else {
+                clear_pending(&[to_idx]);
+                this.bind_mut().clear_trail(&mut trail);
+                tracing::warn!("node {node_idx:?} was freed mid-walk, stopping the path walk");
+                return;
+            };
+            let cancelling = node.bind().get_cancelling();
+            if cancelling {
+                node.bind_mut().set_cancelling(false);
+            }
+            let cancel2 = cancel.clone();
+            this.bind_mut()
+                .spawn_local_task(false, info_span!("play"), async move |_this| {
+                    AudioNode::play(&mut node, 1.0, velocity, cancel2).await;
+                });
+            if cancelling {
+                clear_pending(&[to_idx]);
+                this.bind_mut().clear_trail(&mut trail);
+                tracing::info!("path walk stopped by a cancelling node");
+                return;
+            }
+            previous_idx = Some(node_idx);
+        }
+
+        this.bind_mut().clear_trail(&mut trail);
+        tracing::info!("path walk arrived");
+    }
+
+    /// This method waits `beats` ticks after `from_tick` (plus swing) and drives the edge-lerping animation (if there's an edge).
+    /// Returns the `Tick::total_ticks` it landed on (see `TickCountdown::landed_at`) and how late we are (see `wait_after_tick`), or None if cancelled.
     pub async fn wait_for_ticks_and_lerp_edge(
         this: &mut Gd<Self>,
-        beats: usize,
-        (edge_id, edge_dir): (EdgeIndex, Direction),
+        (from_tick, beats): (usize, usize),
+        (edge, glow): (Option<(EdgeIndex, Direction)>, f32),
         ticks: &mut TickReceiver,
-        panic_button_cancel: CancellationToken,
-    ) -> bool {
-        let bpm = AudioState::autoload().bind().get_bpm(); //TODO update this every time you receive a tick, so you can detect tempo changes.
-        let ticks_per_beat = 4; //TODO update this every time you receive a tick, so you can detect time signature changes.
+        cancel: CancellationToken,
+    ) -> Option<(usize, Duration)> {
+        let swing = AudioState::autoload().bind().get_swing();
+
+        if let Some(edge) = edge {
+            let start_offset = swing_delay_ticks(from_tick, swing);
+            let duration_ticks = swung_duration_ticks(from_tick, beats, swing);
+            Self::lerp_edge(
+                this,
+                (start_offset, duration_ticks),
+                (edge, glow),
+                cancel.clone(),
+            );
+        }
+
+        // Wait for next `beats` ticks, not counting the ones while paused
+        let mut last_tick = None;
+        let mut countdown = TickCountdown::new(from_tick, beats);
+        while !countdown.is_done() {
+            let tick_future = ticks.wait();
+            select! {
+                tick = tick_future => {
+                    countdown.tick(tick.total_ticks, WALKS_PAUSED.get());
+                    last_tick = Some(tick);
+                }
+                _ = cancel.cancelled() => { return None; }
+            }
+        }
+        let landed_at = countdown.landed_at().unwrap();
+        let Some(last_tick) = last_tick else {
+            return Some((landed_at, Duration::ZERO)); // Didn't wait at all
+        };
+
+        // The tick thread doesn't know about swing, so delay off-beat landings ourselves
+        // Read after the last tick, so we follow BPM changes
+        let delay = swing_delay_ticks(landed_at, swing) * tick_interval(last_tick.ticks_per_beat);
+        let late = wait_after_tick(&last_tick, delay, &cancel).await?;
+        Some((landed_at, late))
+    }
 
-        let edge_index = edge_id.index() as i32;
+    /// Drives the edge-lerping animation in the background, it takes `duration_ticks` ticks, see `EdgeProgress`.
+    /// `start_offset` is how far into the current tick we are, and `glow` is the brightness of the progress indicator.
+    /// The progress follows `AudioGraph::edge_ease`.
+    /// Freezes while walks are paused, see `WALKS_PAUSED`.
+    pub fn lerp_edge(
+        this: &mut Gd<Self>,
+        (start_offset, duration_ticks): (f64, f64),
+        ((edge_id, edge_dir), glow): ((EdgeIndex, Direction), f32),
+        cancel: CancellationToken,
+    ) {
+        // Takes the edge over from older tweens right away, and resets it however the task ends (even if it never runs)
+        let tween =
+            EdgeTweenGuard::new(this.bind().edge_renderer(), this.bind().edge_slot(edge_id));
+        let edge_ease = this.bind().edge_ease();
+
+        // Subscribe now and not in the task, so we don't miss any ticks
+        let mut ticks = subscribe_to_ticks();
+        let mut interval = tick_interval(default_ticks_per_beat());
+        let mut edge_progress = EdgeProgress::new(start_offset, duration_ticks);
+        let mut since_last_tick = 0.0; // Only counts unpaused time
+        let mut last_frame = Instant::now();
 
         // Note - we use our own tweening logic here, since we may have to change the tweening speed during the tween, which is not supported with Godot tweens.
-        // Also note - this may override other tweens on the same edge.
-        let panic_button_cancel2 = panic_button_cancel.clone();
+        // Only the newest tween of an edge shows its progress, see `EdgeRenderer::start_tween`.
         this.bind_mut().spawn_local_task(
             true,
             info_span!("cylindrical_tween"),
-            async move |this| {
-                let mut progress = 0.0;
-
-                let mut multi = this
-                    .bind()
-                    .get_multimesh_instance()
-                    .get_multimesh()
-                    .unwrap();
-
+            async move |_this| {
                 // Drive the edge-lerp animation
-                while progress < 1.0 {
-                    //Don't forget to check the delta every frame
-                    let delta = this.bind().base().get_process_delta_time();
-                    let lerp_increment =
-                        (ticks_per_beat as f64 / beats as f64) * (bpm / 60.0) * delta; //TODO this will not be accurate if BPM changes during the animation!
-
-                    progress += lerp_increment;
+                loop {
+                    let paused = WALKS_PAUSED.get();
+                    if !paused {
+                        since_last_tick += last_frame.elapsed().as_secs_f64();
+                    }
+                    last_frame = Instant::now();
+                    let progress = edge_progress.progress(since_last_tick, interval);
+                    let eased_progress =
+                        edge_ease.apply(progress, edge_progress.progress(0.0, interval));
 
                     let final_progress = match edge_dir {
-                        Direction::Outgoing => progress,
-                        Direction::Incoming => 1.0 - progress,
+                        Direction::Outgoing => eased_progress,
+                        Direction::Incoming => 1.0 - eased_progress,
                     };
 
-                    multi.set_instance_custom_data(
-                        edge_index,
-                        Color::from_rgba(final_progress as f32, 0.0, 0.0, 0.0),
-                    );
+                    tween.set_custom(Color::from_rgba(final_progress as f32, 0.0, 0.0, glow));
+
+                    if progress >= 1.0 {
+                        break;
+                    }
 
                     let wait = wait_for_next_frame();
                     select! {
+                        tick = ticks.wait() => {
+                            if !WALKS_PAUSED.get() {
+                                edge_progress.tick();
+                                since_last_tick = 0.0;
+                                last_frame = Instant::now();
+                            }
+                            interval = tick_interval(tick.ticks_per_beat); // Follow BPM changes
+                        }
                         _ = wait => { /* continue */ }
-                        _ = panic_button_cancel2.cancelled() => {
+                        _ = cancel.cancelled() => {
                             tracing::info!("edge tween cancelled");
                             break;
                         }
                     };
                 }
-
-                //When done reset progress
-                multi.set_instance_custom_data(
-                    edge_index,
-                    Color::from_rgba(DEFAULT_EDGE_TWEEN_PROGRESS, 0.0, 0.0, 0.0),
-                );
+                drop(tween); // Resets the progress
             },
         );
-
-        // Wait for next `beats` ticks
-        for _ in 0..beats {
-            let tick_future = ticks.wait();
-            select! {
-                _ = tick_future => { /* continue */ }
-                _ = panic_button_cancel.cancelled() => { return false; }
-            }
-        }
-
-        true
     }
 }
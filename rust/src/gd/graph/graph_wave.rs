@@ -0,0 +1,131 @@
+//! Waves: a whole island playing as a pulse expanding from a shift-clicked node, see `AudioGraph::wave_from`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+use godot::prelude::*;
+use petgraph::{
+    Direction,
+    graph::{EdgeIndex, NodeIndex},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::info_span;
+
+use crate::{
+    async_node::AsyncNode as _,
+    gd::{
+        autoload::state_tick::TickReceiver,
+        graph::{
+            graph_main::{AudioGraph, GraphTypedef, live_node},
+            graph_walk::{LaunchQuantization, wait_for_launch},
+        },
+        node_main::AudioNode,
+    },
+};
+
+/// The nodes at every breadth-first depth from `origin`, so `layers[0]` is just `origin`.
+/// Only walks nodes for which `within` is true (so bridges to other islands can be skipped),
+/// and stops after `max_depth` layers past the origin, 0 is unlimited.
+pub fn bfs_layers(
+    graph: &GraphTypedef,
+    origin: NodeIndex,
+    max_depth: usize,
+    within: impl Fn(NodeIndex) -> bool,
+) -> Vec<Vec<NodeIndex>> {
+    let mut layers = vec![vec![origin]];
+    let mut seen = BTreeSet::from([origin]);
+    while max_depth == 0 || layers.len() <= max_depth {
+        let next = layers
+            .last()
+            .unwrap()
+            .iter()
+            .flat_map(|node_idx| graph.neighbors(*node_idx))
+            .filter(|node_idx| within(*node_idx) && seen.insert(*node_idx))
+            .collect::<Vec<_>>();
+        if next.is_empty() {
+            break;
+        }
+        layers.push(next);
+    }
+    layers
+}
+
+/// The edges from `layer` to `next_layer` (see `bfs_layers`), pointing outwards. Not only the ones BFS used, every edge between them.
+pub fn wave_edges(
+    graph: &GraphTypedef,
+    layer: &[NodeIndex],
+    next_layer: &[NodeIndex],
+) -> Vec<(EdgeIndex, Direction)> {
+    layer
+        .iter()
+        .flat_map(|from| {
+            next_layer
+                .iter()
+                .filter_map(|to| graph.find_edge_undirected(*from, *to))
+        })
+        .collect()
+}
+
+impl AudioGraph {
+    /// Plays the `layers` of a wave (see `bfs_layers`), one every beat. The edges to the next layer animate during the beat.
+    /// Pads play `pad_duration_mult` times longer, so the wave ends on a sustained chord.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn wave(
+        mut this: Gd<Self>,
+        layers: Vec<Vec<NodeIndex>>,
+        graph: Rc<GraphTypedef>,
+        graph_assoc: Rc<BTreeMap<NodeIndex, Gd<AudioNode>>>,
+        mut ticks: TickReceiver,
+        launch: LaunchQuantization,
+        pad_duration_mult: f32,
+        cancel: CancellationToken,
+    ) {
+        let mut origin = Gd::clone(&graph_assoc[&layers[0][0]]);
+        origin.bind_mut().set_pending(true);
+
+        let mut quantization = launch;
+        for (depth, layer) in layers.iter().enumerate() {
+            let Some((tick, _)) = wait_for_launch(&mut ticks, quantization, &cancel).await else {
+                // The node may be freed already, see `AudioGraph::regenerate`
+                if origin.is_instance_valid() {
+                    origin.bind_mut().set_pending(false);
+                }
+                tracing::info!("wave cancelled at depth {depth}");
+                return;
+            };
+            quantization = LaunchQuantization::NextBeat; // Every layer after the first gets a beat
+
+            for node_idx in layer {
+                let Some(mut node) = live_node(&graph_assoc, *node_idx) else {
+                    tracing::warn!("node {node_idx:?} was freed mid-wave, skipping it");
+                    continue;
+                };
+                let duration_mult = if node.bind().get_is_pad() {
+                    pad_duration_mult
+                } else {
+                    1.0
+                };
+                let cancel = cancel.clone();
+                this.bind_mut()
+                    .spawn_local_task(false, info_span!("play"), async move |_this| {
+                        AudioNode::play(&mut node, duration_mult, 1.0, cancel).await;
+                    });
+            }
+
+            if let Some(next_layer) = layers.get(depth + 1) {
+                for edge in wave_edges(&graph, layer, next_layer) {
+                    Self::lerp_edge(
+                        &mut this,
+                        (0.0, tick.ticks_per_beat as f64),
+                        (edge, 1.0),
+                        cancel.clone(),
+                    );
+                }
+            }
+        }
+
+        tracing::info!("wave finished after {} layers", layers.len());
+    }
+}
@@ -1,3 +1,16 @@
+pub mod graph_automation;
+pub mod graph_box_select;
+pub mod graph_builder;
+pub mod graph_edge_renderer;
+pub mod graph_edge_slots;
+pub mod graph_export;
 pub mod graph_generate;
+pub mod graph_import;
+pub mod graph_lifetime;
 pub mod graph_main;
+pub mod graph_metrics;
+pub mod graph_record;
+pub mod graph_spheres;
+pub mod graph_stress;
 pub mod graph_walk;
+pub mod graph_wave;
@@ -1,10 +1,14 @@
-use std::sync::{Arc, atomic::Ordering};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, atomic::Ordering},
+};
 
+use async_executor::LocalExecutor;
 use godot::{
     classes::{
-        AudioStreamPlayer3D, IStaticBody3D, MeshInstance3D, StandardMaterial3D, StaticBody3D,
-        Texture2D, Tween,
-        base_material_3d::TextureParam,
+        AudioStreamPlayer3D, IStaticBody3D, MeshInstance3D, ShaderMaterial, StaticBody3D, Tween,
+        node::ProcessMode,
         tween::{EaseType, TransitionType},
     },
     prelude::*,
@@ -13,15 +17,111 @@ use rand::{Rng, seq::IndexedRandom as _};
 use rand_xoshiro::Xoshiro256Plus;
 use tokio::select;
 use tokio_util::sync::CancellationToken;
-use tracing::instrument;
+use tracing::{info_span, instrument};
 
 use crate::{
+    async_node::{AsyncNode, wait_for_next_frame},
     chords::Chord,
+    flags::WALKS_PAUSED,
     format_gdobj,
-    gd::node_stream::{NodalAudioStream, Waveform},
+    gd::{
+        autoload::{state_main::AudioState, state_tick::subscribe_to_ticks},
+        graph::{
+            graph_main::CancellingCount,
+            graph_spheres::{SphereBatch, SphereInstance},
+        },
+        node_stream::{AtomicWaveform, NodalAudioStream, Waveform},
+    },
+    palette::{MAX_COLOR_OCTAVE, MIN_COLOR_OCTAVE, NodeColors},
     util::{AtomicF32, LerpSmooth},
 };
 
+/// How long the pending indicator flashes red, see `AudioNode::flash_rejected`. Also used by `AudioNode::flash_cancelling`.
+const REJECTED_FLASH_DURATION: f64 = 0.4;
+
+/// How big the pending indicator pops on every beat, and how fast it shrinks back (lerp speed), see `AudioNode::pulse_pending_on_beats`.
+const PENDING_PULSE_SCALE: f32 = 1.5;
+const PENDING_PULSE_DECAY: f32 = 15.0;
+
+/// Alpha of muted nodes (and nodes outside the soloed islands), so you can see they're silent. See `AudioNode::is_effectively_muted`.
+const MUTED_ALPHA: f32 = 0.02;
+
+/// Alpha of idle nodes on the hovered island, a bit more than the usual 0.1. See `AudioNode::set_highlighted`.
+const HIGHLIGHTED_ALPHA: f32 = 0.25;
+
+/// How much the scale and alpha have to change before the node writes them to the SphereBatch (perf optimization).
+/// NOTE - this does mean the lerp smooth may not end up exactly at the target (e.g. 0.07 instead of 0.1), see `AudioNode::sleep`
+const MIN_SCALE_DIFF: f32 = 0.01; // 0.025 looks choppy
+const MIN_ALPHA_DIFF: f32 = 0.05;
+
+/// How many frames in a row a node has to be idle (see `IdleFrames`) before it stops processing.
+pub const IDLE_FRAMES_BEFORE_SLEEP: u32 = 30;
+
+/// Scale of the sphere when a node is spawned, it shrinks to its idle size from there.
+const DEFAULT_SCALE: f32 = 1.0;
+
+/// Stopping a node fades it out this fast (unless it's silent already), so it never cuts off with a click. See `AudioNode::stop`.
+const STOP_RELEASE: f64 = 0.02;
+
+/// Shortest release, so a note always has something to tween (an empty tween never finishes).
+const MIN_RELEASE: f64 = 0.01;
+
+/// What `AudioNode::play` does if the node is still sounding, e.g. when branches overlap or a walk crosses itself.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export)]
+#[godot(via = i64)]
+pub enum RetriggerMode {
+    /// Attack again from silence, which can stutter
+    #[default]
+    Restart,
+    /// Let the note that's sounding finish, don't play again
+    Ignore,
+    /// Keep the note sounding at its current amplitude and start its decay over, without attacking again
+    Extend,
+}
+
+/// What `AudioNode::play` ends up doing, see `RetriggerMode::action`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayAction {
+    Attack,
+    Skip,
+    Extend,
+}
+
+/// How `AudioNode::play` ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayOutcome {
+    /// The envelope went all the way through
+    Completed,
+    /// By the panic button (or whatever else cancelled the token)
+    Cancelled,
+    /// Another `play` or `stop` killed its tween (or the node got freed), so the node is someone else's now
+    Interrupted,
+    /// It didn't play at all, see `PlayAction::Skip`
+    Skipped,
+}
+
+impl PlayOutcome {
+    /// `tween_finished` is None if the cancel token won, Some(false) if the tween got freed before it finished.
+    pub fn from_tween(tween_finished: Option<bool>) -> Self {
+        match tween_finished {
+            Some(true) => PlayOutcome::Completed,
+            Some(false) => PlayOutcome::Interrupted,
+            None => PlayOutcome::Cancelled,
+        }
+    }
+}
+
+impl RetriggerMode {
+    /// What to do when playing a node, `active` is true if it's still sounding.
+    pub fn action(self, active: bool) -> PlayAction {
+        match (self, active) {
+            (_, false) | (RetriggerMode::Restart, true) => PlayAction::Attack,
+            (RetriggerMode::Ignore, true) => PlayAction::Skip,
+            (RetriggerMode::Extend, true) => PlayAction::Extend,
+        }
+    }
+}
+
 #[derive(GodotClass)]
 #[class(init, base=StaticBody3D)]
 pub struct AudioNode {
@@ -31,35 +131,46 @@ pub struct AudioNode {
     #[init(node = "StreamPlayer")]
     audio_player: OnReady<Gd<AudioStreamPlayer3D>>,
 
-    #[init(node = "Vis")]
-    vis: OnReady<Gd<Node3D>>,
-    #[init(node = "Vis/Sphere")]
-    sphere: OnReady<Gd<MeshInstance3D>>,
-
     #[init(node = "IndicatorPending")]
     indicator_pending: OnReady<Gd<MeshInstance3D>>,
     #[init(node = "IndicatorCancelling")]
     indicator_cancelling: OnReady<Gd<MeshInstance3D>>,
 
-    mat: Gd<StandardMaterial3D>,
+    executor: Option<Rc<LocalExecutor<'static>>>,
+    /// Cancels the task that pulses the pending indicator, see `set_pending`.
+    pending_pulse: Option<CancellationToken>,
+    #[init(val = 1.0)]
+    pending_scale: f32,
+
+    /// Where the sphere gets rendered, see `set_sphere_batch`.
+    spheres: Option<Rc<RefCell<SphereBatch>>>,
+    /// Counts this node while it's cancelling, see `set_cancelling_count`.
+    cancelling_count: Option<CancellingCount>,
+    /// True while it's in the pool of AudioGraph, see `reset_for_reuse`.
+    in_pool: bool,
     amplitude_tween: Option<Gd<Tween>>,
     rng: Option<Xoshiro256Plus>,
 
     #[var]
     chord: Chord,
 
-    #[var]
+    #[var(get, set = set_semitone_offset)]
     semitone_offset: f32,
 
-    #[var]
+    #[var(get, set = set_octave)]
     octave: i32,
+    /// Which tone of the chord it plays (an index into its intervals), picked by `configure`. See `refresh_frequency`.
+    chord_tone: usize,
 
     #[init(val = Arc::new(AtomicF32::new(1.0)))]
     amplitude: Arc<AtomicF32>,
     frequency: Arc<AtomicF32>,
 
+    /// Set it before adding the node to the tree, afterwards use `cycle_waveform`.
     #[var]
     waveform: Waveform,
+    /// `waveform`, shared with the stream so it can change while playing
+    shared_waveform: Arc<AtomicWaveform>,
 
     #[init(val = false)]
     active: bool, // True if playing
@@ -67,6 +178,18 @@ pub struct AudioNode {
     #[var]
     duration: f32,
 
+    /// Seconds to fade in, for a `duration` of 1 (`duration` scales the attack and release). Plucks start at full volume by default.
+    #[var]
+    attack: f64,
+    /// Peak amplitude (0 to 1) before velocity, pads hold it for `duration`.
+    #[init(val = 1.0)]
+    #[var]
+    sustain_level: f64,
+    /// Seconds to fade out, for a `duration` of 1.
+    #[init(val = 1.0)]
+    #[var]
+    release: f64,
+
     #[var]
     node_idx: u32, // No longer needed?
 
@@ -76,113 +199,127 @@ pub struct AudioNode {
     #[var]
     is_pad: bool,
 
+    #[export]
+    retrigger_mode: RetriggerMode,
+
+    /// The MIDI note `frequency` is closest to, see `get_node_info`.
+    midi_note: i32,
+    /// Filled in by AudioGraph, see `set_graph_info`.
+    island: Option<usize>,
+    degree: Option<usize>,
+
+    /// Muted nodes still get played (so walks keep their timing), but silently.
+    #[var(get, set = set_muted)]
+    muted: bool,
+    /// True if some islands are soloed, and this node isn't on one of them. See `SoloIslands`.
+    soloed_out: bool,
+    /// True while its island is hovered, see `AudioGraph::highlight_island`.
+    highlighted: bool,
+
     #[var]
     color: Color,
-    cached_color: Color, // Caches the actual color of the material for perf reasons
+    cached_color: Color, // Caches the color in the SphereBatch for perf reasons
 
     scale: f32,
-    cached_scale: f32, // Caches the actual scale of the billboard for perf reasons
+    cached_scale: f32, // Caches the scale in the SphereBatch for perf reasons
+    idle_frames: IdleFrames,
 }
 
 #[godot_api]
 impl IStaticBody3D for AudioNode {
     fn ready(&mut self) {
-        let intervals = self.chord.as_intervals();
-
-        //We receive a rng from AudioGraph, so we can safely mutate it without affecting other things, preventing the spread of nondeterminism throughout the codebase
-        let mut rng = self.rng.take().expect("please set_rng first");
-
-        let freq = frequency_for_random_note_in_chord(&intervals, self.octave, &mut rng)
-            * (self.semitone_offset / 12.0).exp2();
-        self.frequency = Arc::new(AtomicF32::new(freq));
-
-        self.audio_player
-            .set_stream(&Gd::<NodalAudioStream>::from_init_fn(|_| {
-                NodalAudioStream {
-                    waveform: self.waveform,
-                    frequency: Arc::clone(&self.frequency),
-                    amplitude: Arc::clone(&self.amplitude),
-                }
-            }));
-
-        self.indicator_pending.hide();
-        self.indicator_cancelling.hide();
-
-        //Set colors
-        let col = {
-            let mut col = self.waveform.as_color();
-
-            let brightness = 3.0; // Don't put this too high, or it breaks MSAA on the edges
-            col.r *= brightness;
-            col.g *= brightness;
-            col.b *= brightness;
-            col
-        };
-        self.set_color(col);
-
-        //Cache material
-        self.mat = self
-            .sphere
-            .get_material_override()
-            .unwrap()
-            .cast::<StandardMaterial3D>();
-
-        self.set_mat_color(self.color);
-
-        //Set texture if pad
-        let pad_texture = load::<Texture2D>("res://textures/particle/tri.png"); // Note - it seems to load this once per scene change, so that's good
-        if self.is_pad {
-            self.set_mat_texture(Gd::clone(&pad_texture));
-        }
-
-        //Cache scale
-        self.cached_scale =
-            (self.vis.get_scale().x + self.vis.get_scale().y + self.vis.get_scale().z) / 3.0;
-        self.scale = self.cached_scale;
+        self.configure();
     }
 
     fn process(&mut self, delta: f32) {
-        //Update scale -> don't call set_scale every frame, it's slow
+        // Only the pending node has a task, don't schedule a tick for all the others
+        if self
+            .executor
+            .as_ref()
+            .is_some_and(|executor| !executor.is_empty())
+        {
+            self.tick_deferred();
+        }
+
+        //Update scale -> don't call set_scale every frame, AudioGraph only writes the changed spheres to the MultiMesh
         let update_scale = true;
         if update_scale {
-            let target_scale = if self.active {
-                0.1 + self.amplitude.load(Ordering::Relaxed) * 0.7
-            } else {
-                0.1
-            };
-            self.scale = self.scale.lerp_smooth(target_scale, 18.0, delta);
-
-            let min_scale_diff = 0.01; // 0.025 looks choppy
+            self.scale = self.scale.lerp_smooth(self.target_scale(), 18.0, delta);
 
-            if (self.scale - self.cached_scale).abs() > min_scale_diff {
+            if (self.scale - self.cached_scale).abs() > MIN_SCALE_DIFF {
                 self.set_scale(self.scale);
             }
         }
 
-        // Update color -> don't call set_mat_color every frame, it's slow
+        // Update color -> don't call set_mat_color every frame, for the same reason
         let update_color = true;
         if update_color {
-            let target_alpha = if self.active { 1.0 } else { 0.1 };
-            self.color.a = self.color.a.lerp_smooth(target_alpha, 10.0, delta);
-
-            // We require the alpha to change by this much, before we actually update it on the material (perf optimization)
-            // NOTE - this does mean the lerp smooth may not end up exactly at 0.05 (it may converge to a number like 0.07 instead)
-            let min_alpha_diff = 0.05;
+            self.color.a = self.color.a.lerp_smooth(self.target_alpha(), 10.0, delta);
 
-            if (self.color.a - self.cached_color.a).abs() > min_alpha_diff {
+            if (self.color.a - self.cached_color.a).abs() > MIN_ALPHA_DIFF {
                 self.set_mat_color(self.color);
             }
         }
+
+        // Most nodes are idle most of the time, so stop processing them until something changes, see `wake`
+        let converged = !self.active
+            && self
+                .executor
+                .as_ref()
+                .is_none_or(|executor| executor.is_empty())
+            && (self.scale - self.target_scale()).abs() <= MIN_SCALE_DIFF
+            && (self.color.a - self.target_alpha()).abs() <= MIN_ALPHA_DIFF;
+        if self.idle_frames.update(converged) {
+            self.sleep();
+        }
     }
 }
 #[godot_api]
 impl AudioNode {
+    /// When a note of `play` ends, `was_cancelled` is true unless it got to the end of its envelope.
+    #[signal]
+    fn note_finished(was_cancelled: bool);
+
     #[func]
     pub fn set_cancelling(&mut self, cancelling: bool) {
+        if let Some(count) = &self.cancelling_count {
+            count.update(self.cancelling, cancelling);
+        }
         self.cancelling = cancelling;
         self.indicator_cancelling.set_visible(cancelling);
     }
 
+    /// The end of `stop`, after fading out.
+    #[func]
+    fn stop_now(&mut self) {
+        self.amplitude_tween = None;
+        self.set_playing(false);
+    }
+
+    #[func]
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.wake(); // Fades to another alpha
+    }
+
+    /// Takes effect right away once the node is set up, see `refresh_frequency`.
+    #[func]
+    pub fn set_octave(&mut self, octave: i32) {
+        self.octave = octave;
+        if self.is_configured() {
+            self.refresh_frequency();
+        }
+    }
+
+    /// Takes effect right away once the node is set up, see `refresh_frequency`.
+    #[func]
+    pub fn set_semitone_offset(&mut self, semitone_offset: f32) {
+        self.semitone_offset = semitone_offset;
+        if self.is_configured() {
+            self.refresh_frequency();
+        }
+    }
+
     #[func]
     pub fn toggle_cancelling(&mut self) {
         let new_value = !self.get_cancelling();
@@ -192,57 +329,548 @@ impl AudioNode {
     pub fn set_rng(&mut self, rng: Xoshiro256Plus) {
         self.rng = Some(rng);
     }
+
+    /// What this node sounds like and where it is in the graph, e.g. for a tooltip. See `NodeInfo::to_dictionary`.
+    #[func]
+    pub fn get_node_info(&self) -> Dictionary {
+        self.node_info().to_dictionary()
+    }
 }
 
 impl AudioNode {
+    /// Sets the node up from its properties (chord, waveform, ...) and rng. Called by `ready`, and by AudioGraph when it reuses a node, see `reset_for_reuse`.
+    pub fn configure(&mut self) {
+        let intervals = self.chord.as_intervals();
+
+        //We receive a rng from AudioGraph, so we can safely mutate it without affecting other things, preventing the spread of nondeterminism throughout the codebase
+        let mut rng = self.rng.take().expect("please set_rng first");
+
+        self.chord_tone = random_chord_tone(&intervals, &mut rng);
+        let freq = chord_tone_frequency(
+            &intervals,
+            self.chord_tone,
+            self.octave,
+            self.semitone_offset,
+        );
+        self.frequency = Arc::new(AtomicF32::new(freq));
+        self.midi_note = frequency_to_midi_note(freq);
+        self.shared_waveform = Arc::new(AtomicWaveform::new(self.waveform));
+
+        self.audio_player
+            .set_stream(&Gd::<NodalAudioStream>::from_init_fn(|_| {
+                NodalAudioStream {
+                    waveform: Arc::clone(&self.shared_waveform),
+                    frequency: Arc::clone(&self.frequency),
+                    amplitude: Arc::clone(&self.amplitude),
+                }
+            }));
+
+        self.indicator_pending.hide();
+        self.indicator_cancelling.hide();
+
+        //Set up the sphere, pads get a different texture (see node_sphere.gdshader)
+        let colors = AudioState::try_autoload()
+            .map(|state| state.bind().node_colors())
+            .unwrap_or_default();
+        self.set_color(self.node_color(&colors));
+        self.scale = DEFAULT_SCALE;
+        self.cached_color = self.color;
+        self.cached_scale = self.scale;
+        self.wake(); // A reused node may have been asleep
+        let instance = SphereInstance {
+            position: self.base().get_position(),
+            color: self.color,
+            scale: self.scale,
+            is_pad: self.is_pad,
+        };
+        if let Some(spheres) = &self.spheres {
+            spheres.borrow_mut().set(self.node_idx as usize, instance);
+        }
+    }
+
+    /// Derives the frequency again from the chord, octave and semitone_offset, for the chord tone `configure` picked.
+    /// Writes into the same atomic, so a sounding note changes pitch right away (without clicking, the stream keeps its phase).
+    pub fn refresh_frequency(&mut self) {
+        let freq = chord_tone_frequency(
+            &self.chord.as_intervals(),
+            self.chord_tone,
+            self.octave,
+            self.semitone_offset,
+        );
+        self.frequency.store(freq, Ordering::Relaxed);
+        self.midi_note = frequency_to_midi_note(freq);
+    }
+
+    /// Whether `configure` ran (and it isn't in the pool), so changing the octave or semitone_offset has to refresh the frequency.
+    fn is_configured(&self) -> bool {
+        self.base().is_node_ready() && !self.in_pool
+    }
+
+    /// Shifts the node `by` octaves, clamped to the octaves a node can get (see `MIN_COLOR_OCTAVE`).
+    /// Returns its new color (in `ColorMode::Octave` that changes too) for the edges, None if it was at the limit already.
+    pub fn shift_octave(&mut self, by: i32) -> Option<Color> {
+        let octave = (self.octave + by).clamp(MIN_COLOR_OCTAVE, MAX_COLOR_OCTAVE);
+        if octave == self.octave {
+            return None;
+        }
+        self.set_octave(octave);
+
+        let colors = AudioState::try_autoload()
+            .map(|state| state.bind().node_colors())
+            .unwrap_or_default();
+        Some(self.recolor(&colors))
+    }
+
+    /// AudioGraph calls this before adding the node, the node then renders its sphere into `spheres` (at its node_idx).
+    pub fn set_sphere_batch(&mut self, spheres: Rc<RefCell<SphereBatch>>) {
+        self.spheres = Some(spheres);
+    }
+
+    /// AudioGraph calls this before adding the node too, the node then counts itself in `count` while it's cancelling.
+    pub fn set_cancelling_count(&mut self, count: CancellingCount) {
+        self.cancelling_count = Some(count);
+    }
+
+    /// Puts the node back in the state of a freshly instantiated one (see `AudioNodeState::fresh`), so AudioGraph can reuse it instead of freeing it.
+    /// Only keeps what `ready` cached. Hides and disables it, so it doesn't process or receive input while it's in the pool.
+    pub fn reset_for_reuse(&mut self) {
+        if let Some(mut tween) = self.amplitude_tween.take() {
+            tween.kill();
+        }
+        self.set_playing(false);
+        self.set_pending(false);
+        self.set_cancelling(false);
+        self.rng = None;
+
+        // Destructured, so adding a field to `AudioNodeState` doesn't compile until it gets reset here
+        let AudioNodeState {
+            chord,
+            semitone_offset,
+            octave,
+            chord_tone,
+            waveform,
+            duration,
+            envelope,
+            node_idx,
+            cancelling,
+            is_pad,
+            muted,
+            soloed_out,
+            highlighted,
+            midi_note,
+            island,
+            degree,
+            color,
+            cached_color,
+            scale,
+            cached_scale,
+            active,
+            amplitude,
+            frequency,
+            pending_scale,
+            has_rng: _,
+            has_amplitude_tween: _,
+            has_pending_pulse: _,
+        } = AudioNodeState::fresh();
+        self.chord = chord;
+        self.semitone_offset = semitone_offset;
+        self.octave = octave;
+        self.chord_tone = chord_tone;
+        self.waveform = waveform;
+        self.duration = duration;
+        self.set_envelope(envelope);
+        self.node_idx = node_idx;
+        self.cancelling = cancelling;
+        self.is_pad = is_pad;
+        self.muted = muted;
+        self.soloed_out = soloed_out;
+        self.highlighted = highlighted;
+        self.midi_note = midi_note;
+        self.island = island;
+        self.degree = degree;
+        self.color = color;
+        self.cached_color = cached_color;
+        self.scale = scale;
+        self.cached_scale = cached_scale;
+        self.active = active;
+        self.pending_scale = pending_scale;
+        // New ones, so whatever still holds the old ones (like a stream that's winding down) can't change the next use
+        self.amplitude = Arc::new(AtomicF32::new(amplitude));
+        self.frequency = Arc::new(AtomicF32::new(frequency));
+        self.shared_waveform = Arc::new(AtomicWaveform::new(waveform));
+
+        debug_assert_eq!(
+            self.state().diff(&AudioNodeState::fresh()),
+            Vec::<&str>::new(),
+            "reset_for_reuse missed something"
+        );
+
+        self.in_pool = true;
+        self.base_mut().hide();
+        self.base_mut().set_process_mode(ProcessMode::DISABLED);
+    }
+
+    /// Takes the node out of the pool, set it up like a new one (and call `configure` instead of adding it to the tree).
+    pub fn take_from_pool(&mut self) {
+        self.in_pool = false;
+        self.base_mut().set_process_mode(ProcessMode::INHERIT);
+        self.base_mut().show();
+    }
+
+    pub fn is_in_pool(&self) -> bool {
+        self.in_pool
+    }
+
+    /// Everything about the node that changes while using it, see `reset_for_reuse`.
+    pub fn state(&self) -> AudioNodeState {
+        AudioNodeState {
+            chord: self.chord,
+            semitone_offset: self.semitone_offset,
+            octave: self.octave,
+            chord_tone: self.chord_tone,
+            waveform: self.waveform,
+            duration: self.duration,
+            envelope: self.envelope(),
+            node_idx: self.node_idx,
+            cancelling: self.cancelling,
+            is_pad: self.is_pad,
+            muted: self.muted,
+            soloed_out: self.soloed_out,
+            highlighted: self.highlighted,
+            midi_note: self.midi_note,
+            island: self.island,
+            degree: self.degree,
+            color: self.color,
+            cached_color: self.cached_color,
+            scale: self.scale,
+            cached_scale: self.cached_scale,
+            active: self.active,
+            amplitude: self.amplitude.load(Ordering::Relaxed),
+            frequency: self.frequency.load(Ordering::Relaxed),
+            has_rng: self.rng.is_some(),
+            pending_scale: self.pending_scale,
+            has_amplitude_tween: self.amplitude_tween.is_some(),
+            has_pending_pulse: self.pending_pulse.is_some(),
+        }
+    }
+
     pub fn get_active(&self) -> bool {
         self.active
     }
 
+    /// The MIDI note closest to the frequency it plays, see `refresh_frequency`.
+    pub fn midi_note(&self) -> i32 {
+        self.midi_note
+    }
+
     pub fn set_playing(&mut self, active: bool) {
+        if active {
+            self.wake();
+        }
         if self.active != active {
             self.active = active;
             self.audio_player.set_playing(active); // This calls start() and stop() on audio_player
         }
     }
 
+    pub fn node_info(&self) -> NodeInfo {
+        NodeInfo {
+            midi_note: self.midi_note,
+            waveform: self.waveform,
+            octave: self.octave,
+            duration: self.duration,
+            is_pad: self.is_pad,
+            island: self.island,
+            degree: self.degree,
+        }
+    }
+
+    pub fn envelope(&self) -> Envelope {
+        Envelope {
+            attack: self.attack,
+            sustain_level: self.sustain_level,
+            release: self.release,
+        }
+    }
+
+    pub fn set_envelope(&mut self, envelope: Envelope) {
+        self.attack = envelope.attack;
+        self.sustain_level = envelope.sustain_level;
+        self.release = envelope.release;
+    }
+
+    /// The AudioNode doesn't know the graph, so AudioGraph tells it (again after sculpting).
+    /// Set before the node is added, so `ColorMode::Island` can color it right away. See `set_graph_info`.
+    pub fn set_island(&mut self, island: usize) {
+        self.island = Some(island);
+    }
+
+    pub fn set_graph_info(&mut self, island: Option<usize>, degree: usize) {
+        self.island = island;
+        self.degree = Some(degree);
+    }
+
+    /// Switches to the next waveform (see `Waveform::next`), even while it's playing. Returns the new color, for the edges.
+    /// Doesn't touch the rng, so nothing else changes.
+    pub fn cycle_waveform(&mut self) -> Color {
+        self.waveform = self.waveform.next();
+        self.shared_waveform.store(self.waveform, Ordering::Relaxed);
+
+        let colors = AudioState::try_autoload()
+            .map(|state| state.bind().node_colors())
+            .unwrap_or_default();
+        self.recolor(&colors)
+    }
+
+    /// Changes the color for another `ColorMode` (or waveform), even while it's playing. Returns the new color, for the edges.
+    pub fn recolor(&mut self, colors: &NodeColors) -> Color {
+        let color = Color {
+            a: self.color.a, // Keep fading like before
+            ..self.node_color(colors)
+        };
+        self.set_color(color);
+        self.set_mat_color(color);
+        self.wake();
+        color
+    }
+
+    /// The color of this node, brighter than `NodeColors::base_color` so it glows.
+    fn node_color(&self, colors: &NodeColors) -> Color {
+        let mut col = colors.base_color(self.waveform, self.octave, self.island);
+
+        let brightness = 3.0; // Don't put this too high, or it breaks MSAA on the edges
+        col.r *= brightness;
+        col.g *= brightness;
+        col.b *= brightness;
+        col
+    }
+
+    pub fn set_soloed_out(&mut self, soloed_out: bool) {
+        self.soloed_out = soloed_out;
+        self.wake(); // Fades to another alpha
+    }
+
+    /// Brightens it a bit while idle, so you can see which nodes a click would set off.
+    pub fn set_highlighted(&mut self, highlighted: bool) {
+        if self.highlighted != highlighted {
+            self.highlighted = highlighted;
+            self.wake(); // Fades to another alpha
+        }
+    }
+
+    /// What the scale lerps to in `process`.
+    fn target_scale(&self) -> f32 {
+        if self.active {
+            0.1 + self.amplitude.load(Ordering::Relaxed) * 0.7
+        } else {
+            0.1
+        }
+    }
+
+    /// What the alpha lerps to in `process`.
+    fn target_alpha(&self) -> f32 {
+        if self.is_effectively_muted() {
+            MUTED_ALPHA
+        } else if self.active {
+            1.0
+        } else if self.highlighted {
+            HIGHLIGHTED_ALPHA
+        } else {
+            0.1
+        }
+    }
+
+    /// Stops processing, after snapping to the targets (the lerps stop a bit short of them, see `MIN_ALPHA_DIFF`).
+    fn sleep(&mut self) {
+        self.scale = self.target_scale();
+        self.set_scale(self.scale);
+        self.color.a = self.target_alpha();
+        self.set_mat_color(self.color);
+        self.base_mut().set_process(false);
+    }
+
+    /// Starts processing again after `sleep`. Call this whenever something changes what `process` does.
+    pub fn wake(&mut self) {
+        self.idle_frames = IdleFrames::default();
+        self.base_mut().set_process(true);
+    }
+
+    /// True if `play` should be silent, see `muted`.
+    pub fn is_effectively_muted(&self) -> bool {
+        self.muted || self.soloed_out
+    }
+
+    /// What `play` would do right now, see `RetriggerMode`.
+    pub fn play_action(&self) -> PlayAction {
+        self.retrigger_mode.action(self.active)
+    }
+
+    /// While pending, the indicator pulses on every beat, see `pulse_pending_on_beats`.
     pub fn set_pending(&mut self, pending: bool) {
+        self.indicator_pending.set_material_override(Gd::null_arg()); // In case it's flashing, see `flash_rejected`
         self.indicator_pending.set_visible(pending);
+
+        if !pending {
+            if let Some(cancel) = self.pending_pulse.take() {
+                cancel.cancel();
+            }
+            self.set_pending_scale(1.0);
+        } else if self.pending_pulse.is_none() {
+            self.wake(); // The task runs on `process`
+            let cancel = CancellationToken::new();
+            self.pending_pulse = Some(cancel.clone());
+            self.spawn_local_task(false, info_span!("pulse_pending"), async move |mut this| {
+                Self::pulse_pending_on_beats(&mut this, cancel).await;
+            });
+        }
     }
 
-    //////////////
+    /// Pops the pending indicator on every beat (unless the walks are paused), and shrinks it back in between, until `cancel`.
+    async fn pulse_pending_on_beats(this: &mut Gd<Self>, cancel: CancellationToken) {
+        let mut ticks = subscribe_to_ticks();
+        loop {
+            select! {
+                biased;
+                _ = cancel.cancelled() => return,
+                tick = ticks.wait() => {
+                    if tick.is_beat() && !WALKS_PAUSED.get() {
+                        this.bind_mut().set_pending_scale(PENDING_PULSE_SCALE);
+                    }
+                }
+                _ = wait_for_next_frame() => {
+                    let mut node = this.bind_mut();
+                    if node.pending_scale > 1.0 {
+                        let delta = node.base().get_process_delta_time() as f32;
+                        let scale = node.pending_scale.lerp_smooth(1.0, PENDING_PULSE_DECAY, delta);
+                        // Snap, lerp_smooth never gets there exactly
+                        node.set_pending_scale(if scale < 1.001 { 1.0 } else { scale });
+                    }
+                }
+            }
+        }
+    }
 
-    /// Very slow!
-    pub fn set_mat_color(&mut self, color: Color) {
-        self.mat.set_albedo(color);
-        self.cached_color = color; //Cache
+    fn set_pending_scale(&mut self, scale: f32) {
+        self.pending_scale = scale;
+        self.indicator_pending.set_scale(Vector3::ONE * scale);
     }
 
-    /// Very slow!
-    pub fn get_mat_color(&mut self) -> Color {
-        self.mat.get_albedo()
+    /// Briefly flashes the pending indicator red, to show that a click got ignored.
+    pub async fn flash_rejected(this: &mut Gd<Self>) {
+        let mut indicator = Gd::clone(&*this.bind().indicator_pending);
+        if indicator.is_visible() {
+            return; // Already pending, so a walk starts here anyway
+        }
+
+        // The material is shared by all nodes, so tint a copy
+        let Some(mut material) = indicator
+            .get_surface_override_material(0)
+            .and_then(|material| material.duplicate())
+            .and_then(|material| material.try_cast::<ShaderMaterial>().ok())
+        else {
+            return;
+        };
+        material.set_shader_parameter("albedo", &Color::RED.to_variant());
+        indicator.set_material_override(&material);
+        indicator.show();
+
+        let mut tween = this.bind_mut().base_mut().create_tween().unwrap();
+        tween.tween_interval(REJECTED_FLASH_DURATION);
+        if tween
+            .signals()
+            .finished()
+            .to_fallible_future()
+            .await
+            .is_err()
+        {
+            return; // Node got freed
+        }
+
+        // Unless a walk started here in the meantime, see `set_pending`
+        if indicator.get_material_override() == Some(material.upcast()) {
+            indicator.set_material_override(Gd::null_arg());
+            indicator.hide();
+        }
     }
 
-    pub fn set_mat_texture(&mut self, texture: Gd<Texture2D>) {
-        self.mat.set_texture(TextureParam::ALBEDO, &texture);
+    /// Briefly shows the cancelling indicator, without actually cancelling anything.
+    pub async fn flash_cancelling(this: &mut Gd<Self>) {
+        if this.bind().get_cancelling() {
+            return; // Already showing
+        }
+        this.bind_mut().indicator_cancelling.show();
+
+        let mut tween = this.bind_mut().base_mut().create_tween().unwrap();
+        tween.tween_interval(REJECTED_FLASH_DURATION);
+        if tween
+            .signals()
+            .finished()
+            .to_fallible_future()
+            .await
+            .is_err()
+        {
+            return; // Node got freed
+        }
+
+        // Unless it got right-clicked in the meantime
+        let cancelling = this.bind().get_cancelling();
+        this.bind_mut().indicator_cancelling.set_visible(cancelling);
+    }
+
+    //////////////
+
+    /// Shows up on the next frame, when AudioGraph flushes the SphereBatch.
+    pub fn set_mat_color(&mut self, color: Color) {
+        self.cached_color = color; //Cache
+        if let Some(spheres) = &self.spheres
+            && !self.in_pool
+        {
+            spheres
+                .borrow_mut()
+                .set_color(self.node_idx as usize, color);
+        }
     }
 
     //////////////
 
-    // Very slow!
+    /// Shows up on the next frame, when AudioGraph flushes the SphereBatch.
     pub fn set_scale(&mut self, scale: f32) {
-        self.vis.set_scale(Vector3::ONE * scale);
         self.cached_scale = scale; //Cache
+        if let Some(spheres) = &self.spheres
+            && !self.in_pool
+        {
+            spheres
+                .borrow_mut()
+                .set_scale(self.node_idx as usize, scale);
+        }
     }
 
-    /// Plays the node. (Note - we can't take `&mut self` here, otherwise we get a long-lasting borrow)
+    /// Plays the node, `velocity` (0 to 1) scales the peak of the envelope. If it's still sounding, `retrigger_mode` decides what happens.
+    /// (Note - we can't take `&mut self` here, otherwise we get a long-lasting borrow)
     #[cfg_attr(feature = "enable-tracing", instrument(fields(this = format_gdobj!(this))))]
-    pub async fn play(this: &mut Gd<Self>, duration_mult: f32, panic_cancel: CancellationToken) {
-        // Cancel previous tween if any
+    pub async fn play(
+        this: &mut Gd<Self>,
+        duration_mult: f32,
+        velocity: f32,
+        panic_cancel: CancellationToken,
+    ) -> PlayOutcome {
+        // A walk (or a delayed note) can hold on to a node that got freed in the meantime
+        if !this.is_instance_valid() {
+            tracing::warn!("not playing a node that was freed");
+            return PlayOutcome::Interrupted;
+        }
+        let action = this.bind().play_action();
+        if action == PlayAction::Skip {
+            return PlayOutcome::Skipped;
+        }
+
+        // Cancel previous tween if any (e.g. a pad that's still sustaining, or `stop` fading out)
         if let Some(mut prevtween) = this.bind_mut().amplitude_tween.take() {
             prevtween.kill(); // Invalidates it and should remove it from the tree, and then drop it because refcounted
             // NOTE - this is called if you use the panic button too!
         }
+        // Killing it leaves the amplitude wherever it was, so the new envelope starts from there instead of jumping
+        let amp_current = Variant::from(this.bind().amplitude.load(Ordering::Relaxed));
 
         this.bind_mut().set_pending(false);
 
@@ -261,89 +889,470 @@ impl AudioNode {
         // The tween is bound to `this`, so if `this` gets freed, the tween stops as well.
         let mut tween = this.bind_mut().base_mut().create_tween().unwrap();
 
-        let amp_max = Variant::from(1.0);
-        let amp_max_pad = Variant::from(0.5); // Pads are a little less loud than non-pads
+        // Muted nodes go through the whole envelope at zero amplitude, so whatever waits for them keeps its timing
+        let velocity = if this.bind().is_effectively_muted() {
+            0.0
+        } else {
+            velocity.clamp(0.0, 1.0)
+        };
+        let is_pad = this.bind().is_pad;
+        let segments = this
+            .bind()
+            .envelope()
+            .segments(is_pad, final_duration, velocity);
+        let amp_peak = Variant::from(segments.peak);
         let amp_min = Variant::from(0.0);
+        // Pads fade out linearly, plucks with a quintic pluck
+        let release_trans = if is_pad {
+            TransitionType::LINEAR
+        } else {
+            TransitionType::QUINT
+        };
 
-        if this.bind().is_pad {
-            // Linear pad envelope - attack, sustain and release are all equal (for now)
-            let attack = final_duration;
-            let sustain = final_duration;
-            let release = final_duration;
-            tween
-                .tween_method(&tween_callable, &amp_min, &amp_max_pad, attack)
-                .unwrap()
-                .set_ease(EaseType::IN_OUT)
-                .unwrap()
-                .set_trans(TransitionType::LINEAR)
-                .unwrap();
-
+        if action == PlayAction::Extend {
+            // Skip the attack (and for pads the sustain), only the release starts over
             tween
-                .tween_method(&tween_callable, &amp_max_pad, &amp_min, release)
-                .unwrap()
-                .set_delay(sustain)
+                .tween_method(&tween_callable, &amp_current, &amp_min, segments.release)
                 .unwrap()
                 .set_ease(EaseType::OUT)
                 .unwrap()
-                .set_trans(TransitionType::LINEAR)
+                .set_trans(release_trans)
                 .unwrap();
         } else {
-            //Quintic plucky envelope
+            if segments.attack > 0.0 {
+                tween
+                    .tween_method(&tween_callable, &amp_current, &amp_peak, segments.attack)
+                    .unwrap()
+                    .set_ease(EaseType::IN_OUT)
+                    .unwrap()
+                    .set_trans(TransitionType::LINEAR)
+                    .unwrap();
+            } // Without an attack, the release starts at the peak right away (the stream ramps up to it, see `AmplitudeRamp`)
+
             tween
-                .tween_method(&tween_callable, &amp_max, &amp_min, final_duration)
+                .tween_method(&tween_callable, &amp_peak, &amp_min, segments.release)
+                .unwrap()
+                .set_delay(segments.sustain)
                 .unwrap()
                 .set_ease(EaseType::OUT)
                 .unwrap()
-                .set_trans(TransitionType::QUINT)
+                .set_trans(release_trans)
                 .unwrap();
         }
 
-        // Checks if the old tween was None, if not, we have a bug
+        // Checks if the old tween was None, if not, we have a bug.
+        // Extending doesn't reuse the old tween either, it got killed above like when restarting.
         let old_tween = this.bind_mut().amplitude_tween.replace(Gd::clone(&tween));
         assert_eq!(old_tween, None);
 
         let tween_future = tween.signals().finished().to_fallible_future();
+        // From now on only `amplitude_tween` keeps the tween alive. So once another `play` (or `stop`) kills and drops it,
+        // it gets freed and the future fails, instead of never resolving.
+        drop(tween);
 
-        let tween_result = select! {
+        let tween_finished = select! {
             result = tween_future => {
-                Some(result)
+                Some(result.is_ok())
             }
             _ = panic_cancel.cancelled() => {
                 //Panic button hit, so stop the sound
                 None
             }
         };
+        if !this.is_instance_valid() {
+            tracing::warn!("node was freed while it was playing");
+            return PlayOutcome::Interrupted;
+        }
 
-        // Only set playing to false if the tween completed all the way without being cancelled halfway through!
+        // Only set playing to false if the tween completed all the way without being interrupted halfway through!
         // Otherwise, an earlier play could interrupt a later play.
-        match tween_result {
-            result @ (Some(Ok(())) | None) => {
-                // Tween completed or panic button hit
+        let outcome = PlayOutcome::from_tween(tween_finished);
+        match outcome {
+            PlayOutcome::Completed | PlayOutcome::Cancelled => {
+                // Tween completed or panic button hit (then the tween is still running, so stop it)
+                if let Some(mut tween) = this.bind_mut().amplitude_tween.take() {
+                    tween.kill();
+                }
                 this.bind_mut().stop();
+            }
+            PlayOutcome::Interrupted | PlayOutcome::Skipped => {
+                tracing::debug!("note got interrupted");
+            }
+        }
+        this.signals()
+            .note_finished()
+            .emit(outcome != PlayOutcome::Completed);
+        outcome
+    }
 
-                if result.is_none() {
-                    // Panic button hit, so stop tween
-                    if let Some(tween) = this.bind_mut().amplitude_tween.as_mut() {
-                        tween.kill();
-                    }
-                }
+    /// Fades out over `STOP_RELEASE` before it actually stops playing, unless it's silent already.
+    /// Playing again during the fade cancels the stop, just like it interrupts a note.
+    pub fn stop(&mut self) {
+        if let Some(mut tween) = self.amplitude_tween.take() {
+            tween.kill();
+        }
+
+        let amp_current = self.amplitude.load(Ordering::Relaxed);
+        if !self.active || amp_current <= 0.0 {
+            self.set_playing(false);
+            return;
+        }
 
-                this.bind_mut().amplitude_tween = None;
+        let amplitude = Arc::clone(&self.amplitude);
+        let tween_callable = Callable::from_local_fn("", move |args| {
+            amplitude.store(f32::from_variant(args[0]), Ordering::Relaxed);
+            Ok(Variant::nil())
+        });
+        let stop_callable = self.base().callable("stop_now");
+
+        let mut tween = self.base_mut().create_tween().unwrap();
+        tween.tween_method(
+            &tween_callable,
+            &Variant::from(amp_current),
+            &Variant::from(0.0),
+            STOP_RELEASE,
+        );
+        tween.tween_callback(&stop_callable);
+        self.amplitude_tween = Some(tween);
+    }
+
+    /// Plays the note an octave up (`semitone_offset` + 12) on a short-lived extra player, next to whatever the node is playing itself.
+    /// Always a pluck, even for pads.
+    pub async fn play_octave_up(
+        this: &mut Gd<Self>,
+        duration_mult: f32,
+        cancel: CancellationToken,
+    ) {
+        if !this.is_instance_valid() {
+            tracing::warn!("not playing a node that was freed");
+            return;
+        }
+        if this.bind().is_effectively_muted() {
+            return; // Nothing waits for this one, so no need to keep its timing
+        }
+        let frequency = this.bind().frequency.load(Ordering::Relaxed) * 2.0;
+        let amplitude = Arc::new(AtomicF32::new(0.0));
+
+        // Duplicate, so it's set up the same as the node's own player
+        let Some(mut player) = this
+            .bind()
+            .audio_player
+            .duplicate()
+            .and_then(|player| player.try_cast::<AudioStreamPlayer3D>().ok())
+        else {
+            return;
+        };
+        let waveform = Arc::clone(&this.bind().shared_waveform);
+        let stream_amplitude = Arc::clone(&amplitude);
+        player.set_stream(&Gd::<NodalAudioStream>::from_init_fn(|_| {
+            NodalAudioStream {
+                waveform,
+                frequency: Arc::new(AtomicF32::new(frequency)),
+                amplitude: stream_amplitude,
             }
-            Some(Err(err)) => tracing::warn!("{err}"), // Note - this branch seems never reached, because the future's await is never called
+        }));
+        this.add_child(&player);
+        player.play();
+
+        let tween_callable = Callable::from_local_fn("", move |args| {
+            amplitude.store(f32::from_variant(args[0]), Ordering::Relaxed);
+            Ok(Variant::nil())
+        });
+        let duration = (this.bind().duration * duration_mult) as f64;
+        let mut tween = this.bind_mut().base_mut().create_tween().unwrap();
+        tween
+            .tween_method(
+                &tween_callable,
+                &Variant::from(1.0),
+                &Variant::from(0.0),
+                duration,
+            )
+            .unwrap()
+            .set_ease(EaseType::OUT)
+            .unwrap()
+            .set_trans(TransitionType::QUINT)
+            .unwrap();
+
+        select! {
+            _ = tween.signals().finished().to_fallible_future() => {}
+            _ = cancel.cancelled() => { tween.kill(); }
+        }
+        if player.is_instance_valid() {
+            player.queue_free();
         }
     }
+}
+/// Counts the frames in a row an AudioNode was idle, see `IDLE_FRAMES_BEFORE_SLEEP`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IdleFrames(u32);
+
+impl IdleFrames {
+    /// Call this every frame, returns true once it's time to sleep (and starts counting again).
+    pub fn update(&mut self, idle: bool) -> bool {
+        if !idle {
+            self.0 = 0;
+            return false;
+        }
+        self.0 += 1;
+        if self.0 < IDLE_FRAMES_BEFORE_SLEEP {
+            return false;
+        }
+        self.0 = 0;
+        true
+    }
 
-    pub fn stop(&mut self) {
-        self.set_playing(false);
+    pub fn count(&self) -> u32 {
+        self.0
+    }
+}
+
+/// What `AudioNode::get_node_info` shows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeInfo {
+    pub midi_note: i32,
+    pub waveform: Waveform,
+    pub octave: i32,
+    pub duration: f32,
+    pub is_pad: bool,
+    /// None until AudioGraph filled them in
+    pub island: Option<usize>,
+    pub degree: Option<usize>,
+}
+
+impl NodeInfo {
+    /// E.g. "C#4", see `midi_note_name`.
+    pub fn note_name(&self) -> String {
+        midi_note_name(self.midi_note)
     }
+
+    /// Island and degree are -1 if they're unknown.
+    pub fn to_dictionary(&self) -> Dictionary {
+        let or_unknown = |value: Option<usize>| value.map_or(-1, |value| value as i64);
+        dict! {
+            "note_name": self.note_name(),
+            "midi_note": self.midi_note,
+            "waveform": format!("{:?}", self.waveform),
+            "octave": self.octave,
+            "duration": self.duration,
+            "is_pad": self.is_pad,
+            "island": or_unknown(self.island),
+            "degree": or_unknown(self.degree),
+        }
+    }
+}
+
+/// Everything about an AudioNode that changes while using it, see `AudioNode::state`.
+/// A reused node has to end up exactly like a freshly instantiated one, or it plays (or looks) like the node it was before.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioNodeState {
+    pub chord: Chord,
+    pub semitone_offset: f32,
+    pub octave: i32,
+    pub chord_tone: usize,
+    pub waveform: Waveform,
+    pub duration: f32,
+    pub envelope: Envelope,
+    pub node_idx: u32,
+    pub cancelling: bool,
+    pub is_pad: bool,
+    pub muted: bool,
+    pub soloed_out: bool,
+    pub highlighted: bool,
+    pub midi_note: i32,
+    pub island: Option<usize>,
+    pub degree: Option<usize>,
+    pub color: Color,
+    pub cached_color: Color,
+    pub scale: f32,
+    pub cached_scale: f32,
+    pub active: bool,
+    pub amplitude: f32,
+    pub frequency: f32,
+    pub has_rng: bool,
+    pub pending_scale: f32,
+    pub has_amplitude_tween: bool,
+    pub has_pending_pulse: bool,
+}
+
+impl AudioNodeState {
+    /// The state of a freshly instantiated AudioNode, before AudioGraph sets it up. Matches the `#[init]`s of `AudioNode`.
+    pub fn fresh() -> Self {
+        Self {
+            chord: Chord::default(),
+            semitone_offset: 0.0,
+            octave: 0,
+            chord_tone: 0,
+            waveform: Waveform::default(),
+            duration: 0.0,
+            envelope: Envelope::PLUCK,
+            node_idx: 0,
+            cancelling: false,
+            is_pad: false,
+            muted: false,
+            soloed_out: false,
+            highlighted: false,
+            midi_note: 0,
+            island: None,
+            degree: None,
+            color: Color::default(),
+            cached_color: Color::default(),
+            scale: 0.0,
+            cached_scale: 0.0,
+            active: false,
+            amplitude: 1.0,
+            frequency: 0.0,
+            has_rng: false,
+            pending_scale: 1.0,
+            has_amplitude_tween: false,
+            has_pending_pulse: false,
+        }
+    }
+
+    /// The names of the fields that differ.
+    pub fn diff(&self, other: &Self) -> Vec<&'static str> {
+        macro_rules! diff_fields {
+            ($($field:ident),* $(,)?) => {{
+                // Destructured, so adding a field doesn't compile until it's compared here
+                let Self { $($field: _),* } = self;
+                [$((stringify!($field), self.$field != other.$field)),*]
+                    .into_iter()
+                    .filter(|(_, differs)| *differs)
+                    .map(|(field, _)| field)
+                    .collect()
+            }};
+        }
+        diff_fields!(
+            chord,
+            semitone_offset,
+            octave,
+            chord_tone,
+            waveform,
+            duration,
+            envelope,
+            node_idx,
+            cancelling,
+            is_pad,
+            muted,
+            soloed_out,
+            highlighted,
+            midi_note,
+            island,
+            degree,
+            color,
+            cached_color,
+            scale,
+            cached_scale,
+            active,
+            amplitude,
+            frequency,
+            pending_scale,
+            has_rng,
+            has_amplitude_tween,
+            has_pending_pulse,
+        )
+    }
+}
+
+/// How a note fades in and out, see the properties of the same name on `AudioNode`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Envelope {
+    pub attack: f64,
+    pub sustain_level: f64,
+    pub release: f64,
+}
+
+impl Envelope {
+    /// What plucks sounded like before envelopes were configurable: full volume right away, then a quintic fade out.
+    pub const PLUCK: Envelope = Envelope {
+        attack: 0.0,
+        sustain_level: 1.0,
+        release: 1.0,
+    };
+
+    /// What pads sounded like before envelopes were configurable: attack, sustain and release all as long as the note,
+    /// and a little less loud than plucks.
+    pub const PAD: Envelope = Envelope {
+        attack: 1.0,
+        sustain_level: 0.5,
+        release: 1.0,
+    };
+
+    /// The tween segments of a note that lasts `duration` seconds (which scales the attack and release).
+    /// Only pads sustain, for `duration`, so they keep their three phases.
+    pub fn segments(&self, is_pad: bool, duration: f64, velocity: f32) -> EnvelopeSegments {
+        EnvelopeSegments {
+            attack: self.attack.max(0.0) * duration,
+            sustain: if is_pad { duration } else { 0.0 },
+            release: (self.release * duration).max(MIN_RELEASE),
+            peak: self.sustain_level.clamp(0.0, 1.0) as f32 * velocity,
+        }
+    }
+}
+
+/// The seconds every phase of a note takes, see `Envelope::segments`. An attack of 0 means starting at the peak.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvelopeSegments {
+    pub attack: f64,
+    pub sustain: f64,
+    pub release: f64,
+    /// The amplitude after the attack
+    pub peak: f32,
+}
+
+/// The frequency of a node: a random note of the chord in `octave`, shifted by `semitone_offset` semitones.
+pub fn node_frequency<R: Rng>(
+    intervals: &[u8],
+    octave: i32,
+    semitone_offset: f32,
+    rng: &mut R,
+) -> f32 {
+    let chord_tone = random_chord_tone(intervals, rng);
+    chord_tone_frequency(intervals, chord_tone, octave, semitone_offset)
 }
-#[cfg_attr(feature = "enable-tracing", instrument(skip(rng)))]
-fn frequency_for_random_note_in_chord<R: Rng>(intervals: &[u8], octave: i32, rng: &mut R) -> f32 {
-    // Pick random note from chord
-    let note_semitone = *intervals.choose(rng).unwrap() as i32;
+
+/// A random tone of the chord, as an index into its `intervals`.
+pub fn random_chord_tone<R: Rng>(intervals: &[u8], rng: &mut R) -> usize {
+    // Choosing from the indices uses the rng just like choosing from the intervals, so seeds keep their notes
+    let chord_tones = (0..intervals.len()).collect::<Vec<_>>();
+    *chord_tones.choose(rng).unwrap()
+}
+
+/// The frequency of tone `chord_tone` of the chord in `octave`, shifted by `semitone_offset` semitones.
+/// Wraps around if the chord has fewer tones.
+pub fn chord_tone_frequency(
+    intervals: &[u8],
+    chord_tone: usize,
+    octave: i32,
+    semitone_offset: f32,
+) -> f32 {
+    let note_semitone = intervals[chord_tone % intervals.len()] as i32;
     let midi_note = 12 + (12 * octave) + note_semitone;
 
-    440.0 * ((midi_note as f32 - 69.0) / 12.0).exp2()
+    440.0 * ((midi_note as f32 - 69.0) / 12.0).exp2() * (semitone_offset / 12.0).exp2()
     //                           ^^^^ Nice
 }
+
+/// The closest MIDI note, e.g. 69 for 440 Hz (A4).
+pub fn frequency_to_midi_note(frequency: f32) -> i32 {
+    (69.0 + 12.0 * (frequency / 440.0).log2()).round() as i32
+}
+
+/// E.g. "C4" for MIDI note 60 (middle C), with sharps instead of flats.
+pub fn midi_note_name(midi_note: i32) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    let name = NAMES[midi_note.rem_euclid(12) as usize];
+    let octave = midi_note.div_euclid(12) - 1;
+    format!("{name}{octave}")
+}
+
+impl AsyncNode for AudioNode {
+    fn set_executor(&mut self, executor: Option<Rc<LocalExecutor<'static>>>) {
+        self.executor = executor;
+    }
+
+    fn get_executor(&self) -> &Option<Rc<LocalExecutor<'static>>> {
+        &self.executor
+    }
+}
@@ -2,7 +2,7 @@ use std::{
     f32::consts::TAU,
     sync::{
         Arc, LazyLock,
-        atomic::{AtomicBool, AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering},
     },
 };
 
@@ -14,6 +14,7 @@ use godot::{
     prelude::*,
 };
 use rand::{Rng, SeedableRng as _, rngs::SmallRng};
+use serde::{Deserialize, Serialize};
 use strum::{EnumIter, IntoEnumIterator};
 
 use crate::{logging::format_as_pointer, util::AtomicF32};
@@ -23,10 +24,13 @@ use crate::{logging::format_as_pointer, util::AtomicF32};
 /// Counts the amount of currently active audio streams. Use for profiling.
 pub static ACTIVE_STREAMS: LazyLock<AtomicU32> = LazyLock::new(|| AtomicU32::new(0));
 
+/// How long the amplitude takes (at least) to go from silence to full volume or back, so jumps in the amplitude don't click.
+pub const DECLICK_SECS: f32 = 0.005;
+
 #[derive(GodotClass)]
 #[class(base=AudioStream, no_init)]
 pub struct NodalAudioStream {
-    pub waveform: Waveform,
+    pub waveform: Arc<AtomicWaveform>,
     pub frequency: Arc<AtomicF32>,
     pub amplitude: Arc<AtomicF32>,
 }
@@ -40,10 +44,11 @@ impl IAudioStream for NodalAudioStream {
             NodalAudioStreamPlayback {
                 active: true.into(), // Active true by default, seems to reduce latency!
                 sample_rate: AudioServer::singleton().get_mix_rate(), // Seems to be 48khz by default
-                sample_index: 0,
-                waveform: self.waveform,
+                phase: 0.0,
+                waveform: Arc::clone(&self.waveform),
                 frequency: Arc::clone(&self.frequency),
                 amplitude: Arc::clone(&self.amplitude),
+                ramp: AmplitudeRamp::new(AudioServer::singleton().get_mix_rate()),
                 rng: SmallRng::from_os_rng(),
             }
         });
@@ -57,10 +62,11 @@ impl IAudioStream for NodalAudioStream {
 pub struct NodalAudioStreamPlayback {
     active: AtomicBool,
     sample_rate: f32,
-    sample_index: usize,
-    waveform: Waveform,
+    phase: f32, // 0..1, one cycle. Accumulated instead of derived from the time, so changing the frequency doesn't click
+    waveform: Arc<AtomicWaveform>,
     frequency: Arc<AtomicF32>,
     amplitude: Arc<AtomicF32>,
+    ramp: AmplitudeRamp,
     rng: SmallRng, // Non-portable rng, but it's only used for audio noise generation, so it should be fine.
 }
 
@@ -87,6 +93,7 @@ impl IAudioStreamPlayback for NodalAudioStreamPlayback {
     }
 
     fn start(&mut self, _from_pos: f64) {
+        self.ramp.reset(); // Always fade in from silence
         self.active.store(true, Ordering::Relaxed);
     }
 
@@ -108,8 +115,9 @@ impl Drop for NodalAudioStreamPlayback {
 impl NodalAudioStreamPlayback {
     fn render_audio(&mut self, num_requested_frames: i32, buffer: *mut AudioFrame) -> i32 {
         let frequency = self.frequency.load(Ordering::Relaxed);
-        let amp = 0.1 * self.amplitude.load(Ordering::Relaxed);
-        let frac_sample_rate = 1.0 / self.sample_rate;
+        let waveform = self.waveform.load(Ordering::Relaxed);
+        let target_amp = self.amplitude.load(Ordering::Relaxed);
+        let phase_step = frequency / self.sample_rate;
 
         // num_requested_frames = 512 (so about 86 calls to render_audio per second per node)
 
@@ -122,22 +130,20 @@ impl NodalAudioStreamPlayback {
                 return i; // Return the amount of partially processed samples if you return early
             }
 
-            let time = self.sample_index as f32 * frac_sample_rate;
+            let phase = self.phase;
 
+            let amp = 0.1 * self.ramp.next(target_amp);
             let sample = amp
-                * match self.waveform {
-                    Waveform::Sine => {
-                        let phase = TAU * frequency * time;
-                        phase.sin()
-                    }
-                    Waveform::Triangle => {
-                        4.0 * ((frequency * time + 0.25).fract() - 0.5).abs() - 1.0
-                    }
-                    Waveform::Saw => 2.0 * (frequency * time).fract() - 1.0,
+                * match waveform {
+                    Waveform::Sine => (TAU * phase).sin(),
+                    Waveform::Triangle => 4.0 * ((phase + 0.25).fract() - 0.5).abs() - 1.0,
+                    Waveform::Saw => 2.0 * phase - 1.0,
                     Waveform::Square => {
-                        let phase = TAU * frequency * time;
-                        let sin = phase.sin();
-                        if sin >= 0.0 { 1. } else { -1. }
+                        if phase < 0.5 {
+                            1.
+                        } else {
+                            -1.
+                        }
                     }
                     Waveform::Noise => self.rng.random::<f32>() * 2.0 - 1.0, //-1 ... 1
                 };
@@ -150,14 +156,63 @@ impl NodalAudioStreamPlayback {
                     right: sample,
                 };
             }
-            self.sample_index += 1;
+            self.phase = (self.phase + phase_step).fract();
         }
 
         num_requested_frames
     }
 }
 
-#[derive(Clone, Copy, GodotConvert, Var, Export, Default, Debug, EnumIter, Eq, PartialEq)]
+/// Limits how fast the amplitude of a stream changes per sample, see `DECLICK_SECS`.
+/// The tweens only write the amplitude once per frame (and a killed tween leaves it wherever it was), this smooths out the steps in between.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmplitudeRamp {
+    current: f32,
+    max_step: f32,
+}
+
+impl AmplitudeRamp {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            current: 0.0,
+            max_step: 1.0 / (DECLICK_SECS * sample_rate),
+        }
+    }
+
+    /// How much the amplitude may change from one sample to the next.
+    pub fn max_step(&self) -> f32 {
+        self.max_step
+    }
+
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    pub fn reset(&mut self) {
+        self.current = 0.0;
+    }
+
+    /// The amplitude of the next sample, one step closer to `target`.
+    pub fn next(&mut self, target: f32) -> f32 {
+        self.current += (target - self.current).clamp(-self.max_step, self.max_step);
+        self.current
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    GodotConvert,
+    Var,
+    Export,
+    Default,
+    Debug,
+    EnumIter,
+    Eq,
+    PartialEq,
+    Serialize,
+    Deserialize,
+)]
 #[godot(via = i64)]
 pub enum Waveform {
     Sine,
@@ -168,8 +223,18 @@ pub enum Waveform {
     Noise,
 }
 
+/// `Waveform::as_color` of every waveform (in `Waveform::iter` order), so the gradient only gets evaluated once.
+static WAVEFORM_COLORS: LazyLock<Vec<Color>> =
+    LazyLock::new(|| Waveform::iter().map(Waveform::gradient_color).collect());
+
 impl Waveform {
+    /// Cheap, it comes from a table. Used a lot, e.g. in the loops of `AudioGraph::generate_stats`.
     pub fn as_color(&self) -> Color {
+        WAVEFORM_COLORS[*self as usize]
+    }
+
+    /// What `as_color` looks up, evaluated from the gradient. Slow, only public so tests can check the table against it.
+    pub fn gradient_color(self) -> Color {
         let grad = colorgrad::preset::turbo(); // Very nice color scheme
 
         match self {
@@ -181,4 +246,42 @@ impl Waveform {
             Waveform::Noise => Color::GRAY,
         }
     }
+
+    /// The next waveform, wrapping around from the last one to the first.
+    pub fn next(self) -> Self {
+        Waveform::iter()
+            .cycle()
+            .skip_while(|waveform| *waveform != self)
+            .nth(1)
+            .unwrap()
+    }
+}
+
+/// A waveform that can change while the stream is playing, see `AudioNode::cycle_waveform`.
+#[derive(Debug)]
+pub struct AtomicWaveform {
+    index: AtomicU8, // The index in `Waveform::iter`
+}
+
+impl AtomicWaveform {
+    pub fn new(waveform: Waveform) -> Self {
+        Self {
+            index: AtomicU8::new(waveform as u8),
+        }
+    }
+
+    pub fn store(&self, waveform: Waveform, ordering: Ordering) {
+        self.index.store(waveform as u8, ordering);
+    }
+
+    pub fn load(&self, ordering: Ordering) -> Waveform {
+        let index = self.index.load(ordering);
+        Waveform::iter().nth(index as usize).unwrap_or_default()
+    }
+}
+
+impl Default for AtomicWaveform {
+    fn default() -> Self {
+        Self::new(Waveform::default())
+    }
 }
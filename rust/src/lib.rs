@@ -1,16 +1,29 @@
 #![feature(duration_millis_float)]
 
+use std::time::Duration;
+
 use godot::prelude::*;
 use tracing::instrument;
 
-use crate::{flags::USE_METRONOME, gd::autoload::cli::GAME_ARGS, logging::setup_logging};
+use crate::{
+    flags::USE_METRONOME,
+    gd::autoload::{cli::GAME_ARGS, state_tick::shutdown_tick_thread},
+    logging::setup_logging,
+};
 
 pub mod async_node;
 pub mod chords;
 pub mod flags;
 pub mod gd;
+pub mod link_sync;
 pub mod logging;
+pub mod midi_sync;
+pub mod palette;
+pub mod presets;
 pub mod profile;
+pub mod session;
+pub mod settings;
+pub mod stats_export;
 pub mod ui;
 pub mod util;
 
@@ -19,6 +32,9 @@ pub mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
+/// How long to wait for the tick thread on unload. It only checks for shutdown between ticks, so this should be longer than one tick.
+const TICK_THREAD_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
 struct MusicalConstellationsExtension;
 
 #[gdextension]
@@ -41,4 +57,11 @@ unsafe impl ExtensionLibrary for MusicalConstellationsExtension {
             InitLevel::Editor => (),
         }
     }
+
+    fn on_level_deinit(level: InitLevel) {
+        if level == InitLevel::Scene {
+            // Otherwise the editor complains about a leaked thread when the extension reloads
+            shutdown_tick_thread(TICK_THREAD_SHUTDOWN_TIMEOUT);
+        }
+    }
 }
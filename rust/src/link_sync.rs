@@ -0,0 +1,191 @@
+//! Ableton Link, so the tempo and beat grid are shared with other apps on the network. Enable it with `--link` or `AudioState::link_enabled`.
+//! Actually joining a session needs the `link` feature, the syncing logic doesn't.
+
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+/// Tempos closer than this count as the same. `CURRENT_BPM` is an f32, so exact comparisons would see changes that aren't there.
+const LINK_BPM_EPSILON: f64 = 0.01;
+
+/// How many updates `LinkTempoSync` waits for our tempo to catch up with the session, before trusting it again.
+const LOCAL_TEMPO_GRACE_UPDATES: usize = 4;
+
+fn same_bpm(a: f64, b: f64) -> bool {
+    (a - b).abs() < LINK_BPM_EPSILON
+}
+
+/// Which way a tempo change has to go, see `LinkTempoSync::update`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TempoSync {
+    /// The session changed its tempo, so we follow.
+    ToLocal(f64),
+    /// We changed our tempo (e.g. with the slider or tap tempo), so the session follows.
+    ToLink(f64),
+}
+
+/// Decides which side of a tempo difference changed, so changes propagate both ways without echoing back.
+#[derive(Debug, Clone)]
+pub struct LinkTempoSync {
+    /// The tempo both sides agreed on last.
+    synced: f64,
+    /// Updates left until our tempo should have caught up with the session, see `LOCAL_TEMPO_GRACE_UPDATES`.
+    catching_up: usize,
+}
+
+impl LinkTempoSync {
+    pub fn new(bpm: f64) -> Self {
+        Self {
+            synced: bpm,
+            catching_up: 0,
+        }
+    }
+
+    /// Call this regularly with both tempos. The session wins if both changed.
+    pub fn update(&mut self, link_bpm: f64, local_bpm: f64) -> Option<TempoSync> {
+        if !same_bpm(link_bpm, self.synced) {
+            self.synced = link_bpm;
+            if same_bpm(local_bpm, link_bpm) {
+                self.catching_up = 0;
+                return None;
+            }
+            // Our tempo only changes on the next tick, don't mistake the old one for a change of our own
+            self.catching_up = LOCAL_TEMPO_GRACE_UPDATES;
+            return Some(TempoSync::ToLocal(link_bpm));
+        }
+
+        if self.catching_up > 0 {
+            self.catching_up -= 1;
+            if same_bpm(local_bpm, self.synced) {
+                self.catching_up = 0;
+            }
+            return None;
+        }
+
+        if !same_bpm(local_bpm, self.synced) {
+            self.synced = local_bpm;
+            return Some(TempoSync::ToLink(local_bpm));
+        }
+        None
+    }
+}
+
+/// Converts a time on Link's clock (in microseconds, see `AblLink::clock_micros`) to an `Instant`, given what both clocks say now.
+pub fn link_time_to_instant(time_micros: i64, now_micros: i64, now: Instant) -> Instant {
+    let offset = time_micros - now_micros;
+    if offset >= 0 {
+        now + Duration::from_micros(offset as u64)
+    } else {
+        now - Duration::from_micros(offset.unsigned_abs())
+    }
+}
+
+/// Session tempo changes, as BPMs. `AudioState` applies them on the main thread, so the slider follows.
+static INCOMING_TEMPO: LazyLock<(flume::Sender<f64>, flume::Receiver<f64>)> =
+    LazyLock::new(flume::unbounded);
+
+/// The session tempo changes that came in since the last call, only the last one matters.
+pub fn incoming_link_tempos() -> impl Iterator<Item = f64> {
+    INCOMING_TEMPO.1.try_iter()
+}
+
+#[cfg(feature = "link")]
+mod session {
+    use std::{
+        sync::{Arc, Mutex, atomic::Ordering},
+        thread,
+        time::Instant,
+    };
+
+    use rusty_link::{AblLink, SessionState};
+
+    use super::{INCOMING_TEMPO, LinkTempoSync, TempoSync, link_time_to_instant};
+    use crate::{
+        flags::EXTERNAL_CLOCK,
+        gd::autoload::state_tick::{BpmMessage, CURRENT_BPM, set_bpm_internal, subscribe_to_ticks},
+    };
+
+    /// Created on the first `set_link_enabled(true)`, and kept around after that so it can be enabled again.
+    static LINK: Mutex<Option<Arc<AblLink>>> = Mutex::new(None);
+
+    /// Joins or leaves the Link session, and returns whether we're in it now. `bpm` is our tempo, in case we're the first one there.
+    pub fn set_link_enabled(enabled: bool, bpm: f64) -> bool {
+        let mut link = LINK.lock().unwrap();
+        if link.is_none() && !enabled {
+            return false;
+        }
+        let link = link.get_or_insert_with(|| {
+            let link = Arc::new(AblLink::new(bpm));
+            let sync_link = Arc::clone(&link);
+            thread::spawn(move || sync_with_link(&sync_link, bpm));
+            link
+        });
+        link.enable(enabled);
+        tracing::info!(enabled, "Link");
+        enabled
+    }
+
+    /// How many other apps are in the session, 0 if we're not in one.
+    pub fn link_peer_count() -> u64 {
+        match &*LINK.lock().unwrap() {
+            Some(link) if link.is_enabled() => link.num_peers(),
+            _ => 0,
+        }
+    }
+
+    /// Syncs the tempo on every tick, and nudges our beat grid onto the session's on every bar (see `BpmMessage::Nudge`).
+    /// Nothing syncs while the transport is stopped, since no ticks arrive then.
+    fn sync_with_link(link: &AblLink, bpm: f64) {
+        let mut ticks = subscribe_to_ticks();
+        let mut session = SessionState::new();
+        let mut tempo_sync = LinkTempoSync::new(bpm);
+
+        while let Some(tick) = ticks.blocking_wait() {
+            if !link.is_enabled() || EXTERNAL_CLOCK.get() {
+                continue;
+            }
+            link.capture_app_session_state(&mut session);
+
+            let local_bpm = CURRENT_BPM.load(Ordering::Relaxed) as f64;
+            match tempo_sync.update(session.tempo(), local_bpm) {
+                Some(TempoSync::ToLocal(bpm)) => {
+                    tracing::info!("following the Link session tempo of {bpm:.2} BPM");
+                    let _ = INCOMING_TEMPO.0.send(bpm);
+                }
+                Some(TempoSync::ToLink(bpm)) => {
+                    session.set_tempo(bpm, link.clock_micros());
+                    link.commit_app_session_state(&session);
+                }
+                None => {}
+            }
+
+            if tick.is_bar() {
+                // The tick thread handles the nudge right after this bar's first tick, so it moves the beat after that
+                let (now_micros, now) = (link.clock_micros(), Instant::now());
+                let quantum = tick.beats_per_bar as f64;
+                let next_beat = session.beat_at_time(now_micros, quantum).floor() + 1.0;
+                let next_beat_micros = session.time_at_beat(next_beat, quantum);
+                set_bpm_internal(BpmMessage::Nudge {
+                    next_beat: link_time_to_instant(next_beat_micros, now_micros, now),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(feature = "link")]
+pub use session::{link_peer_count, set_link_enabled};
+
+#[cfg(not(feature = "link"))]
+pub fn set_link_enabled(enabled: bool, _bpm: f64) -> bool {
+    if enabled {
+        tracing::error!("Link needs the `link` feature, not enabling it");
+    }
+    false
+}
+
+#[cfg(not(feature = "link"))]
+pub fn link_peer_count() -> u64 {
+    0
+}
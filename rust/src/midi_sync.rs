@@ -0,0 +1,400 @@
+//! MIDI clock input, so the tick thread follows the tempo of external gear. Enable it with `--midi-in`.
+//! And MIDI note input, so a keyboard plays the nodes closest in pitch. Enable it with `--midi-notes`.
+//! Actually receiving anything needs the `midi` feature, the tempo estimation and note mapping don't.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::LazyLock,
+    time::Duration,
+};
+
+use itertools::Itertools;
+use petgraph::graph::NodeIndex;
+
+use crate::gd::autoload::{midi_clock::MIDI_CLOCKS_PER_BEAT, state_tick::Transport};
+
+/// Status byte of a Note On on channel 1, the low nibble is the channel.
+pub const MIDI_NOTE_ON: u8 = 0x90;
+
+/// How many clock intervals the moving average covers. A beat's worth, so jitter averages out without lagging far behind tempo changes.
+const CLOCK_AVERAGE_WINDOW: usize = MIDI_CLOCKS_PER_BEAT;
+
+/// How many clock intervals we need before trusting the average, a sixteenth's worth.
+const MIN_CLOCK_INTERVALS: usize = MIDI_CLOCKS_PER_BEAT / 4;
+
+/// Estimates the BPM of incoming MIDI clock (24 per beat), see `ClockTempoEstimator::clock`.
+/// Timestamps can be relative to anything, as long as they all use the same clock.
+#[derive(Debug, Default, Clone)]
+pub struct ClockTempoEstimator {
+    last_clock: Option<Duration>,
+    intervals: VecDeque<Duration>,
+    bpm: Option<f64>,
+}
+
+impl ClockTempoEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The last estimate, this survives a `reset`.
+    pub fn bpm(&self) -> Option<f64> {
+        self.bpm
+    }
+
+    /// Feeds a clock that arrived `at`, and returns the new BPM estimate once there are enough clocks for one.
+    pub fn clock(&mut self, at: Duration) -> Option<f64> {
+        if let Some(last_clock) = self.last_clock.replace(at) {
+            self.intervals.push_back(at.saturating_sub(last_clock));
+            if self.intervals.len() > CLOCK_AVERAGE_WINDOW {
+                self.intervals.pop_front();
+            }
+        }
+        if self.intervals.len() < MIN_CLOCK_INTERVALS {
+            return None;
+        }
+
+        let average = self.intervals.iter().sum::<Duration>() / self.intervals.len() as u32;
+        if average.is_zero() {
+            return None;
+        }
+        let bpm = 60.0 / (average.as_secs_f64() * MIDI_CLOCKS_PER_BEAT as f64);
+        self.bpm = Some(bpm);
+        Some(bpm)
+    }
+
+    /// Whether the clock stopped, i.e. nothing arrived for more than a beat at the last estimated tempo.
+    pub fn timed_out(&self, now: Duration) -> bool {
+        match (self.last_clock, self.bpm) {
+            (Some(last_clock), Some(bpm)) => {
+                now.saturating_sub(last_clock) > Duration::from_secs_f64(60.0 / bpm)
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets the clocks but keeps the BPM, so the gap until the clock comes back doesn't count as one long interval.
+    pub fn reset(&mut self) {
+        self.last_clock = None;
+        self.intervals.clear();
+    }
+}
+
+/// Incoming MIDI Start/Stop/Continue, as transport changes. `AudioState` applies them on the main thread, so its signals still fire.
+static INCOMING_TRANSPORT: LazyLock<(flume::Sender<Transport>, flume::Receiver<Transport>)> =
+    LazyLock::new(flume::unbounded);
+
+/// The transport changes that came in over MIDI since the last call.
+pub fn incoming_midi_transports() -> impl Iterator<Item = Transport> {
+    INCOMING_TRANSPORT.1.try_iter()
+}
+
+/// What a MIDI realtime message means for our transport, Start plays from the top.
+pub fn midi_transports(message: u8) -> &'static [Transport] {
+    use crate::gd::autoload::midi_clock::{MIDI_CONTINUE, MIDI_START, MIDI_STOP};
+
+    match message {
+        MIDI_START => &[Transport::Reset, Transport::Play],
+        MIDI_CONTINUE => &[Transport::Play],
+        MIDI_STOP => &[Transport::Stop],
+        _ => &[],
+    }
+}
+
+/// A Note On that came in over MIDI. Note Offs are ignored, the envelopes are one-shot anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidiNoteOn {
+    pub note: u8,
+    /// 1 to 127, a Note On with velocity 0 is a Note Off
+    pub velocity: u8,
+}
+
+/// What a Note On does, see `MidiNoteOn::action`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiNoteAction {
+    /// Starts a walk on the node, like clicking it
+    Walk,
+    /// Only plays the node itself
+    Play,
+}
+
+impl MidiNoteOn {
+    /// Parses a Note On, on `channel` (1 to 16) or on any channel if that's None. Everything else is None, Note Offs included.
+    pub fn parse(message: &[u8], channel: Option<u8>) -> Option<Self> {
+        let &[status, note, velocity] = message else {
+            return None;
+        };
+        if status & 0xF0 != MIDI_NOTE_ON || velocity == 0 {
+            return None;
+        }
+        if channel.is_some_and(|channel| (status & 0x0F) + 1 != channel) {
+            return None;
+        }
+        Some(Self { note, velocity })
+    }
+
+    /// Hitting a key at least as hard as `walk_velocity` starts a walk, softer ones only play the node.
+    pub fn action(&self, walk_velocity: u8) -> MidiNoteAction {
+        if self.velocity >= walk_velocity {
+            MidiNoteAction::Walk
+        } else {
+            MidiNoteAction::Play
+        }
+    }
+
+    /// The velocity for `AudioNode::play`, from 0 to 1.
+    pub fn play_velocity(&self) -> f32 {
+        self.velocity as f32 / 127.0
+    }
+}
+
+/// Which nodes play which MIDI note, so incoming notes can find the closest node. Build it after spawning, and again when nodes change pitch.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MidiNoteIndex {
+    nodes_by_note: BTreeMap<i32, Vec<NodeIndex>>,
+}
+
+impl MidiNoteIndex {
+    /// From the MIDI note of every node, see `AudioNode::midi_note`.
+    pub fn new(node_notes: impl IntoIterator<Item = (NodeIndex, i32)>) -> Self {
+        let mut nodes_by_note = BTreeMap::<i32, Vec<NodeIndex>>::new();
+        for (node_idx, note) in node_notes {
+            nodes_by_note.entry(note).or_default().push(node_idx);
+        }
+        for nodes in nodes_by_note.values_mut() {
+            nodes.sort();
+        }
+        Self { nodes_by_note }
+    }
+
+    pub fn nodes(&self, note: i32) -> &[NodeIndex] {
+        self.nodes_by_note.get(&note).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes_by_note.is_empty()
+    }
+
+    /// The node with the MIDI note closest to `note` that `is_available` (e.g. isn't playing already).
+    /// Ties go to the lower note, and then to the lowest NodeIndex, so the same note picks the same node.
+    pub fn nearest(
+        &self,
+        note: i32,
+        is_available: impl Fn(NodeIndex) -> bool,
+    ) -> Option<NodeIndex> {
+        self.nodes_by_note
+            .iter()
+            .sorted_by_key(|&(&node_note, _)| ((node_note - note).abs(), node_note))
+            .flat_map(|(_, nodes)| nodes)
+            .copied()
+            .find(|&node_idx| is_available(node_idx))
+    }
+}
+
+/// Incoming MIDI Note Ons, `AudioGraph` plays them on the main thread.
+static INCOMING_NOTES: LazyLock<(flume::Sender<MidiNoteOn>, flume::Receiver<MidiNoteOn>)> =
+    LazyLock::new(flume::unbounded);
+
+/// The Note Ons that came in over MIDI since the last call.
+pub fn incoming_midi_notes() -> impl Iterator<Item = MidiNoteOn> {
+    INCOMING_NOTES.1.try_iter()
+}
+
+#[cfg(feature = "midi")]
+mod input {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use midir::{Ignore, MidiInput, MidiInputConnection, MidiInputPort};
+
+    use super::{
+        ClockTempoEstimator, INCOMING_NOTES, INCOMING_TRANSPORT, MidiNoteOn, midi_transports,
+    };
+    use crate::{
+        flags::EXTERNAL_CLOCK,
+        gd::autoload::{
+            midi_clock::MIDI_CLOCK,
+            state_tick::{BpmMessage, set_bpm_internal},
+        },
+    };
+
+    /// Don't bother the tick thread with changes smaller than this.
+    const BPM_SEND_THRESHOLD: f64 = 0.05;
+
+    /// How often to check whether the clock stopped.
+    const TIMEOUT_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+    /// How often to check whether the note input port got unplugged.
+    const UNPLUG_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Keeps the connection alive.
+    static MIDI_IN: Mutex<Option<MidiInputConnection<()>>> = Mutex::new(None);
+
+    /// Same, for `start_midi_notes`. Dropping it disconnects.
+    static MIDI_NOTES_IN: Mutex<Option<MidiInputConnection<()>>> = Mutex::new(None);
+
+    #[derive(Debug, Default)]
+    struct SyncState {
+        estimator: ClockTempoEstimator,
+        sent_bpm: Option<f64>,
+    }
+
+    impl SyncState {
+        fn send_bpm(&mut self, bpm: f64) {
+            if self
+                .sent_bpm
+                .is_none_or(|sent_bpm| (bpm - sent_bpm).abs() >= BPM_SEND_THRESHOLD)
+            {
+                set_bpm_internal(BpmMessage::Set(bpm));
+                self.sent_bpm = Some(bpm);
+            }
+        }
+    }
+
+    /// Connects to the first MIDI input port with `port_filter` in its name (case-insensitive), and follows its clock and transport from then on.
+    /// Failing to connect is logged, but not fatal.
+    pub fn start_midi_sync(port_filter: &str) {
+        let Ok(mut input) = MidiInput::new("Musical Constellations")
+            .inspect_err(|err| tracing::error!("failed to open MIDI input: {err}"))
+        else {
+            return;
+        };
+        input.ignore(Ignore::None); // Clock is a timing message, which some backends ignore by default
+        let Some((name, port)) = find_port(&input, port_filter) else {
+            return;
+        };
+
+        let origin = Instant::now();
+        let state = Arc::new(Mutex::new(SyncState::default()));
+        let callback_state = Arc::clone(&state);
+        let connection = input.connect(
+            &port,
+            "sync",
+            move |_, message, _| match message {
+                [MIDI_CLOCK] => {
+                    let mut state = callback_state.lock().unwrap();
+                    if let Some(bpm) = state.estimator.clock(origin.elapsed()) {
+                        state.send_bpm(bpm);
+                    }
+                }
+                [message] => {
+                    for &transport in midi_transports(*message) {
+                        let _ = INCOMING_TRANSPORT.0.send(transport);
+                    }
+                }
+                _ => {}
+            },
+            (),
+        );
+        match connection {
+            Ok(connection) => *MIDI_IN.lock().unwrap() = Some(connection),
+            Err(err) => {
+                tracing::error!("failed to connect to MIDI port {name:?}: {err}");
+                return;
+            }
+        }
+        tracing::info!("following MIDI clock from {name:?}");
+        EXTERNAL_CLOCK.set(true);
+
+        // Without clocks the callback never runs, so something else has to notice they stopped
+        thread::spawn(move || {
+            loop {
+                thread::sleep(TIMEOUT_CHECK_INTERVAL);
+                let mut state = state.lock().unwrap();
+                if state.estimator.timed_out(origin.elapsed()) {
+                    let bpm = state.estimator.bpm().unwrap_or_default();
+                    tracing::warn!("MIDI clock stopped, staying at {bpm:.2} BPM");
+                    state.sent_bpm = None; // Even if it's close to what we sent, this is the best estimate we'll get
+                    state.send_bpm(bpm);
+                    state.estimator.reset();
+                }
+            }
+        });
+    }
+
+    /// Connects to the first MIDI input port with `port_filter` in its name (case-insensitive), and sends its Note Ons on `channel` (1 to 16, None for all of them) to `incoming_midi_notes`.
+    /// Failing to connect is logged, and so is the port getting unplugged, which disables note input until the next start.
+    pub fn start_midi_notes(port_filter: &str, channel: Option<u8>) {
+        let Ok(input) = MidiInput::new("Musical Constellations")
+            .inspect_err(|err| tracing::error!("failed to open MIDI input: {err}"))
+        else {
+            return;
+        };
+        let Some((name, port)) = find_port(&input, port_filter) else {
+            return;
+        };
+
+        let connection = input.connect(
+            &port,
+            "notes",
+            move |_, message, _| {
+                if let Some(note_on) = MidiNoteOn::parse(message, channel) {
+                    let _ = INCOMING_NOTES.0.send(note_on);
+                }
+            },
+            (),
+        );
+        match connection {
+            Ok(connection) => *MIDI_NOTES_IN.lock().unwrap() = Some(connection),
+            Err(err) => {
+                tracing::error!("failed to connect to MIDI port {name:?}: {err}");
+                return;
+            }
+        }
+        tracing::info!(?channel, "playing notes from MIDI port {name:?}");
+
+        // The backends don't tell the callback the port is gone, so poll for it
+        thread::spawn(move || {
+            let Ok(watcher) = MidiInput::new("Musical Constellations watcher") else {
+                return;
+            };
+            loop {
+                thread::sleep(UNPLUG_CHECK_INTERVAL);
+                if watcher.ports().contains(&port) {
+                    continue;
+                }
+                tracing::warn!("MIDI port {name:?} was unplugged, disabling note input");
+                if let Some(connection) = MIDI_NOTES_IN.lock().unwrap().take() {
+                    connection.close();
+                }
+                return;
+            }
+        });
+    }
+
+    /// The first input port with `port_filter` in its name (case-insensitive), logs the available ones if there's none.
+    fn find_port(input: &MidiInput, port_filter: &str) -> Option<(String, MidiInputPort)> {
+        let ports = input
+            .ports()
+            .into_iter()
+            .filter_map(|port| Some((input.port_name(&port).ok()?, port)))
+            .collect::<Vec<_>>();
+        let filter = port_filter.to_lowercase();
+        let found = ports
+            .iter()
+            .find(|(name, _)| name.to_lowercase().contains(&filter))
+            .cloned();
+        if found.is_none() {
+            let names = ports.iter().map(|(name, _)| name).collect::<Vec<_>>();
+            tracing::error!(
+                "no MIDI input port matches {port_filter:?}, available ports: {names:?}"
+            );
+        }
+        found
+    }
+}
+
+#[cfg(feature = "midi")]
+pub use input::{start_midi_notes, start_midi_sync};
+
+#[cfg(not(feature = "midi"))]
+pub fn start_midi_sync(_port_filter: &str) {
+    tracing::error!("--midi-in needs the `midi` feature, ignoring it");
+}
+
+#[cfg(not(feature = "midi"))]
+pub fn start_midi_notes(_port_filter: &str, _channel: Option<u8>) {
+    tracing::error!("--midi-notes needs the `midi` feature, ignoring it");
+}
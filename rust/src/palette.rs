@@ -0,0 +1,137 @@
+//! The colors of the nodes (and their edges and the stats), see `ColorMode`.
+
+use colorgrad::Gradient;
+use godot::prelude::*;
+
+use crate::{gd::node_stream::Waveform, util::color_to_html};
+
+/// The octaves a node can get, see `AudioGraph::spawn_audio_node`. `ColorMode::Octave` spreads these over the palette.
+pub const MIN_COLOR_OCTAVE: i32 = 2;
+pub const MAX_COLOR_OCTAVE: i32 = 8;
+
+/// The palette if `Settings::palette` isn't one of `PALETTES`.
+pub const DEFAULT_PALETTE: &str = "turbo";
+
+/// The colorgrad presets that `Settings::palette` can name.
+pub const PALETTES: [&str; 11] = [
+    "turbo", "viridis", "inferno", "magma", "plasma", "cividis", "rainbow", "sinebow", "spectral",
+    "warm", "cool",
+];
+
+/// What the color of a node tells you, see `AudioState::color_mode`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, GodotConvert, Var, Export)]
+#[godot(via = i64)]
+pub enum ColorMode {
+    /// See `Waveform::as_color`, doesn't use the palette
+    #[default]
+    Waveform,
+    /// Low octaves at the start of the palette, high ones at the end
+    Octave,
+    /// Every island gets its own color from the palette
+    Island,
+}
+
+impl ColorMode {
+    /// The next mode, wrapping around from the last one to the first.
+    pub fn next(self) -> Self {
+        match self {
+            ColorMode::Waveform => ColorMode::Octave,
+            ColorMode::Octave => ColorMode::Island,
+            ColorMode::Island => ColorMode::Waveform,
+        }
+    }
+}
+
+/// The colorgrad preset called `name`, None if it isn't one of `PALETTES`.
+pub fn palette_gradient(name: &str) -> Option<Box<dyn Gradient>> {
+    use colorgrad::preset::*;
+
+    Some(match name {
+        "turbo" => turbo().boxed(),
+        "viridis" => viridis().boxed(),
+        "inferno" => inferno().boxed(),
+        "magma" => magma().boxed(),
+        "plasma" => plasma().boxed(),
+        "cividis" => cividis().boxed(),
+        "rainbow" => rainbow().boxed(),
+        "sinebow" => sinebow().boxed(),
+        "spectral" => spectral().boxed(),
+        "warm" => warm().boxed(),
+        "cool" => cool().boxed(),
+        _ => return None,
+    })
+}
+
+/// Picks the colors for a `ColorMode`. Build it once and reuse it, some palettes take a while to build.
+#[derive(Clone)]
+pub struct NodeColors {
+    mode: ColorMode,
+    gradient: Box<dyn Gradient>,
+}
+
+impl Default for NodeColors {
+    fn default() -> Self {
+        Self::new(ColorMode::default(), DEFAULT_PALETTE)
+    }
+}
+
+impl NodeColors {
+    /// Falls back to `DEFAULT_PALETTE` if `palette` is unknown.
+    pub fn new(mode: ColorMode, palette: &str) -> Self {
+        let gradient = palette_gradient(palette).unwrap_or_else(|| {
+            tracing::warn!("unknown palette {palette:?}, using {DEFAULT_PALETTE}");
+            palette_gradient(DEFAULT_PALETTE).unwrap()
+        });
+        Self { mode, gradient }
+    }
+
+    pub fn mode(&self) -> ColorMode {
+        self.mode
+    }
+
+    /// The color of a node, before the brightness boost of `node_color`. Gray if the mode needs an island it doesn't know (yet).
+    pub fn base_color(&self, waveform: Waveform, octave: i32, island: Option<usize>) -> Color {
+        match self.mode {
+            ColorMode::Waveform => waveform.as_color(),
+            ColorMode::Octave => self.octave_color(octave),
+            ColorMode::Island => island.map_or(Color::GRAY, |island| self.island_color(island)),
+        }
+    }
+
+    pub fn octave_color(&self, octave: i32) -> Color {
+        let progress = (octave.clamp(MIN_COLOR_OCTAVE, MAX_COLOR_OCTAVE) - MIN_COLOR_OCTAVE) as f32
+            / (MAX_COLOR_OCTAVE - MIN_COLOR_OCTAVE) as f32;
+        self.at(progress)
+    }
+
+    /// Neighbouring island indices get very different colors, and the colors never repeat exactly.
+    pub fn island_color(&self, island: usize) -> Color {
+        const GOLDEN_RATIO_CONJUGATE: f64 = 0.618_033_988_749_895;
+        self.at((island as f64 * GOLDEN_RATIO_CONJUGATE).fract() as f32)
+    }
+
+    fn at(&self, progress: f32) -> Color {
+        let [r, g, b, a] = self.gradient.at(progress).to_array();
+        Color::from_rgba(r, g, b, a)
+    }
+
+    /// What the colors mean, for the stats.
+    pub fn legend(&self) -> String {
+        match self.mode {
+            ColorMode::Waveform => "by waveform".to_string(),
+            ColorMode::Octave => {
+                let octaves = (MIN_COLOR_OCTAVE..=MAX_COLOR_OCTAVE)
+                    .map(|octave| {
+                        format!(
+                            "[color={}]{octave}[/color]",
+                            color_to_html(self.octave_color(octave))
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("by octave {octaves}")
+            }
+            ColorMode::Island => "by island".to_string(),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Named presets: a constellation (seed and generation parameters) plus the tempo and walk settings, saved under a name to recall later.
+//! See `AudioState::save_preset`. Every preset is its own file in `PRESETS_DIR`, so they're easy to share.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gd::graph::{
+    graph_generate::{Connectivity, EdgeStrategy, PointDistribution},
+    graph_walk::WalkStrategy,
+};
+
+pub const PRESETS_DIR: &str = "user://presets";
+
+/// Longest preset name, so it fits in a file name on every platform.
+pub const MAX_PRESET_NAME_LEN: usize = 64;
+
+const PRESET_EXTENSION: &str = "toml";
+
+/// The parameters of `AudioGraph` that decide what the constellation looks like (together with the seed).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    pub num_points: i64,
+    pub distribution: PointDistribution,
+    pub edge_strategy: EdgeStrategy,
+    pub connectivity: Connectivity,
+    pub bridges_per_island: u32,
+    pub lloyd_iterations: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    #[serde(with = "crate::settings::hex_seed")]
+    pub seed: u64,
+    pub bpm: f64,
+    #[serde(flatten)]
+    pub generation: GenerationParams,
+    pub walk_strategy: WalkStrategy,
+    /// See `Settings::palette`.
+    pub palette: String,
+}
+
+#[derive(Debug)]
+pub enum PresetError {
+    InvalidName(String),
+    AlreadyExists(String),
+    NotFound(String),
+    Io(io::Error),
+    Toml(toml::de::Error),
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresetError::InvalidName(name) => write!(
+                f,
+                "invalid preset name {name:?}, use at most {MAX_PRESET_NAME_LEN} letters, digits, spaces, - or _"
+            ),
+            PresetError::AlreadyExists(name) => write!(f, "there already is a preset {name:?}"),
+            PresetError::NotFound(name) => write!(f, "there is no preset {name:?}"),
+            PresetError::Io(err) => write!(f, "failed to access the presets: {err}"),
+            PresetError::Toml(err) => write!(f, "invalid preset file: {err}"),
+        }
+    }
+}
+
+impl From<io::Error> for PresetError {
+    fn from(err: io::Error) -> Self {
+        PresetError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for PresetError {
+    fn from(err: toml::de::Error) -> Self {
+        PresetError::Toml(err)
+    }
+}
+
+/// Names become file names, so only allow what's safe on every platform (no paths, no hidden files).
+pub fn validate_preset_name(name: &str) -> Result<(), PresetError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_PRESET_NAME_LEN
+        && name.trim() == name
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'));
+    if valid {
+        Ok(())
+    } else {
+        Err(PresetError::InvalidName(name.to_string()))
+    }
+}
+
+/// The presets in a directory (`PRESETS_DIR` in the game), one `<name>.toml` per preset.
+#[derive(Debug, Clone)]
+pub struct PresetStore {
+    dir: PathBuf,
+}
+
+impl PresetStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, PresetError> {
+        validate_preset_name(name)?;
+        Ok(self.dir.join(format!("{name}.{PRESET_EXTENSION}")))
+    }
+
+    /// Fails with `AlreadyExists` if there already is a preset with this name, unless `overwrite` is set.
+    pub fn save(&self, name: &str, preset: &Preset, overwrite: bool) -> Result<(), PresetError> {
+        let path = self.path(name)?;
+        if !overwrite && path.exists() {
+            return Err(PresetError::AlreadyExists(name.to_string()));
+        }
+        fs::create_dir_all(&self.dir)?;
+        let toml = toml::to_string(preset).expect("presets are always serializable");
+        fs::write(path, toml)?;
+        Ok(())
+    }
+
+    pub fn load(&self, name: &str) -> Result<Preset, PresetError> {
+        let toml = fs::read_to_string(self.path(name)?).map_err(|err| not_found(err, name))?;
+        Ok(toml::from_str(&toml)?)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), PresetError> {
+        fs::remove_file(self.path(name)?).map_err(|err| not_found(err, name))?;
+        Ok(())
+    }
+
+    /// The names of all presets, sorted. Skips files that don't look like presets.
+    pub fn list(&self) -> Result<Vec<String>, PresetError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]), // Nothing saved yet
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut names = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if let Some(name) = preset_name(&path)
+                && validate_preset_name(name).is_ok()
+            {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+fn preset_name(path: &Path) -> Option<&str> {
+    if path.extension()? != PRESET_EXTENSION {
+        return None;
+    }
+    path.file_stem()?.to_str()
+}
+
+fn not_found(err: io::Error, name: &str) -> PresetError {
+    if err.kind() == io::ErrorKind::NotFound {
+        PresetError::NotFound(name.to_string())
+    } else {
+        PresetError::Io(err)
+    }
+}
@@ -3,6 +3,17 @@ use std::{fmt, time::Duration};
 // Note - the local variables here don't need unique variable names, as macro_rules is hygienic
 #[macro_export]
 macro_rules! profile {
+    // Also records the duration into `$timings` (anything with a `record(label, duration)` method)
+    (into $timings:expr, $label:expr, $expr:expr) => {{
+        let start = std::time::Instant::now();
+        let result = $expr;
+        let duration = start.elapsed();
+        tracing::debug!(func = %$label, time = %$crate::profile::Ms(duration), "profile!");
+        $timings.record($label, duration);
+
+        result
+    }};
+
     ($label:expr, $expr:expr) => {{
         let start = std::time::Instant::now();
         let result = $expr;
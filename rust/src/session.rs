@@ -0,0 +1,164 @@
+//! Session snapshots: a constellation, how it was set up and what was played on it, bundled into one JSON file to share.
+//! See `AudioState::export_session` and `AudioState::import_session`.
+
+use std::{fmt, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    built_info,
+    gd::graph::{
+        graph_main::ConstellationStats, graph_record::WalkRecording, graph_walk::WalkStrategy,
+    },
+    presets::GenerationParams,
+    settings::hex_seed,
+};
+
+/// The settings `import_session` applies, in the order it applies them.
+pub const SESSION_SETTINGS: [&str; 4] = ["seed", "bpm", "generation", "walk_strategy"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    /// Which build made this, only for reference.
+    pub build: BuildInfo,
+    #[serde(with = "hex_seed")]
+    pub seed: u64,
+    pub bpm: f64,
+    pub generation: GenerationParams,
+    pub walk_strategy: WalkStrategy,
+    /// None if the constellation wasn't generated yet.
+    pub stats: Option<ConstellationStats>,
+    /// The latest recording, see `AudioGraph::toggle_recording`.
+    pub recording: Option<WalkRecording>,
+}
+
+/// The `built_info` that `AudioState::get_version_str` shows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub crate_name: String,
+    pub version: String,
+    pub commit: Option<String>,
+    pub features: Vec<String>,
+    pub built_time_utc: String,
+    pub target: String,
+    pub rustc_version: String,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            crate_name: built_info::PKG_NAME.to_string(),
+            version: built_info::PKG_VERSION.to_string(),
+            commit: built_info::GIT_COMMIT_HASH_SHORT.map(str::to_string),
+            features: built_info::FEATURES_LOWERCASE
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect(),
+            built_time_utc: built_info::BUILT_TIME_UTC.to_string(),
+            target: built_info::TARGET.to_string(),
+            rustc_version: built_info::RUSTC_VERSION.to_string(),
+        }
+    }
+}
+
+/// The settings of a session file, each one None if it was missing or invalid.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionSettings {
+    pub seed: Option<u64>,
+    pub bpm: Option<f64>,
+    pub generation: Option<GenerationParams>,
+    pub walk_strategy: Option<WalkStrategy>,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// Valid JSON, but not an object, so it can't be a session
+    NotAnObject,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Io(err) => write!(f, "failed to access the session file: {err}"),
+            SessionError::Json(err) => write!(f, "invalid session file: {err}"),
+            SessionError::NotAnObject => write!(f, "invalid session file: not a JSON object"),
+        }
+    }
+}
+
+impl From<io::Error> for SessionError {
+    fn from(err: io::Error) -> Self {
+        SessionError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SessionError {
+    fn from(err: serde_json::Error) -> Self {
+        SessionError::Json(err)
+    }
+}
+
+pub fn save_session(path: &Path, snapshot: &SessionSnapshot) -> Result<(), SessionError> {
+    let json = serde_json::to_string_pretty(snapshot).expect("sessions are always serializable");
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Reads the settings of a session file. Returns the settings that are missing or invalid as well, see `SESSION_SETTINGS`.
+/// Everything else (e.g. the stats) is ignored, so files from other versions still work as far as possible.
+pub fn load_session_settings(path: &Path) -> Result<(SessionSettings, Vec<String>), SessionError> {
+    session_settings_from_json(&fs::read_to_string(path)?)
+}
+
+/// See `load_session_settings`.
+pub fn session_settings_from_json(
+    json: &str,
+) -> Result<(SessionSettings, Vec<String>), SessionError> {
+    let Value::Object(mut object) = serde_json::from_str(json)? else {
+        return Err(SessionError::NotAnObject);
+    };
+
+    let mut skipped = vec![];
+    let settings = SessionSettings {
+        seed: take_setting(&mut object, "seed", hex_seed::deserialize, &mut skipped),
+        bpm: take_setting(&mut object, "bpm", f64::deserialize, &mut skipped),
+        generation: take_setting(
+            &mut object,
+            "generation",
+            GenerationParams::deserialize,
+            &mut skipped,
+        ),
+        walk_strategy: take_setting(
+            &mut object,
+            "walk_strategy",
+            WalkStrategy::deserialize,
+            &mut skipped,
+        ),
+    };
+    Ok((settings, skipped))
+}
+
+/// The setting `key` if it's there and valid, otherwise it gets added to `skipped`.
+fn take_setting<T>(
+    object: &mut Map<String, Value>,
+    key: &str,
+    parse: fn(Value) -> Result<T, serde_json::Error>,
+    skipped: &mut Vec<String>,
+) -> Option<T> {
+    let setting = match object.remove(key) {
+        None => {
+            tracing::warn!("session file has no {key}");
+            None
+        }
+        Some(value) => parse(value)
+            .inspect_err(|err| tracing::warn!("session file has an invalid {key}: {err}"))
+            .ok(),
+    };
+    if setting.is_none() {
+        skipped.push(key.to_string());
+    }
+    setting
+}
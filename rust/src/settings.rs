@@ -0,0 +1,216 @@
+//! User settings that survive a restart, stored in `SETTINGS_PATH`. Loaded by `AudioState::ready`, and saved (debounced) when they change.
+//! Precedence: defaults < saved settings < CLI args. CLI args only apply to the session, they don't get saved.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use godot::classes::{FileAccess, file_access::ModeFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::gd::{
+    autoload::{cli::InnerArgs, state_main::parse_hexseed},
+    graph::graph_walk::WalkStrategy,
+};
+
+pub const SETTINGS_PATH: &str = "user://settings.toml";
+
+/// Bump this (and add a migration to `migrate`) when `Settings` changes in a way older files can't be read as.
+pub const SETTINGS_VERSION: i64 = 1;
+
+/// Save this long after the last change, so dragging the BPM slider doesn't write the file every frame.
+pub const SETTINGS_SAVE_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// The settings as they are now, see `SettingsV1`.
+pub type Settings = SettingsV1;
+
+/// Missing fields fall back to their defaults, so adding a field doesn't need a new version.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SettingsV1 {
+    pub bpm: f64,
+    /// As 16 hexadecimal characters like `--seed`, since TOML integers can't hold every u64.
+    #[serde(with = "hex_seed")]
+    pub seed: u64,
+    pub use_metronome: bool,
+    /// Multiplies the volume of the Master bus (so 1 keeps the volume from the bus layout).
+    pub master_gain: f64,
+    /// Name of the colorgrad preset (one of `PALETTES`) for the octave and island colors, see `ColorMode`.
+    /// The waveform colors always use turbo.
+    pub palette: String,
+    pub walk_strategy: WalkStrategy,
+}
+
+impl Default for SettingsV1 {
+    fn default() -> Self {
+        Self {
+            bpm: 115.0,
+            seed: 0xDEADBEEF,
+            use_metronome: false,
+            master_gain: 1.0,
+            palette: "turbo".to_string(),
+            walk_strategy: WalkStrategy::default(),
+        }
+    }
+}
+
+impl SettingsV1 {
+    /// These settings, with the ones passed on the command line taking precedence.
+    pub fn with_cli_args(&self, args: &InnerArgs) -> Self {
+        let mut settings = self.clone();
+        if let Some(bpm) = args.bpm {
+            settings.bpm = bpm;
+        }
+        if let Some(seed) = args.seed {
+            settings.seed = seed;
+        }
+        settings
+    }
+}
+
+/// Written next to the settings, so we know how to read them back.
+#[derive(Serialize)]
+struct VersionedSettings<'a> {
+    version: i64,
+    #[serde(flatten)]
+    settings: &'a Settings,
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Toml(toml::de::Error),
+    MissingVersion,
+    /// Written by a newer version of the game, which we can't know how to read.
+    FutureVersion(i64),
+    UnknownVersion(i64),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Toml(err) => write!(f, "invalid settings file: {err}"),
+            SettingsError::MissingVersion => write!(f, "settings file has no version"),
+            SettingsError::FutureVersion(version) => write!(
+                f,
+                "settings file is version {version}, but this version of the game only knows up to {SETTINGS_VERSION}"
+            ),
+            SettingsError::UnknownVersion(version) => {
+                write!(f, "settings file has unknown version {version}")
+            }
+        }
+    }
+}
+
+impl From<toml::de::Error> for SettingsError {
+    fn from(err: toml::de::Error) -> Self {
+        SettingsError::Toml(err)
+    }
+}
+
+pub fn settings_to_toml(settings: &Settings) -> String {
+    toml::to_string(&VersionedSettings {
+        version: SETTINGS_VERSION,
+        settings,
+    })
+    .expect("settings are always serializable")
+}
+
+pub fn settings_from_toml(toml: &str) -> Result<Settings, SettingsError> {
+    let mut table: toml::Table = toml::from_str(toml)?;
+    let version = match table.remove("version") {
+        Some(toml::Value::Integer(version)) => version,
+        _ => return Err(SettingsError::MissingVersion),
+    };
+    migrate(version, table)
+}
+
+/// Reads settings of any version we know, and turns them into the current `Settings`.
+fn migrate(version: i64, table: toml::Table) -> Result<Settings, SettingsError> {
+    match version {
+        // When adding V2: read V1 here, and convert it with a `From<SettingsV1> for SettingsV2`
+        1 => Ok(table.try_into::<SettingsV1>()?),
+        version if version > SETTINGS_VERSION => Err(SettingsError::FutureVersion(version)),
+        version => Err(SettingsError::UnknownVersion(version)),
+    }
+}
+
+/// The saved settings, or the defaults if there are none (or they can't be read).
+pub fn load_settings() -> Settings {
+    if !FileAccess::file_exists(SETTINGS_PATH) {
+        tracing::info!("no settings at {SETTINGS_PATH}, using the defaults");
+        return Settings::default();
+    }
+
+    let toml = FileAccess::get_file_as_string(SETTINGS_PATH).to_string();
+    match settings_from_toml(&toml) {
+        Ok(settings) => {
+            tracing::info!(?settings, "loaded settings from {SETTINGS_PATH}");
+            settings
+        }
+        Err(err) => {
+            tracing::warn!("{err}, using the default settings");
+            Settings::default()
+        }
+    }
+}
+
+pub fn save_settings(settings: &Settings) {
+    let Some(mut file) = FileAccess::open(SETTINGS_PATH, ModeFlags::WRITE) else {
+        tracing::error!(
+            "failed to open {SETTINGS_PATH} for writing: {:?}",
+            FileAccess::get_open_error()
+        );
+        return;
+    };
+    file.store_string(&settings_to_toml(settings));
+    file.close();
+    tracing::info!("saved settings to {SETTINGS_PATH}");
+}
+
+/// Decides when to save changed settings, see `SETTINGS_SAVE_DEBOUNCE`.
+#[derive(Debug, Default, Clone)]
+pub struct SaveDebounce {
+    last_change: Option<Instant>,
+}
+
+impl SaveDebounce {
+    pub fn changed(&mut self, now: Instant) {
+        self.last_change = Some(now);
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.last_change.is_some()
+    }
+
+    /// Forget about the changes, e.g. after saving them.
+    pub fn clear(&mut self) {
+        self.last_change = None;
+    }
+
+    /// True (once) if something changed, and nothing did for `SETTINGS_SAVE_DEBOUNCE`.
+    pub fn take_due(&mut self, now: Instant) -> bool {
+        let due = self
+            .last_change
+            .is_some_and(|last_change| now.duration_since(last_change) >= SETTINGS_SAVE_DEBOUNCE);
+        if due {
+            self.clear();
+        }
+        due
+    }
+}
+
+pub(crate) mod hex_seed {
+    use serde::{Deserialize as _, Deserializer, Serializer, de::Error as _};
+
+    use super::parse_hexseed;
+
+    pub fn serialize<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{seed:016X}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let seed = String::deserialize(deserializer)?;
+        parse_hexseed(&seed).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,59 @@
+//! Stats exports: the Statistics tab as JSON, plus what it takes to generate the same constellation again.
+//! See `AudioGraph::export_stats`. Every export is its own file in `STATS_DIR`, so constellations are easy to compare.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    gd::graph::graph_main::ConstellationStats, presets::GenerationParams, session::BuildInfo,
+    settings::hex_seed,
+};
+
+pub const STATS_DIR: &str = "user://stats";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsExport {
+    /// Which build made this, the stats of a seed can change between versions.
+    pub build: BuildInfo,
+    /// The global seed, `graph_salt` gets mixed in (see `salted_seed`)
+    #[serde(with = "hex_seed")]
+    pub seed: u64,
+    pub graph_salt: u32,
+    pub bpm: f64,
+    pub generation: GenerationParams,
+    pub stats: ConstellationStats,
+}
+
+impl StatsExport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("stats are always serializable")
+    }
+
+    /// `<seed>_<timestamp>.json`, with the seed in hex like in the settings and `time` in UTC, e.g. `00000000DEADBEEF_20250102T030405Z.json`.
+    pub fn file_name(&self, time: OffsetDateTime) -> String {
+        let time = time.to_offset(time::UtcOffset::UTC);
+        format!(
+            "{:016X}_{:04}{:02}{:02}T{:02}{:02}{:02}Z.json",
+            self.seed,
+            time.year(),
+            time.month() as u8,
+            time.day(),
+            time.hour(),
+            time.minute(),
+            time.second()
+        )
+    }
+
+    /// Writes the export to `dir` (creating it if needed), returns the path of the file. See `file_name`.
+    pub fn save(&self, dir: &Path, time: OffsetDateTime) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(self.file_name(time));
+        fs::write(&path, self.to_json())?;
+        Ok(path)
+    }
+}
@@ -1,10 +1,13 @@
-use std::rc::Rc;
+use std::time::Instant;
 
 use godot::prelude::*;
-use tracing::info_span;
 
 use crate::{
-    async_node::AsyncNode, gd::autoload::state_tick::subscribe_to_ticks, util::LerpSmooth,
+    gd::{
+        autoload::state_tick::{TICK_LATENCY, TickReceiver, subscribe_to_ticks},
+        graph::graph_main::AudioGraph,
+    },
+    util::LerpSmooth,
 };
 
 #[derive(GodotClass)]
@@ -12,8 +15,10 @@ use crate::{
 pub struct AudioUI {
     #[base]
     base: Base<Node2D>,
+    /// One per tick in the current bar.
     transparencies: Vec<f32>,
-    executor: Option<Rc<async_executor::LocalExecutor<'static>>>,
+    ticks_per_beat: usize,
+    ticks: TickReceiver,
 }
 
 #[godot_api]
@@ -22,26 +27,24 @@ impl INode2D for AudioUI {
         Self {
             base,
             transparencies: (vec![0.0; 16]),
-            executor: None,
+            ticks_per_beat: 4,
+            ticks: subscribe_to_ticks(),
         }
     }
 
-    fn ready(&mut self) {
-        let mut ticks = subscribe_to_ticks();
-        self.spawn_local_task(false, info_span!("ticker"), async move |mut this| {
-            loop {
-                //TODO: use tick.tick + tick.beat * tick.ticks_per_beat instead here?
-                let tick = ticks.wait().await.total_ticks;
-
-                let len = this.bind().transparencies.len();
-                this.bind_mut().transparencies[tick % len] = 1.0;
-            }
-        });
-    }
-
     fn process(&mut self, delta: f32) {
         self.base_mut().queue_redraw();
-        self.tick_deferred();
+
+        // If several ticks arrived this frame, only the newest one lights up
+        if let Some(tick) = self.ticks.try_latest() {
+            TICK_LATENCY.lock().unwrap().record(&tick, Instant::now());
+
+            // Follow time signature changes
+            self.transparencies
+                .resize(tick.beats_per_bar * tick.ticks_per_beat, 0.0);
+            self.ticks_per_beat = tick.ticks_per_beat;
+            self.transparencies[tick.tick_in_bar()] = 1.0;
+        }
 
         for alpha in &mut self.transparencies {
             *alpha = alpha.lerp_smooth(0.05, 10.0, delta);
@@ -57,14 +60,19 @@ impl INode2D for AudioUI {
             .size;
 
         let transparencies = self.transparencies.clone(); // Cloning a Vec with 16 elements should be fast enough
+        let ticks_per_beat = self.ticks_per_beat;
 
         let spacing = 8.0;
         let spacing_y = 16.0;
         let max_radius = 8.0;
 
         for (i, &a) in transparencies.iter().enumerate() {
-            let fourth = i.is_multiple_of(4);
-            let mut radius = if fourth { max_radius } else { max_radius / 4.0 };
+            let on_beat = i.is_multiple_of(ticks_per_beat);
+            let mut radius = if on_beat {
+                max_radius
+            } else {
+                max_radius / 4.0
+            };
 
             radius *= 1.0 + a;
             let total_width = transparencies.len() as f32 * (max_radius * 2.0 + spacing) - spacing;
@@ -77,19 +85,21 @@ impl INode2D for AudioUI {
             let g = 1.0;
             let b = 1.0;
 
-            let a = if fourth { a } else { a.min(0.2) };
+            let a = if on_beat { a } else { a.min(0.2) };
             self.base_mut()
                 .draw_circle(Vector2::new(x, y), radius, Color { r, g, b, a });
         }
-    }
-}
-
-impl AsyncNode for AudioUI {
-    fn set_executor(&mut self, executor: Option<Rc<async_executor::LocalExecutor<'static>>>) {
-        self.executor = executor;
-    }
 
-    fn get_executor(&self) -> &Option<Rc<async_executor::LocalExecutor<'static>>> {
-        &self.executor
+        if let Some(rect) =
+            AudioGraph::current().and_then(|graph| graph.bind().box_selection_rect())
+        {
+            self.base_mut()
+                .draw_rect(rect, Color::from_rgba(1.0, 1.0, 1.0, 0.1));
+            self.base_mut()
+                .draw_rect_ex(rect, Color::from_rgba(1.0, 1.0, 1.0, 0.6))
+                .filled(false)
+                .width(1.0)
+                .done();
+        }
     }
 }
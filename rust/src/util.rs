@@ -1,7 +1,13 @@
 use std::{
-    f64::consts::TAU,
+    collections::VecDeque,
+    f64::consts::{PI, TAU},
+    fmt,
     hash::Hash,
-    sync::atomic::{AtomicU32, Ordering},
+    sync::{
+        OnceLock,
+        atomic::{AtomicU32, Ordering},
+    },
+    thread::{self, ThreadId},
 };
 
 use godot::builtin::{Color, Vector3};
@@ -46,12 +52,36 @@ pub trait LerpSmooth: Sized + Lerp {
     }
 }
 
+/// Like `Color::to_html_without_alpha` (e.g. "ff8000"), but without calling into Godot, so it also works in tests.
+pub fn color_to_html(color: Color) -> String {
+    let channel = |c: f32| (c * 255.0).round().clamp(0.0, 255.0) as u8;
+    format!(
+        "{:02x}{:02x}{:02x}",
+        channel(color.r),
+        channel(color.g),
+        channel(color.b)
+    )
+}
+
 impl LerpSmooth for f32 {}
 impl LerpSmooth for Vector3 {}
 impl LerpSmooth for Color {}
 
 ///////////////
 
+// Easing curves, they map `t` from 0 to 1 onto 0 to 1. See https://easings.net for what they look like.
+
+pub fn ease_in_out_sine(t: f64) -> f64 {
+    (1.0 - (PI * t.clamp(0.0, 1.0)).cos()) / 2.0
+}
+
+pub fn ease_out_quad(t: f64) -> f64 {
+    let t = t.clamp(0.0, 1.0);
+    1.0 - (1.0 - t) * (1.0 - t)
+}
+
+///////////////
+
 /// Atomic float, taken from https://github.com/rust-lang/rust/issues/72353#issuecomment-1093729062
 #[derive(Default, Debug)]
 pub struct AtomicF32 {
@@ -59,7 +89,7 @@ pub struct AtomicF32 {
 }
 
 impl AtomicF32 {
-    pub fn new(value: f32) -> Self {
+    pub const fn new(value: f32) -> Self {
         let as_u64 = value.to_bits();
         Self {
             storage: AtomicU32::new(as_u64),
@@ -98,7 +128,22 @@ pub fn create_rng_from_seed_and_state(local_seed: u32, global_seed: i64) -> Xosh
     Xoshiro256Plus::from_seed(combined_seed.into())
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The seed of an AudioGraph with `graph_salt` (see `AudioGraph::graph_salt`), so graphs in the same scene differ deterministically.
+/// Salt 0 is the global seed itself, so a single graph gets the same constellation as before there were salts.
+pub fn salted_seed(global_seed: i64, graph_salt: u32) -> i64 {
+    if graph_salt == 0 {
+        return global_seed;
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(global_seed.to_be_bytes());
+    hasher.update(b"graph_salt");
+    hasher.update(graph_salt.to_be_bytes());
+
+    let hash = hasher.finalize();
+    i64::from_be_bytes(hash[..8].try_into().unwrap())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct OrderedVector3 {
     pub x: OrderedFloat<f32>,
     pub y: OrderedFloat<f32>,
@@ -134,3 +179,127 @@ pub fn random_unit_axis<R: Rng>(rng: &mut R) -> NVector3<f64> {
     let y = radius * theta.sin();
     NVector3::new(x, y, z)
 }
+
+/// Returns the closest point in front of `origin` where the ray hits the sphere of `radius` around the origin (0, 0, 0), if any.
+/// `direction` must be normalized.
+pub fn ray_sphere_intersection(
+    origin: Vector3,
+    direction: Vector3,
+    radius: f32,
+) -> Option<Vector3> {
+    // Solve |origin + t * direction|^2 = radius^2 for t
+    let b = origin.dot(direction);
+    let c = origin.length_squared() - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrt = discriminant.sqrt();
+    [-b - sqrt, -b + sqrt]
+        .into_iter()
+        .find(|t| *t >= 0.0)
+        .map(|t| origin + direction * t)
+}
+
+/// The `p`th percentile (0 to 100) of `sorted` with the nearest-rank method, so it's always one of the values. None if `sorted` is empty.
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.saturating_sub(1)])
+}
+
+/// The last `capacity` frame times (in seconds), for the percentiles in the perf overlay.
+#[derive(Debug, Clone)]
+pub struct FrameTimes {
+    times: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl FrameTimes {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            times: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, frame_time: f64) {
+        if self.times.len() == self.capacity {
+            self.times.pop_front();
+        }
+        self.times.push_back(frame_time);
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// The given percentiles (0 to 100) of the frame times, None if there are none yet.
+    pub fn percentiles<const N: usize>(&self, ps: [f64; N]) -> Option<[f64; N]> {
+        let mut sorted = self.times.iter().copied().collect::<Vec<_>>();
+        sorted.sort_by(f64::total_cmp);
+        let mut percentiles = [0.0; N];
+        for (percentile_out, p) in percentiles.iter_mut().zip(ps) {
+            *percentile_out = percentile(&sorted, p)?;
+        }
+        Some(percentiles)
+    }
+}
+
+/// Remembers which thread is the main thread, so code that only works there (like finding an autoload) can return an error instead of panicking.
+#[derive(Debug, Default)]
+pub struct MainThreadGuard {
+    main_thread: OnceLock<ThreadId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadGuardError {
+    /// `set_main_thread` wasn't called yet
+    Uninitialized,
+    NotMainThread,
+}
+
+impl fmt::Display for ThreadGuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThreadGuardError::Uninitialized => write!(f, "the main thread isn't known yet"),
+            ThreadGuardError::NotMainThread => {
+                write!(
+                    f,
+                    "called from {:?}, not the main thread",
+                    thread::current().id()
+                )
+            }
+        }
+    }
+}
+
+impl MainThreadGuard {
+    pub const fn new() -> Self {
+        Self {
+            main_thread: OnceLock::new(),
+        }
+    }
+
+    /// Call this from the main thread. Only the first call counts.
+    pub fn set_main_thread(&self) {
+        let _ = self.main_thread.set(thread::current().id());
+    }
+
+    pub fn check(&self) -> Result<(), ThreadGuardError> {
+        match self.main_thread.get() {
+            None => Err(ThreadGuardError::Uninitialized),
+            Some(main_thread) if *main_thread != thread::current().id() => {
+                Err(ThreadGuardError::NotMainThread)
+            }
+            Some(_) => Ok(()),
+        }
+    }
+}
@@ -3,7 +3,36 @@
 //! Also don't use the built-in hash `DefaultHash` or `ahash`, try `HighwayHash` instead (it's fully portable/deterministic).
 //! Also watch out for HashMap/HashSet, by default they're randomized.
 
-use musical_constellations_rust::gd::graph::graph_generate::ConstellationGraph;
+use musical_constellations_rust::gd::{
+    graph::{
+        graph_builder::ConstellationBuilder,
+        graph_edge_slots::EdgeSlots,
+        graph_generate::{
+            Connectivity, ConstellationGraph, ConstellationParams,
+            DEFAULT_ISLAND_ADJACENCY_DISTANCE, DEFAULT_MIN_ISLAND_SIZE, EdgeStrategy,
+            GenerationBudget, GenerationError, GenerationProgress, GenerationTimings, GraphError,
+            PointDistribution,
+        },
+        graph_import::{
+            PointCloudError, load_points_file, normalize_points, parse_points_csv,
+            parse_points_json,
+        },
+        graph_lifetime::ConstellationLifetime,
+        graph_main::{AudioGraph, IslandData},
+        graph_metrics::GraphMetrics,
+        graph_record::{ReplayAction, WalkEvent, WalkRecorder, WalkRecording},
+        graph_walk::{
+            ActiveWalks, ClickQueue, CountIn, CountInStep, DeadEndBehavior, EdgeEase, EdgeProgress,
+            LaunchQuantization, NodeOccupancy, RevisitPolicy, RhythmGrid, TickCountdown,
+            TrailState, WalkConfig, WalkEnd, WalkState, WalkStrategy, WalkerOverflow,
+            compensate_delay, deterministic_walk_rng, humanize_delay, island_edge_beats,
+            note_velocity, remaining_lag, shortest_path, should_rest, softmax_choice,
+            swing_delay_ticks, swung_duration_ticks, trail_lightening,
+        },
+        graph_wave::{bfs_layers, wave_edges},
+    },
+    node_main::{PlayAction, RetriggerMode},
+};
 use rand::Rng;
 use serde::Serialize;
 #[derive(Serialize)]
@@ -39,6 +68,10 @@ impl ConstellationGraphSnapshot {
 // Unit tests
 #[cfg(test)]
 mod tests {
+    use std::{collections::BTreeSet, time::Duration};
+
+    use godot::builtin::Vector3;
+    use itertools::Itertools as _;
     use rand::SeedableRng as _;
     use rand_xoshiro::Xoshiro256Plus;
 
@@ -60,7 +93,8 @@ mod tests {
 
             let mut rng = Xoshiro256Plus::from_seed(seed_bytes);
             let constellation_graph =
-                ConstellationGraph::new(num_points as usize, radius, max_neighbor_count, &mut rng);
+                ConstellationGraph::new(num_points as usize, radius, max_neighbor_count, &mut rng)
+                    .unwrap();
             let snapshot = ConstellationGraphSnapshot::new(
                 constellation_graph,
                 global_seed,
@@ -74,4 +108,4963 @@ mod tests {
 
         insta::assert_yaml_snapshot!(snapshots);
     }
+
+    /// Tiny islands should always be merged into their neighbors, regardless of the seed.
+    #[test]
+    fn min_island_size() {
+        for global_seed in [1_i64, 2, 3, 0xDEADBEEF] {
+            let mut seed_bytes = [0u8; 32];
+            seed_bytes[0..8].copy_from_slice(&global_seed.to_le_bytes());
+
+            let mut rng = Xoshiro256Plus::from_seed(seed_bytes);
+            let constellation_graph = ConstellationGraph::new(500, 5.0, 3, &mut rng).unwrap();
+
+            let smallest_island = constellation_graph
+                .islands
+                .iter()
+                .map(|island| island.len())
+                .min()
+                .unwrap();
+            assert!(
+                smallest_island >= DEFAULT_MIN_ISLAND_SIZE,
+                "seed {global_seed} has an island of size {smallest_island}"
+            );
+        }
+    }
+
+    /// Every phase should report monotonically increasing progress, ending at 1.0.
+    #[test]
+    fn generation_progress() {
+        let (tx, rx) = flume::unbounded::<GenerationProgress>();
+        let mut rng = Xoshiro256Plus::seed_from_u64(1);
+        ConstellationGraph::with_params(
+            500,
+            5.0,
+            3,
+            &ConstellationParams::default(),
+            Some(tx),
+            &mut rng,
+        )
+        .unwrap();
+
+        let messages = rx.drain().collect::<Vec<_>>();
+        for phase in ["poisson", "voronoi", "connect"] {
+            let fractions = messages
+                .iter()
+                .filter(|progress| progress.phase == phase)
+                .map(|progress| progress.fraction)
+                .collect::<Vec<_>>();
+
+            assert!(!fractions.is_empty(), "no progress for phase {phase}");
+            assert!(
+                fractions.is_sorted(),
+                "progress for phase {phase} went backwards"
+            );
+            assert_eq!(*fractions.last().unwrap(), 1.0);
+            assert!(
+                fractions.len() <= 102,
+                "phase {phase} sent too many messages"
+            ); // Roughly every 1%
+        }
+    }
+
+    /// Dropping the receiver halfway through (e.g. when the AudioGraph is freed) must not panic the generator.
+    #[test]
+    fn generation_progress_receiver_dropped() {
+        let (tx, rx) = flume::unbounded::<GenerationProgress>();
+        drop(rx);
+
+        let mut rng = Xoshiro256Plus::seed_from_u64(1);
+        let constellation_graph = ConstellationGraph::with_params(
+            100,
+            5.0,
+            3,
+            &ConstellationParams::default(),
+            Some(tx),
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(constellation_graph.graph.node_count(), 100);
+    }
+
+    /// The Poisson points are proposed in parallel, so make sure the point sequence doesn't depend on thread scheduling.
+    #[test]
+    fn poisson_determinism() {
+        let mut rng = Xoshiro256Plus::seed_from_u64(1);
+        let points = ConstellationGraph::generate_points(
+            100,
+            5.0,
+            &GenerationBudget::default(),
+            &mut rng,
+            None,
+        );
+
+        // Run it again on a single thread, the result must be exactly the same
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let mut rng = Xoshiro256Plus::seed_from_u64(1);
+        let points_single_threaded = pool.install(|| {
+            ConstellationGraph::generate_points(
+                100,
+                5.0,
+                &GenerationBudget::default(),
+                &mut rng,
+                None,
+            )
+        });
+        assert_eq!(points, points_single_threaded);
+
+        insta::assert_yaml_snapshot!(points);
+    }
+
+    /// Same as `graph_determinism`, but with more points and Lloyd relaxation, which should even out the cluster sizes.
+    #[test]
+    fn lloyd_relaxation() {
+        let mut snapshots = vec![];
+
+        for global_seed in [1_i64, 2] {
+            let num_points = 60;
+            let max_neighbor_count = 2;
+            let radius = 5.0;
+            let params = ConstellationParams {
+                lloyd_iterations: 4,
+                ..Default::default()
+            };
+
+            let mut rng = Xoshiro256Plus::seed_from_u64(global_seed as u64);
+            let relaxed = ConstellationGraph::with_params(
+                num_points,
+                radius,
+                max_neighbor_count,
+                &params,
+                None,
+                &mut rng,
+            )
+            .unwrap();
+
+            let mut rng = Xoshiro256Plus::seed_from_u64(global_seed as u64);
+            let unrelaxed = ConstellationGraph::with_params(
+                num_points,
+                radius,
+                max_neighbor_count,
+                &ConstellationParams {
+                    lloyd_iterations: 0,
+                    ..params.clone()
+                },
+                None,
+                &mut rng,
+            )
+            .unwrap();
+            assert_ne!(relaxed, unrelaxed);
+
+            snapshots.push(ConstellationGraphSnapshot::new(
+                relaxed,
+                global_seed,
+                radius,
+                max_neighbor_count,
+                rng,
+            ));
+        }
+
+        insta::assert_yaml_snapshot!(snapshots);
+    }
+
+    /// The clusters are connected in parallel, so make sure the graph doesn't depend on thread scheduling.
+    #[test]
+    fn connect_clusters_determinism() {
+        let generate = || {
+            let mut rng = Xoshiro256Plus::seed_from_u64(816);
+            ConstellationGraph::new(1000, 5.0, 3, &mut rng).unwrap()
+        };
+
+        let parallel = generate();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let single_threaded = pool.install(generate);
+        assert_eq!(parallel, single_threaded);
+    }
+
+    /// Hand-built graphs with a single defect each, plus a healthy one.
+    #[test]
+    fn validate_graph() {
+        use musical_constellations_rust::gd::graph::graph_main::GraphTypedef;
+        use petgraph::graph::NodeIndex;
+
+        let triangle = || {
+            let mut graph = GraphTypedef::new_undirected();
+            let nodes = (0..3)
+                .map(|i| graph.add_node(Vector3::new(i as f32, 0.0, 0.0)))
+                .collect::<Vec<_>>();
+            graph.add_edge(nodes[0], nodes[1], ());
+            graph.add_edge(nodes[1], nodes[2], ());
+            graph.add_edge(nodes[2], nodes[0], ());
+            graph
+        };
+
+        let report = ConstellationGraph::validate_graph(&triangle()).unwrap();
+        assert_eq!(report.degree_histogram, [(2, 3)].into());
+        assert_eq!(report.component_sizes, vec![3]);
+        assert_eq!(report.isolated_vertex_count, 0);
+        assert_eq!(report.duplicate_edge_count, 0);
+
+        let mut self_loop = triangle();
+        self_loop.add_edge(NodeIndex::new(1), NodeIndex::new(1), ());
+        assert_eq!(
+            ConstellationGraph::validate_graph(&self_loop),
+            Err(GraphError::SelfLoop(NodeIndex::new(1)))
+        );
+
+        // Duplicates are reported with the lowest index first, regardless of edge direction
+        let mut duplicate = triangle();
+        duplicate.add_edge(NodeIndex::new(2), NodeIndex::new(1), ());
+        assert_eq!(
+            ConstellationGraph::validate_graph(&duplicate),
+            Err(GraphError::DuplicateEdge(
+                NodeIndex::new(1),
+                NodeIndex::new(2)
+            ))
+        );
+
+        let mut isolated = triangle();
+        let loose = isolated.add_node(Vector3::ZERO);
+        assert_eq!(
+            ConstellationGraph::validate_graph(&isolated),
+            Err(GraphError::IsolatedVertex(loose))
+        );
+
+        // Self-loops take precedence over the other problems
+        isolated.add_edge(NodeIndex::new(0), NodeIndex::new(0), ());
+        isolated.add_edge(NodeIndex::new(0), NodeIndex::new(1), ());
+        assert_eq!(
+            ConstellationGraph::validate_graph(&isolated),
+            Err(GraphError::SelfLoop(NodeIndex::new(0)))
+        );
+
+        // Generated graphs are always healthy
+        for seed in [1, 2, 3] {
+            let mut rng = Xoshiro256Plus::seed_from_u64(seed);
+            let constellation = ConstellationGraph::new(500, 5.0, 3, &mut rng).unwrap();
+            let report = constellation.validate().unwrap();
+            assert_eq!(report.isolated_vertex_count, 0);
+            assert_eq!(report.component_sizes.len(), constellation.islands.len());
+            assert_eq!(
+                report.degree_histogram.values().sum::<usize>(),
+                constellation.graph.node_count()
+            );
+        }
+    }
+
+    /// The builder must not change anything about the generation itself.
+    #[test]
+    fn builder_matches_legacy() {
+        for seed in [1, 2, 3, 0xDEADBEEF] {
+            let mut rng = Xoshiro256Plus::seed_from_u64(seed);
+            let legacy = ConstellationGraph::with_params(
+                300,
+                4.0,
+                2,
+                &ConstellationParams::default(),
+                None,
+                &mut rng,
+            )
+            .unwrap();
+
+            let mut rng = Xoshiro256Plus::seed_from_u64(seed);
+            let built = ConstellationBuilder::new()
+                .with_num_points(300)
+                .with_radius(4.0)
+                .with_max_neighbors(2)
+                .build(&mut rng)
+                .unwrap();
+            assert_eq!(legacy, built);
+        }
+    }
+
+    /// A stored builder must build the exact same graph again.
+    #[test]
+    fn builder_serialization() {
+        let builder = ConstellationBuilder::new()
+            .with_num_points(200)
+            .with_distribution(PointDistribution::Uniform)
+            .with_topology(Connectivity::Bridged)
+            .with_bridges_per_island(2)
+            .with_min_island_size(4)
+            .with_lloyd_iterations(1);
+
+        let json = serde_json::to_string(&builder).unwrap();
+        let restored = serde_json::from_str::<ConstellationBuilder>(&json).unwrap();
+        assert_eq!(restored.num_points, 200);
+        assert_eq!(restored.params.distribution, PointDistribution::Uniform);
+
+        let mut rng = Xoshiro256Plus::seed_from_u64(818);
+        let original = builder.build(&mut rng).unwrap();
+        let mut rng = Xoshiro256Plus::seed_from_u64(818);
+        let rebuilt = restored.build(&mut rng).unwrap();
+        assert_eq!(original, rebuilt);
+
+        // Uniform generation always places all points, on the sphere
+        let points = original.graph.node_weights().copied().collect::<Vec<_>>();
+        assert_eq!(points.len(), 200);
+        assert!(points.iter().all(|p| (p.length() - 5.0).abs() < 1e-3));
+    }
+
+    /// Triangulated clusters should stay healthy, and be denser than k-NN ones.
+    #[test]
+    fn triangulated_edges() {
+        let build = |seed: u64, edge_strategy: EdgeStrategy| {
+            let mut rng = Xoshiro256Plus::seed_from_u64(seed);
+            ConstellationBuilder::new()
+                .with_num_points(60)
+                .with_max_neighbors(2)
+                .with_edge_strategy(edge_strategy)
+                .build(&mut rng)
+                .unwrap()
+        };
+
+        for seed in [1, 2, 3] {
+            let triangulated = build(seed, EdgeStrategy::Triangulated);
+            triangulated.validate().unwrap();
+
+            let comparison = triangulated.edge_strategy_comparison.unwrap();
+            assert_eq!(comparison.edge_strategy, EdgeStrategy::Triangulated);
+            assert!(comparison.cluster_edge_count > comparison.knn_cluster_edge_count);
+            assert_eq!(
+                build(seed, EdgeStrategy::Knn).edge_strategy_comparison,
+                None
+            );
+        }
+
+        let triangulated = build(1, EdgeStrategy::Triangulated);
+        insta::assert_yaml_snapshot!(ConstellationGraphSnapshot::new(
+            triangulated,
+            1,
+            5.0,
+            2,
+            Xoshiro256Plus::seed_from_u64(1),
+        ));
+    }
+
+    /// Every generation phase should be timed, and the table should line up generations that ran different phases.
+    #[test]
+    fn generation_timings() {
+        let mut rng = Xoshiro256Plus::seed_from_u64(820);
+        let constellation = ConstellationGraph::new(200, 5.0, 3, &mut rng).unwrap();
+        let phases = constellation
+            .timings
+            .phases
+            .iter()
+            .map(|(phase, _)| *phase)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            [
+                "points",
+                "cluster_voronoi",
+                "connect_clusters",
+                "merge_small_islands",
+                "bridge_islands",
+                "scc",
+                "build_island_graph",
+                "metrics"
+            ]
+        );
+
+        let ms = Duration::from_millis;
+        let newest = GenerationTimings {
+            phases: vec![("normalize_points", ms(1)), ("scc", ms(2))],
+        };
+        let oldest = GenerationTimings {
+            phases: vec![("points", ms(10)), ("scc", ms(3)), ("scc", ms(1))],
+        };
+        let table = GenerationTimings::format_table(&[newest, oldest]);
+        let rows = table.lines().collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                "normalize_points          1.0        -",
+                "scc                       2.0      4.0",
+                "points                      -     10.0",
+                "total                     3.0     14.0",
+            ]
+        );
+    }
+
+    /// FNV-1a over the node positions and edges, so big graphs can be compared against a snapshot.
+    fn graph_digest(constellation: &ConstellationGraph) -> String {
+        let mut hash = 0xcbf29ce484222325_u64;
+        let mut feed = |value: u64| {
+            for byte in value.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+
+        for p in constellation.graph.node_weights() {
+            feed(p.x.to_bits() as u64);
+            feed(p.y.to_bits() as u64);
+            feed(p.z.to_bits() as u64);
+        }
+        for edge in constellation.graph.raw_edges() {
+            feed(edge.source().index() as u64);
+            feed(edge.target().index() as u64);
+        }
+        format!("{hash:016x}")
+    }
+
+    /// The kd-trees used during generation are an implementation detail, swapping them must not change any graph.
+    /// Too big for `graph_determinism`, so this only snapshots a digest.
+    /// The snapshot was generated with the incrementally built trees, before they got bulk-built, and didn't change with it.
+    #[test]
+    fn large_graph_digest() {
+        let digests = [1_u64, 2, 3]
+            .into_iter()
+            .flat_map(|seed| {
+                [EdgeStrategy::Knn, EdgeStrategy::Triangulated].map(|edge_strategy| {
+                    let mut rng = Xoshiro256Plus::seed_from_u64(seed);
+                    let constellation = ConstellationBuilder::new()
+                        .with_num_points(2000)
+                        .with_edge_strategy(edge_strategy)
+                        .build(&mut rng)
+                        .unwrap();
+                    format!(
+                        "seed {seed}, {edge_strategy:?}: {} nodes, {} edges, digest {}",
+                        constellation.graph.node_count(),
+                        constellation.graph.edge_count(),
+                        graph_digest(&constellation)
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        insta::assert_yaml_snapshot!(digests);
+    }
+
+    /// Island data is assigned by island index, so it must not depend on the order the islands were found in.
+    #[test]
+    fn canonical_islands() {
+        use rand::seq::SliceRandom as _;
+
+        let mut rng = Xoshiro256Plus::seed_from_u64(822);
+        let constellation = ConstellationGraph::new(500, 5.0, 3, &mut rng).unwrap();
+        assert_eq!(
+            ConstellationGraph::canonicalize_islands(constellation.islands.clone()),
+            constellation.islands
+        );
+
+        let island_data_per_node = |constellation: &ConstellationGraph| {
+            let island_data = AudioGraph::generate_island_data(
+                constellation,
+                &mut Xoshiro256Plus::seed_from_u64(1),
+            );
+            constellation
+                .islands
+                .iter()
+                .zip_eq(island_data)
+                .flat_map(|(island, data)| island.iter().map(move |node| (*node, data)))
+                .sorted_by_key(|(node, _)| *node)
+                .collect::<Vec<_>>()
+        };
+
+        let mut shuffle_rng = Xoshiro256Plus::seed_from_u64(2);
+        for _ in 0..5 {
+            let mut shuffled = constellation.islands.clone();
+            shuffled.shuffle(&mut shuffle_rng);
+            for island in &mut shuffled {
+                island.shuffle(&mut shuffle_rng);
+            }
+
+            let reshuffled = ConstellationGraph {
+                chord: constellation.chord,
+                semitone_offset: constellation.semitone_offset,
+                graph: constellation.graph.clone(),
+                islands: ConstellationGraph::canonicalize_islands(shuffled),
+                bridge_edge_count: constellation.bridge_edge_count,
+                island_graph: constellation.island_graph.clone(),
+                metrics: constellation.metrics.clone(),
+                edge_strategy_comparison: None,
+                timings: Default::default(),
+            };
+            assert_eq!(reshuffled.islands, constellation.islands);
+            assert_eq!(
+                island_data_per_node(&reshuffled),
+                island_data_per_node(&constellation)
+            );
+        }
+    }
+
+    /// Jittered clusters sit at different depths, but never further away than the jitter allows.
+    #[test]
+    fn radial_jitter() {
+        let radius = 5.0;
+        let radial_jitter = 0.1;
+        let build = |radial_jitter: f32| {
+            let mut rng = Xoshiro256Plus::seed_from_u64(823);
+            ConstellationBuilder::new()
+                .with_num_points(40)
+                .with_radius(radius)
+                .with_max_neighbors(2)
+                .with_radial_jitter(radial_jitter)
+                .build(&mut rng)
+                .unwrap()
+        };
+
+        let flat = build(0.0);
+        assert!(
+            flat.graph
+                .node_weights()
+                .all(|p| (p.length() - radius).abs() < 1e-3)
+        );
+
+        let jittered = build(radial_jitter);
+        let depths = jittered
+            .graph
+            .node_weights()
+            .map(|p| p.length())
+            .collect::<Vec<_>>();
+        assert!(depths.iter().all(|depth| {
+            (radius * (1.0 - radial_jitter) - 1e-3..=radius * (1.0 + radial_jitter) + 1e-3)
+                .contains(depth)
+        }));
+        assert!(depths.iter().any(|depth| (depth - radius).abs() > 1e-2));
+
+        insta::assert_yaml_snapshot!(ConstellationGraphSnapshot::new(
+            jittered,
+            823,
+            radius,
+            2,
+            Xoshiro256Plus::seed_from_u64(823),
+        ));
+    }
+
+    /// Small graphs with known metrics: a path of 4 nodes, and a separate triangle with a tail.
+    #[test]
+    fn graph_metrics() {
+        use musical_constellations_rust::gd::graph::graph_main::GraphTypedef;
+
+        let mut graph = GraphTypedef::new_undirected();
+        let nodes = (0..8)
+            .map(|i| graph.add_node(Vector3::new(i as f32, 0.0, 0.0)))
+            .collect::<Vec<_>>();
+
+        // Path 0-1-2-3
+        graph.add_edge(nodes[0], nodes[1], ());
+        graph.add_edge(nodes[1], nodes[2], ());
+        graph.add_edge(nodes[2], nodes[3], ());
+        let path_island = nodes[0..4].to_vec();
+
+        let metrics = GraphMetrics::compute(&graph, std::slice::from_ref(&path_island));
+        assert_eq!(metrics.island_diameters, vec![3]);
+        // Pairs: 3x distance 1, 2x distance 2, 1x distance 3
+        assert!((metrics.average_path_length - 10.0 / 6.0).abs() < 1e-6);
+        assert_eq!(metrics.clustering_coefficient, 0.0);
+
+        // Triangle 4-5-6 with tail 6-7
+        graph.add_edge(nodes[4], nodes[5], ());
+        graph.add_edge(nodes[5], nodes[6], ());
+        graph.add_edge(nodes[6], nodes[4], ());
+        graph.add_edge(nodes[6], nodes[7], ());
+        let triangle_island = nodes[4..8].to_vec();
+
+        let metrics = GraphMetrics::compute(&graph, &[path_island, triangle_island]);
+        assert_eq!(metrics.island_diameters, vec![3, 2]);
+        assert_eq!(metrics.max_island_diameter(), 3);
+        assert_eq!(metrics.average_degree, 2.0 * 7.0 / 8.0);
+        // Triangle island pairs: 4x distance 1, 2x distance 2 (4-7, 5-7), so 10 + 8 over 12 pairs
+        assert!((metrics.average_path_length - 18.0 / 12.0).abs() < 1e-6);
+        // Nodes 4 and 5 have coefficient 1, node 6 has 1 linked pair out of 3, the rest 0
+        assert!((metrics.clustering_coefficient - (2.0 + 1.0 / 3.0) / 8.0).abs() < 1e-6);
+
+        assert_eq!(
+            GraphMetrics::compute(&GraphTypedef::new_undirected(), &[]),
+            GraphMetrics::default()
+        );
+    }
+
+    fn generate_with_connectivity(
+        seed: u64,
+        connectivity: Connectivity,
+        bridges_per_island: usize,
+    ) -> ConstellationGraph {
+        let params = ConstellationParams {
+            connectivity,
+            bridges_per_island,
+            ..Default::default()
+        };
+        let mut rng = Xoshiro256Plus::seed_from_u64(seed);
+        ConstellationGraph::with_params(500, 5.0, 3, &params, None, &mut rng).unwrap()
+    }
+
+    /// Bridging should only ever reduce the amount of islands, and FullyConnected should leave exactly one.
+    #[test]
+    fn connectivity() {
+        for seed in [1, 2, 3] {
+            let islands = generate_with_connectivity(seed, Connectivity::Islands, 1);
+            let bridged = generate_with_connectivity(seed, Connectivity::Bridged, 1);
+            let bridged_more = generate_with_connectivity(seed, Connectivity::Bridged, 3);
+            let fully_connected = generate_with_connectivity(seed, Connectivity::FullyConnected, 1);
+
+            assert_eq!(islands.bridge_edge_count, 0);
+            assert!(bridged.bridge_edge_count > 0);
+            assert!(bridged_more.bridge_edge_count > bridged.bridge_edge_count);
+            assert!(bridged.islands.len() < islands.islands.len());
+            assert_eq!(fully_connected.islands.len(), 1);
+            assert_eq!(
+                fully_connected.bridge_edge_count,
+                islands.islands.len() - 1, // Every bridge merges two islands
+            );
+
+            // Bridges only add edges, the nodes stay the same
+            for graph in [&bridged, &bridged_more, &fully_connected] {
+                assert_eq!(graph.graph.node_count(), islands.graph.node_count());
+                assert_eq!(
+                    graph.graph.edge_count(),
+                    islands.graph.edge_count() + graph.bridge_edge_count
+                );
+            }
+
+            // Bridging is deterministic
+            assert_eq!(
+                bridged,
+                generate_with_connectivity(seed, Connectivity::Bridged, 1)
+            );
+        }
+    }
+
+    /// Parses a `key=value, key="value"` attribute list as written by `to_dot`.
+    fn parse_dot_attributes(attrs: &str) -> std::collections::BTreeMap<&str, &str> {
+        attrs
+            .split(", ")
+            .map(|kv| {
+                let (k, v) = kv.split_once('=').unwrap();
+                (k, v.trim_matches('"'))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn export_dot() {
+        let mut rng = Xoshiro256Plus::seed_from_u64(1);
+        let constellation = ConstellationGraph::new(50, 5.0, 3, &mut rng).unwrap();
+        let island_data = AudioGraph::generate_island_data(&constellation, &mut rng);
+        let dot = constellation.to_dot(Some(&island_data));
+
+        assert!(dot.starts_with("graph constellation {"));
+        assert!(dot.trim_end().ends_with('}'));
+
+        let mut nodes = std::collections::BTreeMap::new();
+        let mut edges = vec![];
+        for line in dot.lines().map(str::trim) {
+            if let Some((a, b)) = line.strip_suffix(';').and_then(|l| l.split_once(" -- ")) {
+                edges.push((a.parse::<usize>().unwrap(), b.parse::<usize>().unwrap()));
+            } else if let Some((idx, attrs)) = line.split_once(" [")
+                && let Ok(idx) = idx.parse::<usize>()
+            {
+                let attrs = parse_dot_attributes(attrs.strip_suffix("];").unwrap());
+                nodes.insert(idx, attrs);
+            }
+        }
+
+        assert_eq!(nodes.len(), constellation.graph.node_count());
+        assert_eq!(edges.len(), constellation.graph.edge_count());
+
+        for (island_idx, island) in constellation.islands.iter().enumerate() {
+            let node = island[0];
+            let attrs = &nodes[&node.index()];
+            let pos = constellation.graph[node];
+            let IslandData {
+                waveform,
+                is_pad,
+                octave_base,
+                ..
+            } = island_data[island_idx];
+
+            assert_eq!(attrs["x"].parse::<f32>().unwrap(), pos.x);
+            assert_eq!(attrs["z"].parse::<f32>().unwrap(), pos.z);
+            assert_eq!(attrs["island"].parse::<usize>().unwrap(), island_idx);
+            assert_eq!(attrs["waveform"], format!("{waveform:?}"));
+            assert_eq!(attrs["pad"].parse::<bool>().unwrap(), is_pad);
+            assert_eq!(attrs["octave_base"].parse::<f64>().unwrap(), octave_base);
+        }
+
+        // Without island data the waveform/octave are omitted
+        let dot = constellation.to_dot(None);
+        assert!(!dot.contains("waveform"));
+        assert!(!dot.contains("octave_base"));
+    }
+
+    #[test]
+    fn export_graphml() {
+        let mut rng = Xoshiro256Plus::seed_from_u64(1);
+        let constellation = ConstellationGraph::new(50, 5.0, 3, &mut rng).unwrap();
+        let island_data = AudioGraph::generate_island_data(&constellation, &mut rng);
+        let graphml = constellation.to_graphml(Some(&island_data));
+
+        assert!(graphml.starts_with("<?xml"));
+        assert_eq!(
+            graphml.matches("<node id=").count(),
+            constellation.graph.node_count()
+        );
+        assert_eq!(
+            graphml.matches("<edge ").count(),
+            constellation.graph.edge_count()
+        );
+        assert_eq!(
+            graphml.matches("<node ").count(),
+            graphml.matches("</node>").count()
+        );
+
+        // Check the attributes of a node in the last island
+        let node = *constellation.islands.last().unwrap().first().unwrap();
+        let IslandData { waveform, .. } = *island_data.last().unwrap();
+        let start = graphml
+            .find(&format!("<node id=\"n{}\">", node.index()))
+            .unwrap();
+        let end = start + graphml[start..].find("</node>").unwrap();
+        let data: std::collections::BTreeMap<_, _> = graphml[start..end]
+            .lines()
+            .filter_map(|line| {
+                let rest = line.trim().strip_prefix("<data key=\"")?;
+                let (key, rest) = rest.split_once("\">")?;
+                Some((key, rest.strip_suffix("</data>")?))
+            })
+            .collect();
+
+        assert_eq!(
+            data["y"].parse::<f32>().unwrap(),
+            constellation.graph[node].y
+        );
+        assert_eq!(
+            data["island"].parse::<usize>().unwrap(),
+            constellation.islands.len() - 1
+        );
+        assert_eq!(data["waveform"], format!("{waveform:?}"));
+    }
+
+    #[test]
+    fn parse_points() {
+        let csv = "x,y,z\n# a comment\n1,2,3\n\n 4.5 , -6, 7e1 \n";
+        let points = parse_points_csv(csv).unwrap();
+        assert_eq!(
+            points,
+            vec![Vector3::new(1.0, 2.0, 3.0), Vector3::new(4.5, -6.0, 70.0)]
+        );
+
+        assert!(matches!(
+            parse_points_csv("1,2,3\n4,five,6"),
+            Err(PointCloudError::Csv { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse_points_csv("1,2"),
+            Err(PointCloudError::Csv { line: 1, .. })
+        ));
+
+        let json = r#"[[1, 2, 3], {"x": 4.5, "y": -6, "z": 70}]"#;
+        assert_eq!(parse_points_json(json).unwrap(), points);
+        assert!(matches!(
+            parse_points_json("[[1, 2]]"),
+            Err(PointCloudError::Json(_))
+        ));
+
+        let missing = std::env::temp_dir().join("musical_constellations_missing_points.csv");
+        assert!(matches!(
+            load_points_file(&missing),
+            Err(PointCloudError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn normalize() {
+        let points = vec![
+            Vector3::new(10.0, 10.0, 10.0),
+            Vector3::new(12.0, 10.0, 10.0),
+            Vector3::new(10.0, 10.0, 10.0), // Duplicate
+            Vector3::new(10.0, 14.0, 10.0),
+        ];
+        let normalized = normalize_points(points, 5.0).unwrap();
+        assert_eq!(normalized.len(), 3);
+
+        let center = normalized.iter().fold(Vector3::ZERO, |acc, p| acc + *p) / 3.0;
+        assert!(center.length() < 1e-5);
+
+        let max_dist = normalized.iter().map(|p| p.length()).fold(0.0, f32::max);
+        assert!((max_dist - 5.0).abs() < 1e-5);
+
+        assert!(matches!(
+            normalize_points(vec![Vector3::ONE, Vector3::ONE], 5.0),
+            Err(PointCloudError::TooFewPoints { count: 1 })
+        ));
+        assert!(matches!(
+            normalize_points(vec![Vector3::ONE, Vector3::new(f32::NAN, 0.0, 0.0)], 5.0),
+            Err(PointCloudError::NonFinite { index: 1 })
+        ));
+    }
+
+    #[test]
+    fn from_points() {
+        // A flat 20x20 grid, with every point duplicated once
+        let grid = (0..400)
+            .map(|i| Vector3::new((i % 20) as f32, 0.0, (i / 20) as f32))
+            .collect::<Vec<_>>();
+        let points = grid.iter().chain(grid.iter()).copied().collect::<Vec<_>>();
+
+        let generate = || {
+            let mut rng = Xoshiro256Plus::seed_from_u64(1);
+            ConstellationGraph::from_points(
+                points.clone(),
+                5.0,
+                3,
+                &ConstellationParams::default(),
+                None,
+                &mut rng,
+            )
+            .unwrap()
+        };
+
+        let constellation = generate();
+        assert_eq!(constellation.graph.node_count(), grid.len());
+        assert!(constellation.graph.node_weights().all(|p| p.y.abs() < 0.01)); // Still flat, apart from the jitter
+        assert!(
+            constellation
+                .islands
+                .iter()
+                .all(|island| island.len() >= DEFAULT_MIN_ISLAND_SIZE)
+        );
+        assert_eq!(constellation, generate());
+    }
+
+    fn generate_with_budget(n: usize, budget: GenerationBudget) -> GenerationError {
+        let params = ConstellationParams {
+            budget,
+            ..Default::default()
+        };
+        let mut rng = Xoshiro256Plus::seed_from_u64(1);
+        ConstellationGraph::with_params(n, 5.0, 3, &params, None, &mut rng).unwrap_err()
+    }
+
+    /// An impossible configuration (way too many points) should fail gracefully with a usable partial graph.
+    #[test]
+    fn generation_budget() {
+        let budget = GenerationBudget {
+            max_iterations: Some(20_000),
+            max_duration: None,
+        };
+        let err = generate_with_budget(1_000_000, budget);
+        let GenerationError::Budget {
+            placed,
+            requested,
+            ref partial,
+        } = err;
+
+        assert_eq!(requested, 1_000_000);
+        assert!(placed > 1 && placed < requested);
+        assert_eq!(partial.graph.node_count(), placed);
+        assert!(partial.graph.edge_count() > 0);
+
+        // The islands are still valid: every node is in exactly one island, and no island is too small
+        let mut nodes = partial.islands.iter().flatten().collect::<Vec<_>>();
+        nodes.sort();
+        nodes.dedup();
+        assert_eq!(nodes.len(), placed);
+        assert!(
+            partial
+                .islands
+                .iter()
+                .all(|island| island.len() >= DEFAULT_MIN_ISLAND_SIZE)
+        );
+
+        // An iteration budget is deterministic
+        let GenerationError::Budget {
+            partial: partial_again,
+            ..
+        } = generate_with_budget(1_000_000, budget);
+        assert_eq!(partial, &partial_again);
+
+        // Even if the budget runs out immediately, we get a (single node) graph
+        let GenerationError::Budget {
+            placed, partial, ..
+        } = generate_with_budget(
+            100,
+            GenerationBudget {
+                max_iterations: None,
+                max_duration: Some(Duration::ZERO),
+            },
+        );
+        assert_eq!(placed, 1);
+        assert_eq!(partial.islands.len(), 1);
+    }
+
+    /// The island graph should connect islands at most `island_adjacency_distance` apart, with the distance as weight.
+    #[test]
+    fn island_graph() {
+        let generate = |island_adjacency_distance| {
+            let params = ConstellationParams {
+                island_adjacency_distance,
+                ..Default::default()
+            };
+            let mut rng = Xoshiro256Plus::seed_from_u64(1);
+            ConstellationGraph::with_params(500, 5.0, 3, &params, None, &mut rng).unwrap()
+        };
+
+        let constellation = generate(DEFAULT_ISLAND_ADJACENCY_DISTANCE);
+        let island_graph = &constellation.island_graph;
+        assert_eq!(island_graph.node_count(), constellation.islands.len());
+        assert!(island_graph.edge_count() > 0);
+
+        for edge in island_graph.raw_edges() {
+            let (a, b) = (island_graph[edge.source()], island_graph[edge.target()]);
+            assert!(a < b);
+            assert!(edge.weight > 0.0 && edge.weight <= DEFAULT_ISLAND_ADJACENCY_DISTANCE);
+
+            // The weight is the distance between the closest points of both islands
+            let closest = constellation.islands[a]
+                .iter()
+                .cartesian_product(&constellation.islands[b])
+                .map(|(p, q)| constellation.graph[*p].distance_to(constellation.graph[*q]))
+                .fold(f32::MAX, f32::min);
+            assert!((closest - edge.weight).abs() < 1e-4);
+        }
+
+        // A larger distance only adds adjacencies
+        let larger = generate(DEFAULT_ISLAND_ADJACENCY_DISTANCE * 2.0);
+        assert!(larger.island_graph.edge_count() > island_graph.edge_count());
+        assert_eq!(generate(0.0).island_graph.edge_count(), 0);
+    }
+
+    /// Regenerating must cancel every task of the old constellation (including walks, which use a child token), but not the new ones.
+    #[test]
+    fn constellation_lifetime() {
+        let mut lifetime = ConstellationLifetime::default();
+        assert_eq!(lifetime.generation(), 0);
+
+        let old_token = lifetime.token();
+        let old_panic_token = old_token.child_token();
+        let old_walk = {
+            let token = old_panic_token.clone();
+            async move {
+                tokio::select! {
+                    biased;
+                    _ = token.cancelled() => "cancelled",
+                    _ = futures::future::pending::<()>() => "finished",
+                }
+            }
+        };
+
+        let new_token = lifetime.renew();
+        assert_eq!(lifetime.generation(), 1);
+        assert!(old_token.is_cancelled());
+        assert!(old_panic_token.is_cancelled());
+        assert!(!new_token.is_cancelled());
+        assert!(!lifetime.token().is_cancelled());
+
+        // A task that was waiting on the old token wakes up and stops
+        assert_eq!(futures::executor::block_on(old_walk), "cancelled");
+
+        // Pressing the panic button only cancels a child token, not the lifetime itself
+        let panic_token = lifetime.token().child_token();
+        panic_token.cancel();
+        assert!(!lifetime.token().is_cancelled());
+
+        lifetime.renew();
+        assert_eq!(lifetime.generation(), 2);
+        assert!(new_token.is_cancelled());
+    }
+
+    /// Randomly adds and removes edges of a real petgraph graph, and checks that every edge keeps its MultiMesh slot,
+    /// even though petgraph shuffles the edge indices around.
+    #[test]
+    fn edge_slots() {
+        use petgraph::{graph::UnGraph, visit::EdgeRef as _};
+
+        let mut rng = Xoshiro256Plus::seed_from_u64(814);
+        let mut graph = UnGraph::<(), u32>::new_undirected();
+        let nodes = (0..20).map(|_| graph.add_node(())).collect::<Vec<_>>();
+
+        // Every edge carries a unique id as weight, the simulated MultiMesh stores which id is in which slot
+        let mut next_id = 0;
+        let mut multimesh = vec![];
+        for i in 0..nodes.len() {
+            let edge = graph.add_edge(nodes[i], nodes[(i + 1) % nodes.len()], next_id);
+            multimesh.push(Some(next_id));
+            next_id += 1;
+            assert_eq!(edge.index(), multimesh.len() - 1);
+        }
+        let mut slots = EdgeSlots::new(graph.edge_count());
+
+        let check = |graph: &UnGraph<(), u32>, slots: &EdgeSlots, multimesh: &[Option<u32>]| {
+            assert_eq!(slots.edge_count(), graph.edge_count());
+            assert!(multimesh.len() >= slots.slot_count());
+            for edge in graph.edge_references() {
+                assert_eq!(multimesh[slots.slot(edge.id())], Some(*edge.weight()));
+            }
+        };
+
+        for _ in 0..2000 {
+            if graph.edge_count() > 0 && rng.random_bool(0.5) {
+                let edge = petgraph::graph::EdgeIndex::new(rng.random_range(0..graph.edge_count()));
+                graph.remove_edge(edge);
+                let slot = slots.remove(edge);
+                multimesh[slot] = None;
+            } else {
+                let a = nodes[rng.random_range(0..nodes.len())];
+                let b = nodes[rng.random_range(0..nodes.len())];
+                let edge = graph.add_edge(a, b, next_id);
+                let slot = slots.insert(edge);
+                if slot >= multimesh.len() {
+                    multimesh.resize(slot + 1, None);
+                }
+                assert_eq!(multimesh[slot], None, "slot {slot} is still in use");
+                multimesh[slot] = Some(next_id);
+                next_id += 1;
+            }
+            check(&graph, &slots, &multimesh);
+
+            if slots.free_count() > 10 {
+                for (from, to) in slots.compact() {
+                    assert_eq!(multimesh[to], None);
+                    multimesh[to] = multimesh[from].take();
+                }
+                multimesh.truncate(slots.slot_count());
+
+                assert_eq!(slots.free_count(), 0);
+                assert_eq!(slots.slot_count(), graph.edge_count());
+                assert!(multimesh.iter().all(Option::is_some));
+                check(&graph, &slots, &multimesh);
+            }
+        }
+    }
+
+    #[test]
+    fn ray_sphere() {
+        use musical_constellations_rust::util::ray_sphere_intersection;
+
+        let origin = Vector3::new(0.0, 0.0, 10.0);
+
+        // Straight at the sphere, hits the near side
+        let hit = ray_sphere_intersection(origin, Vector3::new(0.0, 0.0, -1.0), 5.0).unwrap();
+        assert!((hit - Vector3::new(0.0, 0.0, 5.0)).length() < 1e-5);
+
+        // Misses entirely, or points away from it
+        assert_eq!(
+            ray_sphere_intersection(origin, Vector3::new(0.0, 1.0, 0.0), 5.0),
+            None
+        );
+        assert_eq!(
+            ray_sphere_intersection(origin, Vector3::new(0.0, 0.0, 1.0), 5.0),
+            None
+        );
+
+        // From the inside, it hits the far side
+        let hit = ray_sphere_intersection(Vector3::ZERO, Vector3::new(1.0, 0.0, 0.0), 5.0).unwrap();
+        assert!((hit - Vector3::new(5.0, 0.0, 0.0)).length() < 1e-5);
+    }
+
+    fn walk_sequence(strategy: WalkStrategy, seed: u64) -> Vec<usize> {
+        use musical_constellations_rust::gd::graph::graph_main::GraphTypedef;
+
+        // 4x4 grid with uneven spacing, so edge lengths differ
+        let coords = [0.0, 1.0, 1.5, 3.5];
+        let mut graph = GraphTypedef::new_undirected();
+        let nodes = (0..16)
+            .map(|i| graph.add_node(Vector3::new(coords[i % 4], coords[i / 4], 0.0)))
+            .collect::<Vec<_>>();
+        for i in 0..16 {
+            if i % 4 < 3 {
+                graph.add_edge(nodes[i], nodes[i + 1], ());
+            }
+            if i < 12 {
+                graph.add_edge(nodes[i], nodes[i + 4], ());
+            }
+        }
+
+        let mut rng = Xoshiro256Plus::seed_from_u64(seed);
+
+        // The first step branches out to all neighbors
+        let first = strategy.next_nodes(
+            &graph,
+            nodes[0],
+            None,
+            &BTreeSet::new(),
+            RevisitPolicy::Allow,
+            0.0,
+            &mut rng,
+        );
+        assert_eq!(first.len(), 2);
+
+        // Follow a single branch: 0 -> 1 -> ...
+        let mut sequence = vec![0, 1];
+        let (mut previous, mut current) = (nodes[0], nodes[1]);
+        for _ in 0..10 {
+            let next = strategy.next_nodes(
+                &graph,
+                current,
+                Some(previous),
+                &BTreeSet::new(),
+                RevisitPolicy::Allow,
+                0.0,
+                &mut rng,
+            );
+            assert_eq!(next.len(), 1);
+            (previous, current) = (current, next[0]);
+            sequence.push(current.index());
+        }
+        sequence
+    }
+
+    #[test]
+    fn walk_strategies() {
+        // Goes straight until the corner, then turns and keeps following the border
+        assert_eq!(
+            walk_sequence(WalkStrategy::DirectionPreserving, 42),
+            vec![0, 1, 2, 3, 7, 11, 15, 14, 13, 12, 8, 4]
+        );
+        assert_eq!(
+            walk_sequence(WalkStrategy::RandomNeighbor, 42),
+            vec![0, 1, 5, 6, 10, 11, 15, 14, 15, 14, 10, 9]
+        );
+        assert_eq!(
+            walk_sequence(WalkStrategy::WeightedByDistance, 42),
+            vec![0, 1, 5, 9, 13, 14, 15, 14, 15, 14, 10, 9]
+        );
+        assert_eq!(
+            walk_sequence(WalkStrategy::AvoidBacktrack, 42),
+            vec![0, 1, 5, 9, 13, 14, 15, 11, 10, 14, 13, 9]
+        );
+
+        // Seeded walks are deterministic
+        for strategy in [
+            WalkStrategy::RandomNeighbor,
+            WalkStrategy::WeightedByDistance,
+            WalkStrategy::AvoidBacktrack,
+        ] {
+            assert_eq!(walk_sequence(strategy, 7), walk_sequence(strategy, 7));
+        }
+
+        // Never returns to the node it just came from
+        for seed in 0..20 {
+            let sequence = walk_sequence(WalkStrategy::AvoidBacktrack, seed);
+            assert!(sequence.windows(3).all(|w| w[0] != w[2]));
+        }
+    }
+
+    /// Simulates a branching walk on a cycle graph, returns the nodes in the order they are played
+    fn walk_cycle(strategy: WalkStrategy, revisits: RevisitPolicy, max_steps: usize) -> Vec<usize> {
+        use std::collections::VecDeque;
+
+        use musical_constellations_rust::gd::graph::graph_main::GraphTypedef;
+
+        let n = 12;
+        let mut graph = GraphTypedef::new_undirected();
+        let nodes = (0..n)
+            .map(|i| {
+                let angle = i as f32 / n as f32 * std::f32::consts::TAU;
+                graph.add_node(Vector3::new(angle.cos(), angle.sin(), 0.0))
+            })
+            .collect::<Vec<_>>();
+        for i in 0..n {
+            graph.add_edge(nodes[i], nodes[(i + 1) % n], ());
+        }
+
+        let mut rng = Xoshiro256Plus::seed_from_u64(42);
+        let mut visited = BTreeSet::from([nodes[0]]);
+        let mut queue = VecDeque::from([(nodes[0], None)]);
+        let mut played = vec![];
+
+        while let Some((node, previous)) = queue.pop_front()
+            && played.len() < max_steps
+        {
+            played.push(node.index());
+            let next =
+                strategy.next_nodes(&graph, node, previous, &visited, revisits, 0.0, &mut rng);
+            visited.extend(&next);
+            queue.extend(next.into_iter().map(|next| (next, Some(node))));
+        }
+        played
+    }
+
+    #[test]
+    fn walk_avoid_revisits() {
+        for strategy in [
+            WalkStrategy::DirectionPreserving,
+            WalkStrategy::RandomNeighbor,
+            WalkStrategy::WeightedByDistance,
+            WalkStrategy::AvoidBacktrack,
+        ] {
+            // Both branches go around the cycle, and the walk stops where they meet, after playing every node once
+            let played = walk_cycle(strategy, RevisitPolicy::Stop, 1000);
+            assert_eq!(played.len(), 12, "{strategy:?}");
+            assert_eq!(played.iter().unique().count(), 12, "{strategy:?}");
+
+            // Falling back to visited nodes keeps the walk going, but only once every node was played
+            let played = walk_cycle(strategy, RevisitPolicy::FallBack, 1000);
+            assert!(played.len() > 12, "{strategy:?}");
+            assert_eq!(played[..12].iter().unique().count(), 12, "{strategy:?}");
+        }
+
+        // Without visited-node memory, the branches pass each other and play each other's notes
+        let played = walk_cycle(WalkStrategy::DirectionPreserving, RevisitPolicy::Allow, 16);
+        assert_eq!(played.len(), 16);
+        assert_eq!(played.iter().unique().count(), 12);
+    }
+
+    /// Simulates a walk from the center of a star with 12 arms of 10 nodes, returns the played nodes and the branch count
+    fn walk_star(max_steps: usize, max_concurrent_branches: usize) -> (Vec<usize>, usize) {
+        use std::collections::VecDeque;
+
+        use musical_constellations_rust::gd::graph::graph_main::GraphTypedef;
+
+        let mut graph = GraphTypedef::new_undirected();
+        let center = graph.add_node(Vector3::ZERO);
+        for arm in 0..12 {
+            let angle = arm as f32 / 12.0 * std::f32::consts::TAU;
+            let dir = Vector3::new(angle.cos(), angle.sin(), 0.0);
+            let mut previous = center;
+            for i in 1..=10 {
+                let node = graph.add_node(dir * i as f32);
+                graph.add_edge(previous, node, ());
+                previous = node;
+            }
+        }
+
+        let config = WalkConfig {
+            strategy: WalkStrategy::DirectionPreserving,
+            revisits: RevisitPolicy::FallBack,
+            dead_ends: DeadEndBehavior::Stop,
+            max_steps,
+            max_concurrent_branches,
+            rhythm_grid: RhythmGrid::PowersOfTwo,
+            direction_temperature: 0.0,
+        };
+        let mut rng = Xoshiro256Plus::seed_from_u64(42);
+        let state = WalkState::new(center, &config);
+        let mut queue = VecDeque::from([(center, None)]);
+        let mut played = vec![];
+        let mut branch_count = 0;
+
+        while let Some((node, previous)) = queue.pop_front() {
+            played.push(node.index());
+            let next = config.next_nodes(&graph, node, previous, &state, &mut rng);
+            if previous.is_none() {
+                branch_count = next.len();
+            }
+            queue.extend(next.into_iter().map(|next| (next, Some(node))));
+        }
+        (played, branch_count)
+    }
+
+    #[test]
+    fn walk_limits() {
+        // Unlimited, every arm is walked to the end
+        let (played, branch_count) = walk_star(0, 0);
+        assert_eq!((played.len(), branch_count), (121, 12));
+        assert_eq!(played.iter().unique().count(), 121);
+
+        // Only walks 3 arms, all the way
+        let (played, branch_count) = walk_star(0, 3);
+        assert_eq!((played.len(), branch_count), (31, 3));
+
+        // Stops after 64 notes, every walked edge ends in a played note
+        let (played, branch_count) = walk_star(64, 0);
+        assert_eq!((played.len(), branch_count), (64, 12));
+
+        // Both limits at once, the budget is smaller than the branch count
+        let (played, branch_count) = walk_star(3, 4);
+        assert_eq!((played.len(), branch_count), (3, 2));
+    }
+
+    #[test]
+    fn walk_dead_ends() {
+        use musical_constellations_rust::gd::graph::graph_main::GraphTypedef;
+
+        // Path A-B-C, and a separate island D-E
+        let mut graph = GraphTypedef::new_undirected();
+        let nodes = (0..5)
+            .map(|i| graph.add_node(Vector3::new(i as f32, 0.0, 0.0)))
+            .collect::<Vec<_>>();
+        graph.add_edge(nodes[0], nodes[1], ());
+        graph.add_edge(nodes[1], nodes[2], ());
+        graph.add_edge(nodes[3], nodes[4], ());
+
+        let walk = |dead_ends: DeadEndBehavior, max_steps: usize| {
+            let config = WalkConfig {
+                strategy: WalkStrategy::DirectionPreserving,
+                revisits: RevisitPolicy::FallBack,
+                dead_ends,
+                max_steps,
+                max_concurrent_branches: 0,
+                rhythm_grid: RhythmGrid::PowersOfTwo,
+                direction_temperature: 0.0,
+            };
+            let mut rng = Xoshiro256Plus::seed_from_u64(42);
+            let state = WalkState::new(nodes[0], &config);
+            let (mut previous, mut current) = (None, nodes[0]);
+            let mut played = vec![current.index()];
+            while let [next] = config.next_nodes(&graph, current, previous, &state, &mut rng)[..] {
+                (previous, current) = (Some(current), next);
+                played.push(current.index());
+            }
+            played
+        };
+
+        assert_eq!(walk(DeadEndBehavior::Stop, 5), vec![0, 1, 2]);
+        assert_eq!(walk(DeadEndBehavior::Bounce, 5), vec![0, 1, 2, 1, 0]);
+        assert_eq!(
+            walk(DeadEndBehavior::Bounce, 8),
+            vec![0, 1, 2, 1, 0, 1, 2, 1]
+        );
+
+        // Without a step budget, dead ends always stop the walk
+        assert_eq!(walk(DeadEndBehavior::Bounce, 0), vec![0, 1, 2]);
+
+        // Wraps around within the island
+        let played = walk(DeadEndBehavior::Wrap, 32);
+        assert_eq!(played.len(), 32);
+        assert_eq!(played[..3], [0, 1, 2]);
+        assert!(played.iter().all(|node| *node <= 2));
+    }
+
+    #[test]
+    fn active_walks() {
+        use petgraph::graph::NodeIndex;
+        use tokio_util::sync::CancellationToken;
+
+        let panic_button = CancellationToken::new();
+        let mut walks = ActiveWalks::default();
+        let (a, a_cancel) = walks.start(NodeIndex::new(0), &panic_button);
+        let (b, b_cancel) = walks.start(NodeIndex::new(1), &panic_button);
+        let (c, c_cancel) = walks.start(NodeIndex::new(1), &panic_button);
+        assert_eq!(walks.ids().collect_vec(), vec![a, b, c]);
+
+        // Stopping a walk doesn't stop its siblings
+        assert!(walks.stop(a));
+        assert!(!walks.stop(a));
+        assert!(a_cancel.is_cancelled());
+        assert!(!b_cancel.is_cancelled() && !c_cancel.is_cancelled());
+        assert!(!panic_button.is_cancelled());
+
+        // Finished walks are forgotten without being cancelled
+        walks.finish(c);
+        assert_eq!(walks.ids().collect_vec(), vec![b]);
+        assert!(!c_cancel.is_cancelled());
+
+        assert_eq!(walks.stop_started_at(NodeIndex::new(0)), 0);
+        assert_eq!(walks.stop_started_at(NodeIndex::new(1)), 1);
+        assert!(b_cancel.is_cancelled());
+
+        // The panic button stops all walks, ids are never reused
+        let (d, d_cancel) = walks.start(NodeIndex::new(2), &panic_button);
+        let (e, e_cancel) = walks.start(NodeIndex::new(3), &panic_button);
+        assert!(d > c && e > d);
+        panic_button.cancel();
+        assert!(d_cancel.is_cancelled() && e_cancel.is_cancelled());
+    }
+
+    #[test]
+    fn walk_heads() {
+        use petgraph::graph::NodeIndex;
+        use tokio_util::sync::CancellationToken;
+
+        let panic_button = CancellationToken::new();
+        let mut walks = ActiveWalks::default();
+        let (a, _) = walks.start(NodeIndex::new(0), &panic_button);
+        let (b, _) = walks.start(NodeIndex::new(5), &panic_button);
+
+        // A walk starts on the clicked node, and follows whichever branch moved last
+        assert_eq!(walks.head(a), Some(NodeIndex::new(0)));
+        assert!(walks.moved(a, NodeIndex::new(1)));
+        assert!(walks.moved(a, NodeIndex::new(2)));
+        assert_eq!(walks.head(a), Some(NodeIndex::new(2)));
+        assert_eq!(walks.head(b), Some(NodeIndex::new(5)));
+
+        // Branches of a stopped walk may still get somewhere before they notice, that's not reported
+        walks.stop(a);
+        assert!(!walks.moved(a, NodeIndex::new(3)));
+        assert_eq!(walks.head(a), None);
+        walks.finish(b);
+        assert_eq!(walks.head(b), None);
+    }
+
+    #[test]
+    fn walker_overflow() {
+        use petgraph::graph::NodeIndex;
+        use tokio_util::sync::CancellationToken;
+
+        let panic_button = CancellationToken::new();
+        let start = |walks: &mut ActiveWalks, overflow| {
+            walks.try_start(NodeIndex::new(0), &panic_button, 3, overflow)
+        };
+
+        // Ignores clicks until a walk finishes
+        let mut walks = ActiveWalks::default();
+        let started = (0..3)
+            .map(|_| start(&mut walks, WalkerOverflow::IgnoreClick).unwrap())
+            .collect_vec();
+        assert!(start(&mut walks, WalkerOverflow::IgnoreClick).is_none());
+        assert_eq!(walks.len(), 3);
+        assert!(started.iter().all(|(_, cancel)| !cancel.is_cancelled()));
+
+        walks.finish(started[1].0);
+        let (id, _) = start(&mut walks, WalkerOverflow::IgnoreClick).unwrap();
+        assert_eq!(
+            walks.ids().collect_vec(),
+            vec![started[0].0, started[2].0, id]
+        );
+
+        // Stops the oldest walks to make room
+        let mut walks = ActiveWalks::default();
+        let started = (0..3)
+            .map(|_| start(&mut walks, WalkerOverflow::StopOldest).unwrap())
+            .collect_vec();
+        let (id, _) = start(&mut walks, WalkerOverflow::StopOldest).unwrap();
+        assert_eq!(
+            walks.ids().collect_vec(),
+            vec![started[1].0, started[2].0, id]
+        );
+        assert!(started[0].1.is_cancelled());
+        assert!(!started[1].1.is_cancelled() && !started[2].1.is_cancelled());
+
+        // Unlimited
+        let mut walks = ActiveWalks::default();
+        for _ in 0..100 {
+            walks.try_start(
+                NodeIndex::new(0),
+                &panic_button,
+                0,
+                WalkerOverflow::IgnoreClick,
+            );
+        }
+        assert_eq!(walks.len(), 100);
+    }
+
+    #[test]
+    fn walk_recording_round_trip() {
+        use petgraph::graph::{EdgeIndex, NodeIndex};
+
+        let event = |tick, node, edge: Option<usize>, beats_waited| WalkEvent {
+            tick,
+            node: NodeIndex::new(node),
+            edge: edge.map(EdgeIndex::new),
+            beats_waited,
+            velocity: 1.0,
+        };
+
+        // Two walks, their events arrive interleaved
+        let recorder = WalkRecorder::new(1234);
+        let sender = recorder.sender();
+        for event in [
+            event(100, 0, None, 0),
+            event(104, 1, Some(0), 4),
+            event(104, 5, None, 0),
+            event(106, 6, Some(7), 2),
+            event(112, 2, Some(1), 8),
+        ] {
+            sender.send(event).unwrap();
+        }
+        let recording = recorder.finish();
+
+        // Ignored once the recording is finished
+        assert!(sender.send(event(120, 3, Some(2), 8)).is_err());
+
+        assert_eq!(recording.seed, 1234);
+        assert_eq!(
+            recording.events,
+            vec![
+                event(0, 0, None, 0),
+                event(4, 1, Some(0), 4),
+                event(4, 5, None, 0),
+                event(6, 6, Some(7), 2),
+                event(12, 2, Some(1), 8),
+            ]
+        );
+
+        let json = serde_json::to_string(&recording).unwrap();
+        let deserialized = serde_json::from_str::<WalkRecording>(&json).unwrap();
+        assert_eq!(deserialized, recording);
+
+        // Edge animations start when the previous node plays, and take as long as the walk waited
+        let lerp = |edge, to_node, beats| ReplayAction::LerpEdge {
+            edge: EdgeIndex::new(edge),
+            to_node: NodeIndex::new(to_node),
+            beats,
+        };
+        let play = |node| ReplayAction::Play {
+            node: NodeIndex::new(node),
+            velocity: 1.0,
+        };
+        assert_eq!(
+            deserialized.schedule().into_iter().collect_vec(),
+            vec![
+                (0, vec![play(0), lerp(0, 1, 4)]),
+                (4, vec![play(1), play(5), lerp(7, 6, 2), lerp(1, 2, 8)]),
+                (6, vec![play(6)]),
+                (12, vec![play(2)]),
+            ]
+        );
+
+        // Recordings from before velocity existed play at full velocity
+        let old = r#"{"seed":1,"events":[{"tick":0,"node":3,"edge":null,"beats_waited":0}]}"#;
+        let old = serde_json::from_str::<WalkRecording>(old).unwrap();
+        assert_eq!(old.events, vec![event(0, 3, None, 0)]);
+    }
+
+    #[test]
+    fn swing() {
+        // On-beat sixteenths (even ticks) are never delayed, off-beat ones (odd ticks) by the swing amount
+        for tick in [0, 2, 4, 6, 100] {
+            assert_eq!(swing_delay_ticks(tick, 0.5), 0.0);
+        }
+        for tick in [1, 3, 5, 7, 101] {
+            assert_eq!(swing_delay_ticks(tick, 0.5), 0.5);
+        }
+
+        // Swing 0 doesn't change anything
+        for tick in 0..8 {
+            assert_eq!(swing_delay_ticks(tick, 0.0), 0.0);
+            for beats in [1, 2, 4, 16] {
+                assert_eq!(swung_duration_ticks(tick, beats, 0.0), beats as f64);
+            }
+        }
+
+        // Edges end late when landing off-beat, and start late when leaving off-beat
+        assert_eq!(swung_duration_ticks(0, 1, 0.25), 1.25);
+        assert_eq!(swung_duration_ticks(1, 1, 0.25), 0.75);
+        assert_eq!(swung_duration_ticks(1, 2, 0.25), 2.0);
+        assert_eq!(swung_duration_ticks(0, 2, 0.25), 2.0);
+    }
+
+    #[test]
+    fn rests() {
+        let mut rng = Xoshiro256Plus::seed_from_u64(3);
+        let constellation = ConstellationGraph::new(200, 5.0, 3, &mut rng).unwrap();
+        let island_data = AudioGraph::generate_island_data(&constellation, &mut rng);
+        assert!(
+            island_data
+                .iter()
+                .all(|island| (0.0..=0.3).contains(&island.rest_probability))
+        );
+
+        // Rests happen about as often as the probability says
+        let samples = 100_000;
+        for rest_probability in [0.1, 0.3, 1.0] {
+            let rests = (0..samples)
+                .filter(|_| should_rest(rest_probability, &mut rng))
+                .count();
+            let fraction = rests as f64 / samples as f64;
+            assert!(
+                (fraction - rest_probability).abs() < 0.01,
+                "{fraction} vs {rest_probability}"
+            );
+        }
+
+        // No rests without a probability, and the rng is left alone so the walk goes the same way
+        let before = rng.clone();
+        assert!((0..1000).all(|_| !should_rest(0.0, &mut rng)));
+        assert_eq!(rng, before);
+    }
+
+    #[test]
+    fn velocity() {
+        let base = 0.6;
+        let velocity = |degree, beats| note_velocity(degree, beats, 0.25, 0.2);
+
+        // Short edges and non-junctions aren't accented
+        assert_eq!(velocity(1, 1), base);
+        assert_eq!(velocity(2, 0), base);
+
+        // Junctions are
+        assert_eq!(velocity(3, 1), base + 0.25);
+        assert_eq!(velocity(5, 1), base + 0.25);
+
+        // Longer edges are accented more, on a log2 scale
+        assert_eq!(velocity(2, 4), base + 0.1);
+        assert_eq!(velocity(2, 16), base + 0.2);
+        assert_eq!(velocity(2, 64), base + 0.2);
+        let by_length = [1, 2, 4, 8, 16].map(|beats| velocity(2, beats));
+        assert!(by_length.is_sorted());
+
+        // Never louder than 1
+        assert_eq!(note_velocity(3, 16, 1.0, 1.0), 1.0);
+        assert_eq!(note_velocity(1, 1, -1.0, -1.0), base);
+    }
+
+    #[test]
+    fn edge_progress() {
+        // 4 ticks at 0.1s each, then the BPM doubles halfway through
+        let mut progress = EdgeProgress::new(0.0, 4.0);
+        assert_eq!(progress.progress(0.0, 0.1), 0.0);
+        assert_eq!(progress.progress(0.05, 0.1), 0.125);
+        progress.tick();
+        progress.tick();
+        assert_eq!(progress.progress(0.0, 0.1), 0.5);
+        assert_eq!(progress.progress(0.025, 0.05), 0.625);
+        progress.tick();
+        assert_eq!(progress.progress(0.025, 0.05), 0.875);
+
+        // Doesn't run ahead of a tick that's late, because the tempo slowed down
+        let mut slowed = EdgeProgress::new(0.0, 4.0);
+        slowed.tick();
+        assert_eq!(slowed.progress(10.0, 0.05), 0.5);
+
+        // Integrating the old BPM would've finished at 0.4s, but we finish with the 4th tick
+        progress.tick();
+        assert_eq!(progress.progress(0.0, 0.05), 1.0);
+
+        // Starting on a swung note, a quarter tick late, to the next tick
+        let mut swung = EdgeProgress::new(0.25, 0.75);
+        assert_eq!(swung.progress(0.0, 0.1), 0.0);
+        assert_eq!(swung.progress(1.0, 0.1), 1.0);
+        swung.tick();
+        assert_eq!(swung.progress(0.0, 0.1), 1.0);
+    }
+
+    #[test]
+    fn launch_quantization() {
+        use musical_constellations_rust::gd::autoload::state_tick::Tick;
+
+        // Two bars of 4/4 and one of 3/4, 4 ticks per beat
+        let mut total_ticks = 0;
+        let mut ticks = vec![];
+        for (bar, beats_per_bar) in [(0, 4), (1, 4), (2, 3)] {
+            for beat in 0..beats_per_bar {
+                for tick in 0..4 {
+                    ticks.push(Tick {
+                        tick,
+                        beat,
+                        bar,
+                        ticks_per_beat: 4,
+                        beats_per_bar,
+                        total_ticks,
+                        emitted_at: std::time::Instant::now(),
+                    });
+                    total_ticks += 1;
+                }
+            }
+        }
+
+        let launches = |launch: LaunchQuantization| {
+            ticks
+                .iter()
+                .filter(|tick| launch.is_launch_tick(tick))
+                .map(|tick| tick.total_ticks)
+                .collect_vec()
+        };
+        assert_eq!(
+            launches(LaunchQuantization::NextTick),
+            (0..44).collect_vec()
+        );
+        assert_eq!(
+            launches(LaunchQuantization::NextBeat),
+            (0..44).step_by(4).collect_vec()
+        );
+        assert_eq!(
+            launches(LaunchQuantization::NextHalfBar),
+            vec![0, 8, 16, 24, 32, 36]
+        );
+        assert_eq!(launches(LaunchQuantization::NextBar), vec![0, 16, 32]);
+    }
+
+    #[test]
+    fn rhythm_grid() {
+        let distances = [
+            0.0, 0.4, 1.0, 1.4, 2.4, 2.9, 3.2, 4.4, 5.0, 5.5, 7.0, 10.0, 11.0, 14.0,
+        ];
+        let quantized = |grid: RhythmGrid| distances.map(|ticks| grid.quantize(ticks));
+
+        assert_eq!(
+            quantized(RhythmGrid::PowersOfTwo),
+            [
+                1.0, 0.5, 1.0, 1.0, 2.0, 4.0, 4.0, 4.0, 4.0, 4.0, 8.0, 8.0, 8.0, 16.0
+            ]
+        );
+        assert_eq!(
+            quantized(RhythmGrid::WithTriplets),
+            [
+                1.0, 0.5, 1.0, 1.0, 2.0, 3.0, 3.0, 4.0, 6.0, 6.0, 8.0, 8.0, 8.0, 16.0
+            ]
+        );
+        assert_eq!(
+            quantized(RhythmGrid::WithDotted),
+            [
+                1.0, 0.5, 1.0, 1.0, 2.0, 3.0, 3.0, 4.0, 4.0, 4.0, 8.0, 12.0, 12.0, 16.0
+            ]
+        );
+        assert_eq!(
+            quantized(RhythmGrid::Free),
+            [
+                0.0, 0.0, 1.0, 1.0, 2.0, 3.0, 3.0, 4.0, 5.0, 6.0, 7.0, 10.0, 11.0, 14.0
+            ]
+        );
+    }
+
+    #[test]
+    fn walk_stats() {
+        use std::collections::VecDeque;
+
+        use musical_constellations_rust::gd::graph::graph_main::GraphTypedef;
+        use tokio_util::sync::CancellationToken;
+
+        // Arms of 3, 2 and 4 nodes around a center, 1 unit (8 ticks) apart
+        let mut graph = GraphTypedef::new_undirected();
+        let center = graph.add_node(Vector3::ZERO);
+        let mut arms = vec![];
+        for (dir, len) in [(Vector3::RIGHT, 3), (Vector3::LEFT, 2), (Vector3::UP, 4)] {
+            let mut previous = center;
+            let arm = (1..=len)
+                .map(|i| {
+                    let node = graph.add_node(dir * i as f32);
+                    graph.add_edge(previous, node, ());
+                    previous = node;
+                    node
+                })
+                .collect_vec();
+            arms.push(arm);
+        }
+        let rest_at = arms[2][1];
+
+        // Like `AudioGraph::walk_node`, but without waiting
+        let walk = |max_steps: usize, cancel: &CancellationToken| {
+            let config = WalkConfig {
+                strategy: WalkStrategy::DirectionPreserving,
+                revisits: RevisitPolicy::FallBack,
+                dead_ends: DeadEndBehavior::Stop,
+                max_steps,
+                max_concurrent_branches: 0,
+                rhythm_grid: RhythmGrid::PowersOfTwo,
+                direction_temperature: 0.0,
+            };
+            let mut rng = Xoshiro256Plus::seed_from_u64(42);
+            let state = WalkState::new(center, &config);
+            let mut queue = VecDeque::from([(center, None, 100, 0)]);
+            while let Some((node, previous, tick, run)) = queue.pop_front() {
+                let played = node != rest_at;
+                let run = if played { run + 1 } else { 0 };
+                state.stats.borrow_mut().visit(node, tick, played, run);
+
+                for next in config.next_nodes(&graph, node, previous, &state, &mut rng) {
+                    let beats = config
+                        .rhythm_grid
+                        .quantize((graph[next] - graph[node]).length() as f64 * 8.0);
+                    queue.push_back((next, Some(node), tick + beats as usize, run));
+                }
+            }
+            (state.stats.borrow().clone(), state.end(cancel))
+        };
+
+        let (stats, end) = walk(0, &CancellationToken::new());
+        assert_eq!(end, WalkEnd::DeadEnd);
+        assert_eq!(stats.walked.len(), 10);
+        assert_eq!(stats.notes_played, 9);
+        assert_eq!(stats.total_ticks(), 32);
+        assert_eq!(stats.ticks, Some((100, 132)));
+        assert_eq!(stats.longest_run, 4); // The rest breaks up the longest arm
+
+        let (stats, end) = walk(5, &CancellationToken::new());
+        assert_eq!(end, WalkEnd::StepBudget);
+        assert_eq!((stats.walked.len(), stats.notes_played), (5, 4)); // The rest is in there
+        assert_eq!(stats.total_ticks(), 16);
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert_eq!(walk(0, &cancel).1, WalkEnd::Cancelled);
+    }
+
+    #[test]
+    fn shortest_path_walk() {
+        use musical_constellations_rust::gd::graph::graph_main::GraphTypedef;
+
+        // A square with a long detour A-B-C and a short cut A-D-C, plus a separate island E
+        let mut graph = GraphTypedef::new_undirected();
+        let a = graph.add_node(Vector3::new(0.0, 0.0, 0.0));
+        let b = graph.add_node(Vector3::new(0.0, 3.0, 0.0));
+        let c = graph.add_node(Vector3::new(1.0, 3.0, 0.0));
+        let d = graph.add_node(Vector3::new(1.0, 1.0, 0.0));
+        let e = graph.add_node(Vector3::new(5.0, 5.0, 0.0));
+        graph.add_edge(a, b, ());
+        graph.add_edge(b, c, ());
+        graph.add_edge(a, d, ());
+        graph.add_edge(d, c, ());
+
+        // Both ways from A to C are 2 hops, but the one via D is shorter
+        assert_eq!(shortest_path(&graph, a, c), Some(vec![a, d, c]));
+        assert_eq!(shortest_path(&graph, c, a), Some(vec![c, d, a]));
+        assert_eq!(shortest_path(&graph, b, d), Some(vec![b, c, d]));
+        assert_eq!(shortest_path(&graph, a, a), Some(vec![a]));
+        assert_eq!(shortest_path(&graph, a, e), None);
+
+        // On a real constellation, path walks never branch: every hop walks an edge, and no node is played twice
+        let mut rng = Xoshiro256Plus::seed_from_u64(5);
+        let constellation = ConstellationGraph::new(100, 5.0, 3, &mut rng).unwrap();
+        let graph = &constellation.graph;
+        for island in &constellation.islands {
+            let (from, to) = (island[0], *island.last().unwrap());
+            let path = shortest_path(graph, from, to).unwrap();
+            assert_eq!((path[0], *path.last().unwrap()), (from, to));
+            assert!(
+                path.iter()
+                    .tuple_windows()
+                    .all(|(a, b)| graph.find_edge(*a, *b).is_some())
+            );
+            assert_eq!(path.iter().unique().count(), path.len());
+        }
+    }
+
+    #[test]
+    fn wave_layers() {
+        use musical_constellations_rust::gd::graph::graph_main::GraphTypedef;
+        use petgraph::Direction;
+
+        // A diamond 0-{1,2}-3 with a tail 3-4, plus a separate island 5
+        let mut graph = GraphTypedef::new_undirected();
+        let nodes = (0..6)
+            .map(|i| graph.add_node(Vector3::new(i as f32, 0.0, 0.0)))
+            .collect_vec();
+        for (a, b) in [(0, 1), (0, 2), (1, 3), (2, 3), (3, 4)] {
+            graph.add_edge(nodes[a], nodes[b], ());
+        }
+        let indices = |layers: Vec<Vec<_>>| {
+            layers
+                .into_iter()
+                .map(|layer| {
+                    layer
+                        .into_iter()
+                        .map(|n: petgraph::graph::NodeIndex| n.index())
+                        .sorted()
+                        .collect_vec()
+                })
+                .collect_vec()
+        };
+
+        assert_eq!(
+            indices(bfs_layers(&graph, nodes[0], 0, |_| true)),
+            vec![vec![0], vec![1, 2], vec![3], vec![4]]
+        );
+        assert_eq!(
+            indices(bfs_layers(&graph, nodes[3], 0, |_| true)),
+            vec![vec![3], vec![1, 2, 4], vec![0]]
+        );
+        assert_eq!(
+            indices(bfs_layers(&graph, nodes[0], 2, |_| true)),
+            vec![vec![0], vec![1, 2], vec![3]]
+        );
+        assert_eq!(
+            indices(bfs_layers(&graph, nodes[5], 0, |_| true)),
+            vec![vec![5]]
+        );
+
+        // Both edges into node 3 animate, pointing outwards
+        let layers = bfs_layers(&graph, nodes[0], 0, |_| true);
+        let edges = wave_edges(&graph, &layers[1], &layers[2]);
+        assert_eq!(edges.len(), 2);
+        for (edge, dir) in edges {
+            let (source, target) = graph.edge_endpoints(edge).unwrap();
+            let (from, to) = match dir {
+                Direction::Outgoing => (source, target),
+                Direction::Incoming => (target, source),
+            };
+            assert!(layers[1].contains(&from));
+            assert_eq!(to, nodes[3]);
+        }
+
+        // Doesn't cross into other islands
+        assert_eq!(
+            indices(bfs_layers(&graph, nodes[0], 0, |node| node != nodes[3])),
+            vec![vec![0], vec![1, 2]]
+        );
+
+        // Every node of a real island is in exactly one layer
+        let mut rng = Xoshiro256Plus::seed_from_u64(9);
+        let constellation = ConstellationGraph::new(100, 5.0, 3, &mut rng).unwrap();
+        for island in &constellation.islands {
+            let layers = bfs_layers(&constellation.graph, island[0], 0, |node| {
+                island.contains(&node)
+            });
+            let waved = layers.iter().flatten().copied().collect::<BTreeSet<_>>();
+            assert_eq!(waved.len(), layers.iter().map(Vec::len).sum::<usize>());
+            assert_eq!(waved, island.iter().copied().collect());
+        }
+    }
+
+    #[test]
+    fn walker_collisions() {
+        use musical_constellations_rust::gd::graph::graph_main::GraphTypedef;
+        use petgraph::graph::NodeIndex;
+
+        // Two walkers from both ends of a path graph, one step per tick, returns the nodes they collided on
+        let collisions = |len: usize, start_tick_b: usize| {
+            let mut graph = GraphTypedef::new_undirected();
+            let nodes = (0..len)
+                .map(|i| graph.add_node(Vector3::new(i as f32, 0.0, 0.0)))
+                .collect_vec();
+            for (a, b) in nodes.iter().tuple_windows() {
+                graph.add_edge(*a, *b, ());
+            }
+
+            let walk_a = nodes.iter().enumerate().map(|(i, node)| (100 + i, *node));
+            let walk_b = nodes
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(i, node)| (100 + start_tick_b + i, *node));
+            let mut occupancy = NodeOccupancy::default();
+            let mut collided = vec![];
+            for (tick, node) in walk_a.chain(walk_b).sorted_by_key(|(tick, _)| *tick) {
+                if occupancy.arrive(node, tick) {
+                    collided.push(node.index());
+                }
+                assert!(occupancy.len() <= 2, "old ticks are cleaned up");
+            }
+            collided
+        };
+
+        // Meet in the middle
+        assert_eq!(collisions(7, 0), vec![3]);
+        // Pass each other between two nodes
+        assert!(collisions(6, 0).is_empty());
+        // B starts a tick later, so they meet one node closer to B's end
+        assert_eq!(collisions(8, 1), vec![4]);
+
+        // The same node one tick later isn't a collision
+        let mut occupancy = NodeOccupancy::default();
+        assert!(!occupancy.arrive(NodeIndex::new(0), 5));
+        assert!(!occupancy.arrive(NodeIndex::new(0), 6));
+        assert!(occupancy.arrive(NodeIndex::new(0), 6));
+        assert_eq!(occupancy.len(), 1);
+    }
+
+    #[test]
+    fn deterministic_walks() {
+        use std::collections::VecDeque;
+
+        use petgraph::graph::NodeIndex;
+
+        let mut rng = Xoshiro256Plus::seed_from_u64(11);
+        let constellation = ConstellationGraph::new(200, 5.0, 3, &mut rng).unwrap();
+        let graph = &constellation.graph;
+        let start = constellation.islands[0][0];
+
+        // Simulates a branching walk, returns the played nodes in order
+        let walk = |mut rng: Xoshiro256Plus| {
+            let config = WalkConfig {
+                strategy: WalkStrategy::RandomNeighbor,
+                revisits: RevisitPolicy::Allow,
+                dead_ends: DeadEndBehavior::Wrap,
+                max_steps: 64,
+                max_concurrent_branches: 0,
+                rhythm_grid: RhythmGrid::PowersOfTwo,
+                direction_temperature: 0.0,
+            };
+            let state = WalkState::new(start, &config);
+            let mut queue = VecDeque::from([(start, None)]);
+            let mut played = vec![];
+            while let Some((node, previous)) = queue.pop_front() {
+                played.push(node);
+                let next = config.next_nodes(graph, node, previous, &state, &mut rng);
+                queue.extend(next.into_iter().map(|next| (next, Some(node))));
+            }
+            played
+        };
+
+        let played = walk(deterministic_walk_rng(1234, start, 0));
+        assert_eq!(played.len(), 64);
+        assert_eq!(played, walk(deterministic_walk_rng(1234, start, 0)));
+
+        // Another click, seed or node gets another rng
+        let first = |rng: Xoshiro256Plus| rng.clone().random::<u64>();
+        let reference = first(deterministic_walk_rng(1234, start, 0));
+        assert_ne!(reference, first(deterministic_walk_rng(1234, start, 1)));
+        assert_ne!(reference, first(deterministic_walk_rng(1235, start, 0)));
+        assert_ne!(
+            reference,
+            first(deterministic_walk_rng(1234, NodeIndex::new(1), 0))
+        );
+    }
+
+    #[test]
+    fn humanize() {
+        // Off: no delay, and the walk rng isn't touched, so walks branch exactly like before humanizing existed
+        let mut rng = Xoshiro256Plus::seed_from_u64(3);
+        let untouched = rng.clone();
+        assert_eq!(humanize_delay(0.0, &mut rng), None);
+        assert_eq!(rng, untouched);
+
+        // On: seconds, within 0..humanize_ms
+        for _ in 0..1000 {
+            let delay = humanize_delay(30.0, &mut rng).unwrap();
+            assert!((0.0..0.030).contains(&delay), "{delay}");
+        }
+    }
+
+    #[test]
+    fn pause_tick_countdown() {
+        // Waiting 3 ticks, paused during the 2nd and 3rd tick that arrive
+        let paused = [false, true, true, false, false];
+        let mut countdown = TickCountdown::new(10, 3);
+        let done_at = paused
+            .iter()
+            .enumerate()
+            .position(|(i, paused)| countdown.tick(11 + i, *paused))
+            .unwrap();
+        assert_eq!(done_at, 4);
+        assert_eq!(countdown.landed_at(), Some(15));
+
+        // Once done it stays done, no matter how many ticks arrive
+        assert!(countdown.tick(16, false));
+        assert!(countdown.tick(17, true));
+        assert_eq!(countdown.landed_at(), Some(15));
+
+        // Waiting 0 ticks is done instantly, even while paused
+        assert!(TickCountdown::new(10, 0).is_done());
+        assert!(TickCountdown::new(10, 0).tick(11, true));
+        assert_eq!(TickCountdown::new(10, 0).landed_at(), Some(10));
+
+        // Paused forever never finishes
+        let mut countdown = TickCountdown::new(10, 1);
+        assert!((11..111).all(|tick| !countdown.tick(tick, true)));
+        assert_eq!(countdown.landed_at(), None);
+    }
+
+    /// Swing has to follow the tick a walk really lands on, not the one it would have landed on without pausing.
+    #[test]
+    fn pause_swing_parity() {
+        // Waiting 2 ticks from tick 4, paused for a single tick in between
+        let mut countdown = TickCountdown::new(4, 2);
+        for (tick, paused) in [(5, false), (6, true), (7, false)] {
+            countdown.tick(tick, paused);
+        }
+        let landed_at = countdown.landed_at().unwrap();
+        assert_eq!(landed_at, 7);
+        assert_eq!(swing_delay_ticks(landed_at, 0.5), 0.5, "off-beat, so swung");
+
+        // Without pausing it lands on the beat
+        let mut countdown = TickCountdown::new(4, 2);
+        for tick in [5, 6] {
+            countdown.tick(tick, false);
+        }
+        assert_eq!(countdown.landed_at(), Some(6));
+        assert_eq!(swing_delay_ticks(6, 0.5), 0.0);
+    }
+
+    #[test]
+    fn retrigger_modes() {
+        // A silent node always attacks
+        for mode in [
+            RetriggerMode::Restart,
+            RetriggerMode::Ignore,
+            RetriggerMode::Extend,
+        ] {
+            assert_eq!(mode.action(false), PlayAction::Attack, "{mode:?}");
+        }
+
+        // A sounding node depends on the mode
+        assert_eq!(RetriggerMode::Restart.action(true), PlayAction::Attack);
+        assert_eq!(RetriggerMode::Ignore.action(true), PlayAction::Skip);
+        assert_eq!(RetriggerMode::Extend.action(true), PlayAction::Extend);
+
+        assert_eq!(RetriggerMode::default(), RetriggerMode::Restart);
+    }
+
+    #[test]
+    fn easing_curves() {
+        use musical_constellations_rust::util::{ease_in_out_sine, ease_out_quad};
+
+        for ease in [ease_in_out_sine, ease_out_quad] {
+            assert_eq!(ease(0.0), 0.0);
+            assert!((ease(1.0) - 1.0).abs() < 1e-12);
+
+            // Monotonic, and never leaves 0..=1, even outside of it
+            let samples = (-10..=110)
+                .map(|i| ease(i as f64 / 100.0))
+                .collect::<Vec<_>>();
+            assert!(samples.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert!(samples.iter().all(|t| (0.0..=1.0).contains(t)));
+        }
+    }
+
+    #[test]
+    fn edge_ease() {
+        // Moving in between ticks, except for `SteppedPerTick`
+        assert_eq!(EdgeEase::Linear.apply(0.3, 0.25), 0.3);
+        assert_eq!(EdgeEase::SteppedPerTick.apply(0.3, 0.25), 0.25);
+        assert!(EdgeEase::OutQuad.apply(0.3, 0.25) > 0.3);
+        assert!(EdgeEase::InOutSine.apply(0.3, 0.25) < 0.3);
+
+        // Stepped follows the ticks that arrived, see `EdgeProgress`
+        let mut progress = EdgeProgress::new(0.0, 4.0);
+        let tick_interval = 0.125;
+        let stepped = |progress: &EdgeProgress| {
+            EdgeEase::SteppedPerTick.apply(
+                progress.progress(0.1, tick_interval),
+                progress.progress(0.0, tick_interval),
+            )
+        };
+        assert_eq!(stepped(&progress), 0.0);
+        progress.tick();
+        assert_eq!(stepped(&progress), 0.25);
+        progress.tick();
+        progress.tick();
+        progress.tick();
+        assert_eq!(stepped(&progress), 1.0);
+
+        // Landing after a swing delay (between ticks) still ends up at 1
+        let progress = EdgeProgress::new(0.0, 0.5);
+        assert_eq!(
+            EdgeEase::SteppedPerTick.apply(progress.progress(0.03125, tick_interval), 0.0),
+            0.0
+        );
+        assert_eq!(
+            EdgeEase::SteppedPerTick.apply(progress.progress(0.0625, tick_interval), 0.0),
+            1.0
+        );
+    }
+
+    #[test]
+    fn walker_trail() {
+        use petgraph::graph::EdgeIndex;
+
+        let edges = (0..5).map(EdgeIndex::new).collect::<Vec<_>>();
+        let mut trail = TrailState::new(3);
+
+        // Fills up, then the oldest edge falls off
+        assert_eq!(trail.push(edges[0]), None);
+        assert_eq!(trail.push(edges[1]), None);
+        assert_eq!(trail.push(edges[2]), None);
+        assert_eq!(trail.push(edges[3]), Some(edges[0]));
+
+        // Oldest first, with their age
+        assert_eq!(
+            trail.edges().collect::<Vec<_>>(),
+            vec![(2, edges[1]), (1, edges[2]), (0, edges[3])]
+        );
+
+        // Bouncing puts an edge on the trail twice
+        assert_eq!(trail.push(edges[2]), Some(edges[1]));
+        assert_eq!(
+            trail.edges().collect::<Vec<_>>(),
+            vec![(2, edges[2]), (1, edges[3]), (0, edges[2])]
+        );
+
+        assert_eq!(trail.clear().len(), 3);
+        assert_eq!(trail.edges().count(), 0);
+
+        // No trail at all
+        let mut trail = TrailState::new(0);
+        assert_eq!(trail.push(edges[4]), Some(edges[4]));
+
+        // Older edges are dimmer
+        let lightening = (0..6).map(trail_lightening).collect::<Vec<_>>();
+        assert!(lightening.windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(lightening.iter().all(|amount| (0.0..=1.0).contains(amount)));
+    }
+
+    #[test]
+    fn island_tempo() {
+        let mut rng = Xoshiro256Plus::seed_from_u64(5);
+        let constellation = ConstellationGraph::new(500, 5.0, 3, &mut rng).unwrap();
+        let island_data = AudioGraph::generate_island_data(&constellation, &mut rng);
+        assert!(
+            island_data
+                .iter()
+                .all(|island| [0.5, 1.0, 2.0].contains(&island.tempo_mult))
+        );
+        // Weighted towards 1.0
+        let normal = island_data
+            .iter()
+            .filter(|island| island.tempo_mult == 1.0)
+            .count();
+        assert!(
+            normal * 2 > island_data.len(),
+            "{normal}/{}",
+            island_data.len()
+        );
+
+        // Powers of two stay powers of two, within 1..=16
+        for beats in [1, 2, 4, 8, 16] {
+            for tempo_mult in [0.5, 1.0, 2.0] {
+                let scaled = island_edge_beats(beats, tempo_mult);
+                assert!(
+                    scaled.is_power_of_two() && scaled <= 16,
+                    "{beats} × {tempo_mult}"
+                );
+            }
+        }
+        assert_eq!(island_edge_beats(1, 0.5), 1);
+        assert_eq!(island_edge_beats(4, 0.5), 2);
+        assert_eq!(island_edge_beats(4, 2.0), 8);
+        assert_eq!(island_edge_beats(16, 2.0), 16);
+        assert_eq!(island_edge_beats(0, 2.0), 0);
+    }
+
+    #[test]
+    fn direction_temperature() {
+        let mut rng = Xoshiro256Plus::seed_from_u64(9);
+        let dots = [0.2, 0.9, -0.5, 0.4];
+
+        // Temperature 0 is the old argmax, without touching the rng
+        let untouched = rng.clone();
+        assert_eq!(softmax_choice(&dots, 0.0, &mut rng), Some(1));
+        assert_eq!(rng, untouched);
+        assert_eq!(softmax_choice(&[], 0.0, &mut rng), None);
+        assert_eq!(softmax_choice(&[], 1.0, &mut rng), None);
+
+        // Like `max_by_key`, ties pick the last one
+        assert_eq!(softmax_choice(&[0.5, 0.5, 0.5], 0.0, &mut rng), Some(2));
+
+        // Low temperatures still (almost) always go straight
+        let draws = 10_000;
+        let straight = (0..draws)
+            .filter(|_| softmax_choice(&dots, 0.01, &mut rng) == Some(1))
+            .count();
+        assert_eq!(straight, draws);
+
+        // High temperatures (and all-equal scores) approach uniform
+        for (scores, temperature) in [(&dots[..], 1000.0), (&[0.5; 4][..], 1.0)] {
+            let mut counts = [0; 4];
+            for _ in 0..draws * 10 {
+                counts[softmax_choice(scores, temperature, &mut rng).unwrap()] += 1;
+            }
+            for count in counts {
+                let fraction = count as f64 / (draws * 10) as f64;
+                assert!((fraction - 0.25).abs() < 0.01, "{counts:?}");
+            }
+        }
+
+        // In between, straighter is still likelier
+        let mut counts = [0; 4];
+        for _ in 0..draws {
+            counts[softmax_choice(&dots, 0.5, &mut rng).unwrap()] += 1;
+        }
+        assert!(counts[1] > counts[3] && counts[3] > counts[0] && counts[0] > counts[2]);
+    }
+
+    #[test]
+    fn click_queue() {
+        use petgraph::graph::NodeIndex;
+
+        let [a, b, c] = [0, 1, 2].map(NodeIndex::new);
+        let mut queue = ClickQueue::default();
+        assert!(queue.is_empty());
+
+        // First in, first out, and every node at most once
+        assert!(queue.push(a));
+        assert!(queue.push(b));
+        assert!(!queue.push(a));
+        assert!(queue.push(c));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.pop(), Some(a));
+
+        // Removing keeps the order of the rest
+        assert!(queue.push(a));
+        assert!(queue.remove(c));
+        assert!(!queue.remove(c));
+        assert_eq!(queue.pop(), Some(b));
+        assert_eq!(queue.pop(), Some(a));
+        assert_eq!(queue.pop(), None);
+
+        // Clearing returns what was queued
+        queue.push(c);
+        queue.push(b);
+        assert_eq!(queue.clear(), vec![c, b]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn count_in() {
+        use musical_constellations_rust::gd::autoload::state_tick::Tick;
+
+        // Clicked halfway through a bar of 3/4, 2 ticks per beat
+        let ticks = (4..20)
+            .map(|total_ticks| Tick {
+                tick: total_ticks % 2,
+                beat: total_ticks / 2 % 3,
+                bar: total_ticks / 6,
+                ticks_per_beat: 2,
+                beats_per_bar: 3,
+                total_ticks,
+                emitted_at: std::time::Instant::now(),
+            })
+            .collect_vec();
+
+        let steps = |bars| {
+            let mut count_in = CountIn::new(bars);
+            ticks
+                .iter()
+                .map(|tick| (tick.total_ticks, count_in.tick(tick)))
+                .filter(|(_, step)| *step != CountInStep::Wait)
+                .take_while_inclusive(|(_, step)| *step != CountInStep::Done)
+                .collect_vec()
+        };
+
+        // No count-in starts on the next bar
+        assert_eq!(steps(0), vec![(6, CountInStep::Done)]);
+
+        // Every beat of the next bar clicks, accenting the first one, then the walk starts on the bar after
+        assert_eq!(
+            steps(1),
+            vec![
+                (6, CountInStep::Click { accent: true }),
+                (8, CountInStep::Click { accent: false }),
+                (10, CountInStep::Click { accent: false }),
+                (12, CountInStep::Done),
+            ]
+        );
+        assert_eq!(steps(2).len(), 7);
+        assert_eq!(steps(2).last(), Some(&(18, CountInStep::Done)));
+    }
+
+    #[test]
+    fn time_signature() {
+        use musical_constellations_rust::gd::autoload::state_tick::{TickCounter, TimeSignature};
+
+        let signature = |beats_per_bar, ticks_per_beat| TimeSignature {
+            beats_per_bar,
+            ticks_per_beat,
+        };
+        let bar_lengths = |counter: &mut TickCounter, ticks| {
+            (0..ticks)
+                .map(|_| {
+                    let tick = counter.current();
+                    counter.advance();
+                    tick
+                })
+                .filter(|tick| tick.tick_in_bar() == 0)
+                .map(|tick| (tick.total_ticks, tick.beats_per_bar * tick.ticks_per_beat))
+                .collect_vec()
+        };
+
+        // Before the first tick, changes apply immediately
+        let mut counter = TickCounter::new(TimeSignature::default());
+        counter.set_time_signature(signature(3, 4));
+        assert_eq!(counter.current().beats_per_bar, 3);
+
+        // Mid-bar, changes wait for the next bar, and only the last one counts
+        let mut counter = TickCounter::new(TimeSignature::default());
+        assert_eq!(bar_lengths(&mut counter, 5), vec![(0, 16)]);
+        counter.set_time_signature(signature(5, 4));
+        counter.set_time_signature(signature(7, 2));
+        assert_eq!(counter.current().ticks_per_beat, 4);
+        assert_eq!(
+            bar_lengths(&mut counter, 11 + 14 * 2),
+            vec![(16, 14), (30, 14)]
+        );
+
+        // 6/8 counts beats and ticks within the bar
+        counter.set_time_signature(signature(6, 2));
+        let ticks = (0..12)
+            .map(|_| {
+                let tick = counter.current();
+                counter.advance();
+                (tick.bar, tick.beat, tick.tick)
+            })
+            .collect_vec();
+        assert_eq!(ticks[0], (3, 0, 0));
+        assert_eq!(ticks[11], (3, 5, 1));
+        assert_eq!(counter.current().bar, 4);
+    }
+
+    #[test]
+    fn bpm_ramp() {
+        use musical_constellations_rust::gd::autoload::state_tick::{BpmMessage, Tempo};
+
+        let interval = |bpm: f64| 60.0 / bpm / 4.0;
+
+        // 2 bars of 4/4 at 4 ticks per beat
+        let mut tempo = Tempo::new(100.0);
+        tempo.apply(
+            BpmMessage::Ramp {
+                target: 140.0,
+                bars: 2,
+            },
+            16,
+        );
+        let intervals = (0..40)
+            .map(|_| tempo.next_interval(4).as_secs_f64())
+            .collect_vec();
+
+        // Every tick of the ramp is a bit faster, the last one is at the target
+        assert!(intervals[..32].is_sorted_by(|a, b| a > b));
+        assert!((intervals[0] - interval(100.0 + 40.0 / 32.0)).abs() < 1e-9);
+        assert!((intervals[15] - interval(120.0)).abs() < 1e-9);
+        assert!((intervals[31] - interval(140.0)).abs() < 1e-9);
+        assert!(intervals[32..].iter().all(|&i| i == intervals[31]));
+        assert!(!tempo.is_ramping());
+        assert_eq!(tempo.bpm(), 140.0);
+
+        // Setting the BPM stops a ramp halfway, and a 0-bar ramp is a jump
+        tempo.apply(
+            BpmMessage::Ramp {
+                target: 60.0,
+                bars: 1,
+            },
+            16,
+        );
+        tempo.next_interval(4);
+        assert!(tempo.is_ramping());
+        tempo.apply(BpmMessage::Set(90.0), 16);
+        assert!(!tempo.is_ramping());
+        assert!((tempo.next_interval(4).as_secs_f64() - interval(90.0)).abs() < 1e-9);
+        tempo.apply(
+            BpmMessage::Ramp {
+                target: 80.0,
+                bars: 0,
+            },
+            16,
+        );
+        assert!(!tempo.is_ramping());
+        assert_eq!(tempo.bpm(), 80.0);
+    }
+
+    #[test]
+    fn transport() {
+        use musical_constellations_rust::gd::autoload::state_tick::{TickClock, Transport};
+
+        let mut clock = TickClock::new(120.0);
+        let next_total_ticks =
+            |clock: &mut TickClock| clock.next_tick().map(|(_, tick)| tick.total_ticks);
+
+        // Plays right away
+        assert!(clock.is_playing());
+        assert_eq!(next_total_ticks(&mut clock), Some(0));
+        assert_eq!(next_total_ticks(&mut clock), Some(1));
+
+        // Stopping keeps our place, and stopping twice is harmless
+        clock.transport(Transport::Stop);
+        clock.transport(Transport::Stop);
+        assert!(!clock.is_playing());
+        assert_eq!(next_total_ticks(&mut clock), None);
+        clock.transport(Transport::Play);
+        assert_eq!(next_total_ticks(&mut clock), Some(2));
+
+        // Resetting while stopped starts the next play at bar 0
+        for _ in 0..20 {
+            clock.next_tick();
+        }
+        clock.transport(Transport::Stop);
+        clock.transport(Transport::Reset);
+        assert_eq!(next_total_ticks(&mut clock), None);
+        clock.transport(Transport::Play);
+        let elapsed = clock.elapsed();
+        let (at, tick) = clock.next_tick().unwrap();
+        assert_eq!(
+            (tick.tick, tick.beat, tick.bar, tick.total_ticks),
+            (0, 0, 0, 0)
+        );
+        assert!(((at - elapsed).as_secs_f64() - 0.125).abs() < 1e-9);
+
+        // Resetting while playing doesn't stop
+        clock.transport(Transport::Reset);
+        assert_eq!(next_total_ticks(&mut clock), Some(0));
+    }
+
+    /// A walk that's waiting during a reset lands on the new ticks, see `TickCountdown::landed_at`.
+    #[test]
+    fn transport_reset_mid_walk() {
+        use musical_constellations_rust::gd::autoload::state_tick::{TickClock, Transport};
+        use petgraph::graph::NodeIndex;
+
+        let mut clock = TickClock::new(120.0);
+        let mut occupancy = NodeOccupancy::default();
+
+        // A walk plays node 0 at tick 4, and waits 2 ticks
+        let tick = (0..5).map(|_| clock.next_tick().unwrap().1).last().unwrap();
+        assert_eq!(tick.total_ticks, 4);
+        assert!(!occupancy.arrive(NodeIndex::new(0), tick.total_ticks));
+        let mut countdown = TickCountdown::new(tick.total_ticks, 2);
+
+        // Reset when tick 5 would be next
+        clock.transport(Transport::Reset);
+        while !countdown.is_done() {
+            let tick = clock.next_tick().unwrap().1;
+            countdown.tick(tick.total_ticks, false);
+        }
+
+        // Off-beat, so swung, where counting from before the reset would have been on the beat
+        let landed_at = countdown.landed_at().unwrap();
+        assert_eq!(landed_at, 1);
+        assert_eq!(swing_delay_ticks(landed_at, 0.5), 0.5);
+        assert_eq!(swing_delay_ticks(4 + 2, 0.5), 0.0);
+
+        // Node 0 at tick 4 was before the reset, so another walker getting there now isn't a collision
+        assert!(!occupancy.arrive(NodeIndex::new(0), landed_at));
+        assert!(occupancy.arrive(NodeIndex::new(0), landed_at));
+        assert_eq!(occupancy.len(), 1);
+    }
+
+    #[test]
+    fn tick_latency() {
+        use std::time::{Duration, Instant};
+
+        use musical_constellations_rust::gd::autoload::state_tick::{Tick, TickClock, TickLatency};
+
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let tick = TickClock::new(120.0).next_tick().unwrap().1;
+        let tick = Tick {
+            emitted_at: start,
+            ..tick
+        };
+
+        // Lag is measured from when the tick was sent, and never negative
+        assert_eq!(tick.lag(start + ms(12)), ms(12));
+        assert_eq!(tick.lag(start - ms(5)), Duration::ZERO);
+
+        // Compensating shortens delays, but never below zero, and keeps the rest of the lag for later
+        assert!((compensate_delay(0.030, ms(10)) - 0.020).abs() < 1e-9);
+        assert_eq!(compensate_delay(0.030, ms(50)), 0.0);
+        assert_eq!(compensate_delay(0.0, ms(50)), 0.0);
+        assert_eq!(remaining_lag(0.030, ms(50)), ms(20));
+        assert_eq!(remaining_lag(0.030, ms(10)), Duration::ZERO);
+
+        // Average and max over the last second
+        let mut latency = TickLatency::new();
+        assert_eq!(latency.stats(start), None);
+        let at = |emitted: Duration, lag: Duration| {
+            (
+                Tick {
+                    emitted_at: start + emitted,
+                    ..tick
+                },
+                start + emitted + lag,
+            )
+        };
+        for (tick, now) in [at(ms(0), ms(40)), at(ms(500), ms(10)), at(ms(1000), ms(20))] {
+            latency.record(&tick, now);
+        }
+        assert_eq!(latency.stats(start + ms(1020)), Some((ms(70) / 3, ms(40))));
+
+        // The 40 ms one was handled more than a second ago
+        assert_eq!(latency.stats(start + ms(1100)), Some((ms(15), ms(20))));
+        assert_eq!(latency.stats(start + ms(3000)), None);
+    }
+
+    #[test]
+    fn tick_drift() {
+        use musical_constellations_rust::gd::autoload::state_tick::{
+            BpmMessage, MusicalTimeline, TickClock,
+        };
+
+        // Dozens of tempo changes, with a few odd lengths and BPMs so the intervals don't round nicely
+        let mut rng = Xoshiro256Plus::seed_from_u64(857);
+        let segments = (0..60)
+            .map(|_| (rng.random_range(40.0..240.0), rng.random_range(1..5000)))
+            .collect_vec();
+
+        // The exact time, summing up whole segments instead of ticks
+        let expected = segments
+            .iter()
+            .map(|&(bpm, ticks): &(f64, usize)| ticks as f64 * 60.0 / bpm / 4.0)
+            .sum::<f64>();
+
+        let mut clock = TickClock::new(segments[0].0);
+        let mut at = Default::default();
+        for &(bpm, ticks) in &segments {
+            clock.set_bpm(BpmMessage::Set(bpm));
+            for _ in 0..ticks {
+                at = clock.next_tick().unwrap().0;
+            }
+        }
+        assert!((at.as_secs_f64() - expected).abs() < 0.001);
+        assert_eq!(at, clock.elapsed());
+
+        // Bars stay where they should be in beats as well
+        let mut timeline = MusicalTimeline::new(120.0, 4);
+        for &(bpm, ticks) in &segments {
+            timeline.set_tempo(bpm, 3);
+            for _ in 0..ticks {
+                timeline.advance_tick();
+            }
+        }
+        let total_ticks = segments.iter().map(|&(_, ticks)| ticks).sum::<usize>();
+        assert!((timeline.beats() - total_ticks as f64 / 3.0).abs() < 1e-9);
+        assert!((timeline.secs() - expected * 4.0 / 3.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn midi_clock() {
+        use musical_constellations_rust::gd::autoload::{
+            midi_clock::{
+                MIDI_CLOCKS_PER_BEAT, MIDI_CONTINUE, MIDI_START, MIDI_STOP, MidiTransport,
+                midi_clock_offsets,
+            },
+            state_tick::{TickClock, TimeSignature, Transport},
+        };
+
+        // Every beat gets 24 clocks in order, also when they don't divide evenly over the ticks
+        for ticks_per_beat in 1..=12 {
+            let mut clock = TickClock::new(120.0);
+            clock.set_time_signature(TimeSignature {
+                beats_per_bar: 4,
+                ticks_per_beat,
+            });
+            let offsets = (0..ticks_per_beat * 2)
+                .flat_map(|_| {
+                    let tick = clock.next_tick().unwrap().1;
+                    midi_clock_offsets(&tick).map(move |offset| {
+                        (tick.total_ticks as f64 + offset) / ticks_per_beat as f64
+                    })
+                })
+                .collect_vec();
+            assert_eq!(
+                offsets.len(),
+                2 * MIDI_CLOCKS_PER_BEAT,
+                "{ticks_per_beat} ticks per beat"
+            );
+            for (i, beats) in offsets.iter().enumerate() {
+                assert!((beats - i as f64 / MIDI_CLOCKS_PER_BEAT as f64).abs() < 1e-9);
+            }
+        }
+
+        // Stop and continue, or start over after a reset
+        let mut transport = MidiTransport::new();
+        let mut messages = |transports: &[Transport]| {
+            transports
+                .iter()
+                .filter_map(|&t| transport.message(t))
+                .collect_vec()
+        };
+        assert_eq!(messages(&[Transport::Play]), Vec::<u8>::new());
+        assert_eq!(
+            messages(&[Transport::Stop, Transport::Stop, Transport::Play]),
+            vec![MIDI_STOP, MIDI_CONTINUE]
+        );
+        assert_eq!(
+            messages(&[Transport::Stop, Transport::Reset, Transport::Play]),
+            vec![MIDI_STOP, MIDI_START]
+        );
+        assert_eq!(messages(&[Transport::Reset]), vec![MIDI_START]);
+    }
+
+    /// Needs a MIDI system that supports virtual ports, e.g. ALSA.
+    #[cfg(all(feature = "midi", unix))]
+    #[test]
+    fn midi_clock_loopback() {
+        use std::{
+            sync::{
+                Arc,
+                atomic::{AtomicUsize, Ordering},
+            },
+            time::{Duration, Instant},
+        };
+
+        use midir::{Ignore, MidiInput, os::unix::VirtualInput as _};
+        use musical_constellations_rust::gd::autoload::{
+            midi_clock::{MIDI_CLOCK, MIDI_CLOCKS_PER_BEAT, connect_midi_out, send_midi_clocks},
+            state_tick::{Tick, TickClock, TimeSignature},
+        };
+
+        // A virtual port that counts the clocks it receives
+        let clocks = Arc::new(AtomicUsize::new(0));
+        let mut input = MidiInput::new("midi_clock_loopback").unwrap();
+        input.ignore(Ignore::None);
+        let counter = Arc::clone(&clocks);
+        let _input = input
+            .create_virtual(
+                "midi_clock_loopback_in",
+                move |_, message, _| {
+                    if message == [MIDI_CLOCK] {
+                        counter.fetch_add(1, Ordering::Relaxed);
+                    }
+                },
+                (),
+            )
+            .unwrap();
+        let mut output = connect_midi_out("midi_clock_loopback_in").unwrap();
+
+        // Two beats of 5 ticks, so the clocks don't divide evenly over the ticks
+        let mut clock = TickClock::new(300.0);
+        clock.set_time_signature(TimeSignature {
+            beats_per_bar: 4,
+            ticks_per_beat: 5,
+        });
+        for _ in 0..10 {
+            let tick = Tick {
+                emitted_at: Instant::now(),
+                ..clock.next_tick().unwrap().1
+            };
+            send_midi_clocks(&tick, Duration::from_millis(40), |message| {
+                output.send(message).unwrap()
+            });
+        }
+
+        std::thread::sleep(Duration::from_millis(100)); // Let the last clocks arrive
+        assert_eq!(clocks.load(Ordering::Relaxed), 2 * MIDI_CLOCKS_PER_BEAT);
+    }
+
+    #[test]
+    fn midi_sync() {
+        use std::time::Duration;
+
+        use musical_constellations_rust::{
+            gd::autoload::{
+                midi_clock::{MIDI_CONTINUE, MIDI_START, MIDI_STOP},
+                state_tick::Transport,
+            },
+            midi_sync::{ClockTempoEstimator, midi_transports},
+        };
+
+        let clock_interval = |bpm: f64| 60.0 / bpm / 24.0;
+        let feed = |estimator: &mut ClockTempoEstimator, times: &[f64]| {
+            times
+                .iter()
+                .map(|&t| estimator.clock(Duration::from_secs_f64(t)))
+                .collect_vec()
+        };
+
+        // Steady clock at 120 BPM, needs a few clocks before estimating
+        let mut estimator = ClockTempoEstimator::new();
+        let steady = (0..48)
+            .map(|i| i as f64 * clock_interval(120.0))
+            .collect_vec();
+        let estimates = feed(&mut estimator, &steady);
+        assert!(estimates[..6].iter().all(Option::is_none));
+        assert!(
+            estimates[6..]
+                .iter()
+                .all(|bpm| (bpm.unwrap() - 120.0).abs() < 1e-3)
+        );
+
+        // Jittery clock at 97 BPM, up to 2 ms early or late
+        let mut rng = Xoshiro256Plus::seed_from_u64(859);
+        let mut estimator = ClockTempoEstimator::new();
+        let jittery = (0..24 * 8)
+            .map(|i| i as f64 * clock_interval(97.0) + rng.random_range(-0.002..0.002))
+            .collect_vec();
+        let estimates = feed(&mut estimator, &jittery);
+        assert!(
+            estimates[24..]
+                .iter()
+                .all(|bpm| (bpm.unwrap() - 97.0).abs() < 3.0)
+        );
+        let mean = estimates[24..].iter().map(|bpm| bpm.unwrap()).sum::<f64>()
+            / (estimates.len() - 24) as f64;
+        assert!((mean - 97.0).abs() < 0.5);
+
+        // Follows a tempo change within a beat
+        let mut estimator = ClockTempoEstimator::new();
+        feed(&mut estimator, &steady);
+        let last = steady.last().unwrap();
+        let faster = (1..=24)
+            .map(|i| last + i as f64 * clock_interval(150.0))
+            .collect_vec();
+        let estimates = feed(&mut estimator, &faster);
+        assert!((estimates.last().unwrap().unwrap() - 150.0).abs() < 1e-3);
+
+        // Times out after a beat without clocks, and forgets the gap but not the tempo
+        let last = *faster.last().unwrap();
+        assert!(!estimator.timed_out(Duration::from_secs_f64(last + 0.3)));
+        assert!(estimator.timed_out(Duration::from_secs_f64(last + 0.5)));
+        estimator.reset();
+        assert!(!estimator.timed_out(Duration::from_secs_f64(last + 10.0)));
+        assert!((estimator.bpm().unwrap() - 150.0).abs() < 1e-3);
+        let resumed = (0..7)
+            .map(|i| last + 10.0 + i as f64 * clock_interval(150.0))
+            .collect_vec();
+        assert!((feed(&mut estimator, &resumed)[6].unwrap() - 150.0).abs() < 1e-3);
+
+        // Start plays from the top
+        assert_eq!(
+            midi_transports(MIDI_START),
+            [Transport::Reset, Transport::Play]
+        );
+        assert_eq!(midi_transports(MIDI_CONTINUE), [Transport::Play]);
+        assert_eq!(midi_transports(MIDI_STOP), [Transport::Stop]);
+        assert_eq!(midi_transports(0xF8), []);
+    }
+
+    #[test]
+    fn tick_receiver_helpers() {
+        use futures::executor::block_on;
+        use musical_constellations_rust::gd::autoload::state_tick::{
+            TickCounter, TickReceiver, TimeSignature,
+        };
+        use tokio::sync::broadcast;
+
+        let (sender, _) = broadcast::channel(64);
+        let mut counter = TickCounter::new(TimeSignature {
+            beats_per_bar: 3,
+            ticks_per_beat: 2,
+        });
+        let mut send_ticks = |n| {
+            for _ in 0..n {
+                sender.send(counter.current()).unwrap();
+                counter.advance();
+            }
+        };
+
+        let mut ticks = TickReceiver::new(&sender);
+        assert!(ticks.try_latest().is_none());
+
+        // Skips the off-beat tick 1, lands on beat 1 (tick 2)
+        send_ticks(12);
+        let _ = block_on(ticks.wait());
+        let beat = block_on(ticks.next_beat());
+        assert_eq!((beat.total_ticks, beat.beat, beat.tick), (2, 1, 0));
+        assert!(beat.is_beat() && !beat.is_bar());
+
+        // The next bar starts at tick 6
+        let bar = block_on(ticks.next_bar());
+        assert_eq!((bar.total_ticks, bar.bar), (6, 1));
+        assert!(bar.is_bar());
+
+        // A resubscribed receiver only sees ticks sent after resubscribing
+        let mut resubscribed = ticks.resubscribe();
+        assert!(resubscribed.try_latest().is_none());
+
+        // try_latest drains everything that's queued and returns the newest
+        assert_eq!(ticks.try_latest().map(|tick| tick.total_ticks), Some(11));
+        assert!(ticks.try_latest().is_none());
+
+        send_ticks(2);
+        assert_eq!(
+            resubscribed.try_latest().map(|tick| tick.total_ticks),
+            Some(13)
+        );
+        let matching = block_on(ticks.next_matching(|tick| tick.tick == 1));
+        assert_eq!(matching.total_ticks, 13);
+    }
+
+    #[test]
+    fn beat_emitter_shutdown() {
+        use std::{
+            sync::Arc,
+            thread,
+            time::{Duration, Instant},
+        };
+
+        use musical_constellations_rust::{
+            flags::Flag,
+            gd::autoload::state_tick::{
+                BpmMessage, TickInputs, TickReceiver, Transport, beat_emitter,
+            },
+        };
+        use tokio::sync::broadcast;
+
+        let wait_for_exit = |handle: thread::JoinHandle<()>| {
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while !handle.is_finished() {
+                assert!(Instant::now() < deadline, "beat_emitter didn't exit");
+                thread::sleep(Duration::from_millis(1));
+            }
+            handle.join().unwrap();
+        };
+
+        // Exits while ticking, and whoever is still subscribed sees the channel close
+        let (bpm_tx, bpm_rx) = flume::unbounded();
+        let (transport_tx, transport_rx) = flume::unbounded();
+        let inputs = TickInputs {
+            bpm: bpm_rx,
+            time_signature: flume::unbounded().1,
+            transport: transport_rx,
+        };
+        let (tx, _) = broadcast::channel(128);
+        let mut ticks = TickReceiver::new(&tx);
+        let shutdown = Arc::new(Flag::new(false));
+        let handle = thread::spawn({
+            let (inputs, shutdown) = (inputs.clone(), Arc::clone(&shutdown));
+            move || beat_emitter(tx, &inputs, &shutdown)
+        });
+
+        bpm_tx.send(BpmMessage::Set(600.0)).unwrap();
+        let first = ticks.blocking_wait().unwrap();
+        assert_eq!(first.total_ticks, 0);
+
+        shutdown.set(true);
+        wait_for_exit(handle);
+        while ticks.blocking_wait().is_some() {} // Ticks sent before the shutdown are still delivered
+        assert!(ticks.try_latest().is_none());
+
+        // Exits while stopped
+        let (tx, _) = broadcast::channel(128);
+        let shutdown = Arc::new(Flag::new(false));
+        transport_tx.send(Transport::Stop).unwrap();
+        bpm_tx.send(BpmMessage::Set(600.0)).unwrap();
+        let handle = thread::spawn({
+            let (inputs, shutdown) = (inputs.clone(), Arc::clone(&shutdown));
+            move || beat_emitter(tx, &inputs, &shutdown)
+        });
+        thread::sleep(Duration::from_millis(20));
+        assert!(!handle.is_finished());
+        shutdown.set(true);
+        wait_for_exit(handle);
+
+        // Exits while waiting for the initial BPM
+        let (tx, _) = broadcast::channel(128);
+        let shutdown = Arc::new(Flag::new(false));
+        let handle = thread::spawn({
+            let shutdown = Arc::clone(&shutdown);
+            move || beat_emitter(tx, &inputs, &shutdown)
+        });
+        shutdown.set(true);
+        wait_for_exit(handle);
+    }
+
+    #[test]
+    fn tick_forwarding() {
+        use std::{cell::RefCell, rc::Rc};
+
+        use futures::{executor::LocalPool, task::LocalSpawnExt};
+        use musical_constellations_rust::gd::autoload::state_tick::{
+            TickCounter, TickForwarding, TickReceiver, TimeSignature, forward_ticks,
+        };
+        use tokio::sync::broadcast;
+
+        let (sender, _) = broadcast::channel(64);
+        let mut counter = TickCounter::new(TimeSignature::default());
+        let mut send_tick = || {
+            sender.send(counter.current()).unwrap();
+            counter.advance();
+        };
+
+        let mut pool = LocalPool::new();
+        let forwarded = Rc::new(RefCell::new(vec![]));
+        let mut forwarding = TickForwarding::default();
+        assert!(!forwarding.is_enabled());
+
+        let spawner = pool.spawner();
+        let spawn = |cancel| {
+            let ticks = TickReceiver::new(&sender);
+            let forwarded = Rc::clone(&forwarded);
+            spawner
+                .spawn_local_with_handle(forward_ticks(ticks, cancel, move |tick| {
+                    forwarded.borrow_mut().push(tick.total_ticks)
+                }))
+                .unwrap()
+        };
+
+        // Enabling twice only starts one task
+        let task = spawn(forwarding.enable().unwrap());
+        assert!(forwarding.is_enabled());
+        assert!(forwarding.enable().is_none());
+
+        send_tick();
+        send_tick();
+        pool.run_until_stalled();
+        assert_eq!(*forwarded.borrow(), [0, 1]);
+
+        // Disabling ends the task, even with ticks still queued
+        send_tick();
+        forwarding.disable();
+        assert!(!forwarding.is_enabled());
+        pool.run_until(task);
+        assert_eq!(*forwarded.borrow(), [0, 1]);
+
+        // Enabling again starts from the next tick
+        let _task = spawn(forwarding.enable().unwrap());
+        send_tick();
+        pool.run_until_stalled();
+        assert_eq!(*forwarded.borrow(), [0, 1, 3]);
+        forwarding.disable();
+        forwarding.disable(); // Disabling twice is fine
+    }
+
+    #[test]
+    fn metronome_accent_pattern() {
+        use musical_constellations_rust::gd::{
+            autoload::state_tick::{TickCounter, TimeSignature},
+            graph::graph_main::metronome_volume,
+        };
+
+        // Two bars of 3/4 with 2 ticks per beat
+        let mut counter = TickCounter::new(TimeSignature {
+            beats_per_bar: 3,
+            ticks_per_beat: 2,
+        });
+        let ticks = (0..12)
+            .map(|_| {
+                let tick = counter.current();
+                counter.advance();
+                tick
+            })
+            .collect_vec();
+        let volumes = |pattern: &[f32]| {
+            ticks
+                .iter()
+                .map(|tick| metronome_volume(tick, pattern))
+                .collect_vec()
+        };
+
+        // No pattern, only the downbeat is accented
+        assert_eq!(
+            volumes(&[]),
+            [1.0, 0.1, 0.3, 0.1, 0.3, 0.1, 1.0, 0.1, 0.3, 0.1, 0.3, 0.1]
+        );
+
+        // One volume per beat
+        assert_eq!(
+            volumes(&[0.8, 0.2, 0.5]),
+            [0.8, 0.1, 0.2, 0.1, 0.5, 0.1, 0.8, 0.1, 0.2, 0.1, 0.5, 0.1]
+        );
+
+        // Shorter than the bar, so it repeats from the downbeat
+        assert_eq!(
+            volumes(&[0.9, 0.4]),
+            [0.9, 0.1, 0.4, 0.1, 0.9, 0.1, 0.9, 0.1, 0.4, 0.1, 0.9, 0.1]
+        );
+    }
+
+    #[test]
+    fn tap_tempo() {
+        use std::time::{Duration, Instant};
+
+        use musical_constellations_rust::gd::autoload::state_tick::TapTempo;
+
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        let tap_all = |taps: &[f64]| {
+            let mut tap_tempo = TapTempo::new();
+            let estimates = taps
+                .iter()
+                .map(|&secs| tap_tempo.tap(at(secs)))
+                .collect_vec();
+            (tap_tempo, estimates)
+        };
+        let assert_close = |actual: f64, expected: f64, tolerance: f64| {
+            assert!(
+                (actual - expected).abs() < tolerance,
+                "{actual} isn't within {tolerance} of {expected}"
+            );
+        };
+
+        // 120 BPM, with a few milliseconds of jitter
+        let mut rng = Xoshiro256Plus::seed_from_u64(864);
+        let steady = (0..12)
+            .map(|i| i as f64 * 0.5 + rng.random_range(-0.005..0.005))
+            .collect_vec();
+        let (_, estimates) = tap_all(&steady);
+        assert!(estimates[..3].iter().all(Option::is_none), "needs 4 taps");
+        let estimate = estimates.last().unwrap().unwrap();
+        assert_close(estimate.bpm, 120.0, 1.0);
+        assert_close((estimate.next_tap - start).as_secs_f64(), 6.0, 0.01);
+
+        // A missed tap doesn't move the BPM at all, averaging every interval would give 105 BPM
+        let missed = [0.0, 0.5, 1.0, 1.5, 2.5, 3.0, 3.5, 4.0];
+        let estimate = tap_all(&missed).1.last().unwrap().unwrap();
+        assert_close(estimate.bpm, 120.0, 1e-6);
+        assert_close((estimate.next_tap - start).as_secs_f64(), 4.5, 1e-6);
+
+        // A double tap only skews the interval after it a bit, averaging every interval would give 140 BPM
+        let double = [0.0, 0.5, 1.0, 1.02, 1.5, 2.0, 2.5, 3.0];
+        let estimate = tap_all(&double).1.last().unwrap().unwrap();
+        assert_close(estimate.bpm, 120.0, 1.0);
+
+        // Gradual drift from 100 to 120 BPM, ends up in between
+        let mut secs = 0.0;
+        let drifting = (0..16)
+            .map(|i| {
+                let tap = secs;
+                secs += 60.0 / (100.0 + 20.0 * i as f64 / 15.0);
+                tap
+            })
+            .collect_vec();
+        let estimate = tap_all(&drifting).1.last().unwrap().unwrap();
+        assert!((100.0..120.0).contains(&estimate.bpm), "{}", estimate.bpm);
+
+        // Jumping from 80 to 140 BPM, the old tempo becomes the outlier once the new one has the majority
+        let mut jump = (0..6).map(|i| i as f64 * 0.75).collect_vec();
+        let jump_at = *jump.last().unwrap();
+        jump.extend((1..10).map(|i| jump_at + i as f64 * 60.0 / 140.0));
+        let estimate = tap_all(&jump).1.last().unwrap().unwrap();
+        assert_close(estimate.bpm, 140.0, 1e-6);
+        assert_close(
+            (estimate.next_tap - start).as_secs_f64(),
+            jump_at + 10.0 * 60.0 / 140.0,
+            1e-6,
+        );
+
+        // Only the last 32 taps, from the last 30 seconds count
+        let (tap_tempo, _) = tap_all(&(0..40).map(|i| i as f64 * 0.5).collect_vec());
+        assert_eq!(tap_tempo.tap_count(), 32);
+        let (tap_tempo, estimates) = tap_all(&[0.0, 0.5, 1.0, 1.5, 40.0]);
+        assert_eq!(tap_tempo.tap_count(), 1);
+        assert!(estimates.last().unwrap().is_none());
+    }
+
+    #[test]
+    fn tick_clock_nudge() {
+        use std::time::Duration;
+
+        use musical_constellations_rust::gd::autoload::state_tick::{BpmMessage, Tempo, TickClock};
+
+        // 60 BPM with 4 ticks per beat, so a beat is a second
+        let mut clock = TickClock::new(60.0);
+        let next = |clock: &mut TickClock| {
+            let (at, tick) = clock.next_tick().unwrap();
+            (at.as_secs_f64(), tick.total_ticks)
+        };
+        assert_eq!(next(&mut clock), (0.25, 0));
+        assert_eq!(next(&mut clock), (0.5, 1));
+        // The first tick is on the beat, so the next beat is a second later
+        assert!((clock.next_beat().as_secs_f64() - 1.25).abs() < 1e-9);
+
+        // Move the next beat 100ms later, the ticks before it get shifted along
+        clock.nudge(Duration::from_secs_f64(1.35));
+        let (at, _) = next(&mut clock);
+        assert!((at - 0.85).abs() < 1e-9);
+        let (at, _) = next(&mut clock);
+        assert!((at - 1.1).abs() < 1e-9);
+        let (at, total_ticks) = next(&mut clock);
+        assert!((at - 1.35).abs() < 1e-9 && total_ticks == 4);
+        let (at, _) = next(&mut clock);
+        assert!((at - 1.6).abs() < 1e-9, "applied once, not on every tick");
+
+        // A target almost a beat later is really a bit earlier
+        assert!((clock.next_beat().as_secs_f64() - 2.35).abs() < 1e-9);
+        clock.nudge(Duration::from_secs_f64(2.35 + 0.85));
+        assert!((clock.next_beat().as_secs_f64() - 2.2).abs() < 1e-9);
+
+        // Nudges don't change the tempo
+        let mut tempo = Tempo::new(90.0);
+        tempo.apply(
+            BpmMessage::Nudge {
+                next_beat: std::time::Instant::now(),
+            },
+            16,
+        );
+        assert_eq!(tempo.bpm(), 90.0);
+    }
+
+    #[test]
+    fn bpm_automation() {
+        use musical_constellations_rust::gd::graph::graph_automation::{
+            BpmAutomation, automation_bpm, automation_position,
+        };
+
+        // Loops every `length_bars` bars
+        let positions = (0..6).map(|bar| automation_position(bar, 4)).collect_vec();
+        assert_eq!(positions, [0.0, 0.25, 0.5, 0.75, 0.0, 0.25]);
+        assert_eq!(
+            automation_position(3, 0),
+            0.0,
+            "0 bars doesn't divide by zero"
+        );
+
+        // Curve values map linearly onto the BPM range, and don't leave it
+        assert_eq!(automation_bpm(0.0, 80.0, 160.0), 80.0);
+        assert_eq!(automation_bpm(0.5, 80.0, 160.0), 120.0);
+        assert_eq!(automation_bpm(1.0, 80.0, 160.0), 160.0);
+        assert_eq!(automation_bpm(-0.5, 80.0, 160.0), 80.0);
+        assert_eq!(automation_bpm(1.5, 80.0, 160.0), 160.0);
+
+        // Follow a ramp curve over 4 bars, someone moves the slider in bar 3
+        let curve = |position: f32| position;
+        let mut automation = BpmAutomation::new();
+        let mut bpm = 100.0;
+        let mut bpms = vec![];
+        for bar in 0..12 {
+            if bar == 3 {
+                bpm = 95.0;
+            }
+            if let Some(position) = automation.bar(bpm, 4, 2) {
+                bpm = automation_bpm(curve(position), 80.0, 160.0);
+                automation.set_bpm(bpm);
+            }
+            bpms.push(bpm);
+        }
+        assert_eq!(
+            bpms,
+            [
+                80.0, 100.0, 120.0, 95.0, 95.0, 100.0, 120.0, 140.0, 80.0, 100.0, 120.0, 140.0
+            ],
+            "pauses for 2 bars, then picks up where the curve is by then"
+        );
+        assert!(!automation.is_paused());
+    }
+
+    #[test]
+    fn tick_lag_counter() {
+        use std::time::{Duration, Instant};
+
+        use futures::executor::block_on;
+        use musical_constellations_rust::gd::autoload::state_tick::{
+            TICK_LAG, TickCounter, TickLagCounter, TickReceiver, TimeSignature,
+        };
+        use tokio::sync::broadcast;
+
+        // Falling behind on a channel that only holds 2 ticks
+        let (sender, _) = broadcast::channel(2);
+        let mut counter = TickCounter::new(TimeSignature::default());
+        let mut send_ticks = |n| {
+            for _ in 0..n {
+                sender.send(counter.current()).unwrap();
+                counter.advance();
+            }
+        };
+        let mut ticks = TickReceiver::new(&sender);
+
+        // Same `now` for both, so the window can't start over in between
+        let now = Instant::now();
+        let (missed_before, _) = TICK_LAG.stats(now);
+        send_ticks(5);
+        assert_eq!(block_on(ticks.wait()).total_ticks, 3, "ticks 0-2 got lost");
+        send_ticks(4);
+        assert_eq!(ticks.try_latest().unwrap().total_ticks, 8);
+        let (missed_after, max) = TICK_LAG.stats(now);
+        assert!(
+            missed_after - missed_before >= 3 + 3,
+            "other tests may lag too"
+        );
+        assert!(max >= 3);
+
+        // Resets once a minute
+        let lag = TickLagCounter::new();
+        let start = Instant::now();
+        assert_eq!(lag.stats(start), (0, 0));
+        lag.record(2);
+        lag.record(5);
+        lag.record(1);
+        assert_eq!(lag.stats(start + Duration::from_secs(59)), (8, 5));
+        assert_eq!(lag.stats(start + Duration::from_secs(60)), (0, 0));
+        lag.record(4);
+        assert_eq!(lag.stats(start + Duration::from_secs(100)), (4, 4));
+    }
+
+    #[test]
+    fn link_tempo_sync() {
+        use std::time::{Duration, Instant};
+
+        use musical_constellations_rust::link_sync::{
+            LinkTempoSync, TempoSync, link_time_to_instant,
+        };
+
+        let mut sync = LinkTempoSync::new(120.0);
+        assert_eq!(sync.update(120.0, 120.0), None);
+
+        // The session changes, we follow and don't echo our old tempo back while catching up
+        assert_eq!(sync.update(130.0, 120.0), Some(TempoSync::ToLocal(130.0)));
+        assert_eq!(sync.update(130.0, 120.0), None);
+        assert_eq!(sync.update(130.0, 130.0), None);
+
+        // We change, the session follows
+        assert_eq!(sync.update(130.0, 100.0), Some(TempoSync::ToLink(100.0)));
+        assert_eq!(sync.update(100.0, 100.0), None);
+
+        // Both change, the session wins
+        assert_eq!(sync.update(90.0, 110.0), Some(TempoSync::ToLocal(90.0)));
+
+        let now = Instant::now();
+        assert_eq!(
+            link_time_to_instant(1_500, 1_000, now),
+            now + Duration::from_micros(500)
+        );
+        assert_eq!(
+            link_time_to_instant(500, 1_000, now),
+            now - Duration::from_micros(500)
+        );
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        use musical_constellations_rust::settings::{
+            Settings, SettingsError, settings_from_toml, settings_to_toml,
+        };
+
+        let settings = Settings {
+            bpm: 132.5,
+            seed: 0xFEDCBA9876543210, // Doesn't fit in a TOML integer
+            use_metronome: true,
+            master_gain: 0.5,
+            palette: "viridis".to_string(),
+            walk_strategy: WalkStrategy::AvoidBacktrack,
+        };
+        let toml = settings_to_toml(&settings);
+        assert!(toml.contains("version = 1"), "{toml}");
+        assert_eq!(settings_from_toml(&toml).unwrap(), settings);
+
+        // Missing fields fall back to the defaults
+        let partial = settings_from_toml("version = 1\nbpm = 90.0").unwrap();
+        assert_eq!(
+            partial,
+            Settings {
+                bpm: 90.0,
+                ..Settings::default()
+            }
+        );
+
+        assert!(matches!(
+            settings_from_toml("version = 1\nbpm = \"fast\""),
+            Err(SettingsError::Toml(_))
+        ));
+        assert!(matches!(
+            settings_from_toml("not toml at all ]["),
+            Err(SettingsError::Toml(_))
+        ));
+        assert!(matches!(
+            settings_from_toml("bpm = 90.0"),
+            Err(SettingsError::MissingVersion)
+        ));
+        assert!(matches!(
+            settings_from_toml(&toml.replace("version = 1", "version = 2")),
+            Err(SettingsError::FutureVersion(2))
+        ));
+        assert!(matches!(
+            settings_from_toml(&toml.replace("version = 1", "version = 0")),
+            Err(SettingsError::UnknownVersion(0))
+        ));
+    }
+
+    #[test]
+    fn settings_precedence() {
+        use std::time::{Duration, Instant};
+
+        use musical_constellations_rust::{
+            gd::autoload::cli::InnerArgs,
+            settings::{SETTINGS_SAVE_DEBOUNCE, SaveDebounce, Settings},
+        };
+
+        let saved = Settings {
+            bpm: 100.0,
+            seed: 42,
+            ..Settings::default()
+        };
+
+        // Saved settings win over the defaults
+        let no_args = InnerArgs::default();
+        assert_eq!(saved.with_cli_args(&no_args), saved);
+
+        // CLI args win over the saved settings
+        let args = InnerArgs {
+            bpm: Some(140.0),
+            seed: Some(7),
+            ..InnerArgs::default()
+        };
+        let settings = saved.with_cli_args(&args);
+        assert_eq!((settings.bpm, settings.seed), (140.0, 7));
+        assert_eq!(settings.master_gain, saved.master_gain);
+
+        let start = Instant::now();
+        let mut debounce = SaveDebounce::default();
+        assert!(!debounce.take_due(start + SETTINGS_SAVE_DEBOUNCE));
+        debounce.changed(start);
+        debounce.changed(start + Duration::from_secs(1));
+        assert!(!debounce.take_due(start + SETTINGS_SAVE_DEBOUNCE));
+        assert!(debounce.take_due(start + Duration::from_secs(1) + SETTINGS_SAVE_DEBOUNCE));
+        assert!(!debounce.is_pending());
+    }
+
+    #[test]
+    fn preset_store_round_trip() {
+        use musical_constellations_rust::{
+            gd::graph::graph_generate::{Connectivity, EdgeStrategy, PointDistribution},
+            presets::{GenerationParams, Preset, PresetError, PresetStore},
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = PresetStore::new(dir.path().join("presets")); // Doesn't exist yet
+        assert!(store.list().unwrap().is_empty());
+
+        let preset = Preset {
+            seed: 0xFEDCBA9876543210,
+            bpm: 97.5,
+            generation: GenerationParams {
+                num_points: 300,
+                distribution: PointDistribution::default(),
+                edge_strategy: EdgeStrategy::default(),
+                connectivity: Connectivity::default(),
+                bridges_per_island: 2,
+                lloyd_iterations: 5,
+            },
+            walk_strategy: WalkStrategy::WeightedByDistance,
+            palette: "turbo".to_string(),
+        };
+        store.save("Slow dance", &preset, false).unwrap();
+        store.save("b-side_2", &preset, false).unwrap();
+        assert_eq!(store.load("Slow dance").unwrap(), preset);
+        assert_eq!(store.list().unwrap(), ["Slow dance", "b-side_2"]);
+
+        // Name collisions only overwrite when asked to
+        let faster = Preset {
+            bpm: 140.0,
+            ..preset.clone()
+        };
+        assert!(matches!(
+            store.save("Slow dance", &faster, false),
+            Err(PresetError::AlreadyExists(_))
+        ));
+        assert_eq!(store.load("Slow dance").unwrap(), preset);
+        store.save("Slow dance", &faster, true).unwrap();
+        assert_eq!(store.load("Slow dance").unwrap(), faster);
+
+        for name in [
+            "",
+            "../escape",
+            "a/b",
+            ".hidden",
+            " padded",
+            "x".repeat(65).as_str(),
+        ] {
+            assert!(
+                matches!(
+                    store.save(name, &preset, true),
+                    Err(PresetError::InvalidName(_))
+                ),
+                "{name:?}"
+            );
+        }
+
+        store.delete("b-side_2").unwrap();
+        assert!(matches!(
+            store.delete("b-side_2"),
+            Err(PresetError::NotFound(_))
+        ));
+        assert!(matches!(
+            store.load("b-side_2"),
+            Err(PresetError::NotFound(_))
+        ));
+
+        // Files that aren't presets get skipped, broken presets fail to load
+        std::fs::write(dir.path().join("presets/notes.txt"), "hi").unwrap();
+        std::fs::write(dir.path().join("presets/broken.toml"), "bpm = ").unwrap();
+        assert_eq!(store.list().unwrap(), ["Slow dance", "broken"]);
+        assert!(matches!(store.load("broken"), Err(PresetError::Toml(_))));
+    }
+
+    #[test]
+    fn island_info() {
+        use musical_constellations_rust::gd::graph::graph_main::IslandInfo;
+        use petgraph::graph::NodeIndex;
+
+        let generate = || {
+            let mut rng = Xoshiro256Plus::seed_from_u64(7);
+            let constellation = ConstellationGraph::new(80, 5.0, 3, &mut rng).unwrap();
+            let island_data = AudioGraph::generate_island_data(&constellation, &mut rng);
+            (constellation, island_data)
+        };
+        let (constellation, island_data) = generate();
+        let node_islands = constellation.node_islands();
+        assert_eq!(node_islands.len(), constellation.graph.node_count());
+        assert!(constellation.islands.len() > 1);
+
+        for (island, nodes) in constellation.islands.iter().enumerate() {
+            let info =
+                IslandInfo::new(&constellation.graph, &island_data, &node_islands, island).unwrap();
+            assert_eq!(info.data, island_data[island]);
+            assert_eq!(info.nodes, nodes.iter().copied().sorted().collect_vec());
+
+            let centroid = nodes
+                .iter()
+                .map(|&idx| constellation.graph[idx])
+                .sum::<Vector3>()
+                / nodes.len() as f32;
+            assert!(info.centroid.distance_to(centroid) < 1e-5);
+            for node in nodes {
+                assert_eq!(node_islands[node], island);
+            }
+        }
+        assert_eq!(
+            IslandInfo::new(
+                &constellation.graph,
+                &island_data,
+                &node_islands,
+                island_data.len()
+            ),
+            None
+        );
+        assert!(!node_islands.contains_key(&NodeIndex::new(constellation.graph.node_count())));
+
+        // The same seed gives the same islands
+        let (again, again_data) = generate();
+        assert_eq!(again.node_islands(), node_islands);
+        assert_eq!(again_data, island_data);
+    }
+
+    #[test]
+    fn frame_time_percentiles() {
+        use musical_constellations_rust::util::{FrameTimes, percentile};
+
+        let sorted = (1..=100).map(f64::from).collect_vec();
+        assert_eq!(percentile(&sorted, 50.0), Some(50.0));
+        assert_eq!(percentile(&sorted, 95.0), Some(95.0));
+        assert_eq!(percentile(&sorted, 99.0), Some(99.0));
+        assert_eq!(percentile(&sorted, 0.0), Some(1.0));
+        assert_eq!(percentile(&sorted, 100.0), Some(100.0));
+        assert_eq!(percentile(&[7.0], 50.0), Some(7.0));
+        assert_eq!(percentile(&[], 50.0), None);
+
+        let mut frame_times = FrameTimes::new(4);
+        assert_eq!(frame_times.percentiles([50.0]), None);
+        for frame_time in [0.1, 0.016, 0.017, 0.016, 0.033] {
+            frame_times.push(frame_time);
+        }
+        // The 0.1 stutter fell out of the window
+        assert_eq!(frame_times.len(), 4);
+        assert_eq!(frame_times.percentiles([50.0, 99.0]), Some([0.016, 0.033]));
+    }
+
+    #[test]
+    fn constellation_stats() {
+        use godot::builtin::Color;
+        use musical_constellations_rust::{
+            gd::graph::graph_main::ConstellationStats, palette::NodeColors, util::color_to_html,
+        };
+
+        assert_eq!(color_to_html(Color::from_rgb(1.0, 0.5, 0.0)), "ff8000");
+
+        let mut rng = Xoshiro256Plus::seed_from_u64(3);
+        let constellation = ConstellationGraph::new(120, 5.0, 3, &mut rng).unwrap();
+        let island_data = AudioGraph::generate_island_data(&constellation, &mut rng);
+        let stats = ConstellationStats::new(&constellation, &island_data);
+
+        assert_eq!(stats.node_count, constellation.graph.node_count());
+        assert_eq!(stats.island_sizes.iter().sum::<usize>(), stats.node_count);
+        assert_eq!(stats.island_count(), constellation.islands.len());
+        let waveform_total = stats
+            .waveform_counts
+            .iter()
+            .map(|count| count.islands + count.pad_islands)
+            .sum::<usize>();
+        assert_eq!(waveform_total, stats.island_count());
+        let pad_total = stats
+            .waveform_counts
+            .iter()
+            .map(|count| count.pad_islands)
+            .sum::<usize>();
+        assert_eq!(pad_total, stats.pad_island_count);
+
+        // The rendered string shows the same counts
+        let config = WalkConfig {
+            strategy: WalkStrategy::DirectionPreserving,
+            revisits: RevisitPolicy::FallBack,
+            dead_ends: DeadEndBehavior::Stop,
+            max_steps: 0,
+            max_concurrent_branches: 0,
+            rhythm_grid: RhythmGrid::PowersOfTwo,
+            direction_temperature: 0.0,
+        };
+        let rendered = AudioGraph::generate_stats(
+            &stats,
+            &constellation,
+            &island_data,
+            &config,
+            &NodeColors::default(),
+        );
+        let line = |prefix: &str| {
+            rendered
+                .lines()
+                .find_map(|line| line.strip_prefix(prefix))
+                .unwrap_or_else(|| panic!("no {prefix:?} in {rendered}"))
+                .to_owned()
+        };
+        assert_eq!(
+            line("Vertex/edge count: "),
+            format!("{}, {}", stats.node_count, stats.edge_count)
+        );
+        assert_eq!(line("Island count: "), stats.island_count().to_string());
+        assert_eq!(
+            line("Bridge edge count: "),
+            stats.bridge_edge_count.to_string()
+        );
+        assert!(line("Pad island count: ").starts_with(&format!(
+            "{}/{}",
+            stats.pad_island_count,
+            stats.island_count()
+        )));
+
+        // Non-pads on the first line, pads on the second
+        let occurrences = rendered
+            .lines()
+            .skip_while(|line| *line != "Waveform occurrences:")
+            .skip(1)
+            .take(2)
+            .map(|line| {
+                line.split('×')
+                    .skip(1)
+                    .map(|part| part[..2].parse::<usize>().unwrap())
+                    .collect_vec()
+            })
+            .collect_vec();
+        assert_eq!(
+            occurrences,
+            [
+                stats
+                    .waveform_counts
+                    .iter()
+                    .map(|count| count.islands)
+                    .collect_vec(),
+                stats
+                    .waveform_counts
+                    .iter()
+                    .map(|count| count.pad_islands)
+                    .collect_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn main_thread_guard() {
+        use musical_constellations_rust::util::{MainThreadGuard, ThreadGuardError};
+
+        static GUARD: MainThreadGuard = MainThreadGuard::new();
+        assert_eq!(GUARD.check(), Err(ThreadGuardError::Uninitialized));
+
+        GUARD.set_main_thread();
+        assert_eq!(GUARD.check(), Ok(()));
+
+        std::thread::spawn(|| {
+            assert_eq!(GUARD.check(), Err(ThreadGuardError::NotMainThread));
+            GUARD.set_main_thread(); // Only the first call counts
+            assert_eq!(GUARD.check(), Err(ThreadGuardError::NotMainThread));
+        })
+        .join()
+        .unwrap();
+
+        // Like a rayon task that indirectly touches the autoload
+        let (tx, rx) = flume::bounded(1);
+        rayon::spawn(move || tx.send(GUARD.check()).unwrap());
+        assert_eq!(rx.recv().unwrap(), Err(ThreadGuardError::NotMainThread));
+
+        assert_eq!(GUARD.check(), Ok(()));
+    }
+
+    #[test]
+    fn pause_state() {
+        use musical_constellations_rust::gd::autoload::state_main::{PauseState, fade_toward};
+
+        // Pausing by hand, regaining focus doesn't resume
+        let mut pause = PauseState::default();
+        assert!(pause.pause(false, false));
+        assert!(!pause.pause(false, true));
+        assert_eq!(pause.resume(true), None);
+        assert!(pause.is_paused());
+        assert_eq!(pause.resume(false), Some(false));
+        assert_eq!(pause.resume(false), None);
+
+        // Pausing by focus loss resumes on focus, walks that were paused by hand stay paused
+        assert!(pause.pause(true, true));
+        assert_eq!(pause.resume(true), Some(true));
+        assert!(!pause.is_paused());
+
+        // Fades take `fade_secs` from one end to the other, and don't overshoot
+        assert_eq!(fade_toward(1.0, 0.0, 0.1, 0.25), 0.6);
+        assert_eq!(fade_toward(0.2, 0.0, 0.1, 0.25), 0.0);
+        assert_eq!(fade_toward(0.0, 1.0, 0.125, 0.25), 0.5);
+        assert_eq!(fade_toward(0.9, 1.0, 0.125, 0.25), 1.0);
+    }
+
+    #[test]
+    fn num_points_clamp_and_cli() {
+        use clap::Parser as _;
+        use musical_constellations_rust::gd::autoload::{
+            cli::InnerArgs,
+            state_main::{MAX_NUM_POINTS, MIN_NUM_POINTS, clamp_num_points},
+        };
+
+        assert_eq!(clamp_num_points(2000), 2000);
+        assert_eq!(clamp_num_points(0), MIN_NUM_POINTS);
+        assert_eq!(clamp_num_points(-5), MIN_NUM_POINTS);
+        assert_eq!(clamp_num_points(1_000_000), MAX_NUM_POINTS);
+
+        let args =
+            InnerArgs::try_parse_from(["musical_constellations", "--num-points", "500"]).unwrap();
+        assert_eq!(args.num_points, Some(500));
+        let args = InnerArgs::try_parse_from(["musical_constellations"]).unwrap();
+        assert_eq!(args.num_points, None);
+        assert!(
+            InnerArgs::try_parse_from(["musical_constellations", "--num-points", "lots"]).is_err()
+        );
+    }
+
+    #[test]
+    fn session_round_trip() {
+        use musical_constellations_rust::{
+            chords::Chord,
+            gd::{
+                graph::{
+                    graph_generate::{Connectivity, EdgeStrategy, PointDistribution},
+                    graph_main::{ConstellationStats, WaveformCount},
+                    graph_record::{WalkEvent, WalkRecording},
+                },
+                node_stream::Waveform,
+            },
+            presets::GenerationParams,
+            session::{
+                BuildInfo, SessionError, SessionSettings, SessionSnapshot, load_session_settings,
+                save_session, session_settings_from_json,
+            },
+        };
+        use petgraph::graph::NodeIndex;
+
+        let generation = GenerationParams {
+            num_points: 400,
+            distribution: PointDistribution::default(),
+            edge_strategy: EdgeStrategy::default(),
+            connectivity: Connectivity::default(),
+            bridges_per_island: 1,
+            lloyd_iterations: 3,
+        };
+        let snapshot = SessionSnapshot {
+            build: BuildInfo::current(),
+            seed: 0xFEDCBA9876543210,
+            bpm: 128.0,
+            generation: generation.clone(),
+            walk_strategy: WalkStrategy::AvoidBacktrack,
+            stats: Some(ConstellationStats {
+                chord: Chord::Cmin7,
+                semitone_offset: 3,
+                node_count: 400,
+                edge_count: 900,
+                bridge_edge_count: 4,
+                island_sizes: vec![250, 150],
+                pad_island_count: 1,
+                waveform_counts: vec![WaveformCount {
+                    waveform: Waveform::Saw,
+                    islands: 1,
+                    pad_islands: 1,
+                }],
+            }),
+            recording: Some(WalkRecording {
+                seed: 0xFEDCBA9876543210_u64 as i64,
+                events: vec![WalkEvent {
+                    tick: 0,
+                    node: NodeIndex::new(7),
+                    edge: None,
+                    beats_waited: 0,
+                    velocity: 0.8,
+                }],
+            }),
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        save_session(&path, &snapshot).unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SessionSnapshot>(&json).unwrap(),
+            snapshot
+        );
+
+        let (settings, skipped) = load_session_settings(&path).unwrap();
+        assert_eq!(
+            settings,
+            SessionSettings {
+                seed: Some(0xFEDCBA9876543210),
+                bpm: Some(128.0),
+                generation: Some(generation),
+                walk_strategy: Some(WalkStrategy::AvoidBacktrack),
+            }
+        );
+        assert!(skipped.is_empty());
+
+        // Whatever is missing or invalid gets skipped, the rest still applies. Unknown fields are ignored.
+        let (settings, skipped) = session_settings_from_json(
+            r#"{"seed": "not hex", "bpm": 90.0, "walk_strategy": "Sideways", "from_the_future": 1}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            settings,
+            SessionSettings {
+                bpm: Some(90.0),
+                ..SessionSettings::default()
+            }
+        );
+        assert_eq!(skipped, ["seed", "generation", "walk_strategy"]);
+
+        assert!(matches!(
+            session_settings_from_json("[1, 2, 3]"),
+            Err(SessionError::NotAnObject)
+        ));
+        assert!(matches!(
+            session_settings_from_json("{\"bpm\": "),
+            Err(SessionError::Json(_))
+        ));
+        assert!(matches!(
+            load_session_settings(&dir.path().join("missing.json")),
+            Err(SessionError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn mute_and_solo() {
+        use musical_constellations_rust::gd::graph::graph_main::{
+            SoloIslands, is_effectively_muted,
+        };
+
+        // Nothing soloed, only explicit mutes count
+        let mut solo = SoloIslands::default();
+        assert!(!is_effectively_muted(false, Some(0), &solo));
+        assert!(is_effectively_muted(true, Some(0), &solo));
+        assert!(!is_effectively_muted(false, None, &solo));
+
+        // Soloing silences the other islands (and nodes without a known island)
+        assert!(solo.toggle(2));
+        assert!(!is_effectively_muted(false, Some(2), &solo));
+        assert!(is_effectively_muted(false, Some(1), &solo));
+        assert!(is_effectively_muted(false, None, &solo));
+        // Being soloed doesn't undo an explicit mute
+        assert!(is_effectively_muted(true, Some(2), &solo));
+
+        assert!(solo.toggle(1));
+        assert!(!is_effectively_muted(false, Some(1), &solo));
+
+        // Unsoloing everything unmutes everything again
+        assert!(!solo.toggle(2));
+        assert!(is_effectively_muted(false, Some(2), &solo));
+        assert!(!solo.toggle(1));
+        assert!(solo.is_empty());
+        assert!(!is_effectively_muted(false, Some(2), &solo));
+    }
+
+    #[test]
+    fn node_info() {
+        use musical_constellations_rust::{
+            chords::Chord,
+            gd::{
+                node_main::{NodeInfo, frequency_to_midi_note, midi_note_name, node_frequency},
+                node_stream::Waveform,
+            },
+        };
+        use rand::SeedableRng as _;
+        use rand_xoshiro::Xoshiro256Plus;
+
+        assert_eq!(midi_note_name(60), "C4");
+        assert_eq!(midi_note_name(69), "A4");
+        assert_eq!(midi_note_name(61), "C#4");
+        assert_eq!(midi_note_name(11), "B-1");
+        assert_eq!(frequency_to_midi_note(440.0), 69);
+        assert_eq!(frequency_to_midi_note(261.63), 60);
+        // Detuning rounds to the closest note
+        assert_eq!(frequency_to_midi_note(440.0 * (0.07_f32 / 12.0).exp2()), 69);
+
+        // The same seed always picks the same notes
+        let notes = |seed: u64| {
+            let mut rng = Xoshiro256Plus::seed_from_u64(seed);
+            (0..8)
+                .map(|_| {
+                    let frequency = node_frequency(&Chord::Cmaj.as_intervals(), 4, 2.0, &mut rng);
+                    midi_note_name(frequency_to_midi_note(frequency))
+                })
+                .collect::<Vec<_>>()
+        };
+        // Cmaj shifted up 2 semitones is D major
+        assert_eq!(
+            notes(0xDEADBEEF),
+            ["A4", "A4", "D4", "A4", "D4", "A4", "F#4", "F#4"]
+        );
+        assert_eq!(notes(0xDEADBEEF), notes(0xDEADBEEF));
+
+        let info = NodeInfo {
+            midi_note: 62,
+            waveform: Waveform::Saw,
+            octave: 4,
+            duration: 0.5,
+            is_pad: false,
+            island: Some(3),
+            degree: Some(2),
+        };
+        assert_eq!(info.note_name(), "D4");
+    }
+
+    #[test]
+    fn waveform_cycling() {
+        use std::sync::atomic::Ordering;
+
+        use musical_constellations_rust::gd::node_stream::{AtomicWaveform, Waveform};
+        use strum::IntoEnumIterator as _;
+
+        assert_eq!(Waveform::Sine.next(), Waveform::Triangle);
+        assert_eq!(Waveform::Square.next(), Waveform::Noise);
+        assert_eq!(Waveform::Noise.next(), Waveform::Sine);
+
+        // Cycling visits every waveform once before coming back
+        let cycle = std::iter::successors(Some(Waveform::Sine), |waveform| Some(waveform.next()))
+            .take(Waveform::iter().len() + 1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cycle[..Waveform::iter().len()],
+            Waveform::iter().collect::<Vec<_>>()
+        );
+        assert_eq!(cycle.last(), Some(&Waveform::Sine));
+
+        let shared = AtomicWaveform::default();
+        assert_eq!(shared.load(Ordering::Relaxed), Waveform::default());
+        for waveform in Waveform::iter() {
+            shared.store(waveform, Ordering::Relaxed);
+            assert_eq!(shared.load(Ordering::Relaxed), waveform);
+        }
+    }
+
+    #[test]
+    fn envelope_segments() {
+        use musical_constellations_rust::gd::{
+            graph::graph_main::island_envelope,
+            node_main::{Envelope, EnvelopeSegments},
+        };
+        use rand::SeedableRng as _;
+        use rand_xoshiro::Xoshiro256Plus;
+
+        // The defaults sound like before: plucks start at the peak, pads take the duration for every phase at half the volume
+        assert_eq!(
+            Envelope::PLUCK.segments(false, 0.8, 1.0),
+            EnvelopeSegments {
+                attack: 0.0,
+                sustain: 0.0,
+                release: 0.8,
+                peak: 1.0,
+            }
+        );
+        assert_eq!(
+            Envelope::PAD.segments(true, 2.0, 0.5),
+            EnvelopeSegments {
+                attack: 2.0,
+                sustain: 2.0,
+                release: 2.0,
+                peak: 0.25,
+            }
+        );
+
+        // The duration scales the attack and release, but pads always sustain for the duration
+        let envelope = Envelope {
+            attack: 0.5,
+            sustain_level: 0.6,
+            release: 0.25,
+        };
+        let segments = envelope.segments(true, 4.0, 1.0);
+        assert_eq!(
+            (segments.attack, segments.sustain, segments.release),
+            (2.0, 4.0, 1.0)
+        );
+        assert!((segments.peak - 0.6).abs() < 1e-6);
+
+        // Zero (or nonsense) attacks and releases don't produce empty or negative segments, pads keep their sustain
+        let degenerate = Envelope {
+            attack: -1.0,
+            sustain_level: 2.0,
+            release: 0.0,
+        };
+        let segments = degenerate.segments(true, 1.0, 1.0);
+        assert_eq!(segments.attack, 0.0);
+        assert_eq!(segments.sustain, 1.0);
+        assert!(segments.release > 0.0);
+        assert_eq!(segments.peak, 1.0);
+        assert!(degenerate.segments(false, 0.0, 1.0).release > 0.0);
+
+        // Islands vary a little around the defaults, plucks stay plucky
+        let mut rng = Xoshiro256Plus::seed_from_u64(7);
+        for _ in 0..100 {
+            let pluck = island_envelope(false, &mut rng);
+            assert!((0.0..=0.03).contains(&pluck.attack));
+            assert_eq!(pluck.sustain_level, 1.0);
+            assert!((0.8..=1.2).contains(&pluck.release));
+
+            let pad = island_envelope(true, &mut rng);
+            assert!((0.8..=1.2).contains(&pad.attack));
+            assert!((0.4..=0.6).contains(&pad.sustain_level));
+        }
+    }
+
+    #[test]
+    fn audio_node_reuse_state() {
+        use godot::builtin::Color;
+        use musical_constellations_rust::{
+            chords::Chord,
+            gd::{
+                node_main::{AudioNodeState, Envelope},
+                node_stream::Waveform,
+            },
+        };
+
+        // A fresh node is silent and uncolored, waiting for AudioGraph to set it up
+        let fresh = AudioNodeState::fresh();
+        assert_eq!(fresh.chord, Chord::default());
+        assert_eq!(fresh.waveform, Waveform::default());
+        assert_eq!(fresh.envelope, Envelope::PLUCK);
+        assert_eq!((fresh.octave, fresh.duration, fresh.node_idx), (0, 0.0, 0));
+        assert_eq!(
+            (fresh.island, fresh.degree, fresh.midi_note),
+            (None, None, 0)
+        );
+        assert!(!fresh.active && !fresh.cancelling && !fresh.is_pad);
+        assert!(!fresh.muted && !fresh.soloed_out && !fresh.highlighted);
+        assert_eq!(fresh.color, Color::default());
+        assert_eq!(fresh.cached_color, Color::default());
+        assert_eq!((fresh.scale, fresh.cached_scale), (0.0, 0.0));
+        assert_eq!(fresh.amplitude, 1.0);
+        assert!(!fresh.has_rng && !fresh.has_amplitude_tween);
+        assert_eq!(fresh.pending_scale, 1.0);
+        assert!(!fresh.has_pending_pulse);
+        assert!(fresh.diff(&AudioNodeState::fresh()).is_empty());
+
+        // A node that was used reports every field that would leak into its next use
+        let used = AudioNodeState {
+            chord: Chord::Cmin,
+            octave: 5,
+            waveform: Waveform::Saw,
+            is_pad: true,
+            island: Some(3),
+            cached_color: Color::from_rgb(1.0, 0.5, 0.0),
+            active: true,
+            pending_scale: 1.5,
+            has_rng: true,
+            has_pending_pulse: true,
+            ..AudioNodeState::fresh()
+        };
+        assert_eq!(
+            used.diff(&fresh),
+            [
+                "chord",
+                "octave",
+                "waveform",
+                "is_pad",
+                "island",
+                "cached_color",
+                "active",
+                "pending_scale",
+                "has_rng",
+                "has_pending_pulse"
+            ]
+        );
+    }
+
+    #[test]
+    fn sphere_batch() {
+        use godot::builtin::{Color, Vector3};
+        use musical_constellations_rust::gd::graph::graph_spheres::{SphereBatch, SphereInstance};
+
+        let sphere = |x: f32| SphereInstance {
+            position: Vector3::new(x, 0.0, 0.0),
+            color: Color::WHITE,
+            scale: 1.0,
+            is_pad: false,
+        };
+
+        let mut batch = SphereBatch::default();
+        for i in 0..4 {
+            batch.set(i, sphere(i as f32));
+        }
+        assert_eq!(batch.len(), 4);
+        assert_eq!(batch.take_dirty().len(), 4);
+        assert_eq!(batch.dirty_count(), 0);
+
+        // Only what changed gets written to the MultiMesh
+        batch.set_scale(1, 0.5);
+        batch.set_color(1, Color::RED);
+        batch.set_scale(2, 0.25);
+        let dirty = batch.take_dirty();
+        assert_eq!(
+            dirty.iter().map(|(idx, _)| *idx).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert_eq!(dirty[0].1.unwrap().color, Color::RED);
+        assert_eq!(dirty[0].1.unwrap().scale, 0.5);
+
+        // Nodes without an instance (e.g. pooled ones) can't write
+        batch.set_color(10, Color::RED);
+        assert_eq!(batch.dirty_count(), 0);
+
+        // Removing works like petgraph: the last node takes over the index, its old index gets hidden
+        batch.remove(1);
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch.get(1).unwrap().position.x, 3.0);
+        assert_eq!(batch.take_dirty(), [(1, Some(sphere(3.0))), (3, None)]);
+
+        // Clearing hides everything that was there
+        batch.clear();
+        assert!(batch.is_empty());
+        assert_eq!(batch.take_dirty(), [(0, None), (1, None), (2, None)]);
+    }
+
+    #[test]
+    fn amplitude_ramp_retrigger_stress() {
+        use musical_constellations_rust::gd::node_stream::{AmplitudeRamp, DECLICK_SECS};
+        use rand::{Rng, SeedableRng};
+        use rand_xoshiro::Xoshiro256Plus;
+
+        let sample_rate = 48000.0;
+        let frames_per_block = 512;
+        let mut ramp = AmplitudeRamp::new(sample_rate);
+        assert!((ramp.max_step() * DECLICK_SECS * sample_rate - 1.0).abs() < 1e-4);
+
+        // Retrigger hundreds of times: every killed tween leaves the amplitude somewhere random, and a pluck without attack jumps to its peak
+        let mut rng = Xoshiro256Plus::seed_from_u64(884);
+        let mut previous = ramp.current();
+        for retrigger in 0..500 {
+            let target = match retrigger % 3 {
+                0 => 1.0,
+                1 => 0.0,
+                _ => rng.random_range(0.0..=1.0),
+            };
+            for _ in 0..rng.random_range(1..frames_per_block) {
+                let amp = ramp.next(target);
+                assert!(
+                    (amp - previous).abs() <= ramp.max_step() + 1e-6,
+                    "jumped from {previous} to {amp} on retrigger {retrigger}"
+                );
+                assert!((0.0..=1.0).contains(&amp));
+                previous = amp;
+            }
+        }
+
+        // It does reach the target, within `DECLICK_SECS`
+        let mut ramp = AmplitudeRamp::new(sample_rate);
+        let samples = (DECLICK_SECS * sample_rate).ceil() as usize;
+        for _ in 0..samples {
+            ramp.next(0.8);
+        }
+        assert_eq!(ramp.current(), 0.8);
+
+        // Starting again always fades in from silence
+        ramp.reset();
+        assert_eq!(ramp.current(), 0.0);
+        assert!(ramp.next(1.0) <= ramp.max_step());
+    }
+
+    #[test]
+    fn color_modes() {
+        use godot::builtin::Color;
+        use musical_constellations_rust::{
+            gd::{graph::graph_main::ConstellationStats, node_stream::Waveform},
+            palette::{ColorMode, NodeColors, PALETTES, palette_gradient},
+            util::color_to_html,
+        };
+
+        assert!(PALETTES.iter().all(|name| palette_gradient(name).is_some()));
+        assert!(palette_gradient("nope").is_none());
+        assert_eq!(ColorMode::Island.next(), ColorMode::Waveform);
+        assert_eq!(ColorMode::Waveform.next().next(), ColorMode::Island);
+
+        // Waveform mode looks like it always did, whatever the palette
+        let colors = NodeColors::new(ColorMode::Waveform, "viridis");
+        assert_eq!(
+            colors.base_color(Waveform::Saw, 4, Some(1)),
+            Waveform::Saw.as_color()
+        );
+
+        // Octaves 2..=8 span the palette, everything outside is clamped
+        let colors = NodeColors::new(ColorMode::Octave, "turbo");
+        assert_ne!(colors.octave_color(2), colors.octave_color(8));
+        assert_eq!(colors.octave_color(0), colors.octave_color(2));
+        assert_eq!(colors.octave_color(12), colors.octave_color(8));
+        assert_eq!(
+            colors.base_color(Waveform::Saw, 5, None),
+            colors.octave_color(5)
+        );
+        // The waveform doesn't matter anymore
+        assert_eq!(
+            colors.base_color(Waveform::Sine, 5, None),
+            colors.base_color(Waveform::Noise, 5, None)
+        );
+        assert_eq!(colors.legend().matches("[color=").count(), 7);
+
+        // Islands next to each other get different colors, nodes without an island are gray
+        let colors = NodeColors::new(ColorMode::Island, "turbo");
+        for island in 0..20 {
+            assert_ne!(colors.island_color(island), colors.island_color(island + 1));
+        }
+        assert_eq!(colors.base_color(Waveform::Saw, 4, None), Color::GRAY);
+
+        // Unknown palettes fall back to turbo
+        let fallback = NodeColors::new(ColorMode::Island, "nope");
+        assert_eq!(fallback.island_color(3), colors.island_color(3));
+
+        // The stats histograms follow the mode
+        let mut rng = Xoshiro256Plus::seed_from_u64(3);
+        let constellation = ConstellationGraph::new(120, 5.0, 3, &mut rng).unwrap();
+        let island_data = AudioGraph::generate_island_data(&constellation, &mut rng);
+        let stats = ConstellationStats::new(&constellation, &island_data);
+        let config = WalkConfig {
+            strategy: WalkStrategy::DirectionPreserving,
+            revisits: RevisitPolicy::FallBack,
+            dead_ends: DeadEndBehavior::Stop,
+            max_steps: 0,
+            max_concurrent_branches: 0,
+            rhythm_grid: RhythmGrid::PowersOfTwo,
+            direction_temperature: 0.0,
+        };
+        let rendered =
+            AudioGraph::generate_stats(&stats, &constellation, &island_data, &config, &colors);
+        assert!(rendered.contains("Colors: by island"));
+        let histogram = rendered.split("Island size histogram:").nth(1).unwrap();
+        for island in 0..island_data.len() {
+            let tag = format!("[color={}]", color_to_html(colors.island_color(island)));
+            assert!(histogram.contains(&tag), "no {tag} in {histogram}");
+        }
+    }
+
+    #[test]
+    fn script_node_validation() {
+        use std::collections::BTreeMap;
+
+        use musical_constellations_rust::gd::graph::graph_main::{Liveness, script_node};
+        use petgraph::graph::NodeIndex;
+
+        // Stands in for a Gd<AudioNode>, which can't exist without an engine
+        #[derive(Debug, Clone, PartialEq)]
+        struct FakeNode(bool);
+
+        impl Liveness for FakeNode {
+            fn is_alive(&self) -> bool {
+                self.0
+            }
+        }
+
+        let nodes = BTreeMap::from([
+            (NodeIndex::new(0), FakeNode(true)),
+            (NodeIndex::new(1), FakeNode(false)),
+            (NodeIndex::new(3), FakeNode(true)),
+        ]);
+
+        assert_eq!(
+            script_node(&nodes, 0),
+            Some((NodeIndex::new(0), FakeNode(true)))
+        );
+        assert_eq!(
+            script_node(&nodes, 3),
+            Some((NodeIndex::new(3), FakeNode(true)))
+        );
+        // Each of these makes `start_walk_from` return -1
+        assert_eq!(script_node(&nodes, -1), None, "negative");
+        assert_eq!(script_node(&nodes, i64::MIN), None, "negative");
+        assert_eq!(script_node(&nodes, 4), None, "out of range");
+        assert_eq!(script_node(&nodes, 2), None, "removed");
+        assert_eq!(script_node(&nodes, 1), None, "freed");
+    }
+
+    #[test]
+    fn freed_node_guards() {
+        use std::{cell::Cell, collections::BTreeMap, rc::Rc};
+
+        use musical_constellations_rust::gd::graph::graph_main::{
+            Liveness, forget_node, live_node, remove_dead_nodes,
+        };
+        use petgraph::graph::NodeIndex;
+
+        // Stands in for a Gd<AudioNode>, which can't exist without an engine
+        #[derive(Debug, Clone, PartialEq)]
+        struct FakeNode(u32, Rc<Cell<bool>>);
+
+        impl Liveness for FakeNode {
+            fn is_alive(&self) -> bool {
+                self.1.get()
+            }
+        }
+
+        let nodes = (0..4)
+            .map(|i| {
+                (
+                    NodeIndex::new(i),
+                    FakeNode(i as u32, Rc::new(Cell::new(true))),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        let mut nodes = Rc::new(nodes);
+        let snapshot = Rc::clone(&nodes); // Like a walk holds on to
+
+        assert_eq!(live_node(&snapshot, NodeIndex::new(1)).unwrap().0, 1);
+        assert_eq!(live_node(&snapshot, NodeIndex::new(9)), None);
+
+        // Freed mid-walk: the snapshot still has it, but the guard skips it
+        let freed = snapshot[&NodeIndex::new(1)].clone();
+        freed.1.set(false);
+        assert_eq!(live_node(&snapshot, NodeIndex::new(1)), None);
+
+        // Only the node at that index gets forgotten, and the snapshot stays as it was
+        let other = FakeNode(7, Rc::new(Cell::new(true)));
+        assert!(!forget_node(&mut nodes, NodeIndex::new(1), &other));
+        assert!(
+            Rc::ptr_eq(&nodes, &snapshot),
+            "nothing to remove, so no clone"
+        );
+        assert!(forget_node(&mut nodes, NodeIndex::new(1), &freed));
+        assert!(!forget_node(&mut nodes, NodeIndex::new(1), &freed));
+        assert!(!nodes.contains_key(&NodeIndex::new(1)));
+        assert!(snapshot.contains_key(&NodeIndex::new(1)));
+
+        // The invariant: no dead instances left
+        let mut nodes = (*snapshot).clone();
+        snapshot[&NodeIndex::new(3)].1.set(false);
+        assert_eq!(
+            remove_dead_nodes(&mut nodes),
+            vec![NodeIndex::new(1), NodeIndex::new(3)]
+        );
+        assert!(nodes.values().all(Liveness::is_alive));
+        assert!(remove_dead_nodes(&mut nodes).is_empty());
+    }
+
+    #[test]
+    fn refresh_frequency() {
+        use musical_constellations_rust::{
+            chords::Chord,
+            gd::node_main::{chord_tone_frequency, node_frequency, random_chord_tone},
+        };
+        use rand::{Rng, SeedableRng};
+        use rand_xoshiro::Xoshiro256Plus;
+        use strum::IntoEnumIterator;
+
+        for chord in Chord::iter() {
+            let intervals = chord.as_intervals();
+            for seed in 0..20 {
+                let mut original = Xoshiro256Plus::seed_from_u64(seed);
+                let mut refreshed = original.clone();
+                let (octave, semitone_offset) = (2 + seed as i32 % 7, seed as f32 * 0.01 - 0.07);
+
+                // Unchanged inputs give exactly the same frequency, and use the rng the same way
+                let frequency = node_frequency(&intervals, octave, semitone_offset, &mut original);
+                let chord_tone = random_chord_tone(&intervals, &mut refreshed);
+                assert_eq!(
+                    chord_tone_frequency(&intervals, chord_tone, octave, semitone_offset),
+                    frequency
+                );
+                assert_eq!(original.random::<u64>(), refreshed.random::<u64>());
+
+                // Shifting the octave keeps the chord tone
+                let up = chord_tone_frequency(&intervals, chord_tone, octave + 1, semitone_offset);
+                assert!((up / frequency - 2.0).abs() < 1e-4);
+            }
+        }
+
+        // A chord tone past the end of a (smaller) chord wraps around
+        let intervals = Chord::Cmaj.as_intervals();
+        assert_eq!(
+            chord_tone_frequency(&intervals, intervals.len(), 4, 0.0),
+            chord_tone_frequency(&intervals, 0, 4, 0.0)
+        );
+    }
+
+    #[test]
+    fn idle_frames() {
+        use musical_constellations_rust::gd::node_main::{IDLE_FRAMES_BEFORE_SLEEP, IdleFrames};
+
+        let mut idle_frames = IdleFrames::default();
+        for _ in 1..IDLE_FRAMES_BEFORE_SLEEP {
+            assert!(!idle_frames.update(true));
+        }
+        // A single busy frame starts over
+        assert!(!idle_frames.update(false));
+        assert_eq!(idle_frames.count(), 0);
+
+        let sleeps_after = (1..=IDLE_FRAMES_BEFORE_SLEEP * 3)
+            .filter(|_| idle_frames.update(true))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sleeps_after,
+            [
+                IDLE_FRAMES_BEFORE_SLEEP,
+                IDLE_FRAMES_BEFORE_SLEEP * 2,
+                IDLE_FRAMES_BEFORE_SLEEP * 3
+            ]
+        );
+    }
+
+    #[test]
+    fn waveform_colors() {
+        use godot::builtin::Color;
+        use musical_constellations_rust::gd::node_stream::Waveform;
+        use strum::IntoEnumIterator;
+
+        // Every waveform gets its own color from the table, except noise, which is gray
+        let colors = Waveform::iter()
+            .map(|waveform| waveform.as_color())
+            .collect::<Vec<_>>();
+        assert_eq!(colors.len(), Waveform::iter().len());
+        assert_eq!(Waveform::Noise.as_color(), Color::GRAY);
+        for (i, color) in colors.iter().enumerate() {
+            assert!(colors[..i].iter().all(|other| other != color));
+        }
+        // The table is indexed by discriminant, so it must be in the same order as `Waveform::iter`
+        for waveform in Waveform::iter() {
+            assert_eq!(
+                waveform.as_color(),
+                waveform.gradient_color(),
+                "{waveform:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn play_outcome() {
+        use musical_constellations_rust::gd::node_main::PlayOutcome;
+
+        assert_eq!(PlayOutcome::from_tween(Some(true)), PlayOutcome::Completed);
+        // The tween got killed by another play (or freed with its node) before it finished
+        assert_eq!(
+            PlayOutcome::from_tween(Some(false)),
+            PlayOutcome::Interrupted
+        );
+        assert_eq!(PlayOutcome::from_tween(None), PlayOutcome::Cancelled);
+    }
+
+    #[test]
+    fn intro_pacing() {
+        use musical_constellations_rust::gd::graph::graph_main::{IntroPacing, intro_chunks};
+
+        assert_eq!(IntroPacing::new(true, 1000), IntroPacing::Skipped);
+        assert_eq!(IntroPacing::new(false, 0), IntroPacing::Skipped);
+        assert_eq!(
+            IntroPacing::new(false, 1000),
+            IntroPacing::Animated {
+                nodes_per_second: 1000
+            }
+        );
+
+        let chunks = |count, chunk_size| intro_chunks(count, chunk_size).collect::<Vec<_>>();
+        assert!(chunks(0, 500).is_empty());
+        assert_eq!(chunks(1, 500).len(), 1);
+        assert_eq!(chunks(1, 500)[0], 0..1);
+        assert_eq!(chunks(500, 500).len(), 1);
+        assert_eq!(chunks(500, 500)[0], 0..500);
+        assert_eq!(chunks(501, 500), [0..500, 500..501]);
+        assert_eq!(chunks(1000, 500), [0..500, 500..1000]);
+        assert_eq!(
+            chunks(3, 0),
+            [0..1, 1..2, 2..3],
+            "a chunk size of 0 would never finish"
+        );
+
+        // Every node exactly once, in order
+        for count in [0, 1, 499, 500, 501, 10_000, 10_001] {
+            let spawned = intro_chunks(count, 500).flatten().collect::<Vec<_>>();
+            assert_eq!(spawned, (0..count).collect::<Vec<_>>());
+            assert!(intro_chunks(count, 500).all(|chunk| !chunk.is_empty() && chunk.len() <= 500));
+        }
+    }
+
+    #[test]
+    fn island_anchors() {
+        use std::collections::BTreeMap;
+
+        use musical_constellations_rust::gd::graph::graph_main::island_anchors;
+        use petgraph::graph::NodeIndex;
+
+        // Island 2 is the biggest, islands 0 and 3 tie
+        let node_islands = [
+            (5, 0),
+            (1, 0),
+            (2, 1),
+            (9, 2),
+            (7, 2),
+            (4, 2),
+            (8, 3),
+            (6, 3),
+        ]
+        .into_iter()
+        .map(|(node, island)| (NodeIndex::new(node), island))
+        .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            island_anchors(&node_islands),
+            [
+                (2, NodeIndex::new(4)),
+                (0, NodeIndex::new(1)),
+                (3, NodeIndex::new(6)),
+                (1, NodeIndex::new(2)),
+            ]
+        );
+
+        // Sculpting the anchor away makes the next lowest node the anchor, and can change the order
+        let mut sculpted = node_islands.clone();
+        sculpted.remove(&NodeIndex::new(4));
+        sculpted.remove(&NodeIndex::new(7));
+        assert_eq!(
+            island_anchors(&sculpted),
+            [
+                (0, NodeIndex::new(1)),
+                (3, NodeIndex::new(6)),
+                (1, NodeIndex::new(2)),
+                (2, NodeIndex::new(9)),
+            ]
+        );
+
+        assert!(island_anchors(&BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn midi_note_mapping() {
+        use musical_constellations_rust::midi_sync::{MidiNoteAction, MidiNoteIndex, MidiNoteOn};
+        use petgraph::graph::NodeIndex;
+
+        // Note On on channel 3, a Note On with velocity 0 is a Note Off
+        let note_on = MidiNoteOn::parse(&[0x92, 60, 100], None).unwrap();
+        assert_eq!(
+            note_on,
+            MidiNoteOn {
+                note: 60,
+                velocity: 100
+            }
+        );
+        assert_eq!(MidiNoteOn::parse(&[0x92, 60, 100], Some(3)), Some(note_on));
+        assert_eq!(MidiNoteOn::parse(&[0x92, 60, 100], Some(1)), None);
+        assert_eq!(MidiNoteOn::parse(&[0x92, 60, 0], None), None);
+        assert_eq!(MidiNoteOn::parse(&[0x82, 60, 100], None), None); // Note Off
+        assert_eq!(MidiNoteOn::parse(&[0xF8], None), None); // Clock
+        assert_eq!(note_on.action(100), MidiNoteAction::Walk);
+        assert_eq!(note_on.action(101), MidiNoteAction::Play);
+
+        let n = NodeIndex::new;
+        let index = MidiNoteIndex::new([(n(3), 60), (n(1), 60), (n(2), 64), (n(0), 55)]);
+        assert_eq!(index.nodes(60), [n(1), n(3)]);
+        assert!(index.nodes(61).is_empty());
+
+        let all = |_| true;
+        assert_eq!(index.nearest(60, all), Some(n(1)));
+        assert_eq!(index.nearest(63, all), Some(n(2)));
+        assert_eq!(index.nearest(0, all), Some(n(0)));
+        assert_eq!(index.nearest(127, all), Some(n(2)));
+        // 62 is as close to 60 as to 64, the lower note wins
+        assert_eq!(index.nearest(62, all), Some(n(1)));
+
+        // Playing nodes are skipped
+        assert_eq!(index.nearest(60, |idx| idx != n(1)), Some(n(3)));
+        assert_eq!(index.nearest(60, |idx| idx == n(2)), Some(n(2)));
+        assert_eq!(index.nearest(60, |_| false), None);
+        assert_eq!(MidiNoteIndex::default().nearest(60, all), None);
+    }
+
+    #[test]
+    fn box_selection() {
+        use godot::prelude::*;
+        use musical_constellations_rust::gd::graph::graph_box_select::{
+            BoxSelection, MIN_BOX_SELECTION_DRAG, nodes_in_rect,
+        };
+        use petgraph::graph::NodeIndex;
+
+        // Dragged up and to the left, the rect still has a positive size
+        let mut selection = BoxSelection::new(Vector2::new(100.0, 80.0));
+        assert!(!selection.is_drag());
+        selection.end = Vector2::new(100.0 - MIN_BOX_SELECTION_DRAG / 2.0, 80.0);
+        assert!(!selection.is_drag());
+        selection.end = Vector2::new(20.0, 40.0);
+        assert!(selection.is_drag());
+        assert_eq!(
+            selection.rect(),
+            Rect2::new(Vector2::new(20.0, 40.0), Vector2::new(80.0, 40.0))
+        );
+
+        let n = NodeIndex::new;
+        let projected = [
+            (n(4), Vector2::new(50.0, 50.0)),
+            (n(0), Vector2::new(10.0, 50.0)), // Left of the rect
+            (n(2), Vector2::new(99.0, 79.0)),
+            (n(1), Vector2::new(50.0, 90.0)), // Below it
+            (n(3), Vector2::new(30.0, 60.0)),
+        ];
+        let rect = selection.rect();
+        assert_eq!(nodes_in_rect(projected, rect, 128), [n(2), n(3), n(4)]);
+        // Capped to the lowest indices
+        assert_eq!(nodes_in_rect(projected, rect, 2), [n(2), n(3)]);
+        assert!(nodes_in_rect(projected, rect, 0).is_empty());
+    }
+
+    #[test]
+    fn stress_measurement() {
+        use musical_constellations_rust::gd::graph::graph_stress::StressMeasurement;
+
+        let mut measurement = StressMeasurement::new(0.5);
+        assert_eq!(measurement.summary(256), None);
+
+        // 20 frames at 60 FPS, then a hitch at 5 FPS that crosses the half second
+        for _ in 0..20 {
+            assert!(!measurement.frame(1.0 / 60.0, 100));
+        }
+        assert!(measurement.frame(0.2, 250));
+        // Frames after the window don't count
+        assert!(measurement.frame(1.0, 1000));
+
+        let summary = measurement.summary(256).unwrap();
+        assert_eq!(summary.note_count, 256);
+        assert!((summary.min_fps - 5.0).abs() < 1e-9);
+        let secs = 20.0 / 60.0 + 0.2;
+        assert!((summary.secs - secs).abs() < 1e-9);
+        assert!((summary.avg_fps - 21.0 / secs).abs() < 1e-9);
+        assert_eq!(summary.peak_streams, 250);
+        assert_eq!(
+            summary.to_string(),
+            "Stress test: 256 notes, 5.0 min FPS, 39.4 avg FPS, 250 peak streams over 0.5s"
+        );
+    }
+
+    #[test]
+    fn cancelling_count() {
+        use musical_constellations_rust::gd::graph::graph_main::CancellingCount;
+
+        // Like `AudioNode::set_cancelling`, every node tells the shared count about its own changes
+        let count = CancellingCount::default();
+        let mut flags = [false; 4];
+        let mut set = |node: usize, cancelling: bool| {
+            count.update(flags[node], cancelling);
+            flags[node] = cancelling;
+        };
+
+        // Right-click toggles, twice on node 1
+        set(0, true);
+        set(1, true);
+        set(2, true);
+        set(1, false);
+        assert_eq!(count.get(), 2);
+
+        // Setting what it already was doesn't count twice, e.g. a walk clearing a node that's not cancelling
+        set(0, true);
+        set(3, false);
+        assert_eq!(count.get(), 2);
+
+        // A walk runs into node 2, then everything gets cleared
+        set(2, false);
+        assert_eq!(count.get(), 1);
+        for node in 0..4 {
+            set(node, false);
+        }
+        assert_eq!(count.get(), 0);
+
+        // Clones share the count, since AudioGraph gives every node one
+        let shared = count.clone();
+        shared.update(false, true);
+        assert_eq!(count.get(), 1);
+        // Never goes below zero
+        count.update(true, false);
+        count.update(true, false);
+        assert_eq!(shared.get(), 0);
+    }
+
+    #[test]
+    fn island_hover_debounce() {
+        use std::time::{Duration, Instant};
+
+        use musical_constellations_rust::gd::graph::graph_main::{
+            ISLAND_HOVER_DEBOUNCE, IslandHover,
+        };
+
+        let t0 = Instant::now();
+        let at = |ms: u64| t0 + Duration::from_millis(ms);
+        let debounce = ISLAND_HOVER_DEBOUNCE.as_millis() as u64;
+        let mut hover = IslandHover::default();
+        assert_eq!(hover.update(at(1000)), None);
+
+        // Sweeping over islands 1, 2 and 3 only highlights the last one, once the mouse rests
+        hover.hover(Some(1), at(0));
+        hover.hover(Some(2), at(10));
+        hover.hover(Some(3), at(20));
+        assert_eq!(hover.update(at(20 + debounce - 1)), None);
+        assert_eq!(hover.update(at(20 + debounce)), Some(Some(3)));
+        assert_eq!(hover.highlighted(), Some(3));
+        assert_eq!(hover.update(at(1000)), None); // Only once
+
+        // Moving between nodes of the same island (off one, onto the next) keeps the highlight
+        hover.hover(None, at(1000));
+        hover.hover(Some(3), at(1005));
+        assert_eq!(hover.update(at(2000)), None);
+        assert_eq!(hover.highlighted(), Some(3));
+
+        // Going off the island takes the highlight away after the debounce too
+        hover.hover(None, at(3000));
+        assert_eq!(hover.update(at(3000)), None);
+        assert_eq!(hover.update(at(3000 + debounce)), Some(None));
+        assert_eq!(hover.highlighted(), None);
+    }
+
+    #[test]
+    fn edge_renderer_batching() {
+        use godot::builtin::{Color, Vector3};
+        use musical_constellations_rust::gd::graph::graph_edge_renderer::{
+            BUFFER_FLUSH_CALLS, DEFAULT_EDGE_TWEEN_PROGRESS, EDGE_BUFFER_STRIDE, EdgeFlush,
+            EdgeRenderer,
+        };
+
+        let x = |x: f32| Vector3::new(x, 0.0, 0.0);
+        let mut edges = EdgeRenderer::default();
+        assert_eq!(edges.flush_mode(), EdgeFlush::Nothing);
+
+        // A new set of edges changes the instance count, so the whole buffer gets written
+        edges.reset([(Vector3::ZERO, Vector3::UP), (x(1.0), x(2.0))]);
+        assert_eq!(edges.len(), 2);
+        assert_eq!(edges.flush_mode(), EdgeFlush::Buffer);
+
+        // The buffer is laid out as a 3x4 transform, then the color and the custom data
+        let buffer = edges.buffer();
+        assert_eq!(buffer.len(), 2 * EDGE_BUFFER_STRIDE);
+        assert_eq!(
+            buffer[..EDGE_BUFFER_STRIDE],
+            [
+                1.0,
+                0.0,
+                0.0,
+                0.0, //
+                0.0,
+                1.0,
+                0.0,
+                0.5, //
+                0.0,
+                0.0,
+                1.0,
+                0.0, //
+                0.0,
+                0.0,
+                0.0,
+                1.0, //
+                DEFAULT_EDGE_TWEEN_PROGRESS,
+                0.0,
+                0.0,
+                0.0,
+            ]
+        );
+        edges.take_dirty();
+        assert_eq!(edges.flush_mode(), EdgeFlush::Nothing);
+
+        // Writing the same edge several times in a frame (trails, tweens) is one call per changed part
+        for progress in [0.1, 0.2, 0.3] {
+            edges.set_custom(0, Color::from_rgba(progress, 0.0, 0.0, 1.0));
+        }
+        edges.set_color(0, Color::RED);
+        edges.set_color(0, Color::BLUE);
+        // Writing what's there already isn't a change
+        edges.set_color(1, Color::BLACK);
+        assert_eq!(edges.pending_calls(), 2);
+        assert_eq!(edges.flush_mode(), EdgeFlush::Instances);
+        let dirty = edges.take_dirty();
+        assert_eq!(dirty.len(), 1);
+        assert!(dirty[0].1.color && dirty[0].1.custom && !dirty[0].1.transform);
+        assert_eq!(edges.color(0), Color::BLUE);
+
+        // Lots of changes go in one buffer write
+        edges.set_edge(BUFFER_FLUSH_CALLS, Vector3::ZERO, x(1.0));
+        assert_eq!(edges.len(), (BUFFER_FLUSH_CALLS + 1).next_power_of_two());
+        edges.take_dirty();
+        for slot in 0..=BUFFER_FLUSH_CALLS {
+            edges.set_color(slot, Color::WHITE);
+        }
+        assert_eq!(edges.flush_mode(), EdgeFlush::Buffer);
+        edges.take_dirty();
+
+        // Compacting moves the instance and shrinks, hidden slots are black and collapsed
+        edges.copy(BUFFER_FLUSH_CALLS, 1);
+        edges.hide(0);
+        edges.resize(2);
+        assert_eq!(edges.get(1).unwrap().transform.origin, x(0.5));
+        assert_eq!(edges.color(0), Color::BLACK);
+        assert_eq!(edges.get(0).unwrap().transform.basis.determinant(), 0.0);
+        assert_eq!(edges.flush_mode(), EdgeFlush::Buffer);
+
+        // Straight up or down doesn't need a rotation axis
+        edges.set_edge(0, Vector3::UP, Vector3::ZERO);
+        assert_eq!(edges.get(0).unwrap().transform.origin, Vector3::UP * 0.5);
+
+        // Slots without an instance (e.g. a tween outliving a regenerate) can't write
+        edges.clear();
+        edges.take_dirty();
+        edges.set_custom(0, Color::WHITE);
+        assert_eq!(edges.flush_mode(), EdgeFlush::Nothing);
+    }
+
+    #[test]
+    fn stats_export_shape() {
+        use musical_constellations_rust::{
+            gd::graph::graph_main::ConstellationStats, presets::GenerationParams,
+            session::BuildInfo, stats_export::StatsExport,
+        };
+        use serde_json::Value;
+        use time::macros::datetime;
+
+        let mut rng = Xoshiro256Plus::seed_from_u64(901);
+        let constellation = ConstellationGraph::new(120, 5.0, 3, &mut rng).unwrap();
+        let island_data = AudioGraph::generate_island_data(&constellation, &mut rng);
+        let export = StatsExport {
+            build: BuildInfo::current(),
+            seed: 901,
+            graph_salt: 0,
+            bpm: 115.0,
+            generation: GenerationParams {
+                num_points: 120,
+                distribution: Default::default(),
+                edge_strategy: Default::default(),
+                connectivity: Default::default(),
+                bridges_per_island: 1,
+                lloyd_iterations: 0,
+            },
+            stats: ConstellationStats::new(&constellation, &island_data),
+        };
+
+        let json: Value = serde_json::from_str(&export.to_json()).unwrap();
+        let keys = |value: &Value| {
+            value
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(&json),
+            ["bpm", "build", "generation", "graph_salt", "seed", "stats"]
+        );
+        assert_eq!(json["seed"], "0000000000000385");
+        assert_eq!(json["bpm"], 115.0);
+        assert_eq!(json["generation"]["num_points"], 120);
+        assert_eq!(
+            keys(&json["stats"]),
+            [
+                "bridge_edge_count",
+                "chord",
+                "edge_count",
+                "island_sizes",
+                "node_count",
+                "pad_island_count",
+                "semitone_offset",
+                "waveform_counts",
+            ]
+        );
+        assert_eq!(
+            json["stats"]["node_count"],
+            constellation.graph.node_count()
+        );
+        assert_eq!(
+            json["stats"]["island_sizes"].as_array().unwrap().len(),
+            constellation.islands.len()
+        );
+        assert_eq!(
+            keys(&json["stats"]["waveform_counts"][0]),
+            ["islands", "pad_islands", "waveform"]
+        );
+        assert!(json["build"]["version"].is_string());
+
+        // One file per export, named after the seed and the UTC time
+        let time = datetime!(2025-01-02 04:04:05 +01:00);
+        assert_eq!(
+            export.file_name(time),
+            "0000000000000385_20250102T030405Z.json"
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = export.save(&dir.path().join("stats"), time).unwrap();
+        assert_eq!(
+            serde_json::from_str::<StatsExport>(&std::fs::read_to_string(&path).unwrap()).unwrap(),
+            export
+        );
+
+        // The directory can't be created inside a file
+        assert!(export.save(&path.join("stats"), time).is_err());
+    }
+
+    #[test]
+    fn edge_tween_ownership() {
+        use std::{cell::RefCell, rc::Rc};
+
+        use godot::builtin::{Color, Vector3};
+        use musical_constellations_rust::gd::graph::graph_edge_renderer::{
+            EdgeInstance, EdgeRenderer, EdgeTweenGuard,
+        };
+
+        let edges = Rc::new(RefCell::new(EdgeRenderer::default()));
+        edges.borrow_mut().reset([
+            (Vector3::ZERO, Vector3::UP),
+            (Vector3::ZERO, Vector3::RIGHT),
+        ]);
+        let progress = |progress: f32| Color::from_rgba(progress, 0.0, 0.0, 1.0);
+        let custom = |slot: usize| edges.borrow().get(slot).unwrap().custom;
+        let reset = EdgeInstance::hidden().custom;
+
+        // Two walks cross the same edge: the second tween takes over, the first can't write anymore
+        let first = EdgeTweenGuard::new(edges.clone(), 0);
+        first.set_custom(progress(0.5));
+        assert_eq!(custom(0), progress(0.5));
+        let second = EdgeTweenGuard::new(edges.clone(), 0);
+        first.set_custom(progress(0.6));
+        second.set_custom(progress(0.1));
+        assert_eq!(custom(0), progress(0.1));
+
+        // The first one ending doesn't reset the progress of the second
+        drop(first);
+        assert_eq!(custom(0), progress(0.1));
+        second.set_custom(progress(0.2));
+        assert_eq!(custom(0), progress(0.2));
+
+        // The second one ending (or its task getting dropped after a panic) does
+        drop(second);
+        assert_eq!(custom(0), reset);
+
+        // Out of order the other way around: the newest ends first, then the stale one can't undo the reset or write
+        let stale = EdgeTweenGuard::new(edges.clone(), 1);
+        let newest = EdgeTweenGuard::new(edges.clone(), 1);
+        newest.set_custom(progress(0.9));
+        drop(newest);
+        assert_eq!(custom(1), reset);
+        stale.set_custom(progress(0.3));
+        assert_eq!(custom(1), reset);
+        drop(stale);
+        assert_eq!(custom(1), reset);
+
+        // Compacting moves a tweened edge away from its tween, which resets the progress where it ends up
+        let moving = EdgeTweenGuard::new(edges.clone(), 1);
+        moving.set_custom(progress(0.4));
+        edges.borrow_mut().copy(1, 0);
+        edges.borrow_mut().resize(1);
+        assert_eq!(custom(0), reset);
+        moving.set_custom(progress(0.5));
+        assert_eq!(custom(0), reset);
+        drop(moving);
+
+        // So does regenerating, even though the tween task can outlive it
+        let orphan = EdgeTweenGuard::new(edges.clone(), 0);
+        edges.borrow_mut().reset([(Vector3::ZERO, Vector3::UP)]);
+        orphan.set_custom(progress(0.7));
+        drop(orphan);
+        assert_eq!(custom(0), reset);
+    }
+
+    #[test]
+    fn accepting_input_changes() {
+        use musical_constellations_rust::gd::graph::graph_main::AcceptingInput;
+
+        let mut accepting = AcceptingInput::default();
+        assert!(!accepting.get());
+
+        // Ready: nothing changed, but the UI still needs to hear it's off
+        assert_eq!(accepting.set(false), Some(false));
+        // Spawning done
+        assert_eq!(accepting.set(true), Some(true));
+        assert!(accepting.get());
+        // Regenerating, and regenerating again before the intro was done
+        assert_eq!(accepting.set(false), Some(false));
+        assert_eq!(accepting.set(false), None);
+        assert!(!accepting.get());
+        // Spawning done again
+        assert_eq!(accepting.set(true), Some(true));
+        assert_eq!(accepting.set(true), None);
+    }
+
+    #[test]
+    fn salted_graph_seeds() {
+        use musical_constellations_rust::util::{create_rng_from_seed_and_state, salted_seed};
+        use rand::Rng;
+
+        // Salt 0 keeps the seed, so a lone graph generates what it always did
+        assert_eq!(salted_seed(0xDEADBEEF, 0), 0xDEADBEEF);
+        assert_eq!(salted_seed(-1, 0), -1);
+
+        // Deterministic, but different per salt and per seed
+        assert_eq!(salted_seed(0xDEADBEEF, 1), salted_seed(0xDEADBEEF, 1));
+        let seeds = [
+            salted_seed(0xDEADBEEF, 0),
+            salted_seed(0xDEADBEEF, 1),
+            salted_seed(0xDEADBEEF, 2),
+            salted_seed(0xCAFE, 1),
+        ];
+        for (i, a) in seeds.iter().enumerate() {
+            for b in &seeds[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+
+        // So two graphs with the same global seed get their own rngs, but the same ones every time
+        let rolls = |salt: u32| {
+            let mut rng = create_rng_from_seed_and_state(0xA0A0BE63, salted_seed(0xDEADBEEF, salt));
+            (0..4).map(|_| rng.random::<u64>()).collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(1), rolls(1));
+        assert_ne!(rolls(0), rolls(1));
+        assert_ne!(rolls(1), rolls(2));
+
+        // Two graphs generated from the same seed but different salts end up with different constellations
+        let constellation = |salt: u32| {
+            let mut rng = create_rng_from_seed_and_state(0xA0A0BE63, salted_seed(7, salt));
+            ConstellationGraph::new(60, 5.0, 3, &mut rng).unwrap()
+        };
+        let positions = |salt: u32| {
+            let constellation = constellation(salt);
+            constellation
+                .graph
+                .node_weights()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(3), positions(3));
+        assert_ne!(positions(0), positions(3));
+    }
 }